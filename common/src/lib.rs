@@ -0,0 +1,6 @@
+//! Pure logic shared by the exercise binaries. The binaries keep only argument
+//! parsing and I/O; everything they compute lives, and is tested, here.
+
+pub mod numbers;
+pub mod temperature;
+pub mod words;
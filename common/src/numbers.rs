@@ -0,0 +1,179 @@
+//! Number analysis: sums, extremes, mean, median, parity, and FizzBuzz labels.
+
+use std::fmt;
+
+pub fn is_even(n: i32) -> bool {
+    n % 2 == 0
+}
+
+/// FizzBuzz-style label for a single number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    FizzBuzz,
+    Fizz,
+    Buzz,
+    Even,
+    Odd,
+}
+
+impl Class {
+    pub fn of(n: i32) -> Class {
+        if n % 3 == 0 && n % 5 == 0 {
+            Class::FizzBuzz
+        } else if n % 3 == 0 {
+            Class::Fizz
+        } else if n % 5 == 0 {
+            Class::Buzz
+        } else if is_even(n) {
+            Class::Even
+        } else {
+            Class::Odd
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Class::FizzBuzz => "FizzBuzz",
+            Class::Fizz => "Fizz",
+            Class::Buzz => "Buzz",
+            Class::Even => "Even",
+            Class::Odd => "Odd",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Everything we report about a list of numbers. `max`/`min`/`mean`/`median` are None for empty input.
+#[derive(Debug, PartialEq)]
+pub struct Analysis {
+    // Accumulated in i64: each term is at most 2^31 in magnitude, so overflow
+    // would need more than 2^32 inputs, which can't fit in memory. No saturating
+    // or wrapping is therefore needed.
+    pub sum: i64,
+    pub max: Option<i32>,
+    pub min: Option<i32>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub evens: usize,
+    pub odds: usize,
+    pub classes: Vec<(i32, Class)>,
+}
+
+pub fn analyze(nums: &[i32]) -> Analysis {
+    let mut a = Analysis {
+        sum: 0,
+        max: None,
+        min: None,
+        mean: None,
+        median: None,
+        evens: 0,
+        odds: 0,
+        classes: Vec::with_capacity(nums.len()),
+    };
+    for &n in nums {
+        a.sum += i64::from(n);
+        a.max = Some(a.max.map_or(n, |m| m.max(n)));
+        a.min = Some(a.min.map_or(n, |m| m.min(n)));
+        if is_even(n) {
+            a.evens += 1;
+        } else {
+            a.odds += 1;
+        }
+        a.classes.push((n, Class::of(n)));
+    }
+    if !nums.is_empty() {
+        a.mean = Some(a.sum as f64 / nums.len() as f64);
+        a.median = Some(median(nums));
+    }
+    a
+}
+
+/// Median of a non-empty slice; even counts average the two middle values
+pub fn median(nums: &[i32]) -> f64 {
+    let mut sorted = nums.to_vec();
+    sorted.sort_unstable();
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (f64::from(sorted[mid - 1]) + f64::from(sorted[mid])) / 2.0
+    } else {
+        f64::from(sorted[mid])
+    }
+}
+
+/// Parse every token as an integer, naming the first one that fails
+pub fn parse_numbers<'a>(tokens: impl Iterator<Item = &'a str>) -> Result<Vec<i32>, String> {
+    tokens
+        .map(|tok| {
+            tok.parse::<i32>()
+                .map_err(|e| format!("invalid number '{tok}': {e}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_input() {
+        let a = analyze(&[]);
+        assert_eq!(a.sum, 0);
+        assert_eq!(a.max, None);
+        assert_eq!(a.min, None);
+        assert_eq!((a.mean, a.median), (None, None));
+        assert_eq!((a.evens, a.odds), (0, 0));
+        assert!(a.classes.is_empty());
+    }
+
+    #[test]
+    fn test_all_even() {
+        let a = analyze(&[2, 4, 8]);
+        assert_eq!(a.sum, 14);
+        assert_eq!((a.max, a.min), (Some(8), Some(2)));
+        assert_eq!((a.evens, a.odds), (3, 0));
+        assert!(a.classes.iter().all(|&(_, c)| c == Class::Even));
+    }
+
+    #[test]
+    fn test_mean_and_median() {
+        let a = analyze(&[7, 1, 4]);
+        assert_eq!(a.mean, Some(4.0));
+        assert_eq!(a.median, Some(4.0));
+
+        let a = analyze(&[10, 1, 4, 3]);
+        assert_eq!(a.mean, Some(4.5));
+        assert_eq!(a.median, Some(3.5));
+
+        let a = analyze(&[i32::MAX, i32::MAX]);
+        assert_eq!(a.median, Some(f64::from(i32::MAX)));
+    }
+
+    #[test]
+    fn test_sum_near_i32_max() {
+        let a = analyze(&[i32::MAX, i32::MAX, i32::MAX, 1]);
+        assert_eq!(a.sum, 3 * i64::from(i32::MAX) + 1);
+        let a = analyze(&[i32::MIN, i32::MIN]);
+        assert_eq!(a.sum, 2 * i64::from(i32::MIN));
+    }
+
+    #[test]
+    fn test_mixed_with_fizzbuzz() {
+        let a = analyze(&[15, 3, 10, 7, -30]);
+        assert_eq!(a.sum, 5);
+        assert_eq!((a.max, a.min), (Some(15), Some(-30)));
+        assert_eq!((a.evens, a.odds), (2, 3));
+        let classes: Vec<Class> = a.classes.iter().map(|&(_, c)| c).collect();
+        assert_eq!(
+            classes,
+            vec![
+                Class::FizzBuzz,
+                Class::Fizz,
+                Class::Buzz,
+                Class::Odd,
+                Class::FizzBuzz
+            ]
+        );
+    }
+}
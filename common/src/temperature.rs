@@ -0,0 +1,209 @@
+//! Temperature scales and conversions between them.
+
+use std::fmt;
+use std::str::FromStr;
+
+const FREEZING_POINT_F: f64 = 32.0;
+/// 0 K in Celsius; Kelvin and Rankine both start here.
+pub const ABSOLUTE_ZERO_C: f64 = -273.15;
+
+/// A temperature scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+    Rankine,
+}
+
+impl Unit {
+    /// The lowest temperature this scale can express.
+    pub fn absolute_zero(self) -> f64 {
+        match self {
+            Unit::Celsius => ABSOLUTE_ZERO_C,
+            Unit::Fahrenheit => -459.67,
+            Unit::Kelvin | Unit::Rankine => 0.0,
+        }
+    }
+
+    /// `value`, in this unit, converted to Celsius.
+    pub fn to_celsius(self, value: f64) -> f64 {
+        match self {
+            Unit::Celsius => value,
+            Unit::Fahrenheit => fahrenheit_to_celsius(value),
+            Unit::Kelvin => kelvin_to_celsius(value),
+            Unit::Rankine => rankine_to_celsius(value),
+        }
+    }
+
+    /// Celsius `c` expressed in this unit.
+    pub fn of_celsius(self, c: f64) -> f64 {
+        match self {
+            Unit::Celsius => c,
+            Unit::Fahrenheit => celsius_to_fahrenheit(c),
+            Unit::Kelvin => celsius_to_kelvin(c),
+            Unit::Rankine => celsius_to_rankine(c),
+        }
+    }
+}
+
+/// Accepts the symbol (`C`, `°C`) or the name (`celsius`), in any case.
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Unit, String> {
+        match s.trim_start_matches('°').to_ascii_lowercase().as_str() {
+            "c" | "celsius" => Ok(Unit::Celsius),
+            "f" | "fahrenheit" => Ok(Unit::Fahrenheit),
+            "k" | "kelvin" => Ok(Unit::Kelvin),
+            "r" | "rankine" => Ok(Unit::Rankine),
+            _ => Err(format!("unknown unit '{s}' (expected C, F, K, or R)")),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+            Unit::Kelvin => "K",
+            Unit::Rankine => "°R",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Why `convert` refused a temperature.
+#[derive(Debug, PartialEq)]
+pub enum ConvertError {
+    /// Colder than absolute zero in the given unit.
+    BelowAbsoluteZero(f64, Unit),
+    NotANumber,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::BelowAbsoluteZero(value, unit) => {
+                write!(
+                    f,
+                    "{value}{unit} is below absolute zero ({}{unit})",
+                    unit.absolute_zero()
+                )
+            }
+            ConvertError::NotANumber => write!(f, "temperature is not a number"),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+pub fn fahrenheit_to_celsius(f: f64) -> f64 {
+    (f - FREEZING_POINT_F) * 5.0 / 9.0
+}
+
+pub fn celsius_to_fahrenheit(c: f64) -> f64 {
+    (c * 9.0 / 5.0) + FREEZING_POINT_F
+}
+
+pub fn celsius_to_kelvin(c: f64) -> f64 {
+    c - ABSOLUTE_ZERO_C
+}
+
+pub fn kelvin_to_celsius(k: f64) -> f64 {
+    k + ABSOLUTE_ZERO_C
+}
+
+/// Rankine is Kelvin measured in Fahrenheit-sized degrees.
+pub fn celsius_to_rankine(c: f64) -> f64 {
+    celsius_to_kelvin(c) * 9.0 / 5.0
+}
+
+pub fn rankine_to_celsius(r: f64) -> f64 {
+    kelvin_to_celsius(r * 5.0 / 9.0)
+}
+
+/// Convert `value` from one unit to another, going through Celsius.
+/// Temperatures below absolute zero (and NaN) are rejected.
+pub fn convert(value: f64, from: Unit, to: Unit) -> Result<f64, ConvertError> {
+    if value.is_nan() {
+        return Err(ConvertError::NotANumber);
+    }
+    if value < from.absolute_zero() {
+        return Err(ConvertError::BelowAbsoluteZero(value, from));
+    }
+    if from == to {
+        return Ok(value);
+    }
+    Ok(to.of_celsius(from.to_celsius(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNITS: [Unit; 4] = [Unit::Celsius, Unit::Fahrenheit, Unit::Kelvin, Unit::Rankine];
+
+    fn assert_close(got: f64, want: f64) {
+        assert!((got - want).abs() < 1e-9, "got {got}, want {want}");
+    }
+
+    /// The same temperature in Celsius, Fahrenheit, Kelvin, and Rankine.
+    fn check_fixed_point(values: [f64; 4]) {
+        for (from, &value) in UNITS.iter().zip(&values) {
+            for (to, &want) in UNITS.iter().zip(&values) {
+                assert_close(convert(value, *from, *to).unwrap(), want);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixed_points() {
+        check_fixed_point([0.0, 32.0, 273.15, 491.67]); // water freezes
+        check_fixed_point([100.0, 212.0, 373.15, 671.67]); // water boils
+        check_fixed_point([-40.0, -40.0, 233.15, 419.67]); // Celsius and Fahrenheit agree
+        check_fixed_point([ABSOLUTE_ZERO_C, -459.67, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_named_conversions() {
+        assert_close(celsius_to_kelvin(0.0), 273.15);
+        assert_close(kelvin_to_celsius(0.0), -273.15);
+        assert_close(celsius_to_rankine(0.0), 491.67);
+        assert_close(rankine_to_celsius(491.67), 0.0);
+        assert_close(celsius_to_fahrenheit(fahrenheit_to_celsius(98.6)), 98.6);
+    }
+
+    #[test]
+    fn test_below_absolute_zero() {
+        assert_eq!(
+            convert(-1.0, Unit::Kelvin, Unit::Celsius),
+            Err(ConvertError::BelowAbsoluteZero(-1.0, Unit::Kelvin))
+        );
+        assert_eq!(
+            convert(-300.0, Unit::Celsius, Unit::Celsius),
+            Err(ConvertError::BelowAbsoluteZero(-300.0, Unit::Celsius))
+        );
+        assert!(convert(-460.0, Unit::Fahrenheit, Unit::Kelvin).is_err());
+        assert!(convert(-0.5, Unit::Rankine, Unit::Fahrenheit).is_err());
+        assert_eq!(
+            convert(f64::NAN, Unit::Celsius, Unit::Kelvin),
+            Err(ConvertError::NotANumber)
+        );
+        assert_eq!(
+            ConvertError::BelowAbsoluteZero(-1.0, Unit::Kelvin).to_string(),
+            "-1K is below absolute zero (0K)"
+        );
+    }
+
+    #[test]
+    fn test_parse_unit() {
+        assert_eq!("C".parse(), Ok(Unit::Celsius));
+        assert_eq!("°f".parse(), Ok(Unit::Fahrenheit));
+        assert_eq!("Kelvin".parse(), Ok(Unit::Kelvin));
+        assert_eq!("r".parse(), Ok(Unit::Rankine));
+        assert!("X".parse::<Unit>().is_err());
+        assert!("".parse::<Unit>().is_err());
+    }
+}
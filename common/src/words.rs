@@ -0,0 +1,161 @@
+//! Word counting with optional normalization and stopword filtering.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead};
+
+/// Small built-in English stopword list, returned by `builtin_stopwords`
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "has", "he", "in", "is",
+    "it", "its", "of", "on", "or", "she", "that", "the", "their", "they", "this", "to", "was",
+    "were", "will", "with",
+];
+
+/// Read one stopword per line (or several per line, whitespace-separated)
+pub fn load_stopwords(path: &str) -> io::Result<HashSet<String>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text.split_whitespace().filter_map(normalize_word).collect())
+}
+
+pub fn builtin_stopwords() -> HashSet<String> {
+    STOPWORDS.iter().map(|w| w.to_string()).collect()
+}
+
+/// Lowercase and trim surrounding punctuation; None if nothing is left
+pub fn normalize_word(word: &str) -> Option<String> {
+    let trimmed = word.trim_matches(|c: char| !c.is_alphanumeric());
+    (!trimmed.is_empty()).then(|| trimmed.to_lowercase())
+}
+
+/// How each raw token is turned into a counted word
+pub enum WordFilter {
+    Raw,
+    Normalized,
+    Excluding(HashSet<String>),
+}
+
+impl WordFilter {
+    pub fn apply(&self, token: &str) -> Option<String> {
+        match self {
+            WordFilter::Raw => Some(token.to_string()),
+            WordFilter::Normalized => normalize_word(token),
+            WordFilter::Excluding(stopwords) => {
+                normalize_word(token).filter(|w| !stopwords.contains(w))
+            }
+        }
+    }
+}
+
+/// Word counts in first-seen order, so ties always go to the earliest word
+#[derive(Default)]
+pub struct Counts {
+    words: Vec<(String, usize)>,
+    index: HashMap<String, usize>,
+}
+
+impl Counts {
+    pub fn add(&mut self, word: String) {
+        if let Some(&pos) = self.index.get(&word) {
+            self.words[pos].1 += 1;
+        } else {
+            self.index.insert(word.clone(), self.words.len());
+            self.words.push((word, 1));
+        }
+    }
+
+    pub fn most_frequent(&self) -> Option<(String, usize)> {
+        let mut best: Option<&(String, usize)> = None;
+        for entry in &self.words {
+            if best.is_none_or(|b| entry.1 > b.1) {
+                best = Some(entry);
+            }
+        }
+        best.cloned()
+    }
+
+    // The `n` most frequent words, highest first; ties keep first-seen order
+    pub fn top_n(&self, n: usize) -> Vec<(String, usize)> {
+        let mut sorted = self.words.clone();
+        sorted.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        sorted.truncate(n);
+        sorted
+    }
+}
+
+/// Count words line by line, so large inputs never have to fit in memory at once
+pub fn count_words(reader: impl BufRead, filter: &WordFilter) -> io::Result<Counts> {
+    let mut counts = Counts::default();
+    for line in reader.lines() {
+        let line = line?;
+        for token in line.split_whitespace() {
+            if let Some(word) = filter.apply(token) {
+                counts.add(word);
+            }
+        }
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_collapses() {
+        let counts = count_words("The the THE".as_bytes(), &WordFilter::Normalized).unwrap();
+        assert_eq!(counts.most_frequent(), Some(("the".to_string(), 3)));
+        // Raw counting still keeps them apart
+        let counts = count_words("The the THE".as_bytes(), &WordFilter::Raw).unwrap();
+        assert_eq!(counts.most_frequent().map(|(_, n)| n), Some(1));
+    }
+
+    #[test]
+    fn test_punctuation_trimmed() {
+        let counts = count_words("dog, dog. cat".as_bytes(), &WordFilter::Normalized).unwrap();
+        assert_eq!(counts.most_frequent(), Some(("dog".to_string(), 2)));
+        let counts = count_words("\"dog\" -- dog!".as_bytes(), &WordFilter::Normalized).unwrap();
+        assert_eq!(counts.most_frequent(), Some(("dog".to_string(), 2)));
+    }
+
+    #[test]
+    fn test_stopwords_skipped() {
+        let text = "The quick brown fox jumps over the lazy dog. the quick brown fox";
+        let counts = count_words(text.as_bytes(), &WordFilter::Normalized).unwrap();
+        assert_eq!(counts.most_frequent(), Some(("the".to_string(), 3)));
+        // With "the" gone, quick/brown/fox tie at 2 and the first one seen wins
+        let builtin = WordFilter::Excluding(builtin_stopwords());
+        let counts = count_words(text.as_bytes(), &builtin).unwrap();
+        assert_eq!(counts.most_frequent(), Some(("quick".to_string(), 2)));
+        let custom: HashSet<String> = ["the", "quick"].iter().map(|w| w.to_string()).collect();
+        let counts = count_words(text.as_bytes(), &WordFilter::Excluding(custom)).unwrap();
+        assert_eq!(counts.most_frequent(), Some(("brown".to_string(), 2)));
+        let counts = count_words("the the".as_bytes(), &builtin).unwrap();
+        assert_eq!(counts.most_frequent(), None);
+    }
+
+    #[test]
+    fn test_count_words_streaming() {
+        let input = "the cat\nThe dog.\n\nthe cat sat\n";
+        let counts = count_words(input.as_bytes(), &WordFilter::Normalized).unwrap();
+        assert_eq!(counts.most_frequent(), Some(("the".to_string(), 3)));
+        assert_eq!(
+            counts.top_n(2),
+            vec![("the".to_string(), 3), ("cat".to_string(), 2)]
+        );
+
+        let counts = count_words("".as_bytes(), &WordFilter::Raw).unwrap();
+        assert_eq!(counts.most_frequent(), None);
+        assert!(counts.top_n(3).is_empty());
+    }
+
+    #[test]
+    fn test_empty_input() {
+        for text in ["", "  \n\t "] {
+            let counts = count_words(text.as_bytes(), &WordFilter::Raw).unwrap();
+            assert_eq!(counts.most_frequent(), None);
+        }
+        // Only punctuation normalizes away to nothing
+        let counts = count_words("-- ...".as_bytes(), &WordFilter::Normalized).unwrap();
+        assert_eq!(counts.most_frequent(), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! `--baseline-file`: each URL's usual latency, kept on disk between runs, so a
+//! check is judged against its own history instead of one threshold for the
+//! whole fleet.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{write_atomic, CheckError, Latency, WebsiteStatus};
+
+/// Weight of a run's median when `--update-baseline` blends it into the stored one.
+pub const BASELINE_BLEND: f64 = 0.2;
+/// Successful response times kept per URL to take a run's median from.
+const BASELINE_SAMPLES: usize = 1000;
+
+/// One URL's stored baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    /// Median response time of its successful checks, in milliseconds.
+    pub median_ms: u64,
+}
+
+/// The baselines in a `--baseline-file`, by stats key (the URL, or the pair a
+/// `--compare` checks), and the response times this run has seen.
+#[derive(Debug)]
+pub struct Baselines {
+    path: PathBuf,
+    stored: BTreeMap<String, Baseline>,
+    samples: HashMap<String, VecDeque<u64>>,
+}
+
+impl Baselines {
+    /// The baselines in `path`; a file that doesn't exist yet has none.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let stored = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        };
+        Ok(Baselines { path: path.to_path_buf(), stored, samples: HashMap::new() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The stored baseline for `key`, as loaded.
+    pub fn get(&self, key: &str) -> Option<Baseline> {
+        self.stored.get(key).copied()
+    }
+
+    /// Count `status` toward this run's median for `key` if it passed.
+    pub fn record(&mut self, key: &str, status: &WebsiteStatus) {
+        if !status.ok() {
+            return;
+        }
+        let samples = self.samples.entry(key.to_string()).or_default();
+        if samples.len() == BASELINE_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(status.response_time.as_millis() as u64);
+    }
+
+    /// This run's median response time for `key`.
+    pub fn run_median(&self, key: &str) -> Option<u64> {
+        let samples = self.samples.get(key)?;
+        Latency::of(samples.iter().copied().collect()).map(|l| l.p50)
+    }
+
+    /// Fold this run's medians into the stored baselines: a URL without one
+    /// (every URL, with `rerecord`) takes the run's median, and the rest blend
+    /// it in with weight `blend`, or stay as they were without. Returns how many changed.
+    pub fn update(&mut self, rerecord: bool, blend: Option<f64>) -> usize {
+        let mut changed = 0;
+        for key in self.samples.keys() {
+            let Some(median_ms) = self.run_median(key) else { continue };
+            let next = match (self.stored.get(key), blend) {
+                (Some(_), None) if !rerecord => continue,
+                (Some(old), Some(w)) if !rerecord => {
+                    (old.median_ms as f64 * (1.0 - w) + median_ms as f64 * w).round() as u64
+                }
+                _ => median_ms,
+            };
+            if self.stored.insert(key.clone(), Baseline { median_ms: next }) != Some(Baseline { median_ms: next }) {
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Write the baselines back as indented JSON, replacing the file whole.
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = serde_json::to_string_pretty(&self.stored).map_err(io::Error::other)?;
+        text.push('\n');
+        write_atomic(&self.path, text.as_bytes())
+    }
+}
+
+/// How `--alert-degradation` judges a check against its URL's baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Degradation {
+    /// Slower than this many times the baseline is degraded.
+    pub factor: f64,
+    /// Log a warning instead of failing the check.
+    pub warn_only: bool,
+}
+
+impl Degradation {
+    /// A `--alert-degradation` factor: a number of at least 1.0.
+    pub fn parse_factor(s: &str) -> Result<f64, String> {
+        match s.parse::<f64>() {
+            Ok(f) if f.is_finite() && f >= 1.0 => Ok(f),
+            Ok(_) => Err("must be a number of at least 1.0".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Fail `status`, or warn about it with `warn_only`, if it passed but took
+    /// more than `factor` times `baseline`.
+    pub fn judge(&self, status: &mut WebsiteStatus, baseline: Baseline) {
+        let ms = status.response_time.as_millis() as u64;
+        if !status.ok() || ms as f64 <= baseline.median_ms as f64 * self.factor {
+            return;
+        }
+        let error = CheckError::LatencyDegraded { ms, baseline_ms: baseline.median_ms, factor: self.factor };
+        if self.warn_only {
+            warn!("{}: {error}", status.url);
+        } else {
+            status.status = Err(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{missing_result, UrlEntry, UrlSource};
+    use std::time::Duration;
+
+    fn passed(ms: u64) -> WebsiteStatus {
+        let entry = UrlEntry::new("https://api.test/", UrlSource::Arg(0));
+        WebsiteStatus { status: Ok(200), http_status: Some(200), response_time: Duration::from_millis(ms), ..missing_result(&entry, "") }
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sitecheck-{name}-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_record_and_update() {
+        let path = temp_file("baselines");
+        let mut baselines = Baselines::load(&path).unwrap();
+        assert_eq!(baselines.get("https://api.test/"), None);
+        for ms in [20, 25, 900, 18, 22] {
+            baselines.record("https://api.test/", &passed(ms));
+        }
+        // Failures don't count toward the median
+        baselines.record("https://api.test/", &missing_result(&UrlEntry::new("https://api.test/", UrlSource::Arg(0)), ""));
+        baselines.record("https://slow.test/", &passed(900));
+        assert_eq!(baselines.run_median("https://api.test/"), Some(22));
+
+        // The first run records every URL
+        assert_eq!(baselines.update(false, None), 2);
+        baselines.save().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("\"median_ms\": 22"), "{text}");
+
+        // Later runs keep what is stored, blend it, or replace it
+        let mut baselines = Baselines::load(&path).unwrap();
+        assert_eq!(baselines.get("https://api.test/"), Some(Baseline { median_ms: 22 }));
+        baselines.record("https://api.test/", &passed(122));
+        baselines.record("https://new.test/", &passed(50));
+        assert_eq!(baselines.update(false, None), 1);
+        assert_eq!(baselines.get("https://api.test/"), Some(Baseline { median_ms: 22 }));
+        assert_eq!(baselines.get("https://new.test/"), Some(Baseline { median_ms: 50 }));
+        baselines.update(false, Some(BASELINE_BLEND));
+        assert_eq!(baselines.get("https://api.test/"), Some(Baseline { median_ms: 42 }));
+        baselines.update(true, Some(BASELINE_BLEND));
+        assert_eq!(baselines.get("https://api.test/"), Some(Baseline { median_ms: 122 }));
+        // Untouched by this run
+        assert_eq!(baselines.get("https://slow.test/"), Some(Baseline { median_ms: 900 }));
+
+        fs::write(&path, "{\"https://api.test/\": {\"median_ms\": \"fast\"}}").unwrap();
+        assert!(Baselines::load(&path).unwrap_err().starts_with(&path.display().to_string()));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_judge() {
+        let baseline = Baseline { median_ms: 100 };
+        let fail = Degradation { factor: 2.0, warn_only: false };
+        let mut status = passed(200);
+        fail.judge(&mut status, baseline);
+        assert!(status.ok());
+
+        let mut status = passed(201);
+        fail.judge(&mut status, baseline);
+        assert_eq!(status.error(), Some(&CheckError::LatencyDegraded { ms: 201, baseline_ms: 100, factor: 2.0 }));
+        assert_eq!(status.error().unwrap().to_string(), "response took 201ms, over 2x the 100ms baseline");
+        assert_eq!(status.http_status, Some(200));
+
+        let mut status = passed(500);
+        Degradation { warn_only: true, ..fail }.judge(&mut status, baseline);
+        assert!(status.ok());
+
+        assert_eq!(Degradation::parse_factor("2.5"), Ok(2.5));
+        assert!(Degradation::parse_factor("0.5").is_err());
+        assert!(Degradation::parse_factor("inf").is_err());
+        assert!(Degradation::parse_factor("twice").is_err());
+    }
+}
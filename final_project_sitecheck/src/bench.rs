@@ -0,0 +1,171 @@
+//! `sitecheck bench`: one URL checked many times through the worker pool, then
+//! throughput, success rate, and the latency distribution.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{failure_kind, format_duration, WebsiteStatus, SCHEMA_VERSION};
+
+/// Latency of the successful requests, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Latency {
+    pub min: u64,
+    pub avg: f64,
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+impl Latency {
+    /// Nearest-rank percentiles of `samples`; None if there are none.
+    pub fn of(mut samples: Vec<u64>) -> Option<Latency> {
+        samples.sort_unstable();
+        let n = samples.len();
+        let rank = |p: f64| samples[((p / 100.0 * n as f64).ceil() as usize).clamp(1, n) - 1];
+        Some(Latency {
+            min: *samples.first()?,
+            avg: samples.iter().sum::<u64>() as f64 / n as f64,
+            p50: rank(50.0),
+            p95: rank(95.0),
+            p99: rank(99.0),
+            max: *samples.last()?,
+        })
+    }
+}
+
+/// Tallies a benchmark's results as they arrive.
+#[derive(Debug, Clone, Default)]
+pub struct Bench {
+    requests: usize,
+    ok: usize,
+    latencies_ms: Vec<u64>,
+    failures: BTreeMap<String, usize>,
+}
+impl Bench {
+    pub fn record(&mut self, status: &WebsiteStatus) {
+        self.requests += 1;
+        if status.ok() {
+            self.ok += 1;
+            self.latencies_ms.push(status.response_time.as_millis() as u64);
+        } else {
+            *self.failures.entry(failure_kind(status)).or_default() += 1;
+        }
+    }
+
+    /// Results recorded so far.
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// The final numbers for `url`, run `concurrency` at a time in `elapsed`.
+    pub fn report(self, url: &str, concurrency: usize, elapsed: Duration) -> BenchReport {
+        let secs = elapsed.as_secs_f64();
+        BenchReport {
+            schema_version: SCHEMA_VERSION,
+            url: url.to_string(),
+            requests: self.requests,
+            concurrency,
+            elapsed_ms: elapsed.as_millis() as u64,
+            throughput: if secs > 0.0 { self.requests as f64 / secs } else { 0.0 },
+            ok: self.ok,
+            success_rate: if self.requests > 0 { 100.0 * self.ok as f64 / self.requests as f64 } else { 0.0 },
+            latency_ms: Latency::of(self.latencies_ms),
+            failures: self.failures,
+        }
+    }
+}
+
+/// What `sitecheck bench` prints; `--format json` writes it as one object.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchReport {
+    pub schema_version: u32,
+    pub url: String,
+    pub requests: usize,
+    pub concurrency: usize,
+    pub elapsed_ms: u64,
+    /// Requests per second, failures included.
+    pub throughput: f64,
+    /// Requests that passed every validation.
+    pub ok: usize,
+    /// `ok` as a percentage of `requests`.
+    pub success_rate: f64,
+    /// None when no request succeeded.
+    pub latency_ms: Option<Latency>,
+    /// Failed requests by error kind, as in the stats summary.
+    pub failures: BTreeMap<String, usize>,
+}
+impl BenchReport {
+    /// The report as one JSON object, for `--format json`.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    pub fn write_text(&self, out: &mut impl Write) -> io::Result<()> {
+        let elapsed = format_duration(Duration::from_millis(self.elapsed_ms));
+        writeln!(out, "{}: {} requests, {} concurrent, in {elapsed}", self.url, self.requests, self.concurrency)?;
+        writeln!(out, "throughput: {:.1} req/s", self.throughput)?;
+        writeln!(out, "success:    {}/{} ({:.1}%)", self.ok, self.requests, self.success_rate)?;
+        match &self.latency_ms {
+            Some(l) => writeln!(
+                out,
+                "latency:    min {}ms, avg {:.1}ms, p50 {}ms, p95 {}ms, p99 {}ms, max {}ms",
+                l.min, l.avg, l.p50, l.p95, l.p99, l.max
+            )?,
+            None => writeln!(out, "latency:    n/a (no successful requests)")?,
+        }
+        if !self.failures.is_empty() {
+            let failures: Vec<String> = self.failures.iter().map(|(kind, n)| format!("{n} {kind}")).collect();
+            writeln!(out, "failures:   {}", failures.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CheckError;
+
+    fn status(ms: u64, result: Result<u16, CheckError>) -> WebsiteStatus {
+        WebsiteStatus { response_time: Duration::from_millis(ms), ..WebsiteStatus::sample("https://x.com/", result) }
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let l = Latency::of((1..=100).rev().collect()).unwrap();
+        assert_eq!((l.min, l.p50, l.p95, l.p99, l.max), (1, 50, 95, 99, 100));
+        assert_eq!(l.avg, 50.5);
+        let l = Latency::of(vec![7]).unwrap();
+        assert_eq!((l.min, l.p50, l.p99, l.max), (7, 7, 7, 7));
+        assert_eq!(Latency::of(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_bench_report() {
+        let mut bench = Bench::default();
+        for ms in [10, 20, 30] {
+            bench.record(&status(ms, Ok(200)));
+        }
+        bench.record(&status(0, Err(CheckError::ConnectionRefused { detail: "refused".into() })));
+        assert_eq!(bench.requests(), 4);
+        let report = bench.report("https://x.com/", 2, Duration::from_secs(2));
+        assert_eq!((report.ok, report.throughput, report.success_rate), (3, 2.0, 75.0));
+        // Failed requests don't drag the latency numbers down
+        assert_eq!(report.latency_ms.as_ref().map(|l| (l.min, l.avg)), Some((10, 20.0)));
+        assert_eq!(report.failures, BTreeMap::from([("connection_refused".to_string(), 1)]));
+
+        let mut text = Vec::new();
+        report.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("https://x.com/: 4 requests, 2 concurrent, in 2s\n"), "{text}");
+        assert!(text.contains("success:    3/4 (75.0%)\n"), "{text}");
+        assert!(text.contains("p50 20ms, p95 30ms, p99 30ms, max 30ms\n"), "{text}");
+        assert!(text.ends_with("failures:   1 connection_refused\n"), "{text}");
+
+        let report = Bench::default().report("https://x.com/", 1, Duration::ZERO);
+        assert_eq!((report.throughput, report.success_rate, report.latency_ms), (0.0, 0.0, None));
+    }
+}
@@ -0,0 +1,447 @@
+//! Peer certificates for `--cert-info`: a rustls verifier that records each
+//! server's certificate as it verifies it, and just enough of a DER reader to
+//! pull out the subject, issuer, validity, and subjectAltName.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, TimeZone, Utc};
+use log::debug;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+
+/// The leaf certificate a server presented.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Certificate {
+    /// None for a certificate that names its hosts in `sans` alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject_cn: Option<String>,
+    /// The issuing CA's name, most specific part first, e.g. `CN=R11, O=Let's Encrypt, C=US`.
+    pub issuer: String,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// DNS names and IP addresses from subjectAltName.
+    pub sans: Vec<String>,
+    /// Whether the chain led to a trusted root and covered the host.
+    pub chain_valid: bool,
+    /// Why it didn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_error: Option<String>,
+}
+
+/// The certificate each host last presented, shared by every agent of a client.
+/// A pooled connection shakes hands once, so its checks all get that one.
+#[derive(Debug, Default)]
+pub struct PeerCerts {
+    by_host: Mutex<HashMap<String, Certificate>>,
+}
+
+impl PeerCerts {
+    pub fn new() -> Self {
+        PeerCerts::default()
+    }
+
+    /// The certificate for `url`'s host, if it is https and was seen.
+    pub fn for_url(&self, url: &str) -> Option<Certificate> {
+        let host = https_host(url)?;
+        self.by_host.lock().unwrap().get(&host).cloned()
+    }
+
+    /// The certificate for `url`'s host if its chain failed to verify, taken
+    /// so a later, unrelated failure isn't blamed on it.
+    pub(crate) fn take_invalid(&self, url: &str) -> Option<Certificate> {
+        let host = https_host(url)?;
+        let mut by_host = self.by_host.lock().unwrap();
+        by_host.get(&host).filter(|cert| !cert.chain_valid)?;
+        by_host.remove(&host)
+    }
+
+    fn record(&self, host: &str, cert: Certificate) {
+        self.by_host.lock().unwrap().insert(host.to_string(), cert);
+    }
+}
+
+/// `url`'s host as rustls names it: lowercased, IPv6 without brackets.
+fn https_host(url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok().filter(|u| u.scheme() == "https")?;
+    let host = url.host_str()?;
+    Some(host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase())
+}
+
+/// Verifies as usual, recording the certificate and the outcome on the way.
+#[derive(Debug)]
+struct Recorder {
+    inner: Arc<WebPkiServerVerifier>,
+    certs: Arc<PeerCerts>,
+}
+
+impl ServerCertVerifier for Recorder {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now);
+        let host = server_name.to_str();
+        match parse_certificate(end_entity) {
+            Ok(cert) => {
+                let chain_error = verified.as_ref().err().map(ToString::to_string);
+                self.certs.record(&host.to_ascii_lowercase(), Certificate { chain_valid: chain_error.is_none(), chain_error, ..cert })
+            }
+            Err(e) => debug!("{host}: unreadable certificate: {e}"),
+        }
+        verified
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// The TLS setup ureq uses by default (ring, the webpki roots), but recording
+/// every server certificate into `certs`.
+pub(crate) fn recording_tls_config(certs: Arc<PeerCerts>) -> Arc<rustls::ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let roots = Arc::new(RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() });
+    let inner = WebPkiServerVerifier::builder_with_provider(roots, provider.clone())
+        .build()
+        .expect("the webpki roots are usable");
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(Recorder { inner, certs }))
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const OID: u8 = 0x06;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const BOOLEAN: u8 = 0x01;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+/// `[0]` around tbsCertificate's version, `[3]` around its extensions.
+const VERSION: u8 = 0xa0;
+const EXTENSIONS: u8 = 0xa3;
+/// subjectAltName's dNSName and iPAddress choices.
+const SAN_DNS: u8 = 0x82;
+const SAN_IP: u8 = 0x87;
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+/// The first DER element of `der`: its tag, its contents, and what follows.
+fn element(der: &[u8]) -> Result<(u8, &[u8], &[u8]), String> {
+    let truncated = || "truncated".to_string();
+    let (&tag, rest) = der.split_first().ok_or_else(truncated)?;
+    let (&first, rest) = rest.split_first().ok_or_else(truncated)?;
+    let (len, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x84 => {
+            let n = (first & 0x7f) as usize;
+            let bytes = rest.get(..n).ok_or_else(truncated)?;
+            (bytes.iter().fold(0, |len, &b| len << 8 | b as usize), &rest[n..])
+        }
+        _ => return Err(format!("unsupported length byte {first:#04x}")),
+    };
+    let contents = rest.get(..len).ok_or_else(truncated)?;
+    Ok((tag, contents, &rest[len..]))
+}
+
+/// The first element of `der`, which must have `tag`: its contents and what follows.
+fn expect<'a>(der: &'a [u8], tag: u8, what: &str) -> Result<(&'a [u8], &'a [u8]), String> {
+    match element(der) {
+        Ok((found, contents, rest)) if found == tag => Ok((contents, rest)),
+        Ok((found, ..)) => Err(format!("{what}: expected tag {tag:#04x}, found {found:#04x}")),
+        Err(e) => Err(format!("{what}: {e}")),
+    }
+}
+
+/// The fields of a DER X.509 certificate that `Certificate` reports; the
+/// chain is left marked valid for the caller to judge.
+pub(crate) fn parse_certificate(der: &[u8]) -> Result<Certificate, String> {
+    let (cert, _) = expect(der, SEQUENCE, "certificate")?;
+    let (tbs, _) = expect(cert, SEQUENCE, "tbsCertificate")?;
+    let mut rest = tbs;
+    if rest.first() == Some(&VERSION) {
+        rest = element(rest)?.2;
+    }
+    let (_, rest) = expect(rest, INTEGER, "serial number")?;
+    let (_, rest) = expect(rest, SEQUENCE, "signature algorithm")?;
+    let (issuer, rest) = expect(rest, SEQUENCE, "issuer")?;
+    let (validity, rest) = expect(rest, SEQUENCE, "validity")?;
+    let (subject, rest) = expect(rest, SEQUENCE, "subject")?;
+    let (_, mut rest) = expect(rest, SEQUENCE, "public key")?;
+
+    let (not_before, after) = time(validity)?;
+    let (not_after, _) = time(after)?;
+    let subject = name(subject)?;
+    let subject_cn = subject.iter().rev().find(|(oid, _)| oid == OID_COMMON_NAME).map(|(_, value)| value.clone());
+    let issuer = name(issuer)?.iter().rev().map(|(oid, value)| format!("{}={value}", attribute_name(oid))).collect::<Vec<_>>();
+
+    // Unique identifiers, if any, come before the extensions
+    let mut sans = Vec::new();
+    while !rest.is_empty() {
+        let (tag, contents, next) = element(rest)?;
+        if tag == EXTENSIONS {
+            sans = subject_alt_names(contents)?;
+        }
+        rest = next;
+    }
+    Ok(Certificate {
+        subject_cn,
+        issuer: issuer.join(", "),
+        not_before,
+        not_after,
+        sans,
+        chain_valid: true,
+        chain_error: None,
+    })
+}
+
+/// A Name's attributes in order, as (OID, value) pairs.
+fn name(der: &[u8]) -> Result<Vec<(Vec<u8>, String)>, String> {
+    let mut attributes = Vec::new();
+    let mut rdns = der;
+    while !rdns.is_empty() {
+        let (rdn, next) = expect(rdns, SET, "name")?;
+        let mut rdn = rdn;
+        while !rdn.is_empty() {
+            let (attribute, next) = expect(rdn, SEQUENCE, "name attribute")?;
+            let (oid, value) = expect(attribute, OID, "attribute type")?;
+            let (tag, value, _) = element(value)?;
+            attributes.push((oid.to_vec(), string(tag, value)));
+            rdn = next;
+        }
+        rdns = next;
+    }
+    Ok(attributes)
+}
+
+/// A directory string: UTF-8, printable, IA5, and teletex are read as UTF-8, BMP as UTF-16.
+fn string(tag: u8, value: &[u8]) -> String {
+    const BMP_STRING: u8 = 0x1e;
+    if tag == BMP_STRING {
+        let units: Vec<u16> = value.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        return String::from_utf16_lossy(&units);
+    }
+    String::from_utf8_lossy(value).into_owned()
+}
+
+/// The usual short name of an attribute type (`CN`, `O`, ...), else its dotted OID.
+fn attribute_name(oid: &[u8]) -> String {
+    let short = match oid {
+        [0x55, 0x04, 0x03] => "CN",
+        [0x55, 0x04, 0x06] => "C",
+        [0x55, 0x04, 0x07] => "L",
+        [0x55, 0x04, 0x08] => "ST",
+        [0x55, 0x04, 0x0a] => "O",
+        [0x55, 0x04, 0x0b] => "OU",
+        _ => return dotted(oid),
+    };
+    short.to_string()
+}
+
+/// An OID in dotted form, e.g. `2.5.4.97`.
+fn dotted(oid: &[u8]) -> String {
+    let Some((&first, rest)) = oid.split_first() else { return String::new() };
+    let mut arcs = vec![(first / 40).min(2) as u64, first as u64 - 40 * (first / 40).min(2) as u64];
+    let mut arc = 0u64;
+    for &b in rest {
+        arc = arc << 7 | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    arcs.iter().map(u64::to_string).collect::<Vec<_>>().join(".")
+}
+
+/// A UTCTime or GeneralizedTime, and what follows it.
+fn time(der: &[u8]) -> Result<(DateTime<Utc>, &[u8]), String> {
+    let (tag, value, rest) = element(der)?;
+    let text = std::str::from_utf8(value).map_err(|_| "time is not ASCII".to_string())?;
+    let digits = text.strip_suffix('Z').filter(|d| d.bytes().all(|b| b.is_ascii_digit()));
+    let (year, rest_digits) = match (tag, digits) {
+        (UTC_TIME, Some(d)) if d.len() == 12 => {
+            // RFC 5280: two-digit years from 50 are 19xx
+            let yy: i32 = d[..2].parse().unwrap_or_default();
+            (if yy >= 50 { 1900 + yy } else { 2000 + yy }, &d[2..])
+        }
+        (GENERALIZED_TIME, Some(d)) if d.len() == 14 => (d[..4].parse().unwrap_or_default(), &d[4..]),
+        _ => return Err(format!("unsupported time {text:?}")),
+    };
+    let field = |i: usize| rest_digits[i..i + 2].parse::<u32>().unwrap_or_default();
+    let at = Utc
+        .with_ymd_and_hms(year, field(0), field(2), field(4), field(6), field(8))
+        .single()
+        .ok_or_else(|| format!("invalid time {text:?}"))?;
+    Ok((at, rest))
+}
+
+/// The DNS names and IP addresses in the subjectAltName extension, if
+/// `extensions` has one.
+fn subject_alt_names(extensions: &[u8]) -> Result<Vec<String>, String> {
+    let (mut list, _) = expect(extensions, SEQUENCE, "extensions")?;
+    while !list.is_empty() {
+        let (extension, next) = expect(list, SEQUENCE, "extension")?;
+        let (oid, mut rest) = expect(extension, OID, "extension id")?;
+        if rest.first() == Some(&BOOLEAN) {
+            rest = element(rest)?.2;
+        }
+        if oid == OID_SUBJECT_ALT_NAME {
+            let (value, _) = expect(rest, OCTET_STRING, "subjectAltName")?;
+            let (mut names, _) = expect(value, SEQUENCE, "subjectAltName")?;
+            let mut sans = Vec::new();
+            while !names.is_empty() {
+                let (tag, value, next) = element(names)?;
+                match (tag, value.len()) {
+                    (SAN_DNS, _) => sans.push(String::from_utf8_lossy(value).into_owned()),
+                    (SAN_IP, 4) => sans.push(IpAddr::from(Ipv4Addr::from(<[u8; 4]>::try_from(value).unwrap())).to_string()),
+                    (SAN_IP, 16) => sans.push(IpAddr::from(Ipv6Addr::from(<[u8; 16]>::try_from(value).unwrap())).to_string()),
+                    // Email addresses, URIs, and the rest don't name a host
+                    _ => {}
+                }
+                names = next;
+            }
+            return Ok(sans);
+        }
+        list = next;
+    }
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A DER element, with the long length form past 127 bytes.
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut der = vec![tag];
+        match contents.len() {
+            n @ 0..=0x7f => der.push(n as u8),
+            n @ 0x80..=0xff => der.extend([0x81, n as u8]),
+            n => der.extend([0x82, (n >> 8) as u8, n as u8]),
+        }
+        der.extend_from_slice(contents);
+        der
+    }
+
+    fn seq(parts: &[Vec<u8>]) -> Vec<u8> {
+        tlv(SEQUENCE, &parts.concat())
+    }
+
+    /// A Name of one attribute per RDN, in order.
+    fn name(attributes: &[(&[u8], u8, &[u8])]) -> Vec<u8> {
+        let rdns: Vec<Vec<u8>> =
+            attributes.iter().map(|(oid, tag, value)| tlv(SET, &seq(&[tlv(OID, oid), tlv(*tag, value)]))).collect();
+        seq(&rdns)
+    }
+
+    const UTF8: u8 = 0x0c;
+    const PRINTABLE: u8 = 0x13;
+    const C: &[u8] = &[0x55, 0x04, 0x06];
+    const O: &[u8] = &[0x55, 0x04, 0x0a];
+
+    fn certificate(with_version: bool, subject: Vec<u8>, extensions: Option<Vec<u8>>) -> Vec<u8> {
+        let mut tbs = Vec::new();
+        if with_version {
+            tbs.push(tlv(VERSION, &tlv(INTEGER, &[2])));
+        }
+        tbs.push(tlv(INTEGER, &[0x0f, 0x42]));
+        tbs.push(seq(&[tlv(OID, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02])]));
+        tbs.push(name(&[(C, PRINTABLE, b"US"), (O, UTF8, b"Let's Encrypt"), (OID_COMMON_NAME, PRINTABLE, b"R11")]));
+        tbs.push(seq(&[tlv(UTC_TIME, b"250101000000Z"), tlv(GENERALIZED_TIME, b"20250401235959Z")]));
+        tbs.push(subject);
+        tbs.push(seq(&[seq(&[tlv(OID, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01])]), tlv(0x03, &[0; 66])]));
+        if let Some(extensions) = extensions {
+            tbs.push(tlv(EXTENSIONS, &extensions));
+        }
+        seq(&[seq(&tbs), seq(&[tlv(OID, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02])]), tlv(0x03, &[0; 72])])
+    }
+
+    #[test]
+    fn test_parse_certificate() {
+        let names = seq(&[
+            tlv(SAN_DNS, b"example.com"),
+            tlv(0x81, b"ops@example.com"),
+            tlv(SAN_DNS, b"www.example.com"),
+            tlv(SAN_IP, &[192, 0, 2, 1]),
+            tlv(SAN_IP, &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+        ]);
+        let extensions = seq(&[
+            // basicConstraints, critical, ahead of the SANs
+            seq(&[tlv(OID, &[0x55, 0x1d, 0x13]), tlv(BOOLEAN, &[0xff]), tlv(OCTET_STRING, &seq(&[]))]),
+            seq(&[tlv(OID, OID_SUBJECT_ALT_NAME), tlv(OCTET_STRING, &names)]),
+        ]);
+        let subject = name(&[(O, UTF8, b"Example"), (OID_COMMON_NAME, UTF8, b"example.com")]);
+        let cert = parse_certificate(&certificate(true, subject, Some(extensions))).unwrap();
+        assert_eq!(cert.subject_cn.as_deref(), Some("example.com"));
+        assert_eq!(cert.issuer, "CN=R11, O=Let's Encrypt, C=US");
+        assert_eq!(cert.not_before, Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(cert.not_after, Utc.with_ymd_and_hms(2025, 4, 1, 23, 59, 59).unwrap());
+        assert_eq!(cert.sans, ["example.com", "www.example.com", "192.0.2.1", "2001:db8::1"]);
+        assert!(cert.chain_valid && cert.chain_error.is_none());
+
+        // v1: no version, no extensions, and a subject without a CN
+        let subject = name(&[(&[0x55, 0x04, 0x61], UTF8, b"VATGB-123")]);
+        let cert = parse_certificate(&certificate(false, subject, None)).unwrap();
+        assert_eq!((cert.subject_cn, cert.sans), (None, Vec::new()));
+
+        assert!(parse_certificate(b"").is_err());
+        let good = certificate(true, name(&[]), None);
+        assert!(parse_certificate(&good[..good.len() - 10]).unwrap_err().contains("truncated"));
+        assert!(parse_certificate(&tlv(SEQUENCE, &tlv(INTEGER, &[1]))).is_err());
+    }
+
+    #[test]
+    fn test_dotted_and_strings() {
+        assert_eq!(dotted(&[0x55, 0x04, 0x61]), "2.5.4.97");
+        assert_eq!(dotted(&[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01]), "1.2.840.10045.2.1");
+        assert_eq!(attribute_name(&[0x55, 0x04, 0x0b]), "OU");
+        assert_eq!(string(0x1e, &[0x00, 0x48, 0x00, 0x69]), "Hi");
+        assert_eq!(string(UTF8, "Zürich".as_bytes()), "Zürich");
+    }
+
+    #[test]
+    fn test_peer_certs() {
+        let certs = PeerCerts::new();
+        let cert = parse_certificate(&certificate(true, name(&[(OID_COMMON_NAME, UTF8, b"a.test")]), None)).unwrap();
+        certs.record("a.test", cert.clone());
+        assert_eq!(certs.for_url("https://A.test:8443/x"), Some(cert.clone()));
+        assert_eq!(certs.for_url("http://a.test/"), None);
+        // Only a certificate that failed verification is taken, and only once
+        assert_eq!(certs.take_invalid("https://a.test/"), None);
+        let invalid = Certificate { chain_valid: false, chain_error: Some("UnknownIssuer".into()), ..cert };
+        certs.record("a.test", invalid.clone());
+        assert_eq!(certs.take_invalid("https://a.test/"), Some(invalid));
+        assert_eq!(certs.take_invalid("https://a.test/"), None);
+    }
+}
@@ -0,0 +1,294 @@
+//! Full-screen terminal dashboard for `--tui`: one row per URL, refreshed as results arrive.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+use sitecheck::{TimeDisplay, WebsiteStatus};
+
+/// Checks kept per URL for the latency sparkline.
+const HISTORY: usize = 60;
+/// Failures kept per URL for the detail pane.
+const FAILURES_KEPT: usize = 20;
+/// Characters kept of each stored error message.
+const ERROR_CHARS: usize = 200;
+
+#[derive(Debug, Default)]
+struct UrlRow {
+    url: String,
+    last: Option<(bool, String)>, // (up, status shown in the table)
+    latencies: VecDeque<u64>,
+    checks: usize,
+    up: usize,
+    last_error: Option<String>,
+    failures: VecDeque<(DateTime<Utc>, String)>,
+}
+
+impl UrlRow {
+    fn uptime(&self) -> Option<f64> {
+        (self.checks > 0).then(|| self.up as f64 * 100.0 / self.checks as f64)
+    }
+}
+
+/// Everything the dashboard shows, kept apart from the terminal so it can be tested.
+#[derive(Debug, Default)]
+struct State {
+    rows: Vec<UrlRow>,
+    index: HashMap<String, usize>,
+    table: TableState,
+    stopping: bool,
+    times: TimeDisplay,
+}
+
+impl State {
+    fn new<'a>(urls: impl IntoIterator<Item = &'a str>, times: TimeDisplay) -> Self {
+        let mut state = State { times, ..Default::default() };
+        for url in urls {
+            state.index.insert(url.to_string(), state.rows.len());
+            state.rows.push(UrlRow { url: url.to_string(), ..Default::default() });
+        }
+        state.table.select((!state.rows.is_empty()).then_some(0));
+        state
+    }
+
+    fn record(&mut self, status: &WebsiteStatus, up: bool) {
+        let i = match self.index.get(&status.url) {
+            Some(&i) => i,
+            None => {
+                self.index.insert(status.url.clone(), self.rows.len());
+                self.rows.push(UrlRow { url: status.url.clone(), ..Default::default() });
+                self.rows.len() - 1
+            }
+        };
+        let row = &mut self.rows[i];
+        // Maintenance failures are shown but left out of uptime
+        if !status.maintenance {
+            row.checks += 1;
+            if up {
+                row.up += 1;
+            }
+        }
+        if row.latencies.len() == HISTORY {
+            row.latencies.pop_front();
+        }
+        row.latencies.push_back(status.response_time.as_millis() as u64);
+        let shown = status.http_code().map_or_else(|| "ERR".to_string(), |code| code.to_string());
+        row.last = Some((up, shown));
+        if let Some(e) = status.error() {
+            let message = clip(e.to_string());
+            row.last_error = Some(message.clone());
+            if row.failures.len() == FAILURES_KEPT {
+                row.failures.pop_front();
+            }
+            row.failures.push_back((status.timestamp, message));
+        }
+    }
+
+    /// Apply a key press; returns true when the user asked to quit.
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        let last = self.rows.len().saturating_sub(1);
+        let selected = self.table.selected().unwrap_or(0);
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            // Raw mode swallows SIGINT, so Ctrl+C arrives here as a key
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Down | KeyCode::Char('j') => self.table.select(Some((selected + 1).min(last))),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select(Some(selected.saturating_sub(1))),
+            KeyCode::Home | KeyCode::Char('g') => self.table.select(Some(0)),
+            KeyCode::End | KeyCode::Char('G') => self.table.select(Some(last)),
+            _ => {}
+        }
+        false
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [table_area, detail_area, help_area] =
+            Layout::vertical([Constraint::Min(5), Constraint::Length(8), Constraint::Length(1)]).areas(frame.area());
+
+        let header = ["URL", "Status", "Latency", "History", "Uptime", "Last error"];
+        let rows = self.rows.iter().map(|r| {
+            let (status, color) = match &r.last {
+                None => ("…".to_string(), Color::Gray),
+                Some((true, shown)) => (shown.clone(), Color::Green),
+                Some((false, shown)) => (shown.clone(), Color::Red),
+            };
+            Row::new(vec![
+                Cell::from(r.url.clone()),
+                Cell::from(status).style(Style::new().fg(color)),
+                Cell::from(r.latencies.back().map(|ms| format!("{ms} ms")).unwrap_or_default()),
+                Cell::from(sparkline(&r.latencies)),
+                Cell::from(r.uptime().map(|u| format!("{u:.1}%")).unwrap_or_default()),
+                Cell::from(r.last_error.clone().unwrap_or_default()),
+            ])
+        });
+        let widths = [
+            Constraint::Percentage(30),
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(HISTORY as u16),
+            Constraint::Length(7),
+            Constraint::Fill(1),
+        ];
+        let title = if self.stopping {
+            format!(" sitecheck: {} URL(s), stopping after in-flight checks… ", self.rows.len())
+        } else {
+            format!(" sitecheck: {} URL(s) ", self.rows.len())
+        };
+        let table = Table::new(rows, widths)
+            .header(Row::new(header).style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(Block::bordered().title(title))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, table_area, &mut self.table);
+
+        let selected = self.table.selected().and_then(|i| self.rows.get(i));
+        let (detail_title, lines) = match selected {
+            Some(r) if !r.failures.is_empty() => (
+                format!(" Recent failures: {} ", r.url),
+                r.failures
+                    .iter()
+                    .rev()
+                    .map(|(at, err)| Line::from(format!("{}  {}", self.times.format_or(*at, "%H:%M:%S"), err)))
+                    .collect(),
+            ),
+            Some(r) => (format!(" Recent failures: {} ", r.url), vec![Line::from("no failures recorded")]),
+            None => (" Recent failures ".to_string(), vec![]),
+        };
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(detail_title)), detail_area);
+        frame.render_widget(Paragraph::new("↑/↓ select   g/G first/last   q quit"), help_area);
+    }
+}
+
+/// Shorten `message` to `ERROR_CHARS`, so one huge error body can't pin memory for the whole run.
+fn clip(mut message: String) -> String {
+    if let Some((i, _)) = message.char_indices().nth(ERROR_CHARS) {
+        message.truncate(i);
+        message.push('…');
+    }
+    message.shrink_to_fit();
+    message
+}
+
+/// Render latencies as a one-line bar chart scaled to the largest value.
+fn sparkline(values: &VecDeque<u64>) -> String {
+    sitecheck::sparkline(values.iter().copied(), false)
+}
+
+/// The live dashboard. Owns the terminal while alive and restores it on drop.
+pub struct Dashboard {
+    terminal: DefaultTerminal,
+    state: State,
+    quit: bool,
+}
+
+impl Dashboard {
+    pub fn start<'a>(urls: impl IntoIterator<Item = &'a str>, times: TimeDisplay) -> io::Result<Dashboard> {
+        let terminal = ratatui::try_init()?;
+        Ok(Dashboard { terminal, state: State::new(urls, times), quit: false })
+    }
+
+    pub fn record(&mut self, status: &WebsiteStatus, up: bool) {
+        self.state.record(status, up);
+    }
+
+    /// Show that shutdown is in progress (after `q` or an external stop).
+    pub fn set_stopping(&mut self) {
+        self.state.stopping = true;
+    }
+
+    /// Handle pending key presses and redraw. Returns true once the user has asked to quit.
+    pub fn tick(&mut self) -> io::Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && self.state.handle_key(key.code, key.modifiers) {
+                    self.quit = true;
+                }
+            }
+        }
+        self.terminal.draw(|frame| self.state.draw(frame))?;
+        Ok(self.quit)
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sitecheck::{missing_result, CheckError, UrlEntry, UrlSource};
+
+    // The binary sees the library without its test helpers, so this builds on `missing_result`
+    fn status(url: &str, result: Result<u16, CheckError>, ms: u64) -> WebsiteStatus {
+        WebsiteStatus {
+            http_status: result.as_ref().ok().copied(),
+            status: result,
+            attempts: 1,
+            response_time: Duration::from_millis(ms),
+            ..missing_result(&UrlEntry::new(url, UrlSource::Arg(1)), "")
+        }
+    }
+
+    #[test]
+    fn test_record_and_history() {
+        let mut state = State::new(["https://a/", "https://b/"], TimeDisplay::default());
+        for i in 0..(HISTORY as u64 + 5) {
+            state.record(&status("https://a/", Ok(200), i), true);
+        }
+        state.record(&status("https://a/", Err(CheckError::ReadTimeout { detail: "timed out".into() }), 0), false);
+
+        let a = &state.rows[0];
+        assert_eq!(a.latencies.len(), HISTORY);
+        assert_eq!(a.checks, HISTORY + 6);
+        assert_eq!(a.last, Some((false, "ERR".to_string())));
+        assert_eq!(a.last_error.as_deref(), Some("read timeout: timed out"));
+        assert_eq!(a.failures.len(), 1);
+        assert!(state.rows[1].last.is_none());
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut state = State::new(["https://a/"], TimeDisplay::default());
+        let detail = "é".repeat(10_000);
+        for i in 0..100_000 {
+            state.record(&status("https://a/", Err(CheckError::ReadTimeout { detail: detail.clone() }), i), false);
+        }
+        let a = &state.rows[0];
+        assert_eq!((a.latencies.len(), a.failures.len()), (HISTORY, FAILURES_KEPT));
+        assert_eq!(a.latencies.back(), Some(&99_999));
+        for (_, message) in &a.failures {
+            assert_eq!(message.chars().count(), ERROR_CHARS + 1);
+            assert!(message.ends_with('…'));
+        }
+        assert_eq!(a.last_error.as_ref(), a.failures.back().map(|(_, m)| m));
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut state = State::new(["https://a/", "https://b/", "https://c/"], TimeDisplay::default());
+        assert!(!state.handle_key(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(state.table.selected(), Some(1));
+        state.handle_key(KeyCode::End, KeyModifiers::NONE);
+        state.handle_key(KeyCode::Down, KeyModifiers::NONE);
+        assert_eq!(state.table.selected(), Some(2));
+        assert!(state.handle_key(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert!(state.handle_key(KeyCode::Char('c'), KeyModifiers::CONTROL));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let values: VecDeque<u64> = [0, 50, 100].into_iter().collect();
+        assert_eq!(sparkline(&values), "▁▄█");
+        assert_eq!(sparkline(&VecDeque::new()), "");
+    }
+}
@@ -0,0 +1,287 @@
+//! `--dns-server`: look hosts up through one chosen DNS server instead of the
+//! system resolver, for split-horizon setups where the answer depends on who is
+//! asked. A minimal stub resolver: A and AAAA questions over UDP, asked again
+//! over TCP when the UDP answer comes back truncated.
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+/// Port used when `--dns-server` names only an address.
+pub const DNS_PORT: u16 = 53;
+/// Largest UDP answer read; servers truncate anything longer without EDNS.
+const UDP_BYTES: usize = 4096;
+
+/// The server lookups go to, and how long each question may take.
+#[derive(Debug)]
+pub struct DnsServer {
+    addr: SocketAddr,
+    timeout: Duration,
+    next_id: AtomicU16,
+}
+
+impl DnsServer {
+    pub fn new(addr: SocketAddr, timeout: Duration) -> Self {
+        DnsServer { addr, timeout, next_id: AtomicU16::new(std::process::id() as u16) }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// A `--dns-server`: `IP` or `IP:PORT`, IPv6 in brackets when a port is given.
+    pub fn parse_addr(s: &str) -> Result<SocketAddr, String> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(addr);
+        }
+        let ip = s.trim_start_matches('[').trim_end_matches(']');
+        ip.parse::<IpAddr>()
+            .map(|ip| SocketAddr::new(ip, DNS_PORT))
+            .map_err(|_| format!("expected IP or IP:PORT, got {s:?}"))
+    }
+
+    /// The addresses of `netloc` (`host:port`, as ureq asks), IPv4 first.
+    pub fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("bad host:port {netloc:?}"));
+        let (host, port) = netloc.rsplit_once(':').ok_or_else(invalid)?;
+        let port: u16 = port.parse().map_err(|_| invalid())?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+        let v4 = self.query(host, TYPE_A);
+        let v6 = self.query(host, TYPE_AAAA);
+        let ips = match (v4, v6) {
+            (Err(e), Err(_)) => return Err(e),
+            (v4, v6) => v4.unwrap_or_default().into_iter().chain(v6.unwrap_or_default()).collect::<Vec<_>>(),
+        };
+        if ips.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{host}: no addresses from {}", self.addr)));
+        }
+        Ok(ips.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+
+    /// Ask for the `qtype` records of `host`, over TCP if UDP's answer was cut short.
+    fn query(&self, host: &str, qtype: u16) -> io::Result<Vec<IpAddr>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let question = encode_query(id, host, qtype)?;
+        let answer = match parse_answer(&self.udp(&question, id)?, id, host, self.addr) {
+            Err(Truncated) => self.tcp(&question)?,
+            Ok(ips) => return ips,
+        };
+        match parse_answer(&answer, id, host, self.addr) {
+            Ok(ips) => ips,
+            Err(Truncated) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{host}: truncated answer over TCP"))),
+        }
+    }
+
+    fn udp(&self, question: &[u8], id: u16) -> io::Result<Vec<u8>> {
+        let local: SocketAddr = match self.addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        socket.connect(self.addr)?;
+        socket.send(question)?;
+        let mut buf = [0u8; UDP_BYTES];
+        // A late answer to an earlier question may still arrive; skip it
+        loop {
+            let n = socket.recv(&mut buf).map_err(|e| timed_out(e, self.addr))?;
+            if n >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+                return Ok(buf[..n].to_vec());
+            }
+        }
+    }
+
+    fn tcp(&self, question: &[u8]) -> io::Result<Vec<u8>> {
+        let mut stream = TcpStream::connect_timeout(&self.addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut framed = (question.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(question);
+        stream.write_all(&framed)?;
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).map_err(|e| timed_out(e, self.addr))?;
+        let mut answer = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut answer).map_err(|e| timed_out(e, self.addr))?;
+        Ok(answer)
+    }
+}
+
+/// Say which server didn't answer in time.
+fn timed_out(e: io::Error, server: SocketAddr) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => io::Error::new(io::ErrorKind::TimedOut, format!("no answer from {server}")),
+        _ => e,
+    }
+}
+
+/// A question for the `qtype` records of `host`, recursion desired.
+fn encode_query(id: u16, host: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(18 + host.len());
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("bad host name {host:?}")));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+/// The UDP answer had the TC bit set: ask again over TCP.
+struct Truncated;
+
+/// The addresses in answer `msg` to question `id`, whatever names (a CNAME
+/// chain) they are filed under.
+fn parse_answer(msg: &[u8], id: u16, host: &str, server: SocketAddr) -> Result<io::Result<Vec<IpAddr>>, Truncated> {
+    let invalid = |what: &str| Err(io::Error::new(io::ErrorKind::InvalidData, format!("{host}: bad answer from {server}: {what}")));
+    if msg.len() < 12 || u16::from_be_bytes([msg[0], msg[1]]) != id || msg[2] & 0x80 == 0 {
+        return Ok(invalid("not a response to the question"));
+    }
+    if msg[2] & 0x02 != 0 {
+        return Err(Truncated);
+    }
+    match msg[3] & 0x0f {
+        0 => {}
+        3 => return Ok(Err(io::Error::new(io::ErrorKind::NotFound, format!("{host}: no such host (asked {server})")))),
+        rcode => return Ok(Err(io::Error::other(format!("{host}: {server} answered with rcode {rcode}")))),
+    }
+    let count = |at: usize| u16::from_be_bytes([msg[at], msg[at + 1]]) as usize;
+    let (questions, answers) = (count(4), count(6));
+    let mut at = 12;
+    for _ in 0..questions {
+        let Some(end) = skip_name(msg, at) else { return Ok(invalid("question cut off")) };
+        at = end + 4;
+    }
+    let mut ips = Vec::new();
+    for _ in 0..answers {
+        let Some(end) = skip_name(msg, at).filter(|&end| end + 10 <= msg.len()) else { return Ok(invalid("record cut off")) };
+        let rtype = count(end) as u16;
+        let len = count(end + 8);
+        let data = end + 10;
+        let Some(rdata) = msg.get(data..data + len) else { return Ok(invalid("record cut off")) };
+        match (rtype, len) {
+            (TYPE_A, 4) => ips.push(IpAddr::from(<[u8; 4]>::try_from(rdata).unwrap())),
+            (TYPE_AAAA, 16) => ips.push(IpAddr::from(<[u8; 16]>::try_from(rdata).unwrap())),
+            _ => {}
+        }
+        at = data + len;
+    }
+    Ok(Ok(ips))
+}
+
+/// Where the (possibly compressed) name starting at `at` ends.
+fn skip_name(msg: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(at)? as usize;
+        match len {
+            0 => return Some(at + 1),
+            // A pointer ends the name where it stands
+            _ if len & 0xc0 == 0xc0 => return (at + 2 <= msg.len()).then_some(at + 2),
+            _ => at += 1 + len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const SERVER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DNS_PORT);
+
+    /// The answer to `query`: its question echoed, flags and rcode set, then
+    /// `records` as (type, rdata) filed under the question's name.
+    fn reply(query: &[u8], flags: u8, rcode: u8, records: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut msg = query.to_vec();
+        msg[2] = 0x80 | flags;
+        msg[3] = 0x80 | rcode;
+        msg[6..8].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        for (rtype, rdata) in records {
+            msg.extend_from_slice(&[0xc0, 12]);
+            msg.extend_from_slice(&rtype.to_be_bytes());
+            msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+            msg.extend_from_slice(&300u32.to_be_bytes());
+            msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            msg.extend_from_slice(rdata);
+        }
+        msg
+    }
+
+    #[test]
+    fn test_parse_answer() {
+        let query = encode_query(7, "www.example.test", TYPE_A).unwrap();
+        assert_eq!(&query[12..16], b"\x03www");
+        // A CNAME, then the address it leads to
+        let cname = b"\x03web\xc0\x10";
+        let msg = reply(&query, 0, 0, &[(5, cname), (TYPE_A, &[10, 0, 0, 7])]);
+        let ips = parse_answer(&msg, 7, "www.example.test", SERVER).ok().unwrap().unwrap();
+        assert_eq!(ips, [IpAddr::from([10, 0, 0, 7])]);
+
+        let err = parse_answer(&reply(&query, 0, 3, &[]), 7, "www.example.test", SERVER).ok().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "www.example.test: no such host (asked 127.0.0.1:53)");
+        assert!(parse_answer(&reply(&query, 0x02, 0, &[]), 7, "www.example.test", SERVER).is_err());
+        assert!(parse_answer(&reply(&query, 0, 0, &[]), 8, "www.example.test", SERVER).ok().unwrap().is_err());
+        let mut cut = msg.clone();
+        cut.truncate(msg.len() - 2);
+        assert!(parse_answer(&cut, 7, "www.example.test", SERVER).ok().unwrap().is_err());
+        assert!(encode_query(1, "a..b", TYPE_A).is_err());
+
+        assert_eq!(DnsServer::parse_addr("10.0.0.53"), Ok("10.0.0.53:53".parse().unwrap()));
+        assert_eq!(DnsServer::parse_addr("10.0.0.53:5353"), Ok("10.0.0.53:5353".parse().unwrap()));
+        assert_eq!(DnsServer::parse_addr("[::1]:53"), Ok("[::1]:53".parse().unwrap()));
+        assert_eq!(DnsServer::parse_addr("::1"), Ok("[::1]:53".parse().unwrap()));
+        assert!(DnsServer::parse_addr("dns.example").is_err());
+    }
+
+    #[test]
+    fn test_dns_server_udp_then_tcp() {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).unwrap();
+        let server = thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            // The A question comes back truncated, and is asked again over TCP
+            let (n, from) = udp.recv_from(&mut buf).unwrap();
+            udp.send_to(&reply(&buf[..n], 0x02, 0, &[]), from).unwrap();
+            let (mut stream, _) = tcp.accept().unwrap();
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).unwrap();
+            let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut query).unwrap();
+            let answer = reply(&query, 0, 0, &[(TYPE_A, &[10, 1, 2, 3])]);
+            stream.write_all(&(answer.len() as u16).to_be_bytes()).unwrap();
+            stream.write_all(&answer).unwrap();
+            // There's no AAAA record
+            let (n, from) = udp.recv_from(&mut buf).unwrap();
+            udp.send_to(&reply(&buf[..n], 0, 0, &[]), from).unwrap();
+        });
+
+        let dns = DnsServer::new(addr, Duration::from_secs(2));
+        assert_eq!(dns.resolve("api.internal:8443").unwrap(), ["10.1.2.3:8443".parse().unwrap()]);
+        server.join().unwrap();
+        // Addresses need no lookup
+        assert_eq!(dns.resolve("[::1]:80").unwrap(), ["[::1]:80".parse().unwrap()]);
+        assert!(dns.resolve("no-port").is_err());
+    }
+
+    #[test]
+    fn test_dns_server_timeout() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let dns = DnsServer::new(silent.local_addr().unwrap(), Duration::from_millis(50));
+        let err = dns.resolve("api.internal:443").unwrap_err();
+        assert_eq!(err.to_string(), format!("no answer from {}", silent.local_addr().unwrap()));
+    }
+}
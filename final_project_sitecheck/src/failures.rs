@@ -0,0 +1,242 @@
+//! `--save-failures DIR`: the response behind each failed check, kept on disk
+//! for post-mortems within a size cap.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde::Serialize;
+
+use crate::{CheckError, CheckResponse, SCHEMA_VERSION};
+
+/// Default for `--save-failures-max-mb`.
+pub const DEFAULT_SAVE_FAILURES_MAX_MB: u64 = 100;
+const BODY_EXT: &str = ".html";
+const META_EXT: &str = ".meta.json";
+/// Longest URL part of a file name; the rest is cut off.
+const NAME_CHARS: usize = 80;
+
+/// Where failing responses go, and how many bytes of them are kept at most.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SaveFailures {
+    pub dir: PathBuf,
+    pub max_bytes: u64,
+}
+
+/// Saves failing responses as `<url>-<timestamp>.html` plus a `.meta.json`
+/// beside it. Once they would take more than `max_bytes`, the oldest saves go
+/// first, including ones left by earlier runs.
+#[derive(Debug)]
+pub struct FailureStore {
+    config: SaveFailures,
+    saved: Mutex<Option<Saves>>, // Read from the directory at the first save
+}
+
+/// The saves on disk, oldest first.
+#[derive(Debug, Default)]
+struct Saves {
+    files: VecDeque<(PathBuf, u64)>, // Body path and bytes with its meta
+    bytes: u64,
+}
+
+/// The `.meta.json` beside a saved body.
+#[derive(Serialize)]
+struct Meta<'a> {
+    schema_version: u32,
+    url: &'a str,
+    timestamp: DateTime<Utc>,
+    http_status: u16,
+    headers: Vec<(&'a str, &'a str)>, // In the order received, names lowercased
+    error: &'a CheckError,
+    body_file: String,
+}
+
+impl FailureStore {
+    pub fn new(config: SaveFailures) -> Self {
+        FailureStore { config, saved: Mutex::new(None) }
+    }
+
+    /// Save `response` as the failure of `url` at `at`, making room for it;
+    /// returns the body's path. A response that can't be saved is logged and
+    /// skipped, as is one bigger than the whole cap.
+    pub fn save(&self, url: &str, at: DateTime<Utc>, response: &CheckResponse, error: &CheckError) -> Option<PathBuf> {
+        let mut saved = self.saved.lock().unwrap_or_else(PoisonError::into_inner);
+        let saves = saved.get_or_insert_with(|| scan(&self.config.dir));
+        match self.write(saves, url, at, response, error) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("saving the failed response of {url} to {} failed: {e}", self.config.dir.display());
+                None
+            }
+        }
+    }
+
+    fn write(
+        &self,
+        saves: &mut Saves,
+        url: &str,
+        at: DateTime<Utc>,
+        response: &CheckResponse,
+        error: &CheckError,
+    ) -> io::Result<Option<PathBuf>> {
+        let body = response.body.as_deref().unwrap_or_default();
+        let base = format!("{}-{}", file_name(url), at.format("%Y%m%dT%H%M%S%.3fZ"));
+        let (mut file, path) = create_unique(&self.config.dir, &base)?;
+        let meta = Meta {
+            schema_version: SCHEMA_VERSION,
+            url,
+            timestamp: at,
+            http_status: response.status,
+            headers: response.headers.iter().collect(),
+            error,
+            body_file: path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        };
+        let meta = serde_json::to_vec_pretty(&meta).map_err(io::Error::other)?;
+        let bytes = (body.len() + meta.len()) as u64;
+        if bytes > self.config.max_bytes {
+            drop(file);
+            fs::remove_file(&path)?;
+            warn!("not saving the failed response of {url}: {bytes} bytes is more than the whole --save-failures-max-mb");
+            return Ok(None);
+        }
+        while saves.bytes + bytes > self.config.max_bytes {
+            let Some((old, old_bytes)) = saves.files.pop_front() else { break };
+            debug!("evicting saved failure {}", old.display());
+            let _ = fs::remove_file(&old);
+            let _ = fs::remove_file(meta_path(&old));
+            saves.bytes = saves.bytes.saturating_sub(old_bytes);
+        }
+        file.write_all(body)?;
+        fs::write(meta_path(&path), meta)?;
+        saves.files.push_back((path.clone(), bytes));
+        saves.bytes += bytes;
+        Ok(Some(path))
+    }
+}
+
+/// `url` as a file name: no scheme, and anything but letters, digits, `.`,
+/// and `-` turned into `_`.
+fn file_name(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let name: String = rest
+        .trim_end_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .take(NAME_CHARS)
+        .collect();
+    if name.is_empty() { "_".to_string() } else { name }
+}
+
+/// The meta file beside the body at `body`.
+fn meta_path(body: &Path) -> PathBuf {
+    let name = body.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    body.with_file_name(format!("{}{META_EXT}", name.trim_end_matches(BODY_EXT)))
+}
+
+/// A new `<base>.html` in `dir`, numbered `<base>-2.html`, ... if taken.
+fn create_unique(dir: &Path, base: &str) -> io::Result<(fs::File, PathBuf)> {
+    for n in 1.. {
+        let name = if n == 1 { format!("{base}{BODY_EXT}") } else { format!("{base}-{n}{BODY_EXT}") };
+        let path = dir.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of file numbers")
+}
+
+/// The saves already in `dir`, oldest first.
+fn scan(dir: &Path) -> Saves {
+    let mut found: Vec<(SystemTime, PathBuf, u64)> = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if !path.to_string_lossy().ends_with(BODY_EXT) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else { continue };
+        let meta_bytes = fs::metadata(meta_path(&path)).map_or(0, |m| m.len());
+        found.push((meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), path, meta.len() + meta_bytes));
+    }
+    found.sort();
+    let bytes = found.iter().map(|(_, _, bytes)| bytes).sum();
+    Saves { files: found.into_iter().map(|(_, path, bytes)| (path, bytes)).collect(), bytes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HeaderView;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sitecheck-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn response(body: &str) -> CheckResponse {
+        let headers = HeaderView::new([("Content-Type", "text/html"), ("Server", "test")]);
+        CheckResponse { status: 200, headers, body: Some(body.as_bytes().to_vec()), certificate: None }
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("https://example.com/a/b?q=1&x=%20"), "example.com_a_b_q_1_x__20");
+        assert_eq!(file_name("http://example.com/"), "example.com");
+        assert_eq!(file_name(&format!("https://{}", "a".repeat(200))).len(), NAME_CHARS);
+        assert_eq!(meta_path(Path::new("/d/x.html-2.html")), Path::new("/d/x.html-2.meta.json"));
+    }
+
+    #[test]
+    fn test_save_failures() {
+        let dir = temp_dir("saved");
+        let error = CheckError::BodyValidation { missing: "Welcome".into() };
+        let store = FailureStore::new(SaveFailures { dir: dir.clone(), max_bytes: 10_000 });
+        let path = store.save("https://example.com/", Utc::now(), &response("<p>Oops</p>"), &error).unwrap();
+        assert!(path.starts_with(&dir));
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("example.com-"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<p>Oops</p>");
+        let meta: serde_json::Value = serde_json::from_slice(&fs::read(meta_path(&path)).unwrap()).unwrap();
+        assert_eq!(meta["http_status"], 200);
+        assert_eq!(meta["error"]["kind"], "body_validation");
+        assert_eq!(meta["headers"][1], serde_json::json!(["server", "test"]));
+
+        // Saved at the same moment, the next one gets its own name
+        let again = store.save("https://example.com/", Utc::now(), &response("again"), &error).unwrap();
+        assert_ne!(again, path);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_failures_evicts_oldest() {
+        let dir = temp_dir("evict");
+        let error = CheckError::BodyValidation { missing: "Welcome".into() };
+        let big = "x".repeat(2_000);
+        let cap = 5_000;
+        let saved: Vec<PathBuf> = {
+            let store = FailureStore::new(SaveFailures { dir: dir.clone(), max_bytes: cap });
+            (0..3).map(|i| store.save(&format!("https://{i}.test/"), Utc::now(), &response(&big), &error).unwrap()).collect()
+        };
+        // Two fit: the first went to make room for the third
+        assert!(!saved[0].exists() && !meta_path(&saved[0]).exists());
+        assert!(saved[1].exists() && saved[2].exists());
+
+        // A later run counts what is already there, and drops the oldest of it first
+        let store = FailureStore::new(SaveFailures { dir: dir.clone(), max_bytes: cap });
+        let next = store.save("https://3.test/", Utc::now(), &response(&big), &error).unwrap();
+        assert!(!saved[1].exists() && saved[2].exists() && next.exists());
+
+        // Bigger than the whole cap: not saved, and nothing evicted for it
+        assert!(store.save("https://4.test/", Utc::now(), &response(&"y".repeat(6_000)), &error).is_none());
+        assert!(saved[2].exists() && next.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
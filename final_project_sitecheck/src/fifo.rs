@@ -0,0 +1,208 @@
+//! `--fifo PATH`: result lines streamed to a named pipe, for a local process
+//! to consume live without a file or a network socket.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::{Sink, WebsiteStatus};
+
+/// Result lines held for the reader before `FifoPolicy` applies.
+pub const FIFO_BUFFER_LINES: usize = 1024;
+/// How long shutdown waits for a connected reader to take the buffered lines.
+const DRAIN_WAIT: Duration = Duration::from_secs(2);
+
+/// What `Fifo` does with a result while its buffer is full: no reader has
+/// connected yet, or the one connected is falling behind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FifoPolicy {
+    /// Drop the result, so the run never waits on the reader.
+    #[default]
+    Drop,
+    /// Wait for room. With no reader at all, results stop being collected
+    /// once the buffer fills.
+    Block,
+}
+impl FifoPolicy {
+    /// Parse a `--fifo-policy` value.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "drop" => Ok(FifoPolicy::Drop),
+            "block" => Ok(FifoPolicy::Block),
+            other => Err(format!("unknown fifo policy '{other}' (expected drop, block)")),
+        }
+    }
+    /// The name `parse` accepts.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FifoPolicy::Drop => "drop",
+            FifoPolicy::Block => "block",
+        }
+    }
+}
+
+/// Writes each result as a JSON line to a named pipe. Opening a pipe for
+/// writing waits for a reader, so a writer thread does it and lines queue up
+/// to a fixed buffer meanwhile; a reader that goes away is waited for again.
+pub struct Fifo {
+    path: PathBuf,
+    policy: FifoPolicy,
+    lines: Option<SyncSender<String>>,
+    connected: Arc<AtomicBool>,
+    writer: Option<thread::JoinHandle<()>>,
+    dropped: u64,
+}
+
+impl Fifo {
+    /// Stream to the named pipe at `path`, which must already exist (`mkfifo`).
+    pub fn open(path: &Path, policy: FifoPolicy) -> io::Result<Self> {
+        Fifo::with_buffer(path, policy, FIFO_BUFFER_LINES)
+    }
+
+    /// Like `open`, holding up to `lines` results for the reader.
+    pub fn with_buffer(path: &Path, policy: FifoPolicy, lines: usize) -> io::Result<Self> {
+        if !fs::metadata(path)?.file_type().is_fifo() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a named pipe (create one with mkfifo)"));
+        }
+        let (tx, rx) = mpsc::sync_channel(lines);
+        let connected = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let (path, connected) = (path.to_path_buf(), Arc::clone(&connected));
+            thread::spawn(move || write_lines(&path, &rx, &connected))
+        };
+        Ok(Fifo { path: path.to_path_buf(), policy, lines: Some(tx), connected, writer: Some(writer), dropped: 0 })
+    }
+
+    /// Results dropped so far under `FifoPolicy::Drop`.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// The writer thread: wait for a reader, write lines until it goes away, repeat.
+/// Returns once every line is written and the sink has shut down.
+fn write_lines(path: &Path, lines: &Receiver<String>, connected: &AtomicBool) {
+    // A line the last reader didn't take, for the next one
+    let mut unsent: Option<String> = None;
+    loop {
+        let mut pipe = match OpenOptions::new().write(true).open(path) {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                warn!("fifo {}: {e}", path.display());
+                return;
+            }
+        };
+        connected.store(true, Ordering::SeqCst);
+        debug!("fifo {}: reader connected", path.display());
+        loop {
+            let Some(line) = unsent.take().or_else(|| lines.recv().ok()) else { return };
+            // Usually a broken pipe: the reader closed its end
+            if let Err(e) = pipe.write_all(line.as_bytes()) {
+                debug!("fifo {}: {e}; waiting for a new reader", path.display());
+                unsent = Some(line);
+                break;
+            }
+        }
+        connected.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Sink for Fifo {
+    fn on_result(&mut self, status: &WebsiteStatus) {
+        let Some(lines) = &self.lines else { return };
+        let line = match serde_json::to_string(status) {
+            Ok(js) => js + "\n",
+            Err(_) => format!("{status:?}\n"),
+        };
+        let stopped = match self.policy {
+            FifoPolicy::Block => lines.send(line).is_err(),
+            FifoPolicy::Drop => match lines.try_send(line) {
+                Ok(()) => false,
+                Err(TrySendError::Full(_)) => {
+                    if self.dropped == 0 {
+                        warn!("fifo {}: no reader keeping up; dropping results until one does", self.path.display());
+                    }
+                    self.dropped += 1;
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => true,
+            },
+        };
+        if stopped {
+            warn!("fifo {}: writer stopped; no more results will be sent", self.path.display());
+            self.lines = None;
+        }
+    }
+
+    fn on_shutdown(&mut self) {
+        drop(self.lines.take());
+        if self.dropped > 0 {
+            warn!("fifo {}: dropped {} result(s) in all", self.path.display(), self.dropped);
+        }
+        // A connected reader gets what is still buffered. Without one the writer
+        // is stuck opening the pipe, so it is left behind rather than waited on.
+        if let Some(writer) = self.writer.take() {
+            let until = Instant::now() + DRAIN_WAIT;
+            while self.connected.load(Ordering::SeqCst) && !writer.is_finished() && Instant::now() < until {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn status(url: &str) -> WebsiteStatus {
+        WebsiteStatus::sample(url, Ok(200))
+    }
+
+    fn mkfifo(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sitecheck-{name}-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(Command::new("mkfifo").arg(&path).status().unwrap().success());
+        path
+    }
+
+    #[test]
+    fn test_fifo_drops_without_reader() {
+        let path = mkfifo("fifo-drop");
+        let mut sink = Fifo::with_buffer(&path, FifoPolicy::Drop, 2).unwrap();
+        let started = Instant::now();
+        for i in 0..5 {
+            sink.on_result(&status(&format!("https://{i}.test/")));
+        }
+        // Nobody is reading: two results are buffered, the rest dropped without waiting
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(sink.dropped(), 3);
+        sink.on_shutdown();
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // A reader that turns up later still gets the buffered ones
+        let read = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let urls: Vec<String> = read.lines().map(|l| serde_json::from_str::<WebsiteStatus>(l).unwrap().url).collect();
+        assert_eq!(urls, ["https://0.test/", "https://1.test/"]);
+    }
+
+    #[test]
+    fn test_fifo_rejects_regular_files() {
+        let path = std::env::temp_dir().join(format!("sitecheck-not-fifo-{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let err = Fifo::open(&path, FifoPolicy::Drop).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(FifoPolicy::parse("block"), Ok(FifoPolicy::Block));
+        assert!(FifoPolicy::parse("buffer").is_err());
+    }
+}
@@ -0,0 +1,1107 @@
+//! The HTTP layer under checks: the `HttpClient` trait with its request and
+//! response types, the default ureq client, and (with the `reqwest` feature)
+//! a reqwest-blocking one.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::cert::{recording_tls_config, Certificate, PeerCerts};
+use crate::dns::DnsServer;
+use crate::oauth::Authorization;
+use crate::{CheckError, Config, HeaderView, DEFAULT_MAX_REDIRECTS, DEFAULT_TIMEOUT};
+
+/// Largest sitemap body read; larger ones fail rather than filling memory.
+pub const MAX_BODY_BYTES: u64 = 64 * 1024 * 1024;
+/// Largest body a check reads unless told otherwise (`--max-body-bytes`).
+pub const DEFAULT_MAX_BODY_BYTES: u64 = 4 * 1024 * 1024;
+/// Bodies are read, and searched, this many bytes at a time.
+const CHUNK_BYTES: usize = 16 * 1024;
+/// How long a resolved address is reused unless told otherwise (`--dns-cache-ttl`).
+pub const DEFAULT_DNS_CACHE_TTL: Duration = Duration::from_secs(60);
+/// Unread bodies up to this size are drained so their connection can be
+/// reused; longer ones are dropped along with it.
+const DRAIN_BYTES: u64 = 64 * 1024;
+
+/// Which responses should come back with their body.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadBody {
+    #[default]
+    Never,
+    Always,
+    /// Only `text/html` responses, the ones that can carry a meta refresh.
+    IfHtml,
+}
+
+/// How a request asks for the resource.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Method {
+    #[default]
+    Get,
+    /// Status and headers only.
+    Head,
+    /// A GET of the first byte only (`Range: bytes=0-0`), for servers whose
+    /// HEAD responses leave out `Content-Length`.
+    GetFirstByte,
+}
+
+/// One request issued by a check; a GET unless `method` says otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckRequest {
+    pub url: String,
+    pub method: Method,
+    /// Applies to connecting, reading, and writing separately.
+    pub timeout: Duration,
+    /// HTTP redirects to follow before returning the redirect response itself.
+    pub max_redirects: u32,
+    pub read_body: ReadBody,
+    /// Give up at this instant, whatever stage the request is in (`--deadline`).
+    pub deadline: Option<Instant>,
+    /// Fail with `BodyTooLarge` past this many body bytes; None reads any size.
+    pub max_body_bytes: Option<u64>,
+    /// Stop reading the body once it contains this; the body then ends with it.
+    pub read_until: Option<Vec<u8>>,
+    /// Sent as `Accept-Encoding`. The body comes back as sent, still encoded;
+    /// `decode_body` undoes it.
+    pub accept_encoding: Option<String>,
+    /// Sent as `Authorization` (`--oauth-token-url`).
+    pub authorization: Option<Authorization>,
+    /// The URL this request is part of checking, when that isn't `url` itself
+    /// (a redirect hop, a link). `OAuthClient` only sends the token to its origin.
+    pub checked_url: Option<String>,
+}
+
+/// Whether `b` has the same scheme, host, and port as `a`.
+pub(crate) fn same_origin(a: &str, b: &str) -> bool {
+    match (url::Url::parse(a), url::Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.origin() == b.origin(),
+        _ => false,
+    }
+}
+
+/// What came back. Error statuses (4xx/5xx) are responses too; deciding what
+/// they mean is up to the caller.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckResponse {
+    pub status: u16,
+    pub headers: HeaderView,
+    /// Present when the request's `read_body` asked for it.
+    pub body: Option<Vec<u8>>,
+    /// The server's certificate, from a client recording them (`--cert-info`).
+    pub certificate: Option<Certificate>,
+}
+
+impl CheckResponse {
+    /// The media type without parameters, e.g. `text/html`; `text/plain` if unset.
+    pub fn content_type(&self) -> &str {
+        match self.headers.get("content-type") {
+            Some(ct) => ct.split(';').next().unwrap_or_default().trim(),
+            None => "text/plain",
+        }
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.content_type().eq_ignore_ascii_case("text/html")
+    }
+
+    /// The full size of the resource: the total of a 206's `Content-Range`
+    /// (`bytes 0-0/1234`), otherwise `Content-Length`.
+    pub fn content_length(&self) -> Option<u64> {
+        if self.status == 206 {
+            let range = self.headers.get("content-range")?;
+            return range.rsplit_once('/').and_then(|(_, total)| total.trim().parse().ok());
+        }
+        self.headers.get("content-length").and_then(|n| n.trim().parse().ok())
+    }
+}
+
+/// A request that got no usable response. Becomes the matching `CheckError` in results.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportError {
+    Dns(String),
+    ConnectionRefused(String),
+    ConnectTimeout(String),
+    ConnectionFailed(String),
+    ReadTimeout(String),
+    /// The status line and headers arrived, but the body could not be read.
+    BodyRead { status: u16, detail: String },
+    /// The body went past the request's `max_body_bytes`.
+    BodyTooLarge { status: u16, limit: u64 },
+    /// The TLS handshake failed on the server's certificate, recorded here.
+    InvalidCertificate { detail: String, certificate: Box<Certificate> },
+    Other(String),
+}
+
+impl TransportError {
+    /// The HTTP status, if a response got that far.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            TransportError::BodyRead { status, .. } | TransportError::BodyTooLarge { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// The certificate that failed the handshake, if that is what went wrong.
+    pub fn certificate(&self) -> Option<&Certificate> {
+        match self {
+            TransportError::InvalidCertificate { certificate, .. } => Some(certificate),
+            _ => None,
+        }
+    }
+}
+
+impl From<TransportError> for CheckError {
+    fn from(e: TransportError) -> Self {
+        match e {
+            TransportError::Dns(detail) => CheckError::Dns { detail },
+            TransportError::ConnectionRefused(detail) => CheckError::ConnectionRefused { detail },
+            TransportError::ConnectTimeout(detail) => CheckError::ConnectTimeout { detail },
+            TransportError::ConnectionFailed(detail) | TransportError::InvalidCertificate { detail, .. } => {
+                CheckError::ConnectionFailed { detail }
+            }
+            TransportError::ReadTimeout(detail) => CheckError::ReadTimeout { detail },
+            TransportError::BodyRead { detail, .. } => CheckError::BodyRead { detail },
+            TransportError::BodyTooLarge { limit, .. } => CheckError::BodyTooLarge { limit },
+            TransportError::Other(detail) => CheckError::Request { detail },
+        }
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        CheckError::from(self.clone()).fmt(f)
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// Performs the requests checks make. Implement it to run checks over another
+/// HTTP stack; `Checker::with_client` takes any implementation.
+pub trait HttpClient: Send + Sync {
+    /// GET `req.url`, following up to `req.max_redirects` HTTP redirects.
+    fn execute(&self, req: CheckRequest) -> Result<CheckResponse, TransportError>;
+}
+
+/// Read a body in chunks as `req` allows: failing rather than truncating past
+/// `max_body_bytes`, and stopping early once `read_until` turns up. The deadline
+/// is checked between chunks, so a server dripping the body slowly can't outlast it.
+pub(crate) fn read_body(reader: impl Read, status: u16, req: &CheckRequest) -> Result<Vec<u8>, TransportError> {
+    let mut body = Vec::new();
+    let mut reader = reader.take(req.max_body_bytes.map_or(u64::MAX, |max| max.saturating_add(1)));
+    let mut chunk = [0u8; CHUNK_BYTES];
+    loop {
+        if req.deadline.is_some_and(|d| Instant::now() >= d) {
+            let detail = format!("deadline passed after {} body bytes", body.len());
+            return Err(TransportError::BodyRead { status, detail });
+        }
+        let n = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(TransportError::BodyRead { status, detail: e.to_string() }),
+        };
+        // Search from just before the new chunk, so a match straddling two chunks is found
+        let from = body.len().saturating_sub(req.read_until.as_ref().map_or(0, |n| n.len().saturating_sub(1)));
+        body.extend_from_slice(&chunk[..n]);
+        if let Some(needle) = &req.read_until {
+            if let Some(at) = find(&body[from..], needle) {
+                body.truncate(from + at + needle.len());
+                return Ok(body);
+            }
+        }
+        if let Some(limit) = req.max_body_bytes.filter(|&max| body.len() as u64 > max) {
+            return Err(TransportError::BodyTooLarge { status, limit });
+        }
+    }
+    Ok(body)
+}
+
+/// What becomes of a response body, decided from the head alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyPlan {
+    /// Something looks at it.
+    Read,
+    /// Nothing does, but ureq pools a connection only once its body is read
+    /// to the end, so it's read into nothing.
+    Drain,
+    /// Nothing does and the connection won't be reused: the body stream is never opened.
+    Skip,
+}
+
+impl BodyPlan {
+    /// The plan for a body `wanted` or not, of `length` if the server said.
+    /// A HEAD response has no body, so draining it costs nothing whatever its length.
+    fn new(wanted: bool, method: Method, keepalive: bool, length: Option<u64>) -> Self {
+        if wanted {
+            BodyPlan::Read
+        } else if !keepalive || (method != Method::Head && length.is_some_and(|n| n > DRAIN_BYTES)) {
+            BodyPlan::Skip
+        } else {
+            BodyPlan::Drain
+        }
+    }
+
+    /// Carry out the plan, calling `open` for the body stream only to read or drain it.
+    fn take<R: Read>(self, open: impl FnOnce() -> R, status: u16, req: &CheckRequest) -> Result<Option<Vec<u8>>, TransportError> {
+        match self {
+            BodyPlan::Read => read_body(open(), status, req).map(Some),
+            BodyPlan::Drain => {
+                let _ = io::copy(&mut open().take(DRAIN_BYTES), &mut io::sink());
+                Ok(None)
+            }
+            BodyPlan::Skip => Ok(None),
+        }
+    }
+}
+
+/// Decode a body sent with `Content-Encoding: encoding` (gzip, deflate, or br),
+/// through the same limits as `read_body`, so a small body can't unpack into a huge one.
+pub(crate) fn decode_body(encoding: &str, wire: &[u8], status: u16, req: &CheckRequest) -> Result<Vec<u8>, TransportError> {
+    let decoded = match encoding {
+        "gzip" | "x-gzip" => read_body(flate2::read::GzDecoder::new(wire), status, req),
+        "deflate" => read_body(flate2::read::ZlibDecoder::new(wire), status, req),
+        "br" => read_body(brotli_decompressor::Decompressor::new(wire, CHUNK_BYTES), status, req),
+        _ => {
+            let detail = format!("unsupported Content-Encoding {encoding:?}");
+            return Err(TransportError::BodyRead { status, detail });
+        }
+    };
+    decoded.map_err(|e| match e {
+        TransportError::BodyRead { status, detail } => TransportError::BodyRead { status, detail: format!("{encoding} body: {detail}") },
+        e => e,
+    })
+}
+
+/// Where `needle` first occurs in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Resolves `host:port` the way ureq does by default.
+fn system_lookup(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    netloc.to_socket_addrs().map(Iterator::collect)
+}
+
+/// Look `netloc` up with `server`, or else the system resolver, waiting for
+/// `gate` if there is one.
+fn lookup(netloc: &str, server: Option<&DnsServer>, gate: Option<&LookupGate>) -> io::Result<Vec<SocketAddr>> {
+    let ask = || server.map_or_else(|| system_lookup(netloc), |server| server.resolve(netloc));
+    match gate {
+        Some(gate) => gate.run(ask),
+        None => ask(),
+    }
+}
+
+/// Caps how many host lookups run at once (`--max-dns-concurrency`), so a
+/// round starting hundreds of checks doesn't flood the resolver. Cache hits
+/// don't wait for it.
+#[derive(Debug)]
+pub struct LookupGate {
+    max: usize,
+    running: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl LookupGate {
+    pub fn new(max: usize) -> Self {
+        LookupGate { max: max.max(1), running: Mutex::new(0), freed: Condvar::new() }
+    }
+
+    /// Run `lookup` once fewer than `max` others are running.
+    pub fn run<T>(&self, lookup: impl FnOnce() -> T) -> T {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        while *running >= self.max {
+            running = self.freed.wait(running).unwrap_or_else(PoisonError::into_inner);
+        }
+        *running += 1;
+        drop(running);
+        let result = lookup();
+        *self.running.lock().unwrap_or_else(PoisonError::into_inner) -= 1;
+        self.freed.notify_one();
+        result
+    }
+}
+
+/// Resolved addresses shared by every agent of a client, so checks of many
+/// URLs on one host (and later rounds) skip the lookup until `ttl` passes.
+/// Keyed by `host:port`, as ureq asks; failed lookups are not cached.
+pub struct DnsCache {
+    ttl: Duration,
+    lookup: Box<dyn ureq::Resolver>,
+    entries: Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>,
+}
+
+impl DnsCache {
+    /// A cache that reuses system resolver answers for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        DnsCache::with_lookup(ttl, system_lookup)
+    }
+
+    fn with_lookup(ttl: Duration, lookup: impl ureq::Resolver + 'static) -> Self {
+        DnsCache { ttl, lookup: Box::new(lookup), entries: Mutex::new(HashMap::new()) }
+    }
+
+    /// The addresses of `netloc`, from the cache while they are fresh.
+    pub fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        if let Some((at, addrs)) = self.entries.lock().unwrap().get(netloc) {
+            if at.elapsed() < self.ttl {
+                return Ok(addrs.clone());
+            }
+        }
+        // Looked up unlocked: a slow resolver must not hold up other hosts
+        let addrs = self.lookup.resolve(netloc)?;
+        self.entries.lock().unwrap().insert(netloc.to_string(), (Instant::now(), addrs.clone()));
+        Ok(addrs)
+    }
+
+    /// Forget `netloc`, so its next use resolves it again.
+    pub fn invalidate(&self, netloc: &str) {
+        self.entries.lock().unwrap().remove(netloc);
+    }
+
+    /// Forget the host of `url` after connecting to it failed: the
+    /// addresses may be stale.
+    fn invalidate_url(&self, url: &url::Url) {
+        if let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) {
+            self.invalidate(&format!("{host}:{port}"));
+        }
+    }
+}
+
+impl fmt::Debug for DnsCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hosts = self.entries.lock().map_or(0, |entries| entries.len());
+        f.debug_struct("DnsCache").field("ttl", &self.ttl).field("hosts", &hosts).finish()
+    }
+}
+
+thread_local! {
+    /// Whether this thread's current request opened a connection rather than
+    /// reusing a pooled one. ureq connects on the calling thread.
+    static OPENED_CONNECTION: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Connection-level settings for a ureq agent, all in one place.
+#[derive(Debug, Clone)]
+pub struct AgentOptions {
+    /// Applies to connect, read, and write separately.
+    pub timeout: Duration,
+    pub max_redirects: u32,
+    /// Keep idle connections for reuse; off, every request connects afresh.
+    pub keepalive: bool,
+    /// Resolve hosts through this cache; None asks the resolver every time.
+    pub dns: Option<Arc<DnsCache>>,
+    /// Ask this server instead of the system resolver, when `dns` doesn't
+    /// already (`--dns-server`).
+    pub dns_server: Option<Arc<DnsServer>>,
+    /// Lookups wait here, when `dns` doesn't already make them.
+    pub dns_gate: Option<Arc<LookupGate>>,
+    /// Record every https server's certificate here.
+    pub certs: Option<Arc<PeerCerts>>,
+}
+impl Default for AgentOptions {
+    fn default() -> Self {
+        AgentOptions {
+            timeout: DEFAULT_TIMEOUT,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            keepalive: true,
+            dns: None,
+            dns_server: None,
+            dns_gate: None,
+            certs: None,
+        }
+    }
+}
+impl AgentOptions {
+    /// The options a run's checks use, with a fresh DNS cache unless
+    /// `dns_cache_ttl` is None, asking `dns_server` if set, at most
+    /// `max_dns_concurrency` lookups at a time, and recording certificates
+    /// with `cert_info`.
+    pub fn from_config(config: &Config) -> Self {
+        let dns_server = config.dns_server.map(|addr| Arc::new(DnsServer::new(addr, config.timeout)));
+        let dns_gate = config.max_dns_concurrency.map(|n| Arc::new(LookupGate::new(n)));
+        let dns = config.dns_cache_ttl.map(|ttl| {
+            let (server, gate) = (dns_server.clone(), dns_gate.clone());
+            Arc::new(DnsCache::with_lookup(ttl, move |netloc: &str| lookup(netloc, server.as_deref(), gate.as_deref())))
+        });
+        AgentOptions {
+            timeout: config.timeout,
+            max_redirects: config.check.max_redirects,
+            keepalive: config.keepalive,
+            dns,
+            dns_server,
+            dns_gate,
+            certs: config.cert_info.then(|| Arc::new(PeerCerts::new())),
+        }
+    }
+}
+
+/// A ureq agent set up as `options` say.
+pub fn build_agent(options: &AgentOptions) -> ureq::Agent {
+    let (dns, server, gate) = (options.dns.clone(), options.dns_server.clone(), options.dns_gate.clone());
+    let mut builder = ureq::AgentBuilder::new()
+        .timeout_connect(options.timeout)
+        .timeout_read(options.timeout)
+        .timeout_write(options.timeout)
+        .redirects(options.max_redirects)
+        .resolver(move |netloc: &str| {
+            // Only a new connection is resolved; a pooled one already was
+            OPENED_CONNECTION.with(|opened| opened.set(true));
+            match &dns {
+                Some(dns) => dns.resolve(netloc),
+                None => lookup(netloc, server.as_deref(), gate.as_deref()),
+            }
+        });
+    if let Some(certs) = &options.certs {
+        builder = builder.tls_config(recording_tls_config(certs.clone()));
+    }
+    if options.keepalive {
+        builder.build()
+    } else {
+        builder.max_idle_connections(0).max_idle_connections_per_host(0).build()
+    }
+}
+
+/// The default client. Keeps one ureq agent (and its connection pool) per
+/// timeout and redirect limit, since ureq fixes both at agent construction.
+/// The agents are shared by every worker, so a connection to a host is reused
+/// whichever worker checks it next. Every agent shares the client's other
+/// `AgentOptions`, DNS cache included.
+#[derive(Debug, Default)]
+pub struct UreqClient {
+    agents: Mutex<HashMap<(Duration, u32), ureq::Agent>>,
+    options: AgentOptions,
+}
+
+impl UreqClient {
+    pub fn new() -> Self {
+        UreqClient::default()
+    }
+
+    /// A client whose agents are built from `options`; each request's own
+    /// timeout and redirect limit replace the ones there.
+    pub fn with_options(options: AgentOptions) -> Self {
+        UreqClient { options, ..UreqClient::default() }
+    }
+
+    /// A client whose agents look hosts up through `dns`.
+    pub fn with_dns_cache(dns: Arc<DnsCache>) -> Self {
+        UreqClient::with_options(AgentOptions { dns: Some(dns), ..AgentOptions::default() })
+    }
+
+    /// Open a new connection for every request instead of keeping them alive
+    /// (`--no-keepalive`), so each check pays for the full connection setup.
+    pub fn fresh_connections(mut self, on: bool) -> Self {
+        self.options.keepalive = !on;
+        self
+    }
+
+    fn agent(&self, timeout: Duration, max_redirects: u32) -> ureq::Agent {
+        let mut agents = self.agents.lock().unwrap();
+        let new_agent = || build_agent(&AgentOptions { timeout, max_redirects, ..self.options.clone() });
+        agents.entry((timeout, max_redirects)).or_insert_with(new_agent).clone()
+    }
+}
+
+/// Classify a ureq transport error (dns, refused, connect timeout, read
+/// timeout, ...) instead of reporting a generic request error.
+fn classify_ureq(t: &ureq::Transport) -> TransportError {
+    let io_kind = std::error::Error::source(t)
+        .and_then(|src| src.downcast_ref::<io::Error>())
+        .map(|io| io.kind());
+    let timed_out = matches!(
+        io_kind,
+        Some(io::ErrorKind::TimedOut) | Some(io::ErrorKind::WouldBlock)
+    );
+    let detail = t.to_string();
+    match t.kind() {
+        ureq::ErrorKind::Dns => TransportError::Dns(detail),
+        ureq::ErrorKind::ConnectionFailed => match io_kind {
+            Some(io::ErrorKind::ConnectionRefused) => TransportError::ConnectionRefused(detail),
+            _ if timed_out => TransportError::ConnectTimeout(detail),
+            _ => TransportError::ConnectionFailed(detail),
+        },
+        ureq::ErrorKind::Io if timed_out => TransportError::ReadTimeout(detail),
+        _ => TransportError::Other(detail),
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn execute(&self, req: CheckRequest) -> Result<CheckResponse, TransportError> {
+        let agent = self.agent(req.timeout, req.max_redirects);
+        let mut request = match req.method {
+            Method::Get => agent.get(&req.url),
+            Method::Head => agent.head(&req.url),
+            Method::GetFirstByte => agent.get(&req.url).set("Range", "bytes=0-0"),
+        };
+        if let Some(encoding) = &req.accept_encoding {
+            request = request.set("Accept-Encoding", encoding);
+        }
+        if let Some(authorization) = &req.authorization {
+            request = request.set("Authorization", authorization.as_str());
+        }
+        if let Some(deadline) = req.deadline {
+            // Caps the whole request, body included, on top of the per-stage timeouts
+            request = request.timeout(deadline.saturating_duration_since(Instant::now()));
+        }
+        OPENED_CONNECTION.with(|opened| opened.set(false));
+        let resp = match request.call() {
+            Ok(resp) | Err(ureq::Error::Status(_, resp)) => resp,
+            Err(ureq::Error::Transport(t)) => {
+                let mut err = classify_ureq(&t);
+                if let (Some(dns), Some(url)) = (&self.options.dns, t.url()) {
+                    if matches!(
+                        err,
+                        TransportError::ConnectionRefused(_)
+                            | TransportError::ConnectTimeout(_)
+                            | TransportError::ConnectionFailed(_)
+                    ) {
+                        dns.invalidate_url(url);
+                    }
+                }
+                // A handshake the certificate failed has it recorded, to report with the error
+                if let (Some(certs), Some(url), TransportError::ConnectionFailed(detail)) = (&self.options.certs, t.url(), &err) {
+                    if let Some(certificate) = certs.take_invalid(url.as_str()) {
+                        let certificate = Box::new(certificate);
+                        err = TransportError::InvalidCertificate { detail: detail.clone(), certificate };
+                    }
+                }
+                return Err(err);
+            }
+        };
+        let status = resp.status();
+        // Reuse changes the latency measured, so say which it was
+        debug!("{}: {} connection", req.url, if OPENED_CONNECTION.with(Cell::get) { "new" } else { "reused" });
+        let certificate = self.options.certs.as_ref().and_then(|certs| certs.for_url(resp.get_url()));
+        let names = resp.headers_names();
+        let headers = HeaderView::new(names.iter().filter_map(|n| resp.header(n).map(|v| (n.as_str(), v))));
+        let wanted = match req.read_body {
+            ReadBody::Never => false,
+            ReadBody::Always => true,
+            ReadBody::IfHtml => resp.content_type().eq_ignore_ascii_case("text/html"),
+        };
+        let length = headers.get("content-length").and_then(|n| n.trim().parse::<u64>().ok());
+        let plan = BodyPlan::new(wanted, req.method, self.options.keepalive, length);
+        let body = plan.take(|| resp.into_reader(), status, &req)?;
+        Ok(CheckResponse { status, headers, body, certificate })
+    }
+}
+
+/// A client on reqwest's blocking API, for programs that already depend on
+/// reqwest. Keeps one reqwest client per timeout and redirect limit.
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Default)]
+pub struct ReqwestClient {
+    clients: Mutex<HashMap<(Duration, u32), reqwest::blocking::Client>>,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestClient {
+    pub fn new() -> Self {
+        ReqwestClient::default()
+    }
+
+    fn client(&self, timeout: Duration, max_redirects: u32) -> Result<reqwest::blocking::Client, TransportError> {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&(timeout, max_redirects)) {
+            return Ok(client.clone());
+        }
+        // Policy::limited(0) would fail on a redirect; ureq returns the 3xx instead
+        let redirects = match max_redirects {
+            0 => reqwest::redirect::Policy::none(),
+            n => reqwest::redirect::Policy::limited(n as usize),
+        };
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(timeout)
+            .timeout(timeout)
+            .redirect(redirects)
+            .build()
+            .map_err(|e| TransportError::Other(e.to_string()))?;
+        clients.insert((timeout, max_redirects), client.clone());
+        Ok(client)
+    }
+}
+
+/// Classify a reqwest error the same way `classify_ureq` does.
+#[cfg(feature = "reqwest")]
+fn classify_reqwest(e: &reqwest::Error) -> TransportError {
+    let detail = e.to_string();
+    // hyper reports resolver failures only in the message
+    let (mut dns, mut refused) = (false, false);
+    let mut cause = std::error::Error::source(e);
+    while let Some(c) = cause {
+        dns |= c.to_string().starts_with("dns error");
+        refused |= c.downcast_ref::<io::Error>().is_some_and(|io| io.kind() == io::ErrorKind::ConnectionRefused);
+        cause = c.source();
+    }
+    match (e.is_timeout(), e.is_connect()) {
+        (true, true) => TransportError::ConnectTimeout(detail),
+        (true, false) => TransportError::ReadTimeout(detail),
+        (false, true) if dns => TransportError::Dns(detail),
+        (false, true) if refused => TransportError::ConnectionRefused(detail),
+        (false, true) => TransportError::ConnectionFailed(detail),
+        (false, false) => TransportError::Other(detail),
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpClient for ReqwestClient {
+    fn execute(&self, req: CheckRequest) -> Result<CheckResponse, TransportError> {
+        let client = self.client(req.timeout, req.max_redirects)?;
+        let mut request = match req.method {
+            Method::Get => client.get(&req.url),
+            Method::Head => client.head(&req.url),
+            Method::GetFirstByte => client.get(&req.url).header(reqwest::header::RANGE, "bytes=0-0"),
+        };
+        if let Some(encoding) = &req.accept_encoding {
+            request = request.header(reqwest::header::ACCEPT_ENCODING, encoding);
+        }
+        if let Some(authorization) = &req.authorization {
+            request = request.header(reqwest::header::AUTHORIZATION, authorization.as_str());
+        }
+        if let Some(deadline) = req.deadline {
+            request = request.timeout(deadline.saturating_duration_since(Instant::now()));
+        }
+        let resp = request.send().map_err(|e| classify_reqwest(&e))?;
+        let status = resp.status().as_u16();
+        let headers =
+            HeaderView::new(resp.headers().iter().filter_map(|(n, v)| v.to_str().ok().map(|v| (n.as_str(), v))));
+        let mut response = CheckResponse { status, headers, body: None, certificate: None };
+        let wanted = match req.read_body {
+            ReadBody::Never => false,
+            ReadBody::Always => true,
+            ReadBody::IfHtml => response.is_html(),
+        };
+        if wanted {
+            response.body = Some(read_body(resp, status, &req)?);
+        }
+        Ok(response)
+    }
+}
+
+/// Canned responses by URL, for testing check logic without a server.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct MockClient {
+    routes: HashMap<String, Result<CheckResponse, TransportError>>,
+    /// Answers for one method only, tried before `routes`.
+    method_routes: HashMap<(Method, String), CheckResponse>,
+    pub(crate) requests: Mutex<Vec<CheckRequest>>,
+}
+
+#[cfg(test)]
+impl MockClient {
+    /// Answer `url` with `status`, `headers`, and `body`.
+    pub(crate) fn respond(self, url: &str, status: u16, headers: &[(&str, &str)], body: &str) -> Self {
+        self.respond_bytes(url, status, headers, body.as_bytes())
+    }
+
+    /// `respond` with a body that needn't be text, e.g. a gzipped one.
+    pub(crate) fn respond_bytes(mut self, url: &str, status: u16, headers: &[(&str, &str)], body: &[u8]) -> Self {
+        let response = CheckResponse {
+            status,
+            headers: HeaderView::new(headers.iter().copied()),
+            body: Some(body.to_vec()),
+            certificate: None,
+        };
+        self.routes.insert(url.to_string(), Ok(response));
+        self
+    }
+
+    /// Answer `method` requests for `url` with `status` and `headers`, and no body.
+    pub(crate) fn respond_to(mut self, method: Method, url: &str, status: u16, headers: &[(&str, &str)]) -> Self {
+        let response = CheckResponse { status, headers: HeaderView::new(headers.iter().copied()), ..Default::default() };
+        self.method_routes.insert((method, url.to_string()), response);
+        self
+    }
+
+    /// Fail `url` with `error`.
+    pub(crate) fn fail(mut self, url: &str, error: TransportError) -> Self {
+        self.routes.insert(url.to_string(), Err(error));
+        self
+    }
+}
+
+#[cfg(test)]
+impl HttpClient for MockClient {
+    fn execute(&self, req: CheckRequest) -> Result<CheckResponse, TransportError> {
+        let mut response = match (self.method_routes.get(&(req.method, req.url.clone())), self.routes.get(&req.url)) {
+            (Some(response), _) => response.clone(),
+            (None, Some(route)) => route.clone()?,
+            (None, None) => CheckResponse { status: 404, ..Default::default() },
+        };
+        let wanted = match req.read_body {
+            ReadBody::Never => false,
+            ReadBody::Always => true,
+            ReadBody::IfHtml => response.is_html(),
+        };
+        // Read through the same limits as a real body
+        let body = match response.body.take() {
+            Some(body) if wanted => read_body(&body[..], response.status, &req).map(Some),
+            _ => Ok(None),
+        };
+        self.requests.lock().unwrap().push(req);
+        response.body = body?;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    // httpmock's prelude has a `Method` too
+    use super::Method;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn get(url: String, read_body: ReadBody) -> CheckRequest {
+        CheckRequest {
+            url,
+            method: Method::Get,
+            timeout: Duration::from_secs(2),
+            max_redirects: 2,
+            read_body,
+            deadline: None,
+            max_body_bytes: None,
+            read_until: None,
+            accept_encoding: None,
+            authorization: None,
+            checked_url: None,
+        }
+    }
+
+    #[test]
+    fn test_ureq_client() {
+        let server = MockServer::start();
+        let _page = server.mock(|when, then| {
+            when.method(GET).path("/page");
+            then.status(200).header("Content-Type", "text/html; charset=utf-8").header("X-Test", "yes").body("<p>hi</p>");
+        });
+        let _missing = server.mock(|when, then| {
+            when.method(GET).path("/missing");
+            then.status(404).body("nope");
+        });
+
+        let client = UreqClient::new();
+        let resp = client.execute(get(server.url("/page"), ReadBody::IfHtml)).unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.headers.get("x-test"), Some("yes"));
+        assert!(resp.is_html());
+        assert_eq!(resp.body.as_deref(), Some(&b"<p>hi</p>"[..]));
+
+        // Error statuses are responses, and only requested bodies are read
+        let resp = client.execute(get(server.url("/missing"), ReadBody::IfHtml)).unwrap();
+        assert_eq!(resp.status, 404);
+        assert_eq!(resp.body, None);
+        let resp = client.execute(get(server.url("/missing"), ReadBody::Always)).unwrap();
+        assert_eq!(resp.body.as_deref(), Some(&b"nope"[..]));
+        assert_eq!(client.agents.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ureq_client_refused() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let err = UreqClient::new().execute(get(format!("http://127.0.0.1:{port}/"), ReadBody::Never)).unwrap_err();
+        assert!(matches!(err, TransportError::ConnectionRefused(_)), "{err:?}");
+        assert!(err.to_string().starts_with("connection refused"), "{err}");
+    }
+
+    #[test]
+    fn test_classify_ureq() {
+        let io = |kind: io::ErrorKind| ureq::Error::from(io::Error::new(kind, "boom")).into_transport().unwrap();
+        assert!(matches!(classify_ureq(&io(io::ErrorKind::TimedOut)), TransportError::ReadTimeout(_)));
+        assert!(matches!(classify_ureq(&io(io::ErrorKind::WouldBlock)), TransportError::ReadTimeout(_)));
+        assert!(matches!(classify_ureq(&io(io::ErrorKind::BrokenPipe)), TransportError::Other(_)));
+
+        // ureq only builds connect errors itself, so these come from real (local) attempts
+        let call = |resolve: fn(&str) -> io::Result<Vec<SocketAddr>>| {
+            let agent = ureq::AgentBuilder::new().resolver(resolve).timeout_connect(Duration::from_secs(1)).build();
+            classify_ureq(&agent.get("http://site.test/").call().unwrap_err().into_transport().unwrap())
+        };
+        let err = call(|_| Err(io::Error::new(io::ErrorKind::NotFound, "no such host")));
+        assert!(matches!(err, TransportError::Dns(_)), "{err:?}");
+        let err = call(|_| {
+            let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+            Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))])
+        });
+        assert!(matches!(err, TransportError::ConnectionRefused(_)), "{err:?}");
+        // Connecting to the broadcast address is refused locally, not by a peer
+        let err = call(|_| Ok(vec![SocketAddr::from(([255, 255, 255, 255], 80))]));
+        assert!(matches!(err, TransportError::ConnectionFailed(_)), "{err:?}");
+    }
+
+    /// A cache over the system resolver that counts the lookups it makes.
+    fn counting_cache(ttl: Duration) -> (Arc<DnsCache>, Arc<AtomicUsize>) {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&lookups);
+        let cache = DnsCache::with_lookup(ttl, move |netloc: &str| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            system_lookup(netloc)
+        });
+        (Arc::new(cache), lookups)
+    }
+
+    #[test]
+    fn test_dns_cache() {
+        let (cache, lookups) = counting_cache(Duration::from_secs(60));
+        let addrs = cache.resolve("127.0.0.1:80").unwrap();
+        assert_eq!(addrs, vec![SocketAddr::from(([127, 0, 0, 1], 80))]);
+        assert_eq!(cache.resolve("127.0.0.1:80").unwrap(), addrs);
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+        // Ports are part of the key
+        cache.resolve("127.0.0.1:81").unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+        cache.invalidate("127.0.0.1:80");
+        cache.resolve("127.0.0.1:80").unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 3);
+        // Failures are not remembered
+        assert!(cache.resolve("no port").is_err());
+        assert!(cache.resolve("no port").is_err());
+        assert_eq!(lookups.load(Ordering::SeqCst), 5);
+
+        let (cache, lookups) = counting_cache(Duration::ZERO);
+        cache.resolve("127.0.0.1:80").unwrap();
+        cache.resolve("127.0.0.1:80").unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_lookup_gate() {
+        let gate = Arc::new(LookupGate::new(2));
+        let (running, most) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let (gate, running, most) = (Arc::clone(&gate), Arc::clone(&running), Arc::clone(&most));
+                std::thread::spawn(move || {
+                    gate.run(|| {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        most.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(20));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+
+        // Behind the cache, only a miss waits for the gate and looks the host up
+        let config = Config::builder().max_dns_concurrency(Some(1)).build();
+        let options = AgentOptions::from_config(&config);
+        let cache = options.dns.unwrap();
+        assert_eq!(cache.resolve("127.0.0.1:80").unwrap(), cache.resolve("127.0.0.1:80").unwrap());
+        assert_eq!(options.dns_gate.map(|g| g.max), Some(1));
+    }
+
+    #[test]
+    fn test_ureq_client_dns_cache() {
+        let server = MockServer::start();
+        let _page = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200);
+        });
+        let (cache, lookups) = counting_cache(Duration::from_secs(60));
+        let client = UreqClient::with_dns_cache(Arc::clone(&cache));
+        let url = format!("http://127.0.0.1:{}/", server.port());
+        client.execute(get(url.clone(), ReadBody::Never)).unwrap();
+        // Agents with other settings share the cache
+        client.execute(CheckRequest { timeout: Duration::from_secs(3), ..get(url, ReadBody::Never) }).unwrap();
+        assert_eq!(client.agents.lock().unwrap().len(), 2);
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        // A host that refused the connection is looked up afresh next time
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let refused = format!("http://127.0.0.1:{port}/");
+        assert!(client.execute(get(refused.clone(), ReadBody::Never)).is_err());
+        assert!(client.execute(get(refused, ReadBody::Never)).is_err());
+        assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_ureq_client_keepalive() {
+        let server = MockServer::start();
+        let _page = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200).body("ok");
+        });
+        // Whether each of three requests opened a connection
+        let opened = |client: UreqClient| -> Vec<bool> {
+            (0..3)
+                .map(|_| {
+                    client.execute(get(server.url("/"), ReadBody::Never)).unwrap();
+                    OPENED_CONNECTION.with(Cell::get)
+                })
+                .collect()
+        };
+        assert_eq!(opened(UreqClient::new()), [true, false, false]);
+        assert_eq!(opened(UreqClient::new().fresh_connections(true)), [true, true, true]);
+        let options = AgentOptions { keepalive: false, ..AgentOptions::default() };
+        assert_eq!(opened(UreqClient::with_options(options)), [true, true, true]);
+    }
+
+    #[test]
+    fn test_agent_options_from_config() {
+        let config = Config::builder()
+            .timeout(Duration::from_secs(2))
+            .max_redirects(5)
+            .keepalive(false)
+            .dns_cache_ttl(None)
+            .build();
+        let options = AgentOptions::from_config(&config);
+        assert_eq!((options.timeout, options.max_redirects, options.keepalive), (Duration::from_secs(2), 5, false));
+        assert!(options.dns.is_none());
+        assert!(AgentOptions::from_config(&Config::default()).dns.is_some());
+
+        // --dns-server is asked directly, or through the cache when there is one
+        let server = "10.0.0.53:53".parse().unwrap();
+        let options = AgentOptions::from_config(&Config::builder().dns_cache_ttl(None).dns_server(Some(server)).build());
+        assert_eq!((options.dns.is_none(), options.dns_server.map(|s| s.addr())), (true, Some(server)));
+        let options = AgentOptions::from_config(&Config::builder().dns_server(Some(server)).build());
+        let cached = options.dns.unwrap().resolve("127.0.0.1:80").unwrap();
+        assert_eq!(cached, ["127.0.0.1:80".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_status_only_never_opens_body() {
+        let req = get("http://x.test/".into(), ReadBody::Never);
+        let opened = Cell::new(false);
+        let open = || {
+            opened.set(true);
+            io::Cursor::new(b"<p>hi</p>".to_vec())
+        };
+        // Nothing looks at the body and the connection isn't kept: it's never opened
+        let plan = BodyPlan::new(false, Method::Get, false, Some(9));
+        assert_eq!(plan, BodyPlan::Skip);
+        assert_eq!(plan.take(open, 200, &req).unwrap(), None);
+        assert!(!opened.get());
+        // nor when it's too long to drain for the connection's sake
+        assert_eq!(BodyPlan::new(false, Method::Get, true, Some(DRAIN_BYTES + 1)), BodyPlan::Skip);
+
+        // A short one is drained to pool the connection, and a HEAD one always is
+        let plan = BodyPlan::new(false, Method::Get, true, Some(9));
+        assert_eq!(plan, BodyPlan::Drain);
+        assert_eq!(plan.take(open, 200, &req).unwrap(), None);
+        assert!(opened.take());
+        assert_eq!(BodyPlan::new(false, Method::Head, true, Some(DRAIN_BYTES + 1)), BodyPlan::Drain);
+
+        let plan = BodyPlan::new(true, Method::Get, false, None);
+        assert_eq!(plan.take(open, 200, &req).unwrap().as_deref(), Some(&b"<p>hi</p>"[..]));
+        assert!(opened.get());
+    }
+
+    #[test]
+    fn test_ureq_client_head() {
+        let server = MockServer::start();
+        let head = server.mock(|when, then| {
+            when.method(httpmock::Method::HEAD).path("/video.mp4");
+            then.status(200).header("Content-Length", "1048576");
+        });
+        let req = CheckRequest { method: Method::Head, ..get(server.url("/video.mp4"), ReadBody::Always) };
+        let resp = UreqClient::new().execute(req).unwrap();
+        head.assert();
+        assert_eq!((resp.status, resp.content_length()), (200, Some(1048576)));
+        assert_eq!(resp.body.as_deref(), Some(&b""[..]));
+
+        let ranged = |status, headers: &[(&str, &str)]| CheckResponse {
+            status,
+            headers: HeaderView::new(headers.iter().copied()),
+            ..Default::default()
+        };
+        assert_eq!(ranged(206, &[("Content-Range", "bytes 0-0/5000"), ("Content-Length", "1")]).content_length(), Some(5000));
+        assert_eq!(ranged(206, &[("Content-Range", "bytes 0-0/*")]).content_length(), None);
+        assert_eq!(ranged(200, &[]).content_length(), None);
+    }
+
+    #[test]
+    fn test_content_type() {
+        let resp = |ct: &[(&str, &str)]| CheckResponse { headers: HeaderView::new(ct.iter().copied()), ..Default::default() };
+        assert_eq!(resp(&[("Content-Type", "TEXT/HTML ; charset=utf-8")]).content_type(), "TEXT/HTML");
+        assert!(resp(&[("Content-Type", "TEXT/HTML ; charset=utf-8")]).is_html());
+        assert_eq!(resp(&[]).content_type(), "text/plain");
+        assert!(!resp(&[("content-type", "application/json")]).is_html());
+    }
+
+    /// Hands out one byte per read, pausing before each.
+    struct Drip(Duration);
+
+    impl Read for Drip {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            std::thread::sleep(self.0);
+            buf[0] = b'x';
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_read_body_deadline() {
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let req = CheckRequest { deadline: Some(deadline), ..get("http://x.test/".into(), ReadBody::Always) };
+        let err = read_body(Drip(Duration::from_millis(20)), 200, &req).unwrap_err();
+        assert!(matches!(&err, TransportError::BodyRead { status: 200, detail } if detail.starts_with("deadline passed")), "{err:?}");
+        assert!(Instant::now() < deadline + Duration::from_millis(100));
+
+        let req = CheckRequest { deadline: Some(Instant::now() + Duration::from_secs(5)), ..req };
+        assert_eq!(read_body(&b"done"[..], 200, &req).unwrap(), b"done");
+    }
+
+    #[test]
+    fn test_read_body_limits() {
+        let req = |max: Option<u64>, until: Option<&str>| CheckRequest {
+            max_body_bytes: max,
+            read_until: until.map(|t| t.as_bytes().to_vec()),
+            ..get("http://x.test/".into(), ReadBody::Always)
+        };
+
+        // The needle straddles the first chunk boundary; the endless tail after it is never read
+        let mut data = vec![b'a'; CHUNK_BYTES - 3];
+        data.extend_from_slice(b"needle");
+        let endless = (&data[..]).chain(io::repeat(b'z'));
+        let body = read_body(endless, 200, &req(Some(DEFAULT_MAX_BODY_BYTES), Some("needle"))).unwrap();
+        assert_eq!(body.len(), CHUNK_BYTES + 3);
+        assert!(body.ends_with(b"needle"));
+
+        let err = read_body(io::repeat(b'z'), 200, &req(Some(1024 * 1024), Some("needle"))).unwrap_err();
+        assert_eq!(err, TransportError::BodyTooLarge { status: 200, limit: 1024 * 1024 });
+        assert_eq!(err.to_string(), "body too large (over 1048576 bytes)");
+
+        let big = vec![b'x'; 5 * 1024 * 1024];
+        assert_eq!(read_body(&big[..], 200, &req(None, Some("needle"))).unwrap().len(), big.len());
+        assert_eq!(read_body(&big[..], 200, &req(Some(big.len() as u64), None)).unwrap().len(), big.len());
+        assert!(read_body(&big[..], 200, &req(Some(big.len() as u64 - 1), None)).is_err());
+    }
+
+    #[test]
+    fn test_ureq_client_big_body() {
+        let mut page = vec![b'a'; 5 * 1024 * 1024];
+        page[1024 * 1024..1024 * 1024 + 6].copy_from_slice(b"needle");
+        let server = MockServer::start();
+        let _big = server.mock(|when, then| {
+            when.method(GET).path("/big");
+            then.status(200).body(page.clone());
+        });
+
+        let client = UreqClient::new();
+        let request = |max: Option<u64>, until: Option<&str>| CheckRequest {
+            max_body_bytes: max,
+            read_until: until.map(|t| t.as_bytes().to_vec()),
+            ..get(server.url("/big"), ReadBody::Always)
+        };
+        let err = client.execute(request(Some(DEFAULT_MAX_BODY_BYTES), None)).unwrap_err();
+        assert_eq!(err.status(), Some(200));
+        assert!(matches!(err, TransportError::BodyTooLarge { .. }), "{err:?}");
+        // Found a megabyte in, long before the limit
+        let resp = client.execute(request(Some(DEFAULT_MAX_BODY_BYTES), Some("needle"))).unwrap();
+        assert_eq!(resp.body.map(|b| b.len()), Some(1024 * 1024 + 6));
+        let resp = client.execute(request(None, None)).unwrap();
+        assert_eq!(resp.body.map(|b| b.len()), Some(page.len()));
+    }
+}
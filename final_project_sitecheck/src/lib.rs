@@ -0,0 +1,5025 @@
+//! Concurrent website checking: URL loading and validation, the worker pool,
+//! result statistics, and the sinks results are written to. The `sitecheck`
+//! binary is a CLI over this crate.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone, Utc};
+use log::{debug, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr, DurationMilliSeconds};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use http::decode_body;
+
+mod baseline;
+mod bench;
+mod cert;
+mod dns;
+mod failures;
+mod har;
+#[cfg(unix)]
+mod fifo;
+mod http;
+mod links;
+mod maintenance;
+mod oauth;
+mod sink;
+mod snapshot;
+#[cfg(unix)]
+mod unix;
+mod validate;
+
+pub use baseline::{Baseline, Baselines, Degradation, BASELINE_BLEND};
+pub use bench::{Bench, BenchReport, Latency};
+pub use cert::{Certificate, PeerCerts};
+pub use dns::{DnsServer, DNS_PORT};
+pub use failures::{FailureStore, SaveFailures, DEFAULT_SAVE_FAILURES_MAX_MB};
+#[cfg(unix)]
+pub use fifo::{Fifo, FifoPolicy, FIFO_BUFFER_LINES};
+pub use har::{Har, HarClient, HAR_MAX_ENTRIES};
+#[cfg(feature = "reqwest")]
+pub use http::ReqwestClient;
+pub use http::{
+    build_agent, AgentOptions, CheckRequest, CheckResponse, DnsCache, HttpClient, LookupGate, Method, ReadBody, TransportError, UreqClient,
+    DEFAULT_DNS_CACHE_TTL, DEFAULT_MAX_BODY_BYTES, MAX_BODY_BYTES,
+};
+
+use links::check_page_links;
+pub use links::{extract_links, BrokenLink, LinkReport, LinkSettings, SeenLinks, DEFAULT_MAX_LINKS_PER_PAGE};
+pub use maintenance::MaintenanceWindow;
+pub use oauth::{Authorization, OAuthClient, OAuthSettings, OAuthToken, TOKEN_REFRESH_MARGIN};
+#[cfg(unix)]
+pub use unix::{check_socket, UnixSocketClient, UnixTarget, UnixTargets};
+pub use sink::{
+    write_summary, AlertOn, Csv, Heartbeat, HeartbeatOn, JsonLines, Sink, Sinks, Summary, SummaryTo, Webhook, CSV_HEADER, HEARTBEAT_TIMEOUT,
+};
+pub use snapshot::{stats_json, StatsOut};
+pub use validate::{BodyContains, HeaderEquals, HeaderView, StatusIn, StatusSet, ValidationError, Validator};
+
+/// Version of the `WebsiteStatus` JSON format, bumped whenever a change could
+/// break a reader. Records from before versioning read back as version 0.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The outcome of checking one URL once; serialized as one NDJSON record.
+///
+/// Serializing and deserializing gives back an equal value. `response_time` is
+/// encoded in whole milliseconds, which is also the precision checks record it at.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebsiteStatus {
+    #[serde(default)]
+    pub schema_version: u32,
+    pub url: String,
+    #[serde(deserialize_with = "deserialize_status")]
+    pub status: Result<u16, CheckError>,
+    /// Requests made, including the final one; more than 1 means it took retries.
+    #[serde(default = "one_attempt")]
+    pub attempts: u32,
+    /// Why each attempt before the final one failed, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempt_errors: Vec<CheckError>,
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub response_time: Duration,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>, // Set whenever a response arrived, even if the check failed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirect_chain: Vec<String>,
+    /// Size of the resource as the server reported it, with `--head-size-only`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_length: Option<u64>,
+    /// Down during a maintenance window: left out of uptime and alerts.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub maintenance: bool,
+    /// The `--capture-header` values from the last response, null if it lacked
+    /// one; every value is null if no response arrived.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub captured_headers: CapturedHeaders,
+    /// The start of the last response's body, with `--capture-body-on-failure`;
+    /// only on failed checks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_snippet: Option<String>,
+    /// Where `--save-failures` saved the failed response's body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_body: Option<PathBuf>,
+    /// The canary's side of a `--compare` check.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Comparison>,
+    /// The server's clock against ours, with `--check-clock-skew`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_skew: Option<ClockSkew>,
+    /// How the last response was encoded, with `--require-compression`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// The https server's certificate, with `--cert-info`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<Certificate>,
+    /// The links on the page and which were broken, with `--check-links`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<LinkReport>,
+    #[serde(default)]
+    pub labels: Labels,
+}
+
+impl WebsiteStatus {
+    /// Whether the check passed: a response arrived and every validation held.
+    pub fn ok(&self) -> bool {
+        self.status.is_ok()
+    }
+
+    /// Why the check failed, if it did.
+    pub fn error(&self) -> Option<&CheckError> {
+        self.status.as_ref().err()
+    }
+
+    /// The HTTP status of the final response, kept even when a validation then
+    /// failed the check; `None` only if no response arrived. Records written
+    /// before `http_status` existed carry it in `status` alone.
+    pub fn http_code(&self) -> Option<u16> {
+        self.http_status.or_else(|| self.status.as_ref().ok().copied())
+    }
+}
+
+/// Why a check failed. `Display` gives the human-readable message; in JSON the
+/// error is an object with a stable snake_case `kind`, that `message`, and the
+/// variant's own fields, so consumers can match on `kind` instead of the text.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CheckError {
+    Dns { detail: String },
+    ConnectionRefused { detail: String },
+    ConnectTimeout { detail: String },
+    ConnectionFailed { detail: String },
+    ReadTimeout { detail: String },
+    /// A response arrived with an error status (4xx/5xx).
+    HttpStatus { code: u16, detail: String },
+    /// Any other failure to make the request.
+    Request { detail: String },
+    /// The status was not one `--expect-status` allows.
+    UnexpectedStatus { code: u16, expected: String },
+    HeaderMismatch { name: String, expected: String, got: String },
+    MissingHeader { name: String },
+    /// The body did not contain the `--contains` text.
+    BodyValidation { missing: String },
+    BodyRead { detail: String },
+    /// The body was longer than `--max-body-bytes`.
+    BodyTooLarge { limit: u64 },
+    InvalidMetaRefresh { target: String, detail: String },
+    MetaRefreshLoop { url: String },
+    /// An HTTP redirect back to a URL already visited.
+    RedirectLoop { url: String },
+    TooManyRedirects { max: u32 },
+    /// The check never reported back: it panicked, or the round's deadline passed.
+    NoResult { detail: String },
+    /// The check as a whole, retries included, outlasted `--deadline`.
+    DeadlineExceeded { after_ms: u64 },
+    /// A `--compare` canary didn't match the URL it shadows.
+    CompareMismatch { canary: String, diff: String },
+    /// The server's `Date` was further from our clock than `--check-clock-skew`.
+    ClockSkew { skew_s: i64, max_s: u64 },
+    /// `--require-compression` asked for a compressed body and got an uncompressed one.
+    NotCompressed,
+    /// `--alert-degradation`: slower than `factor` times the URL's `--baseline-file` median.
+    LatencyDegraded { ms: u64, baseline_ms: u64, factor: f64 },
+    /// `--check-links` found this many links on the page broken.
+    BrokenLinks { count: usize },
+    /// Only the message is known, e.g. for results written before errors were typed.
+    Other { message: String },
+}
+impl CheckError {
+    /// The stable name used as `kind` in JSON.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CheckError::Dns { .. } => "dns",
+            CheckError::ConnectionRefused { .. } => "connection_refused",
+            CheckError::ConnectTimeout { .. } => "connect_timeout",
+            CheckError::ConnectionFailed { .. } => "connection_failed",
+            CheckError::ReadTimeout { .. } => "read_timeout",
+            CheckError::HttpStatus { .. } => "http_status",
+            CheckError::Request { .. } => "request",
+            CheckError::UnexpectedStatus { .. } => "unexpected_status",
+            CheckError::HeaderMismatch { .. } => "header_mismatch",
+            CheckError::MissingHeader { .. } => "missing_header",
+            CheckError::BodyValidation { .. } => "body_validation",
+            CheckError::BodyRead { .. } => "body_read",
+            CheckError::BodyTooLarge { .. } => "body_too_large",
+            CheckError::InvalidMetaRefresh { .. } => "invalid_meta_refresh",
+            CheckError::MetaRefreshLoop { .. } => "meta_refresh_loop",
+            CheckError::RedirectLoop { .. } => "redirect_loop",
+            CheckError::TooManyRedirects { .. } => "too_many_redirects",
+            CheckError::NoResult { .. } => "no_result",
+            CheckError::DeadlineExceeded { .. } => "deadline_exceeded",
+            CheckError::CompareMismatch { .. } => "compare_mismatch",
+            CheckError::ClockSkew { .. } => "clock_skew",
+            CheckError::NotCompressed => "not_compressed",
+            CheckError::LatencyDegraded { .. } => "latency_degraded",
+            CheckError::BrokenLinks { .. } => "broken_links",
+            CheckError::Other { .. } => "other",
+        }
+    }
+
+    /// Whether the request ran out of time, connecting or reading.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, CheckError::ConnectTimeout { .. } | CheckError::ReadTimeout { .. })
+    }
+}
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Dns { detail } => write!(f, "dns resolution failed: {detail}"),
+            CheckError::ConnectionRefused { detail } => write!(f, "connection refused: {detail}"),
+            CheckError::ConnectTimeout { detail } => write!(f, "connection timeout: {detail}"),
+            CheckError::ConnectionFailed { detail } => write!(f, "connection failed: {detail}"),
+            CheckError::ReadTimeout { detail } => write!(f, "read timeout: {detail}"),
+            CheckError::HttpStatus { detail, .. } | CheckError::Request { detail } => {
+                write!(f, "request error: {detail}")
+            }
+            CheckError::UnexpectedStatus { code, expected } => {
+                write!(f, "unexpected status {code} (expected {expected})")
+            }
+            CheckError::HeaderMismatch { name, expected, got } => {
+                write!(f, "header mismatch: {name} expected '{expected}' got '{got}'")
+            }
+            CheckError::MissingHeader { name } => write!(f, "missing required header: {name}"),
+            CheckError::BodyValidation { missing } => {
+                write!(f, "body validation failed: missing substring '{missing}'")
+            }
+            CheckError::BodyRead { detail } => write!(f, "body read error: {detail}"),
+            CheckError::BodyTooLarge { limit } => write!(f, "body too large (over {limit} bytes)"),
+            CheckError::InvalidMetaRefresh { target, detail } => {
+                write!(f, "invalid meta-refresh target '{target}': {detail}")
+            }
+            CheckError::MetaRefreshLoop { url } => write!(f, "meta-refresh loop detected at {url}"),
+            CheckError::RedirectLoop { url } => write!(f, "redirect loop detected at {url}"),
+            CheckError::TooManyRedirects { max } => write!(f, "too many redirects (max {max})"),
+            CheckError::NoResult { detail } => write!(f, "no result: {detail}"),
+            CheckError::DeadlineExceeded { after_ms } => {
+                write!(f, "deadline exceeded after {}", format_duration(Duration::from_millis(*after_ms)))
+            }
+            CheckError::CompareMismatch { canary, diff } => write!(f, "canary {canary} differs: {diff}"),
+            CheckError::ClockSkew { skew_s, max_s } => write!(f, "server clock is {skew_s:+}s off ours (max {max_s}s)"),
+            CheckError::NotCompressed => write!(f, "response not compressed (no Content-Encoding)"),
+            CheckError::LatencyDegraded { ms, baseline_ms, factor } => {
+                write!(f, "response took {ms}ms, over {factor}x the {baseline_ms}ms baseline")
+            }
+            CheckError::BrokenLinks { count } => write!(f, "{count} broken link{} on the page", if *count == 1 { "" } else { "s" }),
+            CheckError::Other { message } => write!(f, "{message}"),
+        }
+    }
+}
+impl std::error::Error for CheckError {}
+// Written by hand so the JSON carries `message` next to the derived-style tag and fields
+impl Serialize for CheckError {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut out = s.serialize_struct("CheckError", 5)?;
+        out.serialize_field("kind", self.kind())?;
+        out.serialize_field("message", &self.to_string())?;
+        match self {
+            CheckError::Dns { detail }
+            | CheckError::ConnectionRefused { detail }
+            | CheckError::ConnectTimeout { detail }
+            | CheckError::ConnectionFailed { detail }
+            | CheckError::ReadTimeout { detail }
+            | CheckError::Request { detail }
+            | CheckError::BodyRead { detail }
+            | CheckError::NoResult { detail } => out.serialize_field("detail", detail)?,
+            CheckError::HttpStatus { code, detail } => {
+                out.serialize_field("code", code)?;
+                out.serialize_field("detail", detail)?;
+            }
+            CheckError::UnexpectedStatus { code, expected } => {
+                out.serialize_field("code", code)?;
+                out.serialize_field("expected", expected)?;
+            }
+            CheckError::HeaderMismatch { name, expected, got } => {
+                out.serialize_field("name", name)?;
+                out.serialize_field("expected", expected)?;
+                out.serialize_field("got", got)?;
+            }
+            CheckError::MissingHeader { name } => out.serialize_field("name", name)?,
+            CheckError::BodyValidation { missing } => out.serialize_field("missing", missing)?,
+            CheckError::InvalidMetaRefresh { target, detail } => {
+                out.serialize_field("target", target)?;
+                out.serialize_field("detail", detail)?;
+            }
+            CheckError::MetaRefreshLoop { url } | CheckError::RedirectLoop { url } => out.serialize_field("url", url)?,
+            CheckError::TooManyRedirects { max } => out.serialize_field("max", max)?,
+            CheckError::BodyTooLarge { limit } => out.serialize_field("limit", limit)?,
+            CheckError::DeadlineExceeded { after_ms } => out.serialize_field("after_ms", after_ms)?,
+            CheckError::CompareMismatch { canary, diff } => {
+                out.serialize_field("canary", canary)?;
+                out.serialize_field("diff", diff)?;
+            }
+            CheckError::ClockSkew { skew_s, max_s } => {
+                out.serialize_field("skew_s", skew_s)?;
+                out.serialize_field("max_s", max_s)?;
+            }
+            CheckError::LatencyDegraded { ms, baseline_ms, factor } => {
+                out.serialize_field("ms", ms)?;
+                out.serialize_field("baseline_ms", baseline_ms)?;
+                out.serialize_field("factor", factor)?;
+            }
+            CheckError::BrokenLinks { count } => out.serialize_field("count", count)?,
+            // `message` already holds it, or there is nothing more to say
+            CheckError::Other { .. } | CheckError::NotCompressed => {}
+        }
+        out.end()
+    }
+}
+
+/// Read `status`, accepting both typed errors and the plain strings that
+/// results written by older versions contain.
+fn deserialize_status<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Result<u16, CheckError>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Typed(CheckError),
+        Text(String),
+    }
+    let status: Result<u16, Stored> = Deserialize::deserialize(d)?;
+    Ok(status.map_err(|e| match e {
+        Stored::Typed(e) => e,
+        Stored::Text(message) => CheckError::Other { message },
+    }))
+}
+
+// Results written before attempts were recorded came from a single request
+fn one_attempt() -> u32 {
+    1
+}
+
+/// Free-form key=value tags attached to a URL (team, env, ...).
+pub type Labels = BTreeMap<String, String>;
+
+/// Captured response headers by the name asked for; None if it was absent.
+pub type CapturedHeaders = BTreeMap<String, Option<String>>;
+
+/// How a `--compare` canary fared, fetched right after the URL it shadows.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    pub canary: String,
+    /// Set whenever the canary answered, even if its own check failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary_http_status: Option<u16>,
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub canary_response_time: Duration,
+    /// The first difference found; None if the two matched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// How far the server's clock was from ours, going by its `Date` header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "date", rename_all = "snake_case")]
+pub enum ClockSkew {
+    /// Server time minus ours in whole seconds; positive if the server is ahead.
+    Measured { skew_s: i64 },
+    /// The response had no `Date` header.
+    Missing,
+    /// The `Date` header wasn't an HTTP date.
+    Unparsable { value: String },
+}
+
+/// The encodings `--require-compression` asks for.
+pub const ACCEPT_COMPRESSED: &str = "gzip, br";
+
+/// How a response body came over the wire, with `--require-compression`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Compression {
+    /// The `Content-Encoding`, lowercased; `identity` if there was none.
+    pub encoding: String,
+    /// Body bytes as sent and once decoded; unset without a body, e.g. for HEAD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wire_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded_bytes: Option<u64>,
+    /// `decoded_bytes / wire_bytes`: 4.0 means the body shrank to a quarter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ratio: Option<f64>,
+}
+
+/// Decode `resp`'s body in place as its `Content-Encoding` says, returning
+/// how it was sent.
+fn decompress(resp: &mut CheckResponse, req: &CheckRequest) -> Result<Compression, TransportError> {
+    let encoding = resp
+        .headers
+        .get("content-encoding")
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .unwrap_or("identity")
+        .to_ascii_lowercase();
+    let wire_bytes = resp.body.as_ref().map(|body| body.len() as u64);
+    if encoding != "identity" {
+        if let Some(wire) = resp.body.take() {
+            resp.body = Some(decode_body(&encoding, &wire, resp.status, req)?);
+        }
+    }
+    let decoded_bytes = resp.body.as_ref().map(|body| body.len() as u64);
+    let ratio = match (wire_bytes, decoded_bytes) {
+        (Some(wire), Some(decoded)) if wire > 0 => Some(decoded as f64 / wire as f64),
+        _ => None,
+    };
+    Ok(Compression { encoding, wire_bytes, decoded_bytes, ratio })
+}
+
+/// Compare a `Date` header with our clock at `local_ms` (Unix milliseconds).
+/// `Date` has whole seconds, so ours is cut to the second the same way.
+pub fn clock_skew(date: Option<&str>, local_ms: i64) -> ClockSkew {
+    let Some(date) = date else { return ClockSkew::Missing };
+    match DateTime::parse_from_rfc2822(date.trim()) {
+        Ok(server) => ClockSkew::Measured { skew_s: server.timestamp() - local_ms.div_euclid(1000) },
+        Err(_) => ClockSkew::Unparsable { value: date.to_string() },
+    }
+}
+
+/// The stats key of `url` compared against `canary`, kept apart from `url`'s own.
+pub fn compare_key(url: &str, canary: &str) -> String {
+    format!("{url} vs {canary}")
+}
+
+/// Split a `--compare URL=CANARY` pair at the first `=` that starts a
+/// supported `scheme://`.
+pub fn parse_compare(s: &str) -> Result<(String, String), String> {
+    let split = s.match_indices('=').map(|(i, _)| i).find(|&i| {
+        let rest = &s[i + 1..];
+        SUPPORTED_SCHEMES.iter().any(|scheme| rest.strip_prefix(scheme).is_some_and(|r| r.starts_with("://")))
+    });
+    match split {
+        Some(i) if i > 0 => Ok((s[..i].trim().to_string(), s[i + 1..].trim().to_string())),
+        _ => Err(format!("invalid --compare '{s}' (expected URL=CANARY, the canary with its scheme)")),
+    }
+}
+
+/// Parse a `--compare-ignore-regex` pattern.
+pub fn parse_compare_ignore(s: &str) -> Result<regex::Regex, String> {
+    regex::Regex::new(s).map_err(|e| format!("invalid --compare-ignore-regex '{s}': {e}"))
+}
+
+/// Parse a `--redact-pattern` pattern.
+pub fn parse_redact_pattern(s: &str) -> Result<regex::Regex, String> {
+    regex::Regex::new(s).map_err(|e| format!("invalid --redact-pattern '{s}': {e}"))
+}
+
+/// What counts as "up" for uptime accounting, independent of what is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub enum UpIf {
+    /// The request completed and every validation passed.
+    #[default]
+    #[serde(rename = "valid")]
+    Valid,
+    /// Any HTTP response at all, even a 500: the host is reachable.
+    #[serde(rename = "any-response")]
+    AnyResponse,
+    /// A 2xx status, ignoring header/body validations.
+    #[serde(rename = "2xx")]
+    Status2xx,
+    /// Any status below 400 (2xx or 3xx), ignoring header/body validations.
+    #[serde(rename = "lt-400")]
+    Lt400,
+}
+impl UpIf {
+    /// Parse a `--up-if` preset name.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "valid" => Ok(UpIf::Valid),
+            "any-response" => Ok(UpIf::AnyResponse),
+            "2xx" => Ok(UpIf::Status2xx),
+            "lt-400" => Ok(UpIf::Lt400),
+            other => Err(format!(
+                "unknown --up-if preset '{other}' (expected valid, any-response, 2xx, lt-400)"
+            )),
+        }
+    }
+    /// The name `parse` accepts.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpIf::Valid => "valid",
+            UpIf::AnyResponse => "any-response",
+            UpIf::Status2xx => "2xx",
+            UpIf::Lt400 => "lt-400",
+        }
+    }
+    /// Whether `s` counts as up under this preset.
+    pub fn is_up(&self, s: &WebsiteStatus) -> bool {
+        match self {
+            UpIf::Valid => s.ok(),
+            UpIf::AnyResponse => s.http_code().is_some(),
+            UpIf::Status2xx => s.http_code().is_some_and(|c| (200..300).contains(&c)),
+            UpIf::Lt400 => s.http_code().is_some_and(|c| c < 400),
+        }
+    }
+}
+
+/// Time zone for timestamps shown to people; serialized results always stay UTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DisplayZone {
+    #[default]
+    Utc,
+    Local,
+    Named(chrono_tz::Tz),
+}
+impl DisplayZone {
+    fn parse(s: &str) -> Result<Self, String> {
+        if s.eq_ignore_ascii_case("utc") {
+            Ok(DisplayZone::Utc)
+        } else if s.eq_ignore_ascii_case("local") {
+            Ok(DisplayZone::Local)
+        } else {
+            s.parse().map(DisplayZone::Named).map_err(|_| {
+                format!("unknown --timezone '{s}' (expected local, utc, or an IANA name like Europe/Berlin)")
+            })
+        }
+    }
+}
+impl fmt::Display for DisplayZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayZone::Utc => write!(f, "utc"),
+            DisplayZone::Local => write!(f, "local"),
+            DisplayZone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+/// How the summary and dashboard render timestamps (`--timezone`, `--time-format`).
+#[serde_as]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimeDisplay {
+    #[serde_as(as = "DisplayFromStr")]
+    pub timezone: DisplayZone,
+    pub time_format: Option<String>, // strftime; None => RFC3339
+}
+impl TimeDisplay {
+    /// Build from `--timezone`/`--time-format` values, rejecting unknown zones and bad specifiers.
+    pub fn parse(timezone: Option<&str>, time_format: Option<&str>) -> Result<Self, String> {
+        let timezone = timezone.map(DisplayZone::parse).transpose()?.unwrap_or_default();
+        if let Some(f) = time_format {
+            // chrono only reports a bad specifier when formatting, which would panic mid-run
+            if StrftimeItems::new(f).any(|item| item == Item::Error) {
+                return Err(format!("invalid --time-format '{f}'"));
+            }
+        }
+        Ok(TimeDisplay { timezone, time_format: time_format.map(str::to_string) })
+    }
+
+    /// Render `t` with `--time-format`, or RFC3339 when none was given.
+    pub fn format(&self, t: DateTime<Utc>) -> String {
+        self.render(t, None)
+    }
+
+    /// Like `format`, but with a custom fallback for compact views.
+    pub fn format_or(&self, t: DateTime<Utc>, fallback: &str) -> String {
+        self.render(t, Some(fallback))
+    }
+
+    fn render(&self, t: DateTime<Utc>, fallback: Option<&str>) -> String {
+        let pattern = self.time_format.as_deref().or(fallback);
+        match self.timezone {
+            DisplayZone::Utc => render_in(t, pattern),
+            DisplayZone::Local => render_in(t.with_timezone(&Local), pattern),
+            DisplayZone::Named(tz) => render_in(t.with_timezone(&tz), pattern),
+        }
+    }
+}
+
+fn render_in<Tz: TimeZone>(t: DateTime<Tz>, pattern: Option<&str>) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    match pattern {
+        Some(p) => t.format(p).to_string(),
+        None => t.to_rfc3339_opts(SecondsFormat::Secs, true),
+    }
+}
+
+/// Row order for the stats summary. Ties always fall back to URL (or group) order.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    /// The order URLs were given in (first seen, for `report`).
+    #[default]
+    Input,
+    Url,
+    Uptime,
+    Avg,
+    Checks,
+}
+impl SortBy {
+    /// Parse a `--sort-by` key.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "input" => Ok(SortBy::Input),
+            "url" => Ok(SortBy::Url),
+            "uptime" => Ok(SortBy::Uptime),
+            "avg" => Ok(SortBy::Avg),
+            "checks" => Ok(SortBy::Checks),
+            other => Err(format!("unknown sort key '{other}' (expected input, url, uptime, avg, checks)")),
+        }
+    }
+    /// The name `parse` accepts.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortBy::Input => "input",
+            SortBy::Url => "url",
+            SortBy::Uptime => "uptime",
+            SortBy::Avg => "avg",
+            SortBy::Checks => "checks",
+        }
+    }
+}
+
+/// How the stats summary is laid out: grouping, row order, and timestamps.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SummaryStyle {
+    /// Aggregate per value of this label instead of per URL.
+    pub group_by: Option<String>,
+    pub sort_by: SortBy,
+    pub sort_desc: bool,
+    /// Group uptime is the mean of its URLs' uptimes, not successes over checks.
+    pub equal_weight: bool,
+    /// Draw each URL's sparkline in ASCII rather than Unicode blocks.
+    pub ascii: bool,
+    #[serde(flatten)]
+    pub times: TimeDisplay,
+}
+impl SummaryStyle {
+    /// Order two summary rows, keyed by URL or `label=value`.
+    fn compare(&self, (ka, a): (&str, &UrlStats), (kb, b): (&str, &UrlStats)) -> std::cmp::Ordering {
+        let primary = match self.sort_by {
+            SortBy::Input => a.order.cmp(&b.order),
+            SortBy::Url => ka.cmp(kb),
+            SortBy::Uptime => a.uptime().total_cmp(&b.uptime()),
+            SortBy::Avg => a.avg_ms().total_cmp(&b.avg_ms()),
+            SortBy::Checks => a.checks.cmp(&b.checks),
+        };
+        let primary = if self.sort_desc { primary.reverse() } else { primary };
+        // Fully deterministic, so consecutive summaries can be diffed
+        primary.then_with(|| ka.cmp(kb))
+    }
+}
+
+/// Worker threads used when none are requested.
+pub const DEFAULT_THREADS: usize = 50;
+/// Per-request timeout used when none is given.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Retries per URL used when none are given.
+pub const DEFAULT_RETRIES: usize = 1;
+/// Redirects (HTTP and meta-refresh) followed when no limit is given.
+pub const DEFAULT_MAX_REDIRECTS: u32 = 2;
+
+/// The effective configuration of a run. Build one with `Config::builder()`.
+#[serde_as]
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    pub worker_threads: usize,
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    #[serde(rename = "timeout_ms")]
+    pub timeout: Duration,
+    /// None => run once; Some(d) => repeat every d.
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    #[serde(rename = "period_ms")]
+    pub period: Option<Duration>,
+    /// Max total retries across all URLs per round.
+    pub retry_budget: Option<usize>,
+    pub up_if: UpIf,
+    /// Start periodic rounds on schedule even if the previous one is still draining.
+    pub overlap: bool,
+    /// Failures inside these windows are flagged rather than counted, on top of each URL's own.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub maintenance: Vec<MaintenanceWindow>,
+    /// Spread queuing the first round's checks over this window instead of queuing them all at once.
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    #[serde(rename = "stagger_ms")]
+    pub stagger: Option<Duration>,
+    /// A random pause each worker takes after every check; None goes straight on.
+    pub think_time: Option<ThinkTime>,
+    /// Abandon the run at the first failed check.
+    pub fail_fast: bool,
+    /// Emit each round's results in URL order once it completes, not as they arrive.
+    pub ordered: bool,
+    /// Stop once every URL has been checked this many times.
+    pub checks_per_url: Option<usize>,
+    /// Reuse resolved host addresses for this long, across checks and rounds; None resolves every connection.
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    #[serde(rename = "dns_cache_ttl_ms")]
+    pub dns_cache_ttl: Option<Duration>,
+    /// Resolve hosts by asking this DNS server instead of the system resolver.
+    pub dns_server: Option<SocketAddr>,
+    /// Host lookups allowed at once; None is unlimited.
+    pub max_dns_concurrency: Option<usize>,
+    /// Reuse connections between checks; off, every check connects afresh.
+    pub keepalive: bool,
+    /// Record each https server's certificate in its results.
+    pub cert_info: bool,
+    /// Send every request over this Unix socket instead of TCP.
+    pub unix_socket: Option<PathBuf>,
+    /// `Host` header for `http+unix://` targets.
+    pub unix_host: String,
+    /// Record every request in an HTTP Archive saved here (`--har`).
+    pub har: Option<PathBuf>,
+    /// Send every request with a token from this OAuth2 endpoint (`--oauth-token-url`).
+    pub oauth: Option<OAuthSettings>,
+    /// Send a non-idempotent request (the `oauth` token POST) again after it fails.
+    pub retry_unsafe: bool,
+    pub check: CheckSettings,
+    #[serde(flatten)]
+    pub summary: SummaryStyle,
+    pub urls: Vec<UrlEntry>,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            worker_threads: DEFAULT_THREADS,
+            timeout: DEFAULT_TIMEOUT,
+            period: None,
+            retry_budget: None,
+            up_if: UpIf::default(),
+            overlap: false,
+            maintenance: Vec::new(),
+            stagger: None,
+            think_time: None,
+            fail_fast: false,
+            ordered: false,
+            checks_per_url: None,
+            dns_cache_ttl: Some(DEFAULT_DNS_CACHE_TTL),
+            dns_server: None,
+            max_dns_concurrency: None,
+            keepalive: true,
+            cert_info: false,
+            unix_socket: None,
+            unix_host: DEFAULT_UNIX_HOST.to_string(),
+            har: None,
+            oauth: None,
+            retry_unsafe: true,
+            check: CheckSettings {
+                max_retries: DEFAULT_RETRIES,
+                max_redirects: DEFAULT_MAX_REDIRECTS,
+                max_body_bytes: Some(DEFAULT_MAX_BODY_BYTES),
+                ..CheckSettings::default()
+            },
+            summary: SummaryStyle::default(),
+            urls: Vec::new(),
+        }
+    }
+}
+
+/// Extra time a round gets on top of its worst case, for scheduling and I/O.
+const ROUND_SLACK: Duration = Duration::from_secs(1);
+
+impl Config {
+    /// Start from the same defaults the CLI uses.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Pretty JSON for `--dry-run --format json`, carrying `schema_version`
+    /// first like every other JSON object sitecheck emits.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        #[derive(Serialize)]
+        struct Versioned<'a> {
+            schema_version: u32,
+            #[serde(flatten)]
+            config: &'a Config,
+        }
+        serde_json::to_string_pretty(&Versioned { schema_version: SCHEMA_VERSION, config: self })
+    }
+
+    /// How long a round of `urls` may run before missing results are given up
+    /// on: every check spending all its retries, each at its full timeout for
+    /// both connecting and reading, run `worker_threads` at a time, plus slack.
+    /// Generous on purpose; it only has to stop a lost result hanging a round.
+    pub fn round_deadline(&self, urls: &[UrlEntry]) -> Duration {
+        let worst = urls
+            .iter()
+            .map(|e| {
+                let timeout = e.options.timeout.unwrap_or(self.timeout);
+                let retries = u32::try_from(e.options.retries.unwrap_or(self.check.max_retries)).unwrap_or(u32::MAX);
+                let attempts = retries.saturating_add(1);
+                let backoff = (0..retries as usize)
+                    .map(|attempt| backoff_delay(attempt, &self.check.backoff))
+                    .fold(Duration::ZERO, Duration::saturating_add);
+                let worst = timeout.saturating_mul(2).saturating_mul(attempts).saturating_add(backoff);
+                let worst = self.check.deadline.map_or(worst, |d| worst.min(d));
+                // A comparison checks its canary after the URL
+                if e.compare.is_some() { worst.saturating_mul(2) } else { worst }
+            })
+            .max()
+            .unwrap_or_default();
+        let jobs = urls.len().saturating_mul(self.checks_per_round());
+        let waves = u32::try_from(jobs.div_ceil(self.worker_threads.max(1))).unwrap_or(u32::MAX);
+        // Each check of a wave may be followed by its worker's longest pause
+        let worst = worst.saturating_add(self.think_time.map_or(Duration::ZERO, |t| t.max));
+        worst.saturating_mul(waves).saturating_add(ROUND_SLACK)
+    }
+
+    /// How many times a round checks each URL. A one-shot run with
+    /// `checks_per_url` queues them all at once; otherwise it is one per round.
+    pub fn checks_per_round(&self) -> usize {
+        match (self.period, self.checks_per_url) {
+            (None, Some(n)) => n,
+            _ => 1,
+        }
+    }
+
+    /// The jobs of one round over `urls` as `(seq, entry)`: each URL
+    /// `checks_per_round` times, so the entry is at `seq % urls.len()`.
+    pub fn round_jobs<'a, T>(&self, urls: &'a [T]) -> impl Iterator<Item = (usize, &'a T)> + 'a {
+        (0..urls.len() * self.checks_per_round()).map(move |seq| (seq, &urls[seq % urls.len()]))
+    }
+
+    /// How long after the first round starts its job `i` of `n` is queued:
+    /// evenly spaced over `stagger`, the first at once and all within the window.
+    pub fn stagger_offset(&self, i: usize, n: usize) -> Duration {
+        match self.stagger {
+            Some(window) if n > 1 => window.mul_f64(i as f64 / n as f64),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Flag `status` as a maintenance failure if it is down and was checked
+    /// inside one of the run's or `entry`'s windows, read in `--timezone`.
+    pub fn mark_maintenance(&self, entry: &UrlEntry, status: &mut WebsiteStatus) {
+        if self.up_if.is_up(status) {
+            return;
+        }
+        let zone = self.summary.times.timezone;
+        status.maintenance = self.maintenance.iter().chain(&entry.options.maintenance).any(|w| w.contains(status.timestamp, zone));
+    }
+
+    /// The number of rounds a run is limited to by `checks_per_url`, if any.
+    pub fn max_rounds(&self) -> Option<u64> {
+        let n = self.checks_per_url? as u64;
+        Some(if self.period.is_some() { n } else { 1 })
+    }
+}
+
+/// Builder for `Config`; unset options keep their defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+impl ConfigBuilder {
+    /// Worker threads for `Checker::pool`; at least 1.
+    pub fn threads(mut self, n: usize) -> Self {
+        self.config.worker_threads = n.max(1);
+        self
+    }
+    /// Per-request timeout for connect, read, and write.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+    /// Repeat every `period`; None runs once.
+    pub fn period(mut self, period: Option<Duration>) -> Self {
+        self.config.period = period;
+        self
+    }
+    /// Retries per URL after the first failed attempt.
+    pub fn retries(mut self, n: usize) -> Self {
+        self.config.check.max_retries = n;
+        self
+    }
+    /// Wait between attempts; see `backoff_delay`.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.config.check.backoff = backoff;
+        self
+    }
+    /// Cap on retries across all URLs in one round; None is unlimited.
+    pub fn retry_budget(mut self, budget: Option<usize>) -> Self {
+        self.config.retry_budget = budget;
+        self
+    }
+    /// Redirects to follow, counting meta-refresh hops.
+    pub fn max_redirects(mut self, n: u32) -> Self {
+        self.config.check.max_redirects = n;
+        self
+    }
+    /// Check with HEAD and record the resource's size, falling back to a
+    /// ranged GET for it if `range_fallback`.
+    pub fn head_size_only(mut self, on: bool, range_fallback: bool) -> Self {
+        self.config.check.head_size_only = on;
+        self.config.check.range_fallback = range_fallback;
+        self
+    }
+    /// Measure the server's clock from its `Date` header and fail (or just
+    /// warn, with `warn_only`) when it is more than `max_s` seconds off.
+    pub fn check_clock_skew(mut self, max_s: Option<u64>, warn_only: bool) -> Self {
+        self.config.check.max_clock_skew_s = max_s;
+        self.config.check.clock_skew_warn_only = warn_only;
+        self
+    }
+    /// Ask for a gzip or brotli body, record how it came back, and fail a
+    /// check whose response wasn't compressed.
+    pub fn require_compression(mut self, on: bool) -> Self {
+        self.config.check.require_compression = on;
+        self
+    }
+    /// Check the links on every HTML page that passes, failing it if any are broken.
+    pub fn check_links(mut self, links: Option<LinkSettings>) -> Self {
+        self.config.check.check_links = links;
+        self
+    }
+    /// Keep at most this many hops in each result's `redirect_chain`; None keeps all.
+    pub fn record_redirects(mut self, n: Option<usize>) -> Self {
+        self.config.check.record_redirects = n;
+        self
+    }
+    /// Longest body a check reads before failing; None is unlimited.
+    pub fn max_body_bytes(mut self, max: Option<u64>) -> Self {
+        self.config.check.max_body_bytes = max;
+        self
+    }
+    /// Wall-clock cap on each check, retries included; None is uncapped.
+    pub fn deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.config.check.deadline = deadline;
+        self
+    }
+    /// Follow `<meta http-equiv="refresh">` on text/html pages.
+    pub fn follow_meta_refresh(mut self, on: bool) -> Self {
+        self.config.check.follow_meta_refresh = on;
+        self
+    }
+    /// Require the final status to be in `expected`; None accepts any non-error status.
+    pub fn expect_status(mut self, expected: Option<StatusSet>) -> Self {
+        self.config.check.expect_status = expected;
+        self
+    }
+    /// Require a response header `name` with exactly `value`. Repeatable.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.check.headers.push((name.into(), value.into()));
+        self
+    }
+    /// Record response header `name` in every result. Repeatable.
+    pub fn capture_header(mut self, name: impl Into<String>) -> Self {
+        self.config.check.capture_headers.push(name.into());
+        self
+    }
+    /// Keep the first `n` bytes of the body on failed results; None keeps none.
+    pub fn capture_body_on_failure(mut self, n: Option<usize>) -> Self {
+        self.config.check.capture_body_on_failure = n;
+        self
+    }
+    /// Save failed checks' responses under a directory; None saves nothing.
+    pub fn save_failures(mut self, save: Option<SaveFailures>) -> Self {
+        self.config.check.save_failures = save;
+        self
+    }
+    /// Log the (pretty-printed, redacted) body of every check that failed with a response.
+    pub fn show_body_on_fail(mut self, on: bool) -> Self {
+        self.config.check.show_body_on_fail = on;
+        self
+    }
+    /// Replace matches of `re` with `[redacted]` in bodies shown on failure. Repeatable.
+    pub fn redact_pattern(mut self, re: regex::Regex) -> Self {
+        self.config.check.redact_patterns.push(re);
+        self
+    }
+    /// Strip matches of `re` from both bodies of each `--compare` pair. Repeatable.
+    pub fn compare_ignore(mut self, re: regex::Regex) -> Self {
+        self.config.check.compare_ignore.push(re);
+        self
+    }
+    /// Require the response body to contain `text`.
+    pub fn contains(mut self, text: Option<String>) -> Self {
+        self.config.check.contains = text;
+        self
+    }
+    /// What counts as up for uptime.
+    pub fn up_if(mut self, up_if: UpIf) -> Self {
+        self.config.up_if = up_if;
+        self
+    }
+    /// Start periodic rounds on schedule even while earlier ones drain.
+    pub fn overlap(mut self, on: bool) -> Self {
+        self.config.overlap = on;
+        self
+    }
+    /// Stop at the first URL that is down.
+    pub fn fail_fast(mut self, on: bool) -> Self {
+        self.config.fail_fast = on;
+        self
+    }
+    /// Emit results in URL order per round instead of as they complete.
+    pub fn ordered(mut self, on: bool) -> Self {
+        self.config.ordered = on;
+        self
+    }
+    /// Check each URL exactly this many times, then stop; None is unlimited.
+    pub fn checks_per_url(mut self, n: Option<usize>) -> Self {
+        self.config.checks_per_url = n;
+        self
+    }
+    /// How long resolved addresses are reused; None turns the DNS cache off.
+    pub fn dns_cache_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.config.dns_cache_ttl = ttl;
+        self
+    }
+    /// Ask this DNS server for every host, through the cache if there is one.
+    pub fn dns_server(mut self, addr: Option<SocketAddr>) -> Self {
+        self.config.dns_server = addr;
+        self
+    }
+    /// Run at most `n` host lookups at once; None is unlimited.
+    pub fn max_dns_concurrency(mut self, n: Option<usize>) -> Self {
+        self.config.max_dns_concurrency = n;
+        self
+    }
+    /// Maintenance windows for every URL.
+    pub fn maintenance(mut self, windows: Vec<MaintenanceWindow>) -> Self {
+        self.config.maintenance = windows;
+        self
+    }
+    /// Spread the first round's checks over `window`; None queues them all at once.
+    pub fn stagger(mut self, window: Option<Duration>) -> Self {
+        self.config.stagger = window;
+        self
+    }
+    /// Pause each worker for a random time after every check; None doesn't.
+    pub fn think_time(mut self, think_time: Option<ThinkTime>) -> Self {
+        self.config.think_time = think_time;
+        self
+    }
+    /// Reuse connections between checks (the default), or open one per check.
+    pub fn keepalive(mut self, on: bool) -> Self {
+        self.config.keepalive = on;
+        self
+    }
+    /// Record the certificate of every https server in `certificate`.
+    pub fn cert_info(mut self, on: bool) -> Self {
+        self.config.cert_info = on;
+        self
+    }
+    /// Check over this Unix socket; the URLs' hosts only name the `Host` header.
+    pub fn unix_socket(mut self, path: Option<PathBuf>) -> Self {
+        self.config.unix_socket = path;
+        self
+    }
+    /// The `Host` header `http+unix://` targets are checked with.
+    pub fn unix_host(mut self, host: impl Into<String>) -> Self {
+        self.config.unix_host = host.into();
+        self
+    }
+    /// Record every request and its response in an HTTP Archive, for `Checker::har` to save to `path`.
+    pub fn har(mut self, path: Option<PathBuf>) -> Self {
+        self.config.har = path;
+        self
+    }
+    /// Get tokens with the client_credentials grant and send every request with one.
+    pub fn oauth(mut self, oauth: Option<OAuthSettings>) -> Self {
+        self.config.oauth = oauth;
+        self
+    }
+    /// Send a failed token request again on a later round (the default), or
+    /// leave it failed: it is a POST, so a retry may not be harmless.
+    pub fn retry_unsafe(mut self, on: bool) -> Self {
+        self.config.retry_unsafe = on;
+        self
+    }
+    /// How the stats summary is laid out.
+    pub fn summary(mut self, style: SummaryStyle) -> Self {
+        self.config.summary = style;
+        self
+    }
+    /// The URLs a run checks.
+    pub fn urls(mut self, urls: Vec<UrlEntry>) -> Self {
+        self.config.urls = urls;
+        self
+    }
+    /// Finish building.
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+/// What each check does beyond the bare request: validations, retries, redirects.
+#[serde_as]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CheckSettings {
+    /// Final status must be one of these if set; error statuses in it count as valid.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub expect_status: Option<StatusSet>,
+    /// Header validations: (name, expected value). Names match case-insensitively.
+    pub headers: Vec<(String, String)>,
+    /// Body must contain this substring if set.
+    pub contains: Option<String>,
+    pub max_retries: usize,
+    /// Wait before each retry.
+    pub backoff: Backoff,
+    /// Applies to HTTP redirects and meta-refresh hops.
+    pub max_redirects: u32,
+    /// Follow `<meta http-equiv="refresh">` on text/html pages.
+    pub follow_meta_refresh: bool,
+    /// Send HEAD instead of GET and record the `Content-Length`; nothing that
+    /// needs the body can pass.
+    pub head_size_only: bool,
+    /// With `head_size_only`, ask for the first byte with a ranged GET when
+    /// the HEAD response has no `Content-Length`.
+    pub range_fallback: bool,
+    /// Redirect hops kept in `redirect_chain`; longer chains are still
+    /// followed, up to `max_redirects`. None keeps every hop.
+    pub record_redirects: Option<usize>,
+    /// Bodies longer than this fail the check; None reads any size.
+    pub max_body_bytes: Option<u64>,
+    /// Wall-clock cap on a whole check: every attempt, backoff, and body read.
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    #[serde(rename = "deadline_ms")]
+    pub deadline: Option<Duration>,
+    /// Response headers recorded in `captured_headers`, whatever the outcome.
+    pub capture_headers: Vec<String>,
+    /// Bytes of the body kept as `body_snippet` when a check fails. Bodies are
+    /// then always read, up to `max_body_bytes`.
+    pub capture_body_on_failure: Option<usize>,
+    /// Save the response of every check that got one and failed. Bodies are
+    /// then always read whole, up to `max_body_bytes`.
+    pub save_failures: Option<SaveFailures>,
+    /// Log the body of every check that got a response and failed, cut to
+    /// `max_body_bytes`. Bodies are then always read whole.
+    pub show_body_on_fail: bool,
+    /// Replaced with `REDACTED` in bodies shown by `show_body_on_fail`.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub redact_patterns: Vec<regex::Regex>,
+    /// Cut from both bodies of a `--compare` pair before they are compared.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub compare_ignore: Vec<regex::Regex>,
+    /// Fail a check whose server clock is more than this many seconds off ours.
+    pub max_clock_skew_s: Option<u64>,
+    /// With `max_clock_skew_s`, log a warning instead of failing.
+    pub clock_skew_warn_only: bool,
+    /// Send `Accept-Encoding: gzip, br` and fail a check whose response came
+    /// back uncompressed. Bodies are then always read whole, up to `max_body_bytes`.
+    pub require_compression: bool,
+    /// Check the links on each HTML page that passed (`--check-links`). Bodies
+    /// are then always read whole, up to `max_body_bytes`.
+    pub check_links: Option<LinkSettings>,
+}
+
+/// Wait before retry `attempt + 1`, as `base * multiplier^attempt` capped at
+/// `max`. Without a multiplier the wait grows linearly: base, 2x base, ...
+#[serde_as]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Backoff {
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    #[serde(rename = "base_ms")]
+    pub base: Duration,
+    pub multiplier: Option<f64>,
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    #[serde(rename = "max_ms")]
+    pub max: Option<Duration>,
+}
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff { base: DEFAULT_BACKOFF_BASE, multiplier: None, max: None }
+    }
+}
+
+/// First retry's wait, unless `--backoff-base-ms` says otherwise.
+pub const DEFAULT_BACKOFF_BASE: Duration = Duration::from_millis(200);
+
+/// How long to wait after failed attempt `attempt` (0 for the first) before retrying.
+pub fn backoff_delay(attempt: usize, cfg: &Backoff) -> Duration {
+    let delay = match cfg.multiplier {
+        None => cfg.base.saturating_mul(u32::try_from(attempt + 1).unwrap_or(u32::MAX)),
+        Some(m) => {
+            let exp = i32::try_from(attempt).unwrap_or(i32::MAX);
+            // Grows past any Duration fast; saturate rather than panic
+            Duration::try_from_secs_f64(cfg.base.as_secs_f64() * m.powi(exp)).unwrap_or(Duration::MAX)
+        }
+    };
+    cfg.max.map_or(delay, |max| delay.min(max))
+}
+
+/// Shared cap on the number of retries spent across all URLs in one round.
+#[derive(Debug)]
+pub struct RetryBudget {
+    limit: usize,
+    used: AtomicUsize,
+}
+impl RetryBudget {
+    /// A budget allowing `limit` retries in total.
+    pub fn new(limit: usize) -> Self {
+        RetryBudget { limit, used: AtomicUsize::new(0) }
+    }
+    /// Take one retry from the budget; returns false once it is exhausted.
+    pub fn try_acquire(&self) -> bool {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |u| {
+                if u < self.limit { Some(u + 1) } else { None }
+            })
+            .is_ok()
+    }
+    /// Retries taken so far.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+    /// Retries allowed in total.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+/// `--think-time-ms MIN:MAX`: a pause between `min` and `max`, picked anew
+/// after every check, before a worker takes its next job.
+#[serde_as]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ThinkTime {
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    #[serde(rename = "min_ms")]
+    pub min: Duration,
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    #[serde(rename = "max_ms")]
+    pub max: Duration,
+    /// Seeds the workers' generators, so the same pauses come up every run;
+    /// None seeds them from the OS.
+    pub seed: Option<u64>,
+}
+impl ThinkTime {
+    /// Parse `MIN:MAX` in milliseconds, `MIN` at most `MAX`; a single number pauses exactly that long.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let ms = |v: &str| v.trim().parse::<u64>().map_err(|e| format!("invalid think time '{s}': {e}"));
+        let (min, max) = match s.split_once(':') {
+            Some((min, max)) => (ms(min)?, ms(max)?),
+            None => (ms(s)?, ms(s)?),
+        };
+        if min > max {
+            return Err(format!("invalid think time '{s}': {min}ms is more than {max}ms"));
+        }
+        Ok(ThinkTime { min: Duration::from_millis(min), max: Duration::from_millis(max), seed: None })
+    }
+
+    /// The generator for worker `id`. Each worker gets its own, so seeded
+    /// workers don't all pause in step.
+    fn rng(&self, id: usize) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(id as u64)),
+            None => StdRng::from_os_rng(),
+        }
+    }
+
+    /// The next pause.
+    fn pick(&self, rng: &mut StdRng) -> Duration {
+        let (min, max) = (self.min.as_millis() as u64, self.max.as_millis() as u64);
+        Duration::from_millis(rng.random_range(min..=max))
+    }
+}
+
+/// Longest a worker sleeps between looks at whether the pool is stopping.
+const THINK_SLICE: Duration = Duration::from_millis(50);
+
+/// Sleep for `pause`, in slices, cut short once `stopping` is set.
+fn think(pause: Duration, stopping: &AtomicBool) {
+    let until = Instant::now() + pause;
+    while !stopping.load(Ordering::SeqCst) {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(THINK_SLICE));
+    }
+}
+
+/// One unit of work for a worker, tagged with its round and its place in it.
+struct Job {
+    round: u64,
+    seq: usize,
+    generation: u64, // Backlog generation it was queued in
+    entry: Arc<UrlEntry>, // Shared with the caller's list, so queuing a round copies no URLs
+    budget: Option<Arc<RetryBudget>>,
+}
+
+/// Count of jobs queued but not yet taken by a worker. Discarding bumps the
+/// generation, so workers drop the jobs queued before it instead of running
+/// them; only the workers ever lock the job receiver.
+#[derive(Debug, Default)]
+struct Backlog {
+    generation: u64,
+    queued: usize,
+}
+impl Backlog {
+    /// Claim a job a worker just received; false if it has been discarded.
+    fn take(&mut self, generation: u64) -> bool {
+        if generation != self.generation {
+            return false;
+        }
+        self.queued -= 1;
+        true
+    }
+    /// Drop every job no worker has taken yet; returns how many there were.
+    fn discard(&mut self) -> usize {
+        self.generation += 1;
+        std::mem::take(&mut self.queued)
+    }
+}
+
+/// A worker's answer. The round id lets results land in the right round even
+/// when rounds overlap; `seq` is the number the job was submitted with.
+#[derive(Debug)]
+pub struct JobResult {
+    pub round: u64,
+    pub seq: usize,
+    pub status: WebsiteStatus,
+}
+
+/// The record for a check whose result never came back, so it still counts
+/// as a (failed) check in the stats.
+pub fn missing_result(entry: &UrlEntry, detail: impl Into<String>) -> WebsiteStatus {
+    WebsiteStatus {
+        schema_version: SCHEMA_VERSION,
+        url: entry.url.clone(),
+        status: Err(CheckError::NoResult { detail: detail.into() }),
+        attempts: 0,
+        attempt_errors: Vec::new(),
+        response_time: Duration::ZERO,
+        timestamp: Utc::now(),
+        http_status: None,
+        redirect_chain: Vec::new(),
+        content_length: None,
+        maintenance: false,
+        captured_headers: CapturedHeaders::new(),
+        body_snippet: None,
+        saved_body: None,
+        comparison: None,
+        clock_skew: None,
+        compression: None,
+        certificate: None,
+        links: None,
+        labels: entry.labels.clone(),
+    }
+}
+
+#[cfg(test)]
+impl WebsiteStatus {
+    /// A finished check of `url` for tests: one 42 ms attempt, with the HTTP
+    /// status of an `Ok` result and defaults for everything else.
+    pub(crate) fn sample(url: &str, status: Result<u16, CheckError>) -> Self {
+        WebsiteStatus {
+            http_status: status.as_ref().ok().copied(),
+            status,
+            attempts: 1,
+            response_time: Duration::from_millis(42),
+            ..missing_result(&UrlEntry::new(url, UrlSource::Arg(1)), "")
+        }
+    }
+}
+
+/// Holds a round's results back until it completes, then hands them out in
+/// URL order. Results given up on at the deadline slot in like any other.
+#[derive(Debug, Default)]
+pub struct InOrder {
+    held: BTreeMap<usize, WebsiteStatus>,
+}
+
+impl InOrder {
+    /// Keep the result for position `seq` in the URL list.
+    pub fn hold(&mut self, seq: usize, status: WebsiteStatus) {
+        self.held.insert(seq, status);
+    }
+
+    /// Everything held so far, in URL order.
+    pub fn release(&mut self) -> impl Iterator<Item = WebsiteStatus> {
+        std::mem::take(&mut self.held).into_values()
+    }
+}
+
+/// Where a URL came from, so startup errors can point at the offending input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UrlSource {
+    File { path: PathBuf, line: usize },
+    Arg(usize),
+    Sitemap { sitemap: String, index: usize },
+    Compare(usize),
+}
+impl fmt::Display for UrlSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlSource::File { path, line } => write!(f, "{}:{}", path.display(), line),
+            UrlSource::Arg(pos) => write!(f, "argument #{}", pos),
+            UrlSource::Sitemap { sitemap, index } => write!(f, "{} <loc> #{}", sitemap, index),
+            UrlSource::Compare(pos) => write!(f, "--compare #{}", pos),
+        }
+    }
+}
+
+/// A URL to check, with where it came from, its labels, and any per-URL overrides.
+#[serde_as]
+#[derive(Debug, Clone, Serialize)]
+pub struct UrlEntry {
+    pub url: String,
+    #[serde_as(as = "DisplayFromStr")]
+    pub source: UrlSource,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: Labels,
+    #[serde(flatten)]
+    pub options: UrlOptions,
+    /// With `--compare`, the canary fetched after `url` and compared with it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compare: Option<String>,
+}
+impl UrlEntry {
+    /// An entry with no labels and no overrides.
+    pub fn new(url: impl Into<String>, source: UrlSource) -> Self {
+        UrlEntry { url: url.into(), source, labels: Labels::new(), options: UrlOptions::default(), compare: None }
+    }
+    /// The key of this entry's stats: its URL, or the pair it compares.
+    pub fn stats_key(&self) -> String {
+        self.compare.as_deref().map_or_else(|| self.url.clone(), |canary| compare_key(&self.url, canary))
+    }
+}
+
+/// Per-URL overrides from a `;timeout=DURATION;retries=N;maintenance=WINDOW` suffix.
+#[serde_as]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UrlOptions {
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    #[serde(rename = "timeout_ms", skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<usize>,
+    /// Windows for this URL alone, added to the run's.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub maintenance: Vec<MaintenanceWindow>,
+}
+
+/// URL schemes the checker knows how to probe.
+pub const SUPPORTED_SCHEMES: &[&str] = &["http", "https"];
+/// Start of a URL checked over the Unix socket it names, e.g.
+/// `http+unix:///run/app.sock:/healthz`.
+pub const UNIX_URL_PREFIX: &str = "http+unix://";
+/// `Host` header sent to `http+unix://` targets unless `Config::unix_host` says otherwise.
+pub const DEFAULT_UNIX_HOST: &str = "localhost";
+
+/// Distinct failure kinds kept per URL; further kinds are counted as "other".
+pub const MAX_FAILURE_KINDS: usize = 32;
+/// Heap a URL's stats grow to at most, however many checks are recorded:
+/// every per-URL collection is capped, so long runs stay flat.
+pub const STATS_BYTES_PER_URL: usize = 8 * 1024;
+/// Response times kept per URL for the summary's sparkline.
+pub const SPARKLINE_CHECKS: usize = 20;
+/// Sparkline glyphs, lowest first.
+const SPARK_UNICODE: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const SPARK_ASCII: [char; 8] = ['_', '.', '-', '~', '=', '+', '*', '#'];
+
+/// Which of the 8 sparkline levels `ms` falls in, scaled so `max` is the top.
+pub fn spark_level(ms: u64, max: u64) -> usize {
+    (ms.min(max) * 7 / max.max(1)) as usize
+}
+
+/// Render response times as a one-line bar chart scaled to the largest value,
+/// in ASCII where the terminal can't show the Unicode blocks.
+pub fn sparkline(values: impl IntoIterator<Item = u64> + Clone, ascii: bool) -> String {
+    let glyphs = if ascii { SPARK_ASCII } else { SPARK_UNICODE };
+    let max = values.clone().into_iter().max().unwrap_or(0);
+    values.into_iter().map(|ms| glyphs[spark_level(ms, max)]).collect()
+}
+
+/// Running totals for one URL (or, merged, for a group of URLs).
+#[derive(Debug, Default, Clone)]
+pub struct UrlStats {
+    pub checks: u64,
+    /// Checks that counted as up under the run's `UpIf`.
+    pub successes: u64,
+    /// Successes that needed more than one attempt.
+    pub retried: u64,
+    /// Checks that were down, by why (see `failure_kind`).
+    pub failures: BTreeMap<String, u64>,
+    pub total_response_ms: u128,
+    /// Down checks inside a maintenance window; not part of `checks`.
+    pub maintenance: u64,
+    pub last_check: Option<DateTime<Utc>>,
+    pub labels: Labels,
+    /// The last `SPARKLINE_CHECKS` response times in ms, oldest first; empty
+    /// on groups, where the URLs' checks don't line up.
+    pub recent_ms: VecDeque<u64>,
+    order: usize, // Position in the input, for the default summary order
+    mean_uptime: Option<f64>, // Set on groups weighing their URLs equally
+}
+impl UrlStats {
+    fn merge(&mut self, other: &UrlStats) {
+        self.checks += other.checks;
+        self.successes += other.successes;
+        self.retried += other.retried;
+        for (kind, n) in &other.failures {
+            self.count_failure(kind.clone(), *n);
+        }
+        self.total_response_ms += other.total_response_ms;
+        self.maintenance += other.maintenance;
+        self.last_check = self.last_check.max(other.last_check);
+        self.order = self.order.min(other.order);
+    }
+    fn count_failure(&mut self, kind: String, n: u64) {
+        let kind = if self.failures.len() >= MAX_FAILURE_KINDS && !self.failures.contains_key(&kind) {
+            "other".to_string()
+        } else {
+            kind
+        };
+        *self.failures.entry(kind).or_default() += n;
+    }
+    fn record(&mut self, ok: bool, rt: Duration, attempts: u32) {
+        self.checks += 1;
+        if ok {
+            self.successes += 1;
+            if attempts > 1 {
+                self.retried += 1;
+            }
+        }
+        self.total_response_ms += rt.as_millis();
+        if self.recent_ms.len() == SPARKLINE_CHECKS {
+            self.recent_ms.pop_front();
+        }
+        self.recent_ms.push_back(rt.as_millis() as u64);
+    }
+    /// Share of checks that were up, in percent. For a group under
+    /// `--equal-weight`, the mean of its URLs' uptimes instead.
+    pub fn uptime(&self) -> f64 {
+        if let Some(mean) = self.mean_uptime {
+            return mean;
+        }
+        if self.checks == 0 { 0.0 } else { (self.successes as f64) * 100.0 / (self.checks as f64) }
+    }
+    /// Share of successes that needed retries, in percent; a rising value is an
+    /// early sign of trouble even while uptime holds.
+    pub fn retried_pct(&self) -> f64 {
+        if self.successes == 0 { 0.0 } else { (self.retried as f64) * 100.0 / (self.successes as f64) }
+    }
+    /// Mean response time in milliseconds.
+    pub fn avg_ms(&self) -> f64 {
+        if self.checks == 0 { 0.0 } else { (self.total_response_ms as f64) / (self.checks as f64) }
+    }
+}
+
+/// Parse a `key=value` label; the key must be non-empty.
+pub fn parse_label(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_string(), v.trim().to_string())),
+        _ => Err(format!("invalid label '{s}' (expected key=value)")),
+    }
+}
+
+/// Split a URL file line into the URL and any trailing `key=value` labels.
+fn parse_url_line(line: &str) -> Result<(String, Labels), String> {
+    let mut tokens = line.split_whitespace();
+    let url = tokens.next().unwrap_or_default().to_string();
+    let labels = tokens.map(parse_label).collect::<Result<Labels, String>>()?;
+    Ok((url, labels))
+}
+
+/// Strip trailing `;timeout=DURATION` / `;retries=N` / `;maintenance=WINDOW` options off a URL. Other
+/// `;`-segments (matrix parameters, session ids) are left as part of the URL.
+pub fn split_url_options(raw: &str) -> Result<(String, UrlOptions), String> {
+    let mut url = raw;
+    let mut opts = UrlOptions::default();
+    while let Some((rest, opt)) = url.rsplit_once(';') {
+        let Some((key, value)) = opt.split_once('=') else { break };
+        match key {
+            "timeout" => {
+                let timeout = parse_duration(value).map_err(|e| format!("timeout: {e}"))?;
+                opts.timeout.get_or_insert(timeout);
+            }
+            "retries" => {
+                let n: usize = value.parse().map_err(|_| format!("invalid retries '{value}'"))?;
+                opts.retries.get_or_insert(n);
+            }
+            // Repeatable; read right to left, so insert at the front to keep their order
+            "maintenance" => opts.maintenance.insert(0, MaintenanceWindow::parse(value)?),
+            _ => break,
+        }
+        url = rest;
+    }
+    Ok((url.to_string(), opts))
+}
+
+/// Parse a duration like `30`, `1500ms`, `5m`, or `1h30m`: one or more
+/// `<number><unit>` parts with units `ms`, `s`, `m`, `h`. A bare number is seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{s}' (expected e.g. 30, 1500ms, 5m, 1m30s)");
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    if s.is_empty() {
+        return Err(invalid());
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let n: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let part = match &rest[..unit_len] {
+            "ms" => Duration::from_millis(n),
+            "s" => Duration::from_secs(n),
+            "m" => Duration::from_secs(n.checked_mul(60).ok_or_else(invalid)?),
+            "h" => Duration::from_secs(n.checked_mul(3600).ok_or_else(invalid)?),
+            _ => return Err(invalid()),
+        };
+        rest = &rest[unit_len..];
+        total = total.checked_add(part).ok_or_else(invalid)?;
+    }
+    Ok(total)
+}
+
+/// Inverse of `parse_duration` for display: `1m30s`, `1s500ms`, `0s`.
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let parts = [(secs / 3600, "h"), (secs / 60 % 60, "m"), (secs % 60, "s"), (u64::from(d.subsec_millis()), "ms")];
+    let out: String = parts.iter().filter(|(n, _)| *n > 0).map(|(n, unit)| format!("{n}{unit}")).collect();
+    if out.is_empty() { "0s".to_string() } else { out }
+}
+
+/// Parse a worker count. Zero is rejected: no worker would ever finish a round.
+pub fn parse_thread_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a backoff multiplier: at least 1, so waits never shrink.
+pub fn parse_backoff_multiplier(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(m) if m.is_finite() && m >= 1.0 => Ok(m),
+        Ok(_) => Err("must be a number of at least 1.0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Default for `--max-threads`: past this, spawning OS threads costs more than it helps.
+pub const DEFAULT_MAX_THREADS: usize = 1024;
+
+/// Workers to actually spawn: `requested` capped at `max`, and in one-shot mode
+/// at the number of checks, since extra workers would sit idle for the whole run.
+pub fn worker_count(requested: usize, max: usize, checks: usize, one_shot: bool) -> usize {
+    let mut n = requested;
+    if n > max {
+        warn!("--threads {n} is above the limit of {max}; using {max} (raise it with --max-threads)");
+        n = max;
+    }
+    if one_shot && n > checks {
+        debug!("only {checks} check(s) to run once; starting {} worker(s) instead of {n}", checks.max(1));
+        n = checks;
+    }
+    n.max(1)
+}
+
+/// Split a `Name: Value` header validation; None without a colon.
+pub fn parse_header(s: &str) -> Option<(String, String)> {
+    if let Some((name, value)) = s.split_once(':') {
+        Some((name.trim().to_string(), value.trim().to_string()))
+    } else {
+        None
+    }
+}
+
+/// File extensions picked up when `-f` names a directory.
+const URL_FILE_EXTENSIONS: &[&str] = &["txt", "urls"];
+
+/// Expand one `-f` argument into the files it names: a directory yields its
+/// `*.txt`/`*.urls` files (non-recursive), a pattern with `*`/`?` in the last
+/// component yields the matching files, anything else is taken as a plain path.
+/// Results are sorted so runs are reproducible.
+pub fn resolve_url_files(spec: &str) -> io::Result<Vec<PathBuf>> {
+    let path = PathBuf::from(spec);
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+    // `pattern` is None in directory mode, where the extension decides
+    let (dir, pattern) = if path.is_dir() {
+        (path.clone(), None)
+    } else if name.contains(['*', '?']) {
+        let dir = match path.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        (dir, Some(name))
+    } else {
+        return Ok(vec![path]);
+    };
+    let keep = |p: &std::path::Path| match &pattern {
+        Some(pat) => p.file_name().is_some_and(|n| glob_match(pat, &n.to_string_lossy())),
+        None => p.extension().is_some_and(|ext| URL_FILE_EXTENSIONS.iter().any(|e| ext == *e)),
+    };
+
+    let mut files = vec![];
+    for entry in std::fs::read_dir(&dir)? {
+        let p = entry?.path();
+        if p.is_file() && keep(&p) {
+            files.push(p);
+        }
+    }
+    if files.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{spec}: no URL files found")));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Read a URL file: one URL per line with optional labels, `#` comments and
+/// blank lines skipped, and `@include other.txt` pulling in another URL file
+/// (relative to this one) at that point. Including a file that is already
+/// being read is an error naming the chain of files.
+pub fn read_urls_from_file(path: &PathBuf) -> io::Result<Vec<UrlEntry>> {
+    let mut entries = Vec::new();
+    read_url_file(path, &mut Vec::new(), &mut entries)?;
+    Ok(entries)
+}
+
+/// `read_urls_from_file` for one file of a chain of includes; `including`
+/// holds the canonical paths of the files currently being read.
+fn read_url_file(path: &PathBuf, including: &mut Vec<PathBuf>, entries: &mut Vec<UrlEntry>) -> io::Result<()> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let canonical = std::fs::canonicalize(path)?;
+    if let Some(start) = including.iter().position(|p| *p == canonical) {
+        let chain: Vec<String> = including[start..].iter().chain([&canonical]).map(|p| p.display().to_string()).collect();
+        return Err(invalid(format!("include cycle: {}", chain.join(" -> "))));
+    }
+    including.push(canonical);
+
+    let reader = io::BufReader::new(std::fs::File::open(path)?);
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let source = UrlSource::File { path: path.clone(), line: idx + 1 };
+        if let Some(target) = line.strip_prefix("@include") {
+            let target = target.trim();
+            if target.is_empty() {
+                return Err(invalid(format!("{source}: @include needs a file name")));
+            }
+            let included = path.parent().unwrap_or(Path::new("")).join(target);
+            if let Err(e) = std::fs::metadata(&included) {
+                return Err(io::Error::new(e.kind(), format!("{source}: cannot include {}: {e}", included.display())));
+            }
+            read_url_file(&included, including, entries)?;
+            continue;
+        }
+        let (url, labels) = parse_url_line(line).map_err(|e| invalid(format!("{source}: {e}")))?;
+        entries.push(UrlEntry { url, source, labels, options: UrlOptions::default(), compare: None });
+    }
+    including.pop();
+    Ok(())
+}
+
+/// What a sitemap document lists: pages, or further sitemaps (an index file).
+#[derive(Debug, PartialEq)]
+enum SitemapKind {
+    UrlSet,
+    Index,
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Extract the `<loc>` values from a sitemap or sitemap index. Errors name the
+/// offending element and its line.
+fn parse_sitemap(xml: &str) -> Result<(SitemapKind, Vec<String>), String> {
+    let kind = if xml.contains("<sitemapindex") {
+        SitemapKind::Index
+    } else if xml.contains("<urlset") {
+        SitemapKind::UrlSet
+    } else {
+        return Err("no <urlset> or <sitemapindex> root element".to_string());
+    };
+    let line_of = |pos: usize| xml[..pos].matches('\n').count() + 1;
+
+    let mut locs = Vec::new();
+    let mut from = 0;
+    while let Some(pos) = xml[from..].find("<loc") {
+        let start = from + pos;
+        // Skip look-alikes such as <location>
+        if !xml[start + 4..].starts_with(['>', ' ', '\t', '\r', '\n']) {
+            from = start + 4;
+            continue;
+        }
+        let open_end = xml[start..].find('>').map(|e| start + e + 1);
+        let close = open_end.and_then(|o| xml[o..].find("</loc>").map(|c| (o, o + c)));
+        let Some((body_start, body_end)) = close else {
+            return Err(format!("unclosed <loc> element at line {}", line_of(start)));
+        };
+        let mut body = xml[body_start..body_end].trim();
+        if let Some(inner) = body.strip_prefix("<![CDATA[").and_then(|b| b.strip_suffix("]]>")) {
+            body = inner.trim();
+        } else if body.contains('<') {
+            return Err(format!("unexpected markup inside <loc> at line {}", line_of(start)));
+        }
+        if body.is_empty() {
+            return Err(format!("empty <loc> element at line {}", line_of(start)));
+        }
+        locs.push(xml_unescape(body));
+        from = body_end + "</loc>".len();
+    }
+    Ok((kind, locs))
+}
+
+/// Fetch a sitemap body, transparently gunzipping `.xml.gz` payloads.
+fn fetch_sitemap(client: &dyn HttpClient, url: &str, timeout: Duration, max_redirects: u32) -> Result<String, String> {
+    use std::io::Read;
+    let req = CheckRequest {
+        url: url.to_string(),
+        method: Method::Get,
+        timeout,
+        max_redirects,
+        read_body: ReadBody::Always,
+        deadline: None,
+        max_body_bytes: Some(MAX_BODY_BYTES),
+        read_until: None,
+        accept_encoding: None,
+        authorization: None,
+        checked_url: None,
+    };
+    let resp = client.execute(req).map_err(|e| e.to_string())?;
+    if resp.status >= 400 {
+        return Err(CheckError::HttpStatus { code: resp.status, detail: format!("{url}: status code {}", resp.status) }.to_string());
+    }
+    let mut raw = resp.body.unwrap_or_default();
+    // Sniff the gzip magic rather than trusting the extension or Content-Type
+    if raw.starts_with(&[0x1f, 0x8b]) {
+        let mut unpacked = Vec::new();
+        flate2::read::GzDecoder::new(&raw[..])
+            .read_to_end(&mut unpacked)
+            .map_err(|e| format!("gzip error: {e}"))?;
+        raw = unpacked;
+    }
+    String::from_utf8(raw).map_err(|_| "sitemap is not valid UTF-8".to_string())
+}
+
+/// Load the page URLs from a sitemap, following a sitemap index one level
+/// deep. At most `limit` URLs are returned.
+pub fn load_sitemap(
+    client: &dyn HttpClient,
+    url: &str,
+    limit: Option<usize>,
+    timeout: Duration,
+    max_redirects: u32,
+) -> Result<Vec<UrlEntry>, String> {
+    let limit = limit.unwrap_or(usize::MAX);
+    let parse = |sitemap: &str| {
+        fetch_sitemap(client, sitemap, timeout, max_redirects)
+            .and_then(|xml| parse_sitemap(&xml))
+            .map_err(|e| format!("sitemap {sitemap}: {e}"))
+    };
+    let to_entries = |sitemap: &str, locs: Vec<String>| -> Vec<UrlEntry> {
+        locs.into_iter()
+            .enumerate()
+            .map(|(i, loc)| UrlEntry {
+                url: loc,
+                source: UrlSource::Sitemap { sitemap: sitemap.to_string(), index: i + 1 },
+                labels: Labels::new(),
+                options: UrlOptions::default(),
+                compare: None,
+            })
+            .collect()
+    };
+
+    let (kind, locs) = parse(url)?;
+    let mut entries = match kind {
+        SitemapKind::UrlSet => to_entries(url, locs),
+        SitemapKind::Index => {
+            let mut entries = Vec::new();
+            for child in locs {
+                if entries.len() >= limit {
+                    break;
+                }
+                match parse(&child)? {
+                    (SitemapKind::UrlSet, locs) => entries.extend(to_entries(&child, locs)),
+                    (SitemapKind::Index, _) => warn!("sitemap {child}: nested sitemap index skipped"),
+                }
+            }
+            entries
+        }
+    };
+    entries.truncate(limit);
+    Ok(entries)
+}
+
+/// Upper bound on how many URLs one brace pattern may expand to.
+const MAX_BRACE_EXPANSION: usize = 10_000;
+
+/// Expand numeric brace ranges: `{1..32}`, zero-padded `{01..32}`, stepped
+/// `{0..90..10}`. Several ranges in one URL expand to every combination.
+/// Nested, unbalanced, or non-numeric braces are an error.
+pub fn expand_braces(url: &str) -> Result<Vec<String>, String> {
+    let Some(open) = url.find(['{', '}']) else {
+        return Ok(vec![url.to_string()]);
+    };
+    if url[open..].starts_with('}') {
+        return Err(format!("unmatched '}}' at position {}", open + 1));
+    }
+    let close = match url[open + 1..].find(['{', '}']) {
+        Some(i) if url[open + 1 + i..].starts_with('}') => open + 1 + i,
+        Some(_) => return Err(format!("nested '{{' in brace range at position {}", open + 1)),
+        None => return Err(format!("unclosed '{{' at position {}", open + 1)),
+    };
+    let values = parse_brace_range(&url[open + 1..close])?;
+
+    let (prefix, rest) = (&url[..open], &url[close + 1..]);
+    let tails = expand_braces(rest)?;
+    if values.len().saturating_mul(tails.len()) > MAX_BRACE_EXPANSION {
+        return Err(format!("expands to more than {MAX_BRACE_EXPANSION} URLs"));
+    }
+    let mut out = Vec::with_capacity(values.len() * tails.len());
+    for v in &values {
+        for t in &tails {
+            out.push(format!("{prefix}{v}{t}"));
+        }
+    }
+    Ok(out)
+}
+
+/// Parse the inside of one `{a..b[..step]}` range into its formatted values.
+fn parse_brace_range(spec: &str) -> Result<Vec<String>, String> {
+    let parts: Vec<&str> = spec.split("..").collect();
+    let (from, to, step) = match parts[..] {
+        [a, b] => (a, b, "1"),
+        [a, b, s] => (a, b, s),
+        _ => return Err(format!("invalid brace range '{{{spec}}}' (expected {{a..b}} or {{a..b..step}})")),
+    };
+    let num = |s: &str| {
+        s.parse::<i64>()
+            .map_err(|_| format!("invalid brace range '{{{spec}}}': '{s}' is not an integer"))
+    };
+    let (a, b, step) = (num(from)?, num(to)?, num(step)?);
+    if step <= 0 {
+        return Err(format!("invalid brace range '{{{spec}}}': step must be positive"));
+    }
+    let count = (a.abs_diff(b) / step as u64) as usize + 1;
+    if count > MAX_BRACE_EXPANSION {
+        return Err(format!("brace range '{{{spec}}}' expands to more than {MAX_BRACE_EXPANSION} URLs"));
+    }
+    // A leading zero on either bound pads every value to the wider bound
+    let padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+    let width = if padded(from) || padded(to) { from.len().max(to.len()) } else { 0 };
+    let dir = if a <= b { step } else { -step };
+    Ok((0..count as i64).map(|i| format!("{:0width$}", a + i * dir)).collect())
+}
+
+/// Prepend `scheme://` to a URL typed without one (e.g. `example.com`).
+/// Returns None when the URL already names a scheme, i.e. starts like `http+unix://`;
+/// a `://` later on, as in `example.com/?next=https://x`, doesn't count.
+pub fn apply_default_scheme(raw: &str, scheme: &str) -> Option<String> {
+    let named = raw.split_once("://").is_some_and(|(s, _)| {
+        s.starts_with(|c: char| c.is_ascii_alphabetic())
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+    });
+    (!named).then(|| format!("{scheme}://{raw}"))
+}
+
+/// Check that a URL parses and uses a supported scheme.
+pub fn validate_url(raw: &str) -> Result<(), String> {
+    if raw.starts_with(UNIX_URL_PREFIX) {
+        #[cfg(unix)]
+        return UnixTarget::parse(raw).map(|_| ());
+        #[cfg(not(unix))]
+        return Err(format!("{UNIX_URL_PREFIX} URLs are only supported on Unix"));
+    }
+    let parsed = url::Url::parse(raw).map_err(|e| format!("invalid URL: {e}"))?;
+    if !SUPPORTED_SCHEMES.contains(&parsed.scheme()) {
+        return Err(format!(
+            "unsupported scheme '{}' (expected one of: {})",
+            parsed.scheme(),
+            SUPPORTED_SCHEMES.join(", ")
+        ));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err("missing host".to_string());
+    }
+    Ok(())
+}
+
+/// Split entries into valid ones and (entry, reason) pairs for the rest.
+pub fn partition_valid(entries: Vec<UrlEntry>) -> (Vec<UrlEntry>, Vec<(UrlEntry, String)>) {
+    let mut valid = Vec::with_capacity(entries.len());
+    let mut invalid = Vec::new();
+    for e in entries {
+        let canary = e.compare.as_deref().map_or(Ok(()), |c| validate_url(c).map_err(|reason| format!("canary: {reason}")));
+        match validate_url(&e.url).and(canary) {
+            Ok(()) => valid.push(e),
+            Err(reason) => invalid.push((e, reason)),
+        }
+    }
+    (valid, invalid)
+}
+
+/// Normalize a URL so equivalent spellings compare equal: lowercase scheme and
+/// host, drop default ports, and give a bare host a trailing "/".
+pub fn normalize_url(raw: &str) -> String {
+    let raw = raw.trim();
+    let Some((scheme, rest)) = raw.split_once("://") else {
+        return raw.to_string();
+    };
+    let scheme = scheme.to_ascii_lowercase();
+    let split = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(split);
+
+    // Keep any userinfo untouched; only the host part is case-insensitive
+    let (userinfo, hostport) = match authority.rsplit_once('@') {
+        Some((u, h)) => (Some(u), h),
+        None => (None, authority),
+    };
+    let mut host = hostport.to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" => Some(":80"),
+        "https" => Some(":443"),
+        _ => None,
+    };
+    if let Some(p) = default_port {
+        if let Some(stripped) = host.strip_suffix(p) {
+            host = stripped.to_string();
+        }
+    }
+
+    let tail = if tail.is_empty() || tail.starts_with(['?', '#']) {
+        format!("/{tail}")
+    } else {
+        tail.to_string()
+    };
+    match userinfo {
+        Some(u) => format!("{scheme}://{u}@{host}{tail}"),
+        None => format!("{scheme}://{host}{tail}"),
+    }
+}
+
+/// Normalize every URL and drop later duplicates, keeping first-seen order.
+/// Returns the kept entries plus (original, kept-as) pairs for each collapsed one.
+pub fn dedup_urls(entries: Vec<UrlEntry>, allow_duplicates: bool) -> (Vec<UrlEntry>, Vec<(UrlEntry, String)>) {
+    let mut seen = std::collections::HashSet::new();
+    let mut kept = Vec::with_capacity(entries.len());
+    let mut collapsed = Vec::new();
+    for raw in entries {
+        let url = normalize_url(&raw.url);
+        let compare = raw.compare.as_deref().map(normalize_url);
+        let key = compare.as_deref().map_or_else(|| url.clone(), |canary| compare_key(&url, canary));
+        if allow_duplicates || seen.insert(key) {
+            kept.push(UrlEntry { url, compare, ..raw });
+        } else {
+            collapsed.push((raw, url));
+        }
+    }
+    (kept, collapsed)
+}
+
+/// A --include/--exclude pattern: a glob (`*`, `?`) by default, a regex with a `re:` prefix.
+/// Either way the pattern must match the whole normalized URL.
+#[derive(Debug, Clone)]
+pub enum UrlFilter {
+    Glob(String),
+    Regex(String, regex::Regex),
+}
+impl UrlFilter {
+    /// Parse a filter, compiling `re:` patterns anchored at both ends.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.strip_prefix("re:") {
+            Some(re) => regex::Regex::new(&format!("^(?:{re})$"))
+                .map(|r| UrlFilter::Regex(s.to_string(), r))
+                .map_err(|e| format!("invalid filter regex '{re}': {e}")),
+            None => Ok(UrlFilter::Glob(s.to_string())),
+        }
+    }
+    fn matches(&self, url: &str) -> bool {
+        match self {
+            UrlFilter::Glob(g) => glob_match(g, url),
+            UrlFilter::Regex(_, r) => r.is_match(url),
+        }
+    }
+    fn pattern(&self) -> &str {
+        match self {
+            UrlFilter::Glob(g) => g,
+            UrlFilter::Regex(raw, _) => raw,
+        }
+    }
+}
+
+/// Match `text` against a glob where `*` is any run of characters and `?` is one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None; // (pattern pos after '*', text pos)
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi + 1, ti));
+            pi += 1;
+        } else if let Some((bp, bt)) = backtrack {
+            // Let the last '*' swallow one more character and retry
+            pi = bp;
+            ti = bt + 1;
+            backtrack = Some((bp, bt + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Keep entries matching any include (all, if none given), then drop those
+/// matching any exclude. Unless `allow_empty`, a filter that matches nothing or
+/// an empty selection is an error, so a typo can't silently produce an empty run.
+pub fn apply_filters(
+    entries: Vec<UrlEntry>,
+    include: &[UrlFilter],
+    exclude: &[UrlFilter],
+    allow_empty: bool,
+) -> Result<Vec<UrlEntry>, String> {
+    if !allow_empty {
+        for f in include.iter().chain(exclude) {
+            if !entries.iter().any(|e| f.matches(&e.url)) {
+                return Err(format!("filter '{}' matches no URL", f.pattern()));
+            }
+        }
+    }
+    let selected: Vec<UrlEntry> = entries
+        .into_iter()
+        .filter(|e| include.is_empty() || include.iter().any(|f| f.matches(&e.url)))
+        .filter(|e| !exclude.iter().any(|f| f.matches(&e.url)))
+        .collect();
+    if selected.is_empty() && !allow_empty {
+        return Err("filters excluded every URL".to_string());
+    }
+    Ok(selected)
+}
+
+/// Read an attribute value (quoted or bare) from the text of a single HTML tag.
+pub(crate) fn html_attr(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let needle = format!("{name}=");
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(&needle) {
+        let at = from + pos;
+        from = at + needle.len();
+        // Must start a new attribute, not be the tail of a longer name
+        if at > 0 && !lower.as_bytes()[at - 1].is_ascii_whitespace() {
+            continue;
+        }
+        let rest = &tag[from..];
+        let value = match rest.chars().next() {
+            Some(q @ ('"' | '\'')) => rest[1..].split(q).next().unwrap_or(""),
+            _ => rest
+                .split(|c: char| c.is_ascii_whitespace() || c == '/')
+                .next()
+                .unwrap_or(""),
+        };
+        return Some(value.to_string());
+    }
+    None
+}
+
+/// Find the target of a `<meta http-equiv="refresh" content="N; url=...">` tag.
+fn find_meta_refresh(html: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets identical, so indexes carry over to `html`
+    let lower = html.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find("<meta") {
+        let start = from + pos;
+        let end = lower[start..].find('>').map(|e| start + e)?;
+        let tag = &html[start..end];
+        from = end;
+        let is_refresh = html_attr(tag, "http-equiv").is_some_and(|v| v.eq_ignore_ascii_case("refresh"));
+        if !is_refresh {
+            continue;
+        }
+        let Some(content) = html_attr(tag, "content") else { continue };
+        let Some((_, rest)) = content.split_once(';') else { continue };
+        let rest = rest.trim();
+        let target = match rest.get(..4) {
+            Some(p) if p.eq_ignore_ascii_case("url=") => &rest[4..],
+            _ => rest,
+        };
+        let target = target.trim().trim_matches(|c| c == '\'' || c == '"');
+        if !target.is_empty() {
+            return Some(target.to_string());
+        }
+    }
+    None
+}
+
+/// Outcome of a fetch that passed all validations.
+#[derive(Debug)]
+struct Fetched {
+    status: u16,
+    elapsed: Duration,
+    redirect_chain: Vec<String>, // HTTP redirect and meta-refresh targets followed, in order
+    content_length: Option<u64>,
+}
+
+/// A failed fetch: the error plus the HTTP status, if a response arrived, and
+/// the redirect hops taken before it failed.
+#[derive(Debug)]
+struct FetchError {
+    error: CheckError,
+    http_status: Option<u16>,
+    redirect_chain: Vec<String>,
+}
+impl FetchError {
+    fn new(error: CheckError, http_status: Option<u16>) -> Self {
+        FetchError { error, http_status, redirect_chain: Vec::new() }
+    }
+    fn with_status(error: CheckError, status: u16) -> Self {
+        FetchError::new(error, Some(status))
+    }
+}
+impl From<CheckError> for FetchError {
+    fn from(error: CheckError) -> Self {
+        FetchError::new(error, None)
+    }
+}
+
+/// What a fetch keeps from the last response it got, whatever the outcome.
+#[derive(Debug, Default)]
+struct LastResponse {
+    headers: CapturedHeaders, // Just the `capture_headers`
+    body_snippet: Option<String>,
+    keep_response: bool,
+    response: Option<CheckResponse>, // Whole, if `keep_response`
+    clock_skew: Option<ClockSkew>,   // With `max_clock_skew_s`
+    compression: Option<Compression>, // With `require_compression`
+    certificate: Option<Certificate>, // From a client recording them
+}
+
+/// The first `n` bytes of `body` for `body_snippet`: decoded lossily, with
+/// control characters escaped so a snippet stays on one line.
+fn body_snippet(body: &[u8], n: usize) -> String {
+    let mut snippet = String::new();
+    for c in String::from_utf8_lossy(&body[..n.min(body.len())]).chars() {
+        if c.is_control() {
+            snippet.extend(c.escape_default());
+        } else {
+            snippet.push(c);
+        }
+    }
+    snippet
+}
+
+/// Stands in for a `--redact-pattern` match in a body shown on failure.
+pub const REDACTED: &str = "[redacted]";
+
+/// Marks a body shown on failure that was cut to `--max-body-bytes`.
+pub const BODY_TRUNCATED: &str = "...(truncated)";
+
+/// `body` as `--show-body-on-fail` logs it: pretty-printed if it is JSON,
+/// `redact` matches replaced, then cut to `max` bytes on a character boundary.
+fn failure_body(body: &[u8], redact: &[regex::Regex], max: Option<u64>) -> String {
+    let mut text = match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(json) => serde_json::to_string_pretty(&json).unwrap_or_default(),
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+    for re in redact {
+        text = re.replace_all(&text, REDACTED).into_owned();
+    }
+    if let Some(max) = max.map(|m| m as usize).filter(|&m| text.len() > m) {
+        let cut = (0..=max).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0);
+        text.truncate(cut);
+        text.push_str(BODY_TRUNCATED);
+    }
+    text
+}
+
+/// Marks a `redirect_chain` cut short by `--record-redirects`.
+pub const REDIRECTS_TRUNCATED: &str = "...(truncated)";
+
+/// The part of `chain` a result keeps: all of it, or the first `limit` hops
+/// and `REDIRECTS_TRUNCATED`.
+fn record_chain(mut chain: Vec<String>, limit: Option<usize>) -> Vec<String> {
+    if let Some(limit) = limit.filter(|&limit| chain.len() > limit) {
+        chain.truncate(limit);
+        chain.push(REDIRECTS_TRUNCATED.to_string());
+    }
+    chain
+}
+
+/// Fetch once with validations, following meta-refresh pages if enabled.
+/// `last` ends up with what the last response had, or nothing.
+fn fetch_once(
+    client: &dyn HttpClient,
+    url: &str,
+    timeout: Duration,
+    settings: &CheckSettings,
+    validators: &[Box<dyn Validator>],
+    deadline: Option<Instant>,
+    last: &mut LastResponse,
+) -> Result<Fetched, FetchError> {
+    let start = Instant::now();
+    // Every hop is followed (and loop-checked), but only `record_redirects` of them kept
+    let mut chain: Vec<String> = Vec::new();
+    last.headers.values_mut().for_each(|value| *value = None);
+    last.body_snippet = None;
+    last.response = None;
+    last.clock_skew = None;
+    last.compression = None;
+    last.certificate = None;
+    let outcome = fetch_hops(client, url, timeout, settings, validators, deadline, &mut chain, last);
+    let redirect_chain = record_chain(chain, settings.record_redirects);
+    match outcome {
+        Ok((status, content_length)) => Ok(Fetched { status, elapsed: start.elapsed(), redirect_chain, content_length }),
+        Err(e) => Err(FetchError { redirect_chain, ..e }),
+    }
+}
+
+/// The requests of one fetch: `url`, then each HTTP redirect or meta-refresh
+/// target (pushed onto `chain`) until a final page passes validation. Returns its status and,
+/// with `head_size_only`, its size. `last` is refilled from each response.
+#[allow(clippy::too_many_arguments)]
+fn fetch_hops(
+    client: &dyn HttpClient,
+    url: &str,
+    timeout: Duration,
+    settings: &CheckSettings,
+    validators: &[Box<dyn Validator>],
+    deadline: Option<Instant>,
+    chain: &mut Vec<String>,
+    last: &mut LastResponse,
+) -> Result<(u16, Option<u64>), FetchError> {
+    // Whatever went wrong, a check past its deadline failed because of the deadline
+    let exceeded = |http_status: Option<u16>| {
+        let passed = deadline.is_some_and(|d| Instant::now() >= d);
+        let after_ms = settings.deadline.map_or(0, |d| d.as_millis() as u64);
+        passed.then_some(FetchError::new(CheckError::DeadlineExceeded { after_ms }, http_status))
+    };
+    // Decided once: the body is only downloaded if something will look at it
+    let keep_body = settings.capture_body_on_failure.is_some() || last.keep_response || settings.require_compression;
+    let read_body = if validators.iter().any(|v| v.needs_body()) || keep_body {
+        ReadBody::Always
+    } else if settings.follow_meta_refresh {
+        ReadBody::IfHtml
+    } else {
+        ReadBody::Never
+    };
+    // The --contains text is the only thing read for, so the download can stop at it;
+    // a meta-refresh page, one that may be saved or compared, or one to decode, is kept whole
+    let read_until = settings
+        .contains
+        .as_ref()
+        .filter(|_| !settings.follow_meta_refresh && !last.keep_response && !settings.require_compression)
+        .map(|t| t.as_bytes().to_vec());
+    let request = |target: &str, method: Method| CheckRequest {
+        url: target.to_string(),
+        method,
+        timeout,
+        // Redirects are followed below, so they share the budget with meta-refresh hops
+        max_redirects: 0,
+        read_body,
+        deadline,
+        max_body_bytes: settings.max_body_bytes,
+        read_until: read_until.clone(),
+        accept_encoding: settings.require_compression.then(|| ACCEPT_COMPRESSED.to_string()),
+        authorization: None,
+        checked_url: Some(url.to_string()),
+    };
+    let method = if settings.head_size_only { Method::Head } else { Method::Get };
+    let mut target = url.to_string();
+    loop {
+        if let Some(e) = exceeded(None) {
+            return Err(e);
+        }
+        let failed = |e: TransportError| {
+            let http_status = e.status();
+            exceeded(http_status).unwrap_or_else(|| FetchError::new(e.into(), http_status))
+        };
+        let sent = Instant::now();
+        let mut resp = match client.execute(request(&target, method)) {
+            Ok(resp) => resp,
+            Err(e) => {
+                last.certificate = e.certificate().cloned();
+                return Err(failed(e));
+            }
+        };
+        last.certificate = resp.certificate.take();
+        let status = resp.status;
+        if let Some(location) = redirect_location(&resp, settings.max_redirects) {
+            let next = url::Url::parse(&target)
+                .and_then(|base| base.join(location))
+                .map(|u| normalize_url(u.as_str()))
+                .map_err(|e| {
+                    let error = CheckError::Request { detail: format!("{target}: bad redirect to {location}: {e}") };
+                    FetchError::with_status(error, status)
+                })?;
+            if next == url || chain.contains(&next) {
+                let error = CheckError::RedirectLoop { url: next };
+                return Err(FetchError::with_status(error, status));
+            }
+            if chain.len() >= settings.max_redirects as usize {
+                let error = CheckError::TooManyRedirects { max: settings.max_redirects };
+                return Err(FetchError::with_status(error, status));
+            }
+            chain.push(next.clone());
+            target = next;
+            continue;
+        }
+        if settings.require_compression {
+            last.compression = Some(decompress(&mut resp, &request(&target, method)).map_err(failed)?);
+        }
+        if settings.max_clock_skew_s.is_some() {
+            // The server stamped its Date about halfway through the round trip
+            let half_rtt = (sent.elapsed().as_millis() / 2) as i64;
+            last.clock_skew = Some(clock_skew(resp.headers.get("date"), Utc::now().timestamp_millis() - half_rtt));
+        }
+        for (name, value) in last.headers.iter_mut() {
+            *value = resp.headers.get(name).map(str::to_string);
+        }
+        if let Some(n) = settings.capture_body_on_failure {
+            last.body_snippet = resp.body.as_deref().map(|body| body_snippet(body, n));
+        }
+        if last.keep_response {
+            last.response = Some(resp.clone());
+        }
+        if let Some(e) = exceeded(Some(status)) {
+            return Err(e);
+        }
+        // With --expect-status an error status is just another status to validate
+        if status >= 400 && settings.expect_status.is_none() {
+            let error = CheckError::HttpStatus { code: status, detail: format!("{target}: status code {status}") };
+            return Err(FetchError::with_status(error, status));
+        }
+
+        if settings.follow_meta_refresh && resp.is_html() {
+            let page = resp.body.as_deref().map(String::from_utf8_lossy);
+            if let Some(next) = page.as_deref().and_then(find_meta_refresh) {
+                let next = url::Url::parse(&target)
+                    .and_then(|base| base.join(&next))
+                    .map(|u| normalize_url(u.as_str()))
+                    .map_err(|e| {
+                        let error = CheckError::InvalidMetaRefresh { target: next.clone(), detail: e.to_string() };
+                        FetchError::with_status(error, status)
+                    })?;
+                if next == url || chain.contains(&next) {
+                    let error = CheckError::MetaRefreshLoop { url: next };
+                    return Err(FetchError::with_status(error, status));
+                }
+                if chain.len() >= settings.max_redirects as usize {
+                    let error = CheckError::TooManyRedirects { max: settings.max_redirects };
+                    return Err(FetchError::with_status(error, status));
+                }
+                chain.push(next.clone());
+                target = next;
+                continue;
+            }
+        }
+
+        // Only the final page is validated
+        for v in validators {
+            v.validate(status, &resp.headers, resp.body.as_deref())
+                .map_err(|e| FetchError::with_status(e.into(), status))?;
+        }
+        if last.compression.as_ref().is_some_and(|c| c.encoding == "identity") {
+            return Err(FetchError::with_status(CheckError::NotCompressed, status));
+        }
+        if !settings.head_size_only {
+            return Ok((status, None));
+        }
+        let mut size = resp.content_length();
+        if size.is_none() && settings.range_fallback {
+            // The size is extra information, so a failed probe leaves it unknown
+            match client.execute(CheckRequest { read_body: ReadBody::Never, ..request(&target, Method::GetFirstByte) }) {
+                Ok(probe) => size = probe.content_length(),
+                Err(e) => debug!("{target}: ranged GET for the size failed: {e}"),
+            }
+        }
+        return Ok((status, size));
+    }
+}
+
+/// Where a redirect response points, if it is one to follow. With
+/// `max_redirects` 0 the 3xx itself is the answer, as it is from the clients.
+fn redirect_location(resp: &CheckResponse, max_redirects: u32) -> Option<&str> {
+    let followed = matches!(resp.status, 301 | 302 | 303 | 307 | 308) && max_redirects > 0;
+    followed.then(|| resp.headers.get("location")).flatten()
+}
+
+/// What one check runs with: the shared settings and their validators, the
+/// timeout and retries after the URL's own overrides, and where failures are
+/// saved. Borrowed, so applying an override per job copies nothing.
+#[derive(Clone, Copy)]
+struct CheckParams<'a> {
+    settings: &'a CheckSettings,
+    validators: &'a [Box<dyn Validator>],
+    timeout: Duration,
+    max_retries: usize,
+    failures: Option<&'a FailureStore>,
+    links: Option<&'a SeenLinks>, // With `check_links`, shared by the pool's workers
+}
+impl CheckParams<'_> {
+    /// These params with `options` applied on top.
+    fn with_overrides(self, options: &UrlOptions) -> Self {
+        CheckParams {
+            timeout: options.timeout.unwrap_or(self.timeout),
+            max_retries: options.retries.unwrap_or(self.max_retries),
+            ..self
+        }
+    }
+}
+
+/// Check a URL with retries & validations, returning a WebsiteStatus.
+fn check_with_retries(
+    client: &dyn HttpClient,
+    url: &str,
+    timeout: Duration,
+    settings: &CheckSettings,
+    budget: Option<&RetryBudget>,
+    failures: Option<&FailureStore>,
+) -> WebsiteStatus {
+    let validators = settings.validators();
+    let params = CheckParams { settings, validators: &validators, timeout, max_retries: settings.max_retries, failures, links: None };
+    run_check(client, url, params, budget)
+}
+
+/// `check_with_retries` with the validators already built.
+fn run_check(client: &dyn HttpClient, url: &str, params: CheckParams, budget: Option<&RetryBudget>) -> WebsiteStatus {
+    run_check_keeping(client, url, params, budget, false).0
+}
+
+/// `run_check`, also returning the last response if `keep` is set.
+fn run_check_keeping(
+    client: &dyn HttpClient,
+    url: &str,
+    params: CheckParams,
+    budget: Option<&RetryBudget>,
+    keep: bool,
+) -> (WebsiteStatus, Option<CheckResponse>) {
+    let CheckParams { settings, validators, timeout, max_retries, failures, .. } = params;
+    let deadline = settings.deadline.map(|d| Instant::now() + d);
+    let mut last_err: Option<FetchError> = None;
+    let mut attempt_errors: Vec<CheckError> = Vec::new();
+    let mut attempts = 0;
+    let mut last = LastResponse {
+        headers: settings.capture_headers.iter().map(|name| (name.clone(), None)).collect(),
+        body_snippet: None,
+        keep_response: keep || settings.save_failures.is_some() || settings.show_body_on_fail,
+        response: None,
+        clock_skew: None,
+        compression: None,
+        certificate: None,
+    };
+    for attempt in 0..=max_retries {
+        attempts += 1;
+        match fetch_once(client, url, timeout, settings, validators, deadline, &mut last) {
+            Ok(f) => {
+                debug!("{url}: attempt {}/{} ok: {} in {:?}", attempt + 1, max_retries + 1, f.status, f.elapsed);
+                let status = WebsiteStatus {
+                    schema_version: SCHEMA_VERSION,
+                    url: url.to_string(),
+                    status: skew_check(url, settings, last.clock_skew.as_ref()).map_or(Ok(f.status), Err),
+                    attempts,
+                    attempt_errors,
+                    // Whole milliseconds, as serialized, so a result round-trips unchanged
+                    response_time: Duration::from_millis(f.elapsed.as_millis() as u64),
+                    timestamp: Utc::now(),
+                    http_status: Some(f.status),
+                    redirect_chain: f.redirect_chain,
+                    content_length: f.content_length,
+                    maintenance: false,
+                    captured_headers: last.headers,
+                    body_snippet: None,
+                    saved_body: None,
+                    comparison: None,
+                    clock_skew: last.clock_skew,
+                    compression: last.compression,
+                    certificate: last.certificate,
+                    links: None,
+                    labels: Labels::new(),
+                };
+                return (status, last.response);
+            }
+            Err(e) => {
+                debug!("{url}: attempt {}/{} failed: {}", attempt + 1, max_retries + 1, e.error);
+                attempt_errors.push(e.error.clone());
+                last_err = Some(e);
+                if attempt < max_retries {
+                    let delay = backoff_delay(attempt, &settings.backoff);
+                    // A retry that could only start after the deadline is not worth its backoff
+                    if let Some(left) = deadline.map(|d| d.saturating_duration_since(Instant::now())) {
+                        if left <= delay {
+                            debug!("{url}: only {left:?} of the deadline left, not retrying");
+                            break;
+                        }
+                    }
+                    // Once the shared budget is spent, record the failure without retrying
+                    if !budget.is_none_or(|b| b.try_acquire()) {
+                        debug!("{url}: retry budget exhausted, not retrying");
+                        break;
+                    }
+                    debug!("{url}: backing off {delay:?} before retry");
+                    thread::sleep(delay);
+                }
+            }
+        }
+    }
+    let err = last_err.unwrap_or_else(|| FetchError::from(CheckError::Other { message: "unknown error".to_string() }));
+    // The last failure is the outcome itself, not an earlier attempt
+    attempt_errors.pop();
+    let timestamp = Utc::now();
+    // Only a failure with a response to show for it is saved, not a transport error
+    let saved_body = match (failures, &last.response) {
+        (Some(store), Some(response)) => store.save(url, timestamp, response, &err.error),
+        _ => None,
+    };
+    if let Some(body) = last.response.as_ref().and_then(|r| r.body.as_deref()).filter(|_| settings.show_body_on_fail) {
+        warn!("{url}: {}; response body:\n{}", err.error, failure_body(body, &settings.redact_patterns, settings.max_body_bytes));
+    }
+    let status = WebsiteStatus {
+        schema_version: SCHEMA_VERSION,
+        url: url.to_string(),
+        status: Err(err.error),
+        attempts,
+        attempt_errors,
+        response_time: Duration::from_millis(0),
+        timestamp,
+        http_status: err.http_status,
+        redirect_chain: err.redirect_chain,
+        content_length: None,
+        maintenance: false,
+        captured_headers: last.headers,
+        body_snippet: last.body_snippet,
+        saved_body,
+        comparison: None,
+        clock_skew: last.clock_skew,
+        compression: last.compression,
+        certificate: last.certificate,
+        links: None,
+        labels: Labels::new(),
+    };
+    (status, last.response)
+}
+
+/// The failure for a measured skew past `max_clock_skew_s`; logged instead
+/// with `clock_skew_warn_only`. A missing or unparsable `Date` passes.
+fn skew_check(url: &str, settings: &CheckSettings, skew: Option<&ClockSkew>) -> Option<CheckError> {
+    let (Some(max_s), Some(&ClockSkew::Measured { skew_s })) = (settings.max_clock_skew_s, skew) else {
+        return None;
+    };
+    if skew_s.unsigned_abs() <= max_s {
+        return None;
+    }
+    let error = CheckError::ClockSkew { skew_s, max_s };
+    if settings.clock_skew_warn_only {
+        warn!("{url}: {error}");
+        return None;
+    }
+    Some(error)
+}
+
+/// Check `url`, then `canary`, and fail the result if the canary's status,
+/// `--capture-header` values, or body (less `compare_ignore` matches) differ.
+/// The result is `url`'s, with the canary's side in `comparison`.
+fn run_compare(
+    client: &dyn HttpClient,
+    url: &str,
+    canary: &str,
+    params: CheckParams,
+    budget: Option<&RetryBudget>,
+) -> WebsiteStatus {
+    let (mut status, response) = run_check_keeping(client, url, params, budget, true);
+    let (canary_status, canary_response) = run_check_keeping(client, canary, params, budget, true);
+    let diff = compare_diff((&status, response.as_ref()), (&canary_status, canary_response.as_ref()), params.settings);
+    if let (Ok(_), Some(diff)) = (&status.status, &diff) {
+        status.status = Err(CheckError::CompareMismatch { canary: canary.to_string(), diff: diff.clone() });
+    }
+    status.comparison = Some(Comparison {
+        canary: canary.to_string(),
+        canary_http_status: canary_status.http_status,
+        canary_response_time: canary_status.response_time,
+        diff,
+    });
+    status
+}
+
+/// The first difference between two checks and their last responses, if any:
+/// a missing response, the status, the captured headers, then the bodies.
+fn compare_diff(
+    (a, a_resp): (&WebsiteStatus, Option<&CheckResponse>),
+    (b, b_resp): (&WebsiteStatus, Option<&CheckResponse>),
+    settings: &CheckSettings,
+) -> Option<String> {
+    let failed = |st: &WebsiteStatus| st.error().map_or_else(|| "no response".to_string(), ToString::to_string);
+    match (a.http_code(), b.http_code()) {
+        (None, _) => return Some(format!("{} failed: {}", a.url, failed(a))),
+        (_, None) => return Some(format!("canary failed: {}", failed(b))),
+        (Some(x), Some(y)) if x != y => return Some(format!("status {x} vs {y}")),
+        _ => {}
+    }
+    let differ: Vec<&str> = a
+        .captured_headers
+        .iter()
+        .filter(|(name, value)| b.captured_headers.get(*name) != Some(value))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !differ.is_empty() {
+        return Some(format!("headers differ: {}", differ.join(", ")));
+    }
+    let x = normalize_body(a_resp.and_then(|r| r.body.as_deref()).unwrap_or_default(), &settings.compare_ignore);
+    let y = normalize_body(b_resp.and_then(|r| r.body.as_deref()).unwrap_or_default(), &settings.compare_ignore);
+    if x != y {
+        let at = x.iter().zip(y.iter()).position(|(p, q)| p != q).unwrap_or(x.len().min(y.len()));
+        return Some(format!("bodies differ at byte {at}"));
+    }
+    // Same response, but the canary still failed its own validations
+    b.error().map(|e| format!("canary failed: {e}"))
+}
+
+/// `body` with every match of `ignore` cut out, for comparing.
+fn normalize_body<'a>(body: &'a [u8], ignore: &[regex::Regex]) -> Cow<'a, [u8]> {
+    if ignore.is_empty() {
+        return Cow::Borrowed(body);
+    }
+    let mut text = String::from_utf8_lossy(body).into_owned();
+    for re in ignore {
+        text = re.replace_all(&text, "").into_owned();
+    }
+    Cow::Owned(text.into_bytes())
+}
+
+/// Merge per-URL stats into one entry per value of `label` (missing => "(none)").
+/// With `equal_weight`, each URL counts once toward the group's uptime however
+/// often it was checked.
+fn group_stats(stats: &HashMap<String, UrlStats>, label: &str, equal_weight: bool) -> BTreeMap<String, (usize, UrlStats)> {
+    let mut groups: BTreeMap<String, (usize, UrlStats)> = BTreeMap::new();
+    let mut uptime_sums: HashMap<String, f64> = HashMap::new();
+    for st in stats.values().filter(|st| st.checks > 0) {
+        let key = st.labels.get(label).cloned().unwrap_or_else(|| "(none)".to_string());
+        *uptime_sums.entry(key.clone()).or_default() += st.uptime();
+        let (urls, agg) = groups.entry(key).or_insert_with(|| (0, UrlStats { order: st.order, ..Default::default() }));
+        *urls += 1;
+        agg.merge(st);
+    }
+    if equal_weight {
+        for (key, (urls, agg)) in &mut groups {
+            agg.mean_uptime = Some(uptime_sums[key] / *urls as f64);
+        }
+    }
+    groups
+}
+
+/// Why a down result was down, for the summary's failure tally: the error's
+/// `kind`, except that error statuses are grouped by class (`5xx`). A result
+/// with no error (a 3xx under `--up-if 2xx`, say) is tallied by its status class.
+pub fn failure_kind(status: &WebsiteStatus) -> String {
+    match (status.error(), status.http_code()) {
+        (Some(CheckError::HttpStatus { code, .. }), _) => format!("{}xx", code / 100),
+        (Some(e), _) => e.kind().to_string(),
+        (None, Some(code)) => format!("{}xx", code / 100),
+        (None, None) => "other".to_string(),
+    }
+}
+
+/// Fold one result into the per-URL stats.
+pub fn record_status(stats: &mut HashMap<String, UrlStats>, status: WebsiteStatus, up_if: UpIf) {
+    let ok = up_if.is_up(&status);
+    let next = stats.len();
+    let failed = (!ok).then(|| failure_kind(&status));
+    let key = match &status.comparison {
+        Some(c) => compare_key(&status.url, &c.canary),
+        None => status.url,
+    };
+    let st = stats.entry(key).or_insert_with(|| UrlStats { order: next, ..Default::default() });
+    if status.maintenance {
+        // Expected downtime counts as neither up nor down
+        st.maintenance += 1;
+    } else {
+        st.record(ok, status.response_time, status.attempts);
+        if let Some(kind) = failed {
+            st.count_failure(kind, 1);
+        }
+    }
+    st.last_check = st.last_check.max(Some(status.timestamp));
+    st.labels = status.labels;
+}
+
+/// Empty stats for `urls`, seeded in input order so the default summary order
+/// matches it, whatever order results arrive in.
+pub fn seed_stats(urls: &[UrlEntry]) -> HashMap<String, UrlStats> {
+    let mut stats = HashMap::new();
+    for (order, e) in urls.iter().enumerate() {
+        stats.entry(e.stats_key()).or_insert_with(|| UrlStats { order, ..Default::default() });
+    }
+    stats
+}
+
+/// Rebuild per-URL stats from NDJSON written by an earlier run. Non-JSON lines
+/// (the summary blocks that share stdout with the records) are skipped.
+pub fn read_results(reader: impl BufRead, up_if: UpIf) -> io::Result<HashMap<String, UrlStats>> {
+    let mut stats = HashMap::new();
+    let mut warned = false;
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if !line.starts_with('{') {
+            continue;
+        }
+        let status: WebsiteStatus = serde_json::from_str(line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {e}", idx + 1)))?;
+        if status.schema_version > SCHEMA_VERSION && !warned {
+            warn!(
+                "line {}: results use schema version {}, newer than this sitecheck's {SCHEMA_VERSION}",
+                idx + 1,
+                status.schema_version
+            );
+            warned = true;
+        }
+        record_status(&mut stats, status, up_if);
+    }
+    Ok(stats)
+}
+
+/// Summary rows in display order: `(URL or label=value, URL count, stats)`.
+/// URLs that have no results yet are left out.
+pub fn summary_rows(stats: &HashMap<String, UrlStats>, style: &SummaryStyle) -> Vec<(String, usize, UrlStats)> {
+    let mut rows: Vec<(String, usize, UrlStats)> = match &style.group_by {
+        Some(label) => group_stats(stats, label, style.equal_weight)
+            .into_iter()
+            .map(|(value, (urls, st))| (format!("{label}={value}"), urls, st))
+            .collect(),
+        None => stats
+            .iter()
+            .filter(|(_, st)| st.checks > 0)
+            .map(|(url, st)| (url.clone(), 1, st.clone()))
+            .collect(),
+    };
+    rows.sort_by(|a, b| style.compare((&a.0, &a.2), (&b.0, &b.2)));
+    rows
+}
+
+/// Replace `path` with `contents` through a temporary file beside it, so an
+/// interrupted write can't leave half of one behind.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Check one job's URL with its per-URL overrides applied.
+fn run_job(client: &dyn HttpClient, params: CheckParams, job: &Job) -> WebsiteStatus {
+    let params = params.with_overrides(&job.entry.options);
+    let mut status = match (&job.entry.compare, params.settings.check_links.zip(params.links)) {
+        (Some(canary), _) => run_compare(client, &job.entry.url, canary, params, job.budget.as_deref()),
+        (None, Some((settings, seen))) => {
+            let (mut status, response) = run_check_keeping(client, &job.entry.url, params, job.budget.as_deref(), true);
+            let limits = (params.timeout, params.settings.max_redirects);
+            check_page_links(client, &mut status, response.as_ref(), settings, limits, (seen, job.round));
+            status
+        }
+        (None, None) => run_check(client, &job.entry.url, params, job.budget.as_deref()),
+    };
+    status.labels = job.entry.labels.clone();
+    status
+}
+
+/// What every worker shares; kept by the pool so dead workers can be replaced.
+struct Workers {
+    client: Arc<dyn HttpClient>,
+    check: Arc<CheckSettings>,
+    timeout: Duration,
+    queue: Arc<Mutex<mpsc::Receiver<Job>>>,
+    backlog: Arc<Mutex<Backlog>>,
+    results: mpsc::Sender<JobResult>,
+    think_time: Option<ThinkTime>,
+    stopping: Arc<AtomicBool>, // Cuts think-time pauses short
+    failures: Option<Arc<FailureStore>>,
+    links: Arc<SeenLinks>,
+}
+impl Workers {
+    /// Start a worker that takes jobs from the queue until its sender is
+    /// dropped, checks each URL, and sends back the result. A check that panics
+    /// is reported as a `NoResult` failure and the worker carries on.
+    fn spawn(&self, id: usize) -> thread::JoinHandle<()> {
+        let queue = Arc::clone(&self.queue);
+        let backlog = Arc::clone(&self.backlog);
+        let results = self.results.clone();
+        let client = Arc::clone(&self.client);
+        let settings = Arc::clone(&self.check);
+        let timeout = self.timeout;
+        let think_time = self.think_time;
+        let stopping = Arc::clone(&self.stopping);
+        let failures = self.failures.clone();
+        let links = Arc::clone(&self.links);
+        thread::spawn(move || {
+            debug!("worker {id} started");
+            let mut rng = think_time.map(|t| t.rng(id));
+            // Built once per worker; jobs only swap in their URL's overrides
+            let validators = settings.validators();
+            let params = CheckParams {
+                settings: &settings,
+                validators: &validators,
+                timeout,
+                max_retries: settings.max_retries,
+                failures: failures.as_deref(),
+                links: Some(&links),
+            };
+            loop {
+                // Lock only to receive the next job, then release before doing work.
+                // A poisoned lock still guards a usable receiver.
+                let msg = queue.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                let Ok(job) = msg else { break }; // sender dropped => shutdown
+                if !backlog.lock().unwrap_or_else(PoisonError::into_inner).take(job.generation) {
+                    continue; // discarded
+                }
+                let checked = panic::catch_unwind(AssertUnwindSafe(|| run_job(&*client, params, &job)));
+                let status = checked.unwrap_or_else(|_| {
+                    warn!("worker {id}: check of {} panicked", job.entry.url);
+                    missing_result(&job.entry, "the check panicked")
+                });
+                let _ = results.send(JobResult { round: job.round, seq: job.seq, status });
+                if let (Some(t), Some(rng)) = (think_time, rng.as_mut()) {
+                    think(t.pick(rng), &stopping);
+                }
+            }
+            debug!("worker {id} stopped");
+        })
+    }
+}
+
+/// A running pool of worker threads fed through channels. Dropping it closes
+/// the queue and waits for the workers to finish what is already queued.
+pub struct Pool {
+    jobs: Option<mpsc::Sender<Job>>,
+    results: mpsc::Receiver<JobResult>,
+    workers: Vec<thread::JoinHandle<()>>,
+    shared: Workers,
+}
+impl Pool {
+    /// Queue `entry` as job `seq` of `round`; its result comes back tagged with
+    /// both. Returns false if the pool is no longer taking jobs.
+    pub fn submit(&self, round: u64, seq: usize, entry: Arc<UrlEntry>, budget: Option<Arc<RetryBudget>>) -> bool {
+        let Some(tx) = self.jobs.as_ref() else { return false };
+        // Count it while holding the backlog, so no worker can claim it first
+        let mut backlog = self.shared.backlog.lock().unwrap_or_else(PoisonError::into_inner);
+        let sent = tx.send(Job { round, seq, generation: backlog.generation, entry, budget }).is_ok();
+        backlog.queued += usize::from(sent);
+        sent
+    }
+
+    /// Wait up to `wait` for the next result.
+    pub fn recv_timeout(&self, wait: Duration) -> Option<JobResult> {
+        self.results.recv_timeout(wait).ok()
+    }
+
+    /// Cut workers' think-time pauses short from now on, so what is still
+    /// queued runs without them. For shutting down.
+    pub fn stop(&self) {
+        self.shared.stopping.store(true, Ordering::SeqCst);
+    }
+
+    /// Drop every job no worker has picked up yet; returns how many there were.
+    pub fn discard_queued(&self) -> usize {
+        self.shared.backlog.lock().unwrap_or_else(PoisonError::into_inner).discard()
+    }
+
+    /// Replace any worker thread that has died; returns how many were restarted.
+    /// Whatever it was checking is not retried: its round's deadline covers it.
+    pub fn revive(&mut self) -> usize {
+        if self.jobs.is_none() {
+            return 0;
+        }
+        let mut revived = 0;
+        for (id, worker) in self.workers.iter_mut().enumerate() {
+            if worker.is_finished() {
+                let dead = std::mem::replace(worker, self.shared.spawn(id));
+                let _ = dead.join();
+                warn!("worker {id} died; started a replacement");
+                revived += 1;
+            }
+        }
+        revived
+    }
+}
+impl Drop for Pool {
+    fn drop(&mut self) {
+        // Closing the queue lets each worker exit once it is drained
+        self.stop();
+        self.jobs.take();
+        for w in self.workers.drain(..) {
+            let _ = w.join();
+        }
+    }
+}
+
+/// Checks URLs under one `Config`, either one at a time on the calling thread
+/// or concurrently through a worker pool.
+pub struct Checker {
+    config: Config,
+    client: Arc<dyn HttpClient>,
+    failures: Option<Arc<FailureStore>>,
+    har: Option<Arc<Har>>,
+    oauth: Option<Arc<OAuthToken>>,
+}
+impl Checker {
+    /// A checker that runs with `config`, making requests with ureq through
+    /// a DNS cache unless `dns_cache_ttl` is None, or over `unix_socket`;
+    /// `http+unix://` targets go over the socket they name.
+    pub fn new(config: Config) -> Self {
+        let client: Arc<dyn HttpClient> = match config.unix_socket.clone() {
+            #[cfg(unix)]
+            Some(path) => Arc::new(UnixSocketClient::new(path)),
+            _ => Arc::new(UreqClient::with_options(AgentOptions::from_config(&config))),
+        };
+        #[cfg(unix)]
+        let client: Arc<dyn HttpClient> = Arc::new(UnixTargets::new(client, config.unix_host.clone()));
+        Checker::with_client(config, client)
+    }
+
+    /// A checker that makes its requests through `client`, recording them
+    /// with `--har` and sending them with `--oauth-token-url`'s token.
+    pub fn with_client(config: Config, client: Arc<dyn HttpClient>) -> Self {
+        let failures = config.check.save_failures.clone().map(|save| Arc::new(FailureStore::new(save)));
+        let oauth = config.oauth.clone().map(|settings| Arc::new(OAuthToken::new(settings, config.timeout)));
+        let client: Arc<dyn HttpClient> = match &oauth {
+            Some(token) => Arc::new(OAuthClient::new(client, Arc::clone(token))),
+            None => client,
+        };
+        let har = config.har.clone().map(|path| Arc::new(Har::new(path)));
+        let client: Arc<dyn HttpClient> = match &har {
+            Some(har) => Arc::new(HarClient::new(client, Arc::clone(har))),
+            None => client,
+        };
+        Checker { config, client, failures, har, oauth }
+    }
+
+    /// The HTTP Archive being recorded, with `Config::har`.
+    pub fn har(&self) -> Option<&Har> {
+        self.har.as_deref()
+    }
+
+    /// The token requests are sent with, with `Config::oauth`. It starts out
+    /// empty: the caller gets the first one, and any after it, with `refresh`.
+    pub fn oauth(&self) -> Option<&OAuthToken> {
+        self.oauth.as_deref()
+    }
+
+    /// The configuration this checker runs with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Check `url` on the calling thread, with the configured retries and validations.
+    pub fn check_once(&self, url: &str) -> WebsiteStatus {
+        check_with_retries(&*self.client, url, self.config.timeout, &self.config.check, None, self.failures.as_deref())
+    }
+
+    /// Start `worker_threads` workers. The caller submits jobs and collects results.
+    pub fn pool(&self) -> Pool {
+        self.pool_of(self.config.worker_threads)
+    }
+
+    fn pool_of(&self, n: usize) -> Pool {
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let (res_tx, results) = mpsc::channel::<JobResult>();
+        let shared = Workers {
+            client: Arc::clone(&self.client),
+            check: Arc::new(self.config.check.clone()),
+            timeout: self.config.timeout,
+            queue: Arc::new(Mutex::new(job_rx)), // share one receiver across workers
+            backlog: Arc::new(Mutex::new(Backlog::default())),
+            results: res_tx,
+            think_time: self.config.think_time,
+            stopping: Arc::new(AtomicBool::new(false)),
+            failures: self.failures.clone(),
+            links: Arc::new(SeenLinks::default()),
+        };
+        let workers = (0..n).map(|id| shared.spawn(id)).collect();
+        Pool { jobs: Some(job_tx), results, workers, shared }
+    }
+
+    /// Check every URL once (or `checks_per_url` times), concurrently, handing
+    /// each result to `sink` as it arrives (completion order), or all at once in
+    /// input order with `Config::ordered`. The round gets its own retry budget
+    /// if one is configured. Returns once every check has a result; checks
+    /// still without one at the round deadline get a `NoResult` failure.
+    pub fn run(&self, urls: &[UrlEntry], mut sink: impl FnMut(WebsiteStatus)) {
+        let entries: Vec<Arc<UrlEntry>> = urls.iter().cloned().map(Arc::new).collect();
+        let jobs: Vec<(usize, &Arc<UrlEntry>)> = self.config.round_jobs(&entries).collect();
+        let mut pool = self.pool_of(self.config.worker_threads.min(jobs.len()).max(1));
+        let budget = self.config.retry_budget.map(|n| Arc::new(RetryBudget::new(n)));
+        let limit = self.config.round_deadline(urls);
+        let mut held = self.config.ordered.then(InOrder::default);
+        let mut emit = |seq: usize, mut status: WebsiteStatus| {
+            self.config.mark_maintenance(&urls[seq % urls.len()], &mut status);
+            match held.as_mut() {
+                Some(held) => held.hold(seq, status),
+                None => sink(status),
+            }
+        };
+        let (started, n) = (Instant::now(), jobs.len());
+        let mut left = BTreeSet::new();
+        for (i, (seq, entry)) in jobs.into_iter().enumerate() {
+            thread::sleep((started + self.config.stagger_offset(i, n)).saturating_duration_since(Instant::now()));
+            if pool.submit(1, seq, Arc::clone(entry), budget.clone()) {
+                left.insert(seq);
+            }
+        }
+        // Counted from when the last check was queued, so --stagger doesn't eat into it
+        let deadline = Instant::now() + limit;
+        while !left.is_empty() {
+            pool.revive();
+            let wait = deadline.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                for seq in left {
+                    let entry = &urls[seq % urls.len()];
+                    warn!("no result for {} within the {limit:?} round deadline", entry.url);
+                    emit(seq, missing_result(entry, format!("none within the {limit:?} round deadline")));
+                }
+                break;
+            }
+            if let Some(r) = pool.recv_timeout(wait.min(Duration::from_millis(200))) {
+                if left.remove(&r.seq) {
+                    emit(r.seq, r.status);
+                }
+            }
+        }
+        for status in held.iter_mut().flat_map(InOrder::release) {
+            sink(status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::MockClient;
+    use httpmock::prelude::*;
+    // httpmock's prelude has a `Method` too
+    use super::Method;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    }
+
+    /// Counts allocations and live heap bytes per thread, so tests running in
+    /// parallel don't disturb each other's counts.
+    struct CountingAlloc;
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            let _ = LIVE_BYTES.try_with(|n| n.set(n.get() + layout.size() as isize));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = LIVE_BYTES.try_with(|n| n.set(n.get() - layout.size() as isize));
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+    #[test]
+    fn test_success_ok() {
+        let server = MockServer::start();
+
+        let _m = server.mock(|when, then| {
+            when.method(GET).path("/ok");
+            then.status(200)
+                .header("Server", "unit-test")
+                .body("hello world");
+        });
+
+        let checker = Checker::new(
+            Config::builder()
+                .timeout(Duration::from_secs(2))
+                .header("Server", "unit-test")
+                .contains(Some("hello".to_string()))
+                .build(),
+        );
+        let status = checker.check_once(&format!("{}/ok", server.base_url()));
+
+        assert!(status.status.is_ok());
+        assert!(status.response_time.as_millis() > 0);
+    }
+
+    #[test]
+    fn test_header_mismatch() {
+        let server = MockServer::start();
+
+        let _m = server.mock(|when, then| {
+            when.method(GET).path("/h");
+            then.status(200)
+                .header("Server", "unit-test")
+                .body("ok");
+        });
+
+        let checker = Checker::new(Config::builder().retries(0).header("Server", "expected").build());
+        let status = checker.check_once(&format!("{}/h", server.base_url()));
+
+        assert!(status.status.is_err());
+        let err = status.status.err().unwrap();
+        assert!(matches!(&err, CheckError::HeaderMismatch { name, .. } if name == "Server"), "{err:?}");
+        assert!(err.to_string().contains("header mismatch"));
+    }
+
+    #[test]
+    fn test_capture_headers() {
+        let server = MockServer::start();
+        let _m = server.mock(|when, then| {
+            when.method(GET).path("/c");
+            then.status(503).header("X-Cache", "MISS").body("down");
+        });
+
+        // Captured whatever the outcome, with null for a header the response lacked
+        let config = Config::builder().retries(0).capture_header("X-Cache").capture_header("CF-Ray").build();
+        let status = Checker::new(config).check_once(&format!("{}/c", server.base_url()));
+        assert!(status.status.is_err());
+        let want: CapturedHeaders = [("X-Cache".to_string(), Some("MISS".to_string())), ("CF-Ray".to_string(), None)].into();
+        assert_eq!(status.captured_headers, want);
+        let js = serde_json::to_value(&status).unwrap();
+        assert_eq!(js["captured_headers"], serde_json::json!({"X-Cache": "MISS", "CF-Ray": null}));
+
+        // Nothing asked for, nothing recorded
+        let status = Checker::new(Config::builder().retries(0).build()).check_once(&format!("{}/c", server.base_url()));
+        assert!(status.captured_headers.is_empty());
+        assert!(serde_json::to_value(&status).unwrap().get("captured_headers").is_none());
+    }
+
+    #[test]
+    fn test_body_contains_validation() {
+        let server = MockServer::start();
+
+        let _m = server.mock(|when, then| {
+            when.method(GET).path("/b");
+            then.status(200).body("foo bar baz");
+        });
+
+        let check = |text: &str| {
+            let config = Config::builder().retries(0).contains(Some(text.to_string())).build();
+            Checker::new(config).check_once(&format!("{}/b", server.base_url()))
+        };
+        assert!(check("bar").status.is_ok());
+        assert!(check("nope").status.is_err());
+    }
+
+    #[test]
+    fn test_timeout_error() {
+        let server = MockServer::start();
+
+        let _m = server.mock(|when, then| {
+            when.method(GET).path("/slow");
+            then.status(200)
+                .delay(std::time::Duration::from_secs(3)) // server delays response
+                .body("slow");
+        });
+
+        // 1s timeout -> should time out
+        let checker = Checker::new(Config::builder().timeout(Duration::from_secs(1)).retries(0).build());
+        let status = checker.check_once(&format!("{}/slow", server.base_url()));
+        assert!(status.status.is_err());
+        let err = status.status.err().unwrap();
+        assert!(err.is_timeout(), "{err}");
+    }
+
+    #[test]
+    fn test_sub_second_timeout() {
+        let server = MockServer::start();
+        let _m = server.mock(|when, then| {
+            when.method(GET).path("/slow");
+            then.status(200).delay(Duration::from_secs(2)).body("slow");
+        });
+
+        let client = UreqClient::new();
+        let timeout = parse_duration("300ms").unwrap();
+        let settings = CheckSettings { max_retries: 0, ..Default::default() };
+        let started = Instant::now();
+        let status = check_with_retries(&client, &format!("{}/slow", server.base_url()), timeout, &settings, None, None);
+        assert!(status.status.is_err());
+        assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn test_dns_failure_error() {
+        let client = UreqClient::new();
+        let timeout = Duration::from_secs(2);
+        let status = check_with_retries(&client, "http://no-such-host.invalid/", timeout, &CheckSettings::default(), None, None);
+        let err = status.status.err().unwrap();
+        assert_eq!(err.kind(), "dns");
+        assert!(err.to_string().starts_with("dns resolution failed"), "{err}");
+    }
+
+    #[test]
+    fn test_connection_refused_error() {
+        // Bind then drop a listener so the port is known to be closed
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let client = UreqClient::new();
+        let timeout = Duration::from_secs(2);
+        let url = format!("http://127.0.0.1:{port}/");
+        let status = check_with_retries(&client, &url, timeout, &CheckSettings::default(), None, None);
+        let err = status.status.err().unwrap();
+        assert_eq!(err.kind(), "connection_refused");
+        assert!(err.to_string().starts_with("connection refused"), "{err}");
+    }
+
+    #[test]
+    #[ignore = "depends on the network's route to 10.255.255.1; http::test_classify_ureq covers the mapping"]
+    fn test_unroutable_address_error() {
+        // 10.255.255.1 is not routed; depending on the network this either times out or fails fast
+        let timeout = Duration::from_millis(300);
+        let settings = CheckSettings { max_retries: 0, ..CheckSettings::default() };
+        let err = check_with_retries(&UreqClient::new(), "http://10.255.255.1/", timeout, &settings, None, None).status.unwrap_err();
+        match err.kind() {
+            "connect_timeout" => assert!(err.to_string().starts_with("connection timeout"), "{err}"),
+            "connection_failed" => assert!(err.to_string().starts_with("connection failed"), "{err}"),
+            _ => panic!("{err:?}"),
+        }
+    }
+
+    #[test]
+    fn test_retry_budget_caps_retries() {
+        let server = MockServer::start();
+
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/down");
+            then.status(500);
+        });
+
+        let client = UreqClient::new();
+        let timeout = Duration::from_secs(2);
+        let budget = RetryBudget::new(1);
+        let url = format!("{}/down", server.base_url());
+        let settings = CheckSettings { max_retries: 3, ..Default::default() };
+        let a = check_with_retries(&client, &url, timeout, &settings, Some(&budget), None);
+        let b = check_with_retries(&client, &url, timeout, &settings, Some(&budget), None);
+
+        assert!(a.status.is_err() && b.status.is_err());
+        // 2 initial attempts + the single retry the budget allows
+        assert_eq!(m.hits(), 3);
+        assert_eq!(budget.used(), 1);
+        assert!(!budget.try_acquire());
+    }
+
+    #[test]
+    fn test_up_if_presets() {
+        let at = |status: Result<u16, CheckError>, http_status: Option<u16>| WebsiteStatus {
+            http_status,
+            ..WebsiteStatus::sample("https://example.com/", status)
+        };
+        let ok = at(Ok(200), Some(200));
+        let detail = String::new;
+        let body_fail = at(Err(CheckError::BodyValidation { missing: "ok".into() }), Some(200));
+        let header = |got: &str| CheckError::HeaderMismatch { name: "X".into(), expected: "y".into(), got: got.into() };
+        let redirect = at(Err(header("z")), Some(302));
+        let server_err = at(Err(CheckError::HttpStatus { code: 500, detail: detail() }), Some(500));
+        let dns = at(Err(CheckError::Dns { detail: detail() }), None);
+
+        let verdicts = |p: UpIf| [&ok, &body_fail, &redirect, &server_err, &dns].map(|s| p.is_up(s));
+        assert_eq!(verdicts(UpIf::Valid), [true, false, false, false, false]);
+        assert_eq!(verdicts(UpIf::AnyResponse), [true, true, true, true, false]);
+        assert_eq!(verdicts(UpIf::Status2xx), [true, true, false, false, false]);
+        assert_eq!(verdicts(UpIf::Lt400), [true, true, true, false, false]);
+
+        assert_eq!(UpIf::parse("any-response"), Ok(UpIf::AnyResponse));
+        assert!(UpIf::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn test_error_status_keeps_http_code() {
+        let server = MockServer::start();
+
+        let _m = server.mock(|when, then| {
+            when.method(GET).path("/boom");
+            then.status(503);
+        });
+
+        let client = UreqClient::new();
+        let timeout = Duration::from_secs(2);
+        let status = check_with_retries(&client, &format!("{}/boom", server.base_url()), timeout, &CheckSettings::default(), None, None);
+        assert!(status.status.is_err());
+        assert_eq!(status.http_status, Some(503));
+        assert!(UpIf::AnyResponse.is_up(&status));
+    }
+
+    #[test]
+    fn test_find_meta_refresh() {
+        let html = r#"<html><head><META HTTP-EQUIV="Refresh" CONTENT="0; URL='/next'"></head></html>"#;
+        assert_eq!(find_meta_refresh(html).as_deref(), Some("/next"));
+        let html = r#"<meta charset="utf-8"><meta http-equiv=refresh content="5;url=https://x.test/">"#;
+        assert_eq!(find_meta_refresh(html).as_deref(), Some("https://x.test/"));
+        // A plain timed reload without a target is not a redirect
+        assert_eq!(find_meta_refresh(r#"<meta http-equiv="refresh" content="30">"#), None);
+        assert_eq!(find_meta_refresh("<p>no meta here</p>"), None);
+    }
+
+    #[test]
+    fn test_follow_meta_refresh() {
+        let server = MockServer::start();
+
+        let _start = server.mock(|when, then| {
+            when.method(GET).path("/start");
+            then.status(200)
+                .header("Content-Type", "text/html")
+                .body(r#"<meta http-equiv="refresh" content="0; url=/final">"#);
+        });
+        let _final = server.mock(|when, then| {
+            when.method(GET).path("/final");
+            then.status(200).header("Content-Type", "text/html").body("welcome");
+        });
+
+        let client = UreqClient::new();
+        let timeout = Duration::from_secs(2);
+        let settings = CheckSettings {
+            contains: Some("welcome".into()),
+            max_redirects: 2,
+            follow_meta_refresh: true,
+            ..Default::default()
+        };
+        let status = check_with_retries(&client, &format!("{}/start", server.base_url()), timeout, &settings, None, None);
+        assert_eq!(status.status, Ok(200));
+        assert_eq!(status.redirect_chain, vec![format!("{}/final", server.base_url())]);
+
+        // Off by default: the refresh page itself is the final answer
+        let settings = CheckSettings { follow_meta_refresh: false, ..settings };
+        let status = check_with_retries(&client, &format!("{}/start", server.base_url()), timeout, &settings, None, None);
+        assert!(status.status.is_err());
+    }
+
+    #[test]
+    fn test_meta_refresh_loop() {
+        let server = MockServer::start();
+
+        let _a = server.mock(|when, then| {
+            when.method(GET).path("/a");
+            then.status(200)
+                .header("Content-Type", "text/html")
+                .body(r#"<meta http-equiv="refresh" content="0; url=/b">"#);
+        });
+        let _b = server.mock(|when, then| {
+            when.method(GET).path("/b");
+            then.status(200)
+                .header("Content-Type", "text/html")
+                .body(r#"<meta http-equiv="refresh" content="0; url=/a">"#);
+        });
+
+        let client = UreqClient::new();
+        let timeout = Duration::from_secs(2);
+        let settings = CheckSettings { max_redirects: 5, follow_meta_refresh: true, ..Default::default() };
+        let status = check_with_retries(&client, &format!("{}/a", server.base_url()), timeout, &settings, None, None);
+        let err = status.status.err().unwrap();
+        assert!(matches!(err, CheckError::MetaRefreshLoop { .. }), "{err:?}");
+        assert!(err.to_string().contains("meta-refresh loop"), "{err}");
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(normalize_url("HTTPS://Example.COM"), "https://example.com/");
+        assert_eq!(normalize_url("http://example.com:80/a"), "http://example.com/a");
+        assert_eq!(normalize_url("https://example.com:443?q=1"), "https://example.com/?q=1");
+        assert_eq!(normalize_url("https://example.com:8443/Path"), "https://example.com:8443/Path");
+        assert_eq!(normalize_url("https://user:Pw@Host.com/"), "https://user:Pw@host.com/");
+    }
+
+    #[test]
+    fn test_dedup_urls() {
+        let urls: Vec<UrlEntry> = ["https://example.com", "https://rust-lang.org/", "https://EXAMPLE.com/"]
+            .iter()
+            .enumerate()
+            .map(|(i, u)| UrlEntry {
+                url: u.to_string(),
+                source: UrlSource::Arg(i + 1),
+                labels: Labels::new(),
+                options: UrlOptions::default(),
+                compare: None,
+            })
+            .collect();
+        let (kept, collapsed) = dedup_urls(urls.clone(), false);
+        let kept: Vec<&str> = kept.iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(kept, vec!["https://example.com/", "https://rust-lang.org/"]);
+        assert_eq!(collapsed.len(), 1);
+        assert_eq!(collapsed[0].0.url, "https://EXAMPLE.com/");
+        assert_eq!(collapsed[0].0.source, UrlSource::Arg(3));
+        assert_eq!(collapsed[0].1, "https://example.com/");
+
+        let (kept, collapsed) = dedup_urls(urls, true);
+        assert_eq!(kept.len(), 3);
+        assert!(collapsed.is_empty());
+    }
+
+    #[test]
+    fn test_validate_url() {
+        assert!(validate_url("https://example.com/path").is_ok());
+        assert!(validate_url("http://127.0.0.1:8080").is_ok());
+        assert!(validate_url("exampl e.com").is_err());
+        assert!(validate_url("http://exampl e.com").is_err());
+        assert!(validate_url("://example.com").is_err());
+        let err = validate_url("ftp://foo").unwrap_err();
+        assert!(err.contains("unsupported scheme 'ftp'"), "{err}");
+    }
+
+    #[test]
+    fn test_apply_default_scheme() {
+        assert_eq!(apply_default_scheme("example.com", "https").as_deref(), Some("https://example.com"));
+        assert_eq!(
+            apply_default_scheme("localhost:8080/health", "http").as_deref(),
+            Some("http://localhost:8080/health")
+        );
+        assert_eq!(apply_default_scheme("http://example.com", "https"), None);
+        assert_eq!(apply_default_scheme("http+unix:///run/app.sock:/health", "https"), None);
+        // A URL further along isn't this one's scheme
+        assert_eq!(
+            apply_default_scheme("example.com/?next=https://x", "https").as_deref(),
+            Some("https://example.com/?next=https://x")
+        );
+        assert_eq!(apply_default_scheme("example.com#https://x", "http").as_deref(), Some("http://example.com#https://x"));
+        assert_eq!(apply_default_scheme("1ab://x", "http").as_deref(), Some("http://1ab://x"));
+    }
+
+    #[test]
+    fn test_invalid_urls_report_source() {
+        let path = std::env::temp_dir().join(format!("sitecheck-urls-{}.txt", std::process::id()));
+        std::fs::write(&path, "# comment\nhttps://example.com\n\nftp://foo\n").unwrap();
+        let mut entries = read_urls_from_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        entries.push(UrlEntry {
+            url: "exampl e.com".into(),
+            source: UrlSource::Arg(1),
+            labels: Labels::new(),
+            options: UrlOptions::default(),
+            compare: None,
+        });
+
+        let (valid, invalid) = partition_valid(entries);
+        assert_eq!(valid.len(), 1);
+        assert_eq!(invalid.len(), 2);
+        assert_eq!(invalid[0].0.source, UrlSource::File { path: path.clone(), line: 4 });
+        assert_eq!(invalid[0].0.source.to_string(), format!("{}:4", path.display()));
+        assert_eq!(invalid[1].0.source.to_string(), "argument #1");
+    }
+
+    #[test]
+    fn test_url_file_includes() {
+        let dir = std::env::temp_dir().join(format!("sitecheck-includes-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("team")).unwrap();
+        let root = dir.join("root.txt");
+        std::fs::write(&root, "https://a.com\n@include team/web.txt\n# done\nhttps://d.com\n").unwrap();
+        std::fs::write(dir.join("team/web.txt"), "  @include ../shared.txt\nhttps://b.com team=web\n").unwrap();
+        std::fs::write(dir.join("shared.txt"), "\nhttps://c.com\n").unwrap();
+        std::fs::write(dir.join("loop-a.txt"), "https://a.com\n@include loop-b.txt\n").unwrap();
+        std::fs::write(dir.join("loop-b.txt"), "@include loop-a.txt\n").unwrap();
+        std::fs::write(dir.join("missing.txt"), "https://a.com\n@include nope.txt\n").unwrap();
+        std::fs::write(dir.join("both.txt"), "@include shared.txt\n@include shared.txt\n").unwrap();
+
+        let entries = read_urls_from_file(&root);
+        let cycle = read_urls_from_file(&dir.join("loop-a.txt")).unwrap_err();
+        let missing = read_urls_from_file(&dir.join("missing.txt")).unwrap_err();
+        let shared_twice = read_urls_from_file(&dir.join("both.txt"));
+        std::fs::remove_dir_all(&dir).ok();
+
+        let entries = entries.unwrap();
+        let urls: Vec<&str> = entries.iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(urls, ["https://a.com", "https://c.com", "https://b.com", "https://d.com"]);
+        // Included URLs point at the file and line they came from
+        assert_eq!(entries[1].source, UrlSource::File { path: dir.join("team/../shared.txt"), line: 2 });
+        assert_eq!(entries[2].labels.get("team").map(String::as_str), Some("web"));
+        assert_eq!(entries[3].source, UrlSource::File { path: root, line: 4 });
+
+        assert_eq!(cycle.kind(), io::ErrorKind::InvalidData);
+        let msg = cycle.to_string();
+        assert!(msg.starts_with("include cycle: ") && msg.ends_with("loop-a.txt"), "{msg}");
+        assert!(msg.contains("loop-b.txt -> "), "{msg}");
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        assert!(missing.to_string().contains("missing.txt:2: cannot include"), "{missing}");
+        // Including the same file twice is not a cycle
+        assert_eq!(shared_twice.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_resolve_url_files() {
+        let dir = std::env::temp_dir().join(format!("sitecheck-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.txt", "a.urls", "notes.md", "team-x.txt"] {
+            std::fs::write(dir.join(name), "https://example.com\n").unwrap();
+        }
+
+        let from_dir = resolve_url_files(dir.to_str().unwrap()).unwrap();
+        let glob = dir.join("team-*.txt");
+        let from_glob = resolve_url_files(glob.to_str().unwrap()).unwrap();
+        let missing = resolve_url_files(dir.join("*.csv").to_str().unwrap());
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(from_dir, vec![dir.join("a.urls"), dir.join("b.txt"), dir.join("team-x.txt")]);
+        assert_eq!(from_glob, vec![dir.join("team-x.txt")]);
+        assert!(missing.is_err());
+        assert_eq!(resolve_url_files("urls.txt").unwrap(), vec![PathBuf::from("urls.txt")]);
+    }
+
+    #[test]
+    fn test_parse_sitemap() {
+        let xml = r#"<?xml version="1.0"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.com/</loc><lastmod>2025-01-01</lastmod></url>
+  <url><loc> https://example.com/a?x=1&amp;y=2 </loc></url>
+  <url><loc><![CDATA[https://example.com/b]]></loc></url>
+</urlset>"#;
+        let (kind, locs) = parse_sitemap(xml).unwrap();
+        assert_eq!(kind, SitemapKind::UrlSet);
+        assert_eq!(locs, vec!["https://example.com/", "https://example.com/a?x=1&y=2", "https://example.com/b"]);
+
+        let (kind, _) = parse_sitemap("<sitemapindex><sitemap><loc>https://x/s1.xml</loc></sitemap></sitemapindex>").unwrap();
+        assert_eq!(kind, SitemapKind::Index);
+
+        let err = parse_sitemap("<urlset>\n<url><loc>https://x/</url>\n</urlset>").unwrap_err();
+        assert_eq!(err, "unclosed <loc> element at line 2");
+        let err = parse_sitemap("<urlset><url><loc></loc></url></urlset>").unwrap_err();
+        assert!(err.starts_with("empty <loc>"));
+        assert!(parse_sitemap("<html></html>").is_err());
+    }
+
+    #[test]
+    fn test_load_sitemap_index() {
+        let server = MockServer::start();
+        let base = server.base_url();
+
+        let _index = server.mock(|when, then| {
+            when.method(GET).path("/sitemap.xml");
+            then.status(200).body(format!(
+                "<sitemapindex><sitemap><loc>{base}/a.xml</loc></sitemap><sitemap><loc>{base}/b.xml</loc></sitemap></sitemapindex>"
+            ));
+        });
+        let _a = server.mock(|when, then| {
+            when.method(GET).path("/a.xml");
+            then.status(200).body(format!("<urlset><url><loc>{base}/1</loc></url><url><loc>{base}/2</loc></url></urlset>"));
+        });
+        let _b = server.mock(|when, then| {
+            when.method(GET).path("/b.xml");
+            then.status(200).body(format!("<urlset><url><loc>{base}/3</loc></url></urlset>"));
+        });
+
+        let client = UreqClient::new();
+        let timeout = Duration::from_secs(2);
+        let entries = load_sitemap(&client, &format!("{base}/sitemap.xml"), None, timeout, 2).unwrap();
+        let urls: Vec<_> = entries.iter().map(|e| e.url.clone()).collect();
+        assert_eq!(urls, vec![format!("{base}/1"), format!("{base}/2"), format!("{base}/3")]);
+        assert_eq!(entries[2].source.to_string(), format!("{base}/b.xml <loc> #1"));
+
+        let capped = load_sitemap(&client, &format!("{base}/sitemap.xml"), Some(2), timeout, 2).unwrap();
+        assert_eq!(capped.len(), 2);
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("https://example.com/").unwrap(), vec!["https://example.com/"]);
+        assert_eq!(
+            expand_braces("https://shard{1..3}.example.com/health").unwrap(),
+            vec!["https://shard1.example.com/health", "https://shard2.example.com/health", "https://shard3.example.com/health"]
+        );
+        assert_eq!(expand_braces("http://h/{08..10}").unwrap(), vec!["http://h/08", "http://h/09", "http://h/10"]);
+        assert_eq!(expand_braces("http://h/{0..20..10}").unwrap(), vec!["http://h/0", "http://h/10", "http://h/20"]);
+        assert_eq!(expand_braces("http://h/{3..1}").unwrap(), vec!["http://h/3", "http://h/2", "http://h/1"]);
+        assert_eq!(expand_braces("http://h{1..2}/{1..2}").unwrap().len(), 4);
+
+        assert!(expand_braces("http://h/{1..{2..3}}").unwrap_err().contains("nested"));
+        assert!(expand_braces("http://h/{1..3").unwrap_err().contains("unclosed"));
+        assert!(expand_braces("http://h/1..3}").unwrap_err().contains("unmatched"));
+        assert!(expand_braces("http://h/{a..c}").unwrap_err().contains("not an integer"));
+        assert!(expand_braces("http://h/{1..5..0}").unwrap_err().contains("step"));
+        assert!(expand_braces("http://h/{1..100000}").is_err());
+    }
+
+    #[test]
+    fn test_worker_count() {
+        assert_eq!(worker_count(100_000, DEFAULT_MAX_THREADS, 5000, false), 1024);
+        assert_eq!(worker_count(100_000, 2048, 5000, false), 2048);
+        assert_eq!(worker_count(50, DEFAULT_MAX_THREADS, 3, true), 3);
+        // Periodic runs keep the requested count; one-shot with no URLs still gets a worker
+        assert_eq!(worker_count(50, DEFAULT_MAX_THREADS, 3, false), 50);
+        assert_eq!(worker_count(50, DEFAULT_MAX_THREADS, 0, true), 1);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1500ms"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1m30s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h2m3s4ms"), Ok(Duration::from_millis(3_723_004)));
+        for bad in ["", "5x", "m", "1.5s", "-3s", "5 m"] {
+            let err = parse_duration(bad).unwrap_err();
+            assert!(err.contains("1m30s"), "{bad}: {err}");
+        }
+
+        assert_eq!(format_duration(Duration::from_secs(90)), "1m30s");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1s500ms");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+    }
+
+    #[test]
+    fn test_split_url_options() {
+        let (url, opts) = split_url_options("https://slow.example.com/report;timeout=30;retries=0").unwrap();
+        assert_eq!(url, "https://slow.example.com/report");
+        assert_eq!(opts.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(opts.retries, Some(0));
+
+        let (url, opts) = split_url_options("https://x.com/a;jsessionid=abc").unwrap();
+        assert_eq!(url, "https://x.com/a;jsessionid=abc");
+        assert_eq!(opts, UrlOptions::default());
+
+        assert!(split_url_options("https://x.com;timeout=soon").is_err());
+
+        let (_, opts) = split_url_options("https://x.com;timeout=750ms").unwrap();
+        assert_eq!(opts.timeout, Some(Duration::from_millis(750)));
+
+        let (url, opts) = split_url_options("https://x.com/;maintenance=Sun@02:00-03:00;maintenance=daily@04:00-04:15;retries=1").unwrap();
+        assert_eq!(url, "https://x.com/");
+        let windows: Vec<String> = opts.maintenance.iter().map(ToString::to_string).collect();
+        assert_eq!(windows, ["Sun 02:00-03:00", "daily 04:00-04:15"]);
+        assert!(split_url_options("https://x.com;maintenance=Sun").is_err());
+    }
+
+    #[test]
+    fn test_dry_run_config_json() {
+        let (url, options) = split_url_options("https://slow.example.com/;timeout=30").unwrap();
+        let entry = UrlEntry { options, ..UrlEntry::new(url, UrlSource::Arg(1)) };
+        let cfg = Config::builder().threads(4).up_if(UpIf::Status2xx).urls(vec![entry]).build();
+        let js: serde_json::Value = serde_json::from_str(&serde_json::to_string(&cfg).unwrap()).unwrap();
+        assert_eq!(js["timeout_ms"], 5000);
+        assert_eq!(js["up_if"], "2xx");
+        assert_eq!(js["urls"][0]["source"], "argument #1");
+        assert_eq!(js["urls"][0]["timeout_ms"], 30000);
+        assert!(js["urls"][0].get("retries").is_none());
+    }
+
+    #[test]
+    fn test_time_display() {
+        let t: DateTime<Utc> = "2025-08-21T23:00:00Z".parse().unwrap();
+        assert_eq!(TimeDisplay::default().format(t), "2025-08-21T23:00:00Z");
+
+        let tokyo = TimeDisplay::parse(Some("Asia/Tokyo"), Some("%Y-%m-%d %H:%M %Z")).unwrap();
+        assert_eq!(tokyo.format(t), "2025-08-22 08:00 JST");
+        assert_eq!(TimeDisplay::parse(Some("Asia/Tokyo"), None).unwrap().format(t), "2025-08-22T08:00:00+09:00");
+        assert_eq!(TimeDisplay::parse(Some("UTC"), None).unwrap().format_or(t, "%H:%M:%S"), "23:00:00");
+
+        assert!(TimeDisplay::parse(Some("Mars/Olympus_Mons"), None).is_err());
+        assert!(TimeDisplay::parse(None, Some("%Y %")).is_err());
+    }
+
+    #[test]
+    fn test_read_results() {
+        let ndjson = r#"{"url":"https://a/","status":{"Ok":200},"response_time":100,"timestamp":"2025-08-21T23:00:00Z","labels":{"team":"web"}}
+{"url":"https://a/","status":{"Err":"read timeout: timed out"},"response_time":0,"timestamp":"2025-08-21T23:01:00Z","labels":{"team":"web"}}
+--- stats summary ---
+{"url":"https://b/","status":{"Err":"request error: 503"},"response_time":0,"timestamp":"2025-08-21T23:00:00Z","http_status":503,"labels":{}}
+"#;
+        let stats = read_results(ndjson.as_bytes(), UpIf::Valid).unwrap();
+        assert_eq!(stats["https://a/"].checks, 2);
+        assert_eq!(stats["https://a/"].successes, 1);
+        assert_eq!(stats["https://a/"].labels.get("team").map(String::as_str), Some("web"));
+        assert_eq!(stats["https://b/"].successes, 0);
+
+        let stats = read_results(ndjson.as_bytes(), UpIf::AnyResponse).unwrap();
+        assert_eq!(stats["https://b/"].successes, 1);
+
+        let err = read_results("{not json}\n".as_bytes(), UpIf::Valid).unwrap_err();
+        assert!(err.to_string().starts_with("line 1:"));
+    }
+
+    #[test]
+    fn test_maintenance_failures() {
+        let status = |url: &str, result: Result<u16, CheckError>, timestamp: DateTime<Utc>| WebsiteStatus {
+            timestamp,
+            ..WebsiteStatus::sample(url, result)
+        };
+        let refused = || Err(CheckError::ConnectionRefused { detail: "refused".into() });
+        let sunday = Utc.with_ymd_and_hms(2025, 8, 24, 2, 30, 0).unwrap();
+        let monday = Utc.with_ymd_and_hms(2025, 8, 25, 2, 30, 0).unwrap();
+
+        let config = Config::builder().maintenance(vec![MaintenanceWindow::parse("Sun 02:00-03:00").unwrap()]).build();
+        let plain = UrlEntry::new("https://a/", UrlSource::Arg(1));
+        let mut own = UrlEntry::new("https://b/", UrlSource::Arg(2));
+        own.options.maintenance.push(MaintenanceWindow::parse("Mon 02:00-03:00").unwrap());
+
+        let mut stats = HashMap::new();
+        let mut check = |entry: &UrlEntry, result, at| {
+            let mut s = status(&entry.url, result, at);
+            config.mark_maintenance(entry, &mut s);
+            let flagged = s.maintenance;
+            record_status(&mut stats, s, UpIf::Valid);
+            flagged
+        };
+        // Only failures inside a window are flagged
+        assert!(check(&plain, refused(), sunday));
+        assert!(!check(&plain, Ok(200), sunday));
+        assert!(!check(&plain, refused(), monday));
+        // A URL's own windows add to the run's
+        assert!(check(&own, refused(), monday));
+        assert!(check(&own, refused(), sunday));
+
+        let a = &stats["https://a/"];
+        assert_eq!((a.checks, a.successes, a.maintenance), (2, 1, 1));
+        assert_eq!(a.uptime(), 50.0);
+        let b = &stats["https://b/"];
+        assert_eq!((b.checks, b.maintenance), (0, 2));
+        assert!(b.failures.is_empty());
+
+        // The flag round-trips, and is left out of JSON when unset
+        let mut flagged = status("https://a/", refused(), sunday);
+        flagged.maintenance = true;
+        let json = serde_json::to_string(&flagged).unwrap();
+        assert!(json.contains("\"maintenance\":true"), "{json}");
+        assert!(serde_json::from_str::<WebsiteStatus>(&json).unwrap().maintenance);
+        assert!(!serde_json::to_string(&status("https://a/", Ok(200), sunday)).unwrap().contains("maintenance"));
+    }
+
+    #[test]
+    fn test_check_error_json() {
+        let error = CheckError::HeaderMismatch { name: "Server".into(), expected: "nginx".into(), got: "apache".into() };
+        let status = WebsiteStatus { http_status: Some(200), ..WebsiteStatus::sample("https://a/", Err(error.clone())) };
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(
+            json["status"]["Err"],
+            serde_json::json!({
+                "kind": "header_mismatch",
+                "message": "header mismatch: Server expected 'nginx' got 'apache'",
+                "name": "Server",
+                "expected": "nginx",
+                "got": "apache",
+            })
+        );
+        let back: WebsiteStatus = serde_json::from_value(json).unwrap();
+        assert_eq!(back.status, Err(error));
+
+        let json = serde_json::to_value(CheckError::TooManyRedirects { max: 3 }).unwrap();
+        assert_eq!(json["kind"], "too_many_redirects");
+        assert_eq!(json["max"], 3);
+        assert_eq!(serde_json::from_value::<CheckError>(json).unwrap(), CheckError::TooManyRedirects { max: 3 });
+
+        // Results written before errors were typed still load
+        let legacy = r#"{"url":"https://a/","status":{"Err":"read timeout: timed out"},"response_time":0,"timestamp":"2025-08-21T23:01:00Z","labels":{}}"#;
+        let legacy: WebsiteStatus = serde_json::from_str(legacy).unwrap();
+        assert_eq!(legacy.status, Err(CheckError::Other { message: "read timeout: timed out".into() }));
+        assert_eq!(legacy.schema_version, 0);
+        assert_eq!((legacy.attempts, legacy.attempt_errors.len()), (1, 0));
+    }
+
+    #[test]
+    fn test_website_status_round_trip() {
+        let timestamp = Utc.with_ymd_and_hms(2025, 8, 21, 23, 0, 0).unwrap() + chrono::Duration::nanoseconds(123_456_789);
+        let ok = WebsiteStatus {
+            schema_version: SCHEMA_VERSION,
+            url: "https://a/".into(),
+            status: Ok(200),
+            attempts: 2,
+            attempt_errors: vec![CheckError::ReadTimeout { detail: "timed out".into() }],
+            response_time: Duration::from_millis(1234),
+            timestamp,
+            http_status: Some(200),
+            redirect_chain: vec!["https://a/home".into()],
+            content_length: Some(4096),
+            maintenance: false,
+            captured_headers: CapturedHeaders::new(),
+            body_snippet: None,
+            saved_body: None,
+            comparison: None,
+            clock_skew: None,
+            compression: None,
+            certificate: None,
+            links: None,
+            labels: Labels::from([("team".into(), "web".into())]),
+        };
+        let failed = WebsiteStatus {
+            http_status: Some(404),
+            timestamp,
+            ..WebsiteStatus::sample("https://a/", Err(CheckError::UnexpectedStatus { code: 404, expected: "2xx".into() }))
+        };
+        let unreachable = WebsiteStatus {
+            status: Err(CheckError::Dns { detail: "no such host".into() }),
+            http_status: None,
+            ..failed.clone()
+        };
+        for status in [ok, failed, unreachable] {
+            let json = serde_json::to_string(&status).unwrap();
+            assert!(json.starts_with(r#"{"schema_version":1,"#), "{json}");
+            let back: WebsiteStatus = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, status);
+            assert_eq!(serde_json::to_string(&back).unwrap(), json);
+        }
+
+        // Results as checks produce them round-trip too: the time is already whole milliseconds
+        let client = MockClient::default().respond("http://site.test/", 200, &[], "");
+        let checked = Checker::with_client(Config::default(), Arc::new(client)).check_once("http://site.test/");
+        assert_eq!(checked.response_time.subsec_nanos() % 1_000_000, 0);
+        assert_eq!(serde_json::from_value::<WebsiteStatus>(serde_json::to_value(&checked).unwrap()).unwrap(), checked);
+    }
+
+    #[test]
+    fn test_parse_url_line_labels() {
+        let (url, labels) = parse_url_line("https://x.com team=payments  env=prod").unwrap();
+        assert_eq!(url, "https://x.com");
+        assert_eq!(labels.get("team").map(String::as_str), Some("payments"));
+        assert_eq!(labels.get("env").map(String::as_str), Some("prod"));
+
+        let (_, labels) = parse_url_line("https://x.com").unwrap();
+        assert!(labels.is_empty());
+        assert!(parse_url_line("https://x.com team").is_err());
+        assert!(parse_label("=oops").is_err());
+    }
+
+    #[test]
+    fn test_group_stats_by_label() {
+        let mut stats: HashMap<String, UrlStats> = HashMap::new();
+        let mut add = |url: &str, team: Option<&str>, ok: &[bool]| {
+            let st = stats.entry(url.to_string()).or_default();
+            for &o in ok {
+                st.record(o, Duration::from_millis(100), 1);
+            }
+            if let Some(t) = team {
+                st.labels.insert("team".into(), t.into());
+            }
+        };
+        add("https://a/", Some("payments"), &[true, true]);
+        add("https://b/", Some("payments"), &[false, true]);
+        add("https://c/", Some("search"), &[true]);
+        add("https://d/", None, &[false]);
+
+        let groups = group_stats(&stats, "team", false);
+        let (urls, pay) = &groups["payments"];
+        assert_eq!((*urls, pay.checks, pay.successes), (2, 4, 3));
+        assert_eq!(pay.uptime(), 75.0);
+        assert_eq!(groups["search"].1.checks, 1);
+        assert_eq!(groups["(none)"].1.successes, 0);
+    }
+
+    #[test]
+    fn test_summary_order() {
+        let mut stats: HashMap<String, UrlStats> = HashMap::new();
+        // (url, input position, results); c ties b on uptime and avg
+        for (url, order, ok) in [
+            ("https://c/", 0, &[true, false][..]),
+            ("https://a/", 1, &[true][..]),
+            ("https://b/", 2, &[false, true][..]),
+            ("https://z/", 3, &[][..]), // seeded but never checked
+        ] {
+            let st = stats.entry(url.to_string()).or_insert_with(|| UrlStats { order, ..Default::default() });
+            for &o in ok {
+                st.record(o, Duration::from_millis(100), 1);
+            }
+        }
+        let order = |sort_by, sort_desc| {
+            let style = SummaryStyle { sort_by, sort_desc, ..Default::default() };
+            summary_rows(&stats, &style).into_iter().map(|(url, _, _)| url).collect::<Vec<_>>()
+        };
+        assert_eq!(order(SortBy::Input, false), ["https://c/", "https://a/", "https://b/"]);
+        assert_eq!(order(SortBy::Url, true), ["https://c/", "https://b/", "https://a/"]);
+        // b and c tie at 50%; the tie goes by URL in both directions
+        assert_eq!(order(SortBy::Uptime, false), ["https://b/", "https://c/", "https://a/"]);
+        assert_eq!(order(SortBy::Uptime, true), ["https://a/", "https://b/", "https://c/"]);
+        assert_eq!(order(SortBy::Checks, true), ["https://b/", "https://c/", "https://a/"]);
+
+        assert!(SortBy::parse("latency").is_err());
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!([0, 99, 100, 250, 499, 500].map(|ms| spark_level(ms, 500)), [0, 1, 1, 3, 6, 7]);
+        // Nothing above the top, and an all-zero window stays flat at the bottom
+        assert_eq!((spark_level(900, 500), spark_level(0, 0)), (7, 0));
+        assert_eq!(sparkline([0, 50, 100], false), "▁▄█");
+        assert_eq!(sparkline([0, 50, 100], true), "_~#");
+        assert_eq!(sparkline([], false), "");
+
+        // Only the last SPARKLINE_CHECKS checks are kept; groups get none
+        let mut st = UrlStats::default();
+        for ms in 0..SPARKLINE_CHECKS as u64 + 5 {
+            st.record(true, Duration::from_millis(ms), 1);
+        }
+        assert_eq!(st.recent_ms.len(), SPARKLINE_CHECKS);
+        assert_eq!(st.recent_ms.front(), Some(&5));
+        let stats = HashMap::from([("https://a/".to_string(), st)]);
+        assert!(group_stats(&stats, "team", false)["(none)"].1.recent_ms.is_empty());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "https://a/"));
+        assert!(glob_match("https://*.example.com/*", "https://api.example.com/health"));
+        assert!(glob_match("*pay*", "https://payments.internal/"));
+        assert!(glob_match("https://shard?.x/", "https://shard7.x/"));
+        assert!(!glob_match("https://shard?.x/", "https://shard17.x/"));
+        assert!(!glob_match("*.org/*", "https://example.com/"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+    }
+
+    #[test]
+    fn test_apply_filters() {
+        let entries: Vec<UrlEntry> = ["https://pay.x/", "https://pay.x/admin", "https://search.x/"]
+            .iter()
+            .enumerate()
+            .map(|(i, u)| UrlEntry {
+                url: u.to_string(),
+                source: UrlSource::Arg(i + 1),
+                labels: Labels::new(),
+                options: UrlOptions::default(),
+                compare: None,
+            })
+            .collect();
+        let urls = |v: Vec<UrlEntry>| v.into_iter().map(|e| e.url).collect::<Vec<_>>();
+
+        let inc = [UrlFilter::parse("https://pay.x/*").unwrap()];
+        let exc = [UrlFilter::parse("*admin").unwrap()];
+        let got = apply_filters(entries.clone(), &inc, &exc, false).unwrap();
+        assert_eq!(urls(got), vec!["https://pay.x/"]);
+
+        // A typo'd filter is a hard error unless --allow-empty
+        let typo = [UrlFilter::parse("https://pya.x/*").unwrap()];
+        let err = apply_filters(entries.clone(), &typo, &[], false).unwrap_err();
+        assert!(err.contains("pya"), "{err}");
+        assert!(apply_filters(entries.clone(), &typo, &[], true).unwrap().is_empty());
+
+        // Excluding everything is also an error by default
+        let all = [UrlFilter::parse("*").unwrap()];
+        assert!(apply_filters(entries, &[], &all, false).is_err());
+    }
+
+    #[test]
+    fn test_concurrency_50() {
+        let server = MockServer::start();
+
+        // Create 50 endpoints
+        for i in 0..50 {
+            let path = format!("/ok{i}");
+            server.mock(|when, then| {
+                when.method(GET).path(path.clone());
+                then.status(200).body("ok");
+            });
+        }
+
+        let urls: Vec<UrlEntry> = (0..50)
+            .map(|i| UrlEntry::new(format!("{}/ok{i}", server.base_url()), UrlSource::Arg(i + 1)))
+            .collect();
+
+        // One worker per URL, all through the real pool
+        let checker = Checker::new(Config::builder().threads(50).timeout(Duration::from_secs(2)).build());
+        let mut results: Vec<WebsiteStatus> = Vec::new();
+        checker.run(&urls, |s| results.push(s));
+
+        assert_eq!(results.len(), 50);
+        assert!(results.iter().all(|s| s.status.is_ok()));
+    }
+
+    #[test]
+    fn test_pool_tags_rounds() {
+        let server = MockServer::start();
+        let _m = server.mock(|when, then| {
+            when.method(GET).path("/ok");
+            then.status(200);
+        });
+
+        let checker = Checker::new(Config::builder().threads(2).build());
+        let pool = checker.pool();
+        let url = format!("{}/ok", server.base_url());
+        for round in [1, 2] {
+            assert!(pool.submit(round, 0, Arc::new(UrlEntry::new(url.clone(), UrlSource::Arg(1))), None));
+        }
+        let mut rounds: Vec<u64> = (0..2)
+            .map(|_| pool.recv_timeout(Duration::from_secs(5)).unwrap().round)
+            .collect();
+        rounds.sort();
+        assert_eq!(rounds, [1, 2]);
+        assert_eq!(pool.discard_queued(), 0);
+    }
+
+    #[test]
+    fn test_backlog_discard() {
+        let mut backlog = Backlog { generation: 0, queued: 3 };
+        assert!(backlog.take(0));
+        assert_eq!(backlog.discard(), 2);
+        // Jobs queued before the discard are dropped, later ones still run
+        assert!(!backlog.take(0));
+        backlog.queued += 1;
+        assert!(backlog.take(1));
+        assert_eq!(backlog.discard(), 0);
+    }
+
+    #[test]
+    fn test_mock_client_checks() {
+        let client = MockClient::default()
+            .respond("http://site.test/", 200, &[("Server", "nginx")], "hello there")
+            .respond("http://site.test/down", 500, &[], "oops")
+            .fail("http://gone.test/", TransportError::Dns("no such host".into()))
+            .fail("http://trunc.test/", TransportError::BodyRead { status: 200, detail: "reset".into() });
+        let client = Arc::new(client);
+        let config = Config::builder().retries(0).header("Server", "nginx").contains(Some("hello".into())).build();
+        let checker = Checker::with_client(config, client.clone());
+
+        assert_eq!(checker.check_once("http://site.test/").status, Ok(200));
+        let down = checker.check_once("http://site.test/down");
+        assert_eq!(down.http_status, Some(500));
+        assert!(matches!(down.status, Err(CheckError::HttpStatus { code: 500, .. })), "{:?}", down.status);
+        assert_eq!(checker.check_once("http://gone.test/").status.unwrap_err().kind(), "dns");
+        let trunc = checker.check_once("http://trunc.test/");
+        assert_eq!(trunc.status.unwrap_err().kind(), "body_read");
+        assert_eq!(trunc.http_status, Some(200));
+
+        let requests = client.requests.lock().unwrap();
+        assert!(requests.iter().all(|r| r.read_body == ReadBody::Always && r.timeout == DEFAULT_TIMEOUT));
+    }
+
+    #[test]
+    fn test_mock_client_skips_unneeded_bodies() {
+        let client = Arc::new(MockClient::default().respond("http://site.test/", 200, &[("Server", "apache")], "hi"));
+        let checker = Checker::with_client(Config::builder().retries(0).header("Server", "nginx").build(), client.clone());
+
+        let status = checker.check_once("http://site.test/");
+        assert!(matches!(status.status, Err(CheckError::HeaderMismatch { .. })), "{:?}", status.status);
+        assert_eq!(client.requests.lock().unwrap()[0].read_body, ReadBody::Never);
+    }
+
+    #[test]
+    fn test_mock_client_meta_refresh() {
+        let html = [("Content-Type", "text/html")];
+        let client = MockClient::default()
+            .respond("http://site.test/", 200, &html, r#"<meta http-equiv="refresh" content="0; url=/home">"#)
+            .respond("http://site.test/home", 200, &html, "home")
+            .respond("http://loop.test/a", 200, &html, r#"<meta http-equiv="refresh" content="0; url=/b">"#)
+            .respond("http://loop.test/b", 200, &html, r#"<meta http-equiv="refresh" content="0; url=/a">"#);
+        let config = Config::builder().retries(0).follow_meta_refresh(true).build();
+        let checker = Checker::with_client(config, Arc::new(client));
+
+        let status = checker.check_once("http://site.test/");
+        assert_eq!(status.status, Ok(200));
+        assert_eq!(status.redirect_chain, vec!["http://site.test/home".to_string()]);
+        let status = checker.check_once("http://loop.test/a");
+        assert!(matches!(status.status, Err(CheckError::MetaRefreshLoop { .. })), "{:?}", status.status);
+    }
+
+    #[test]
+    fn test_redirects_share_one_budget() {
+        let html = [("Content-Type", "text/html")];
+        let client = MockClient::default()
+            .respond("http://site.test/", 302, &[("Location", "/login")], "")
+            .respond("http://site.test/login", 200, &html, r#"<meta http-equiv="refresh" content="0; url=/home">"#)
+            .respond("http://site.test/home", 200, &html, "home");
+        let client = Arc::new(client);
+        let check = |max| {
+            let config = Config::builder().retries(0).follow_meta_refresh(true).max_redirects(max).build();
+            Checker::with_client(config, client.clone()).check_once("http://site.test/")
+        };
+
+        let status = check(2);
+        assert_eq!(status.status, Ok(200));
+        assert_eq!(status.redirect_chain, ["http://site.test/login", "http://site.test/home"]);
+        // The HTTP hop uses up the budget the meta-refresh needed
+        let status = check(1);
+        assert_eq!(status.status, Err(CheckError::TooManyRedirects { max: 1 }));
+        assert_eq!(status.redirect_chain, ["http://site.test/login"]);
+        // With no budget the redirect itself is the answer
+        let status = check(0);
+        assert_eq!(status.http_status, Some(302));
+        assert!(status.redirect_chain.is_empty());
+        assert!(client.requests.lock().unwrap().iter().all(|r| r.max_redirects == 0));
+    }
+
+    #[test]
+    fn test_redirect_loop() {
+        let client = MockClient::default()
+            .respond("http://loop.test/a", 302, &[("Location", "/b")], "")
+            .respond("http://loop.test/b", 302, &[("Location", "/c")], "")
+            .respond("http://loop.test/c", 302, &[("Location", "/b")], "");
+        let client = Arc::new(client);
+        let config = Config::builder().retries(0).max_redirects(10).build();
+        let status = Checker::with_client(config, client.clone()).check_once("http://loop.test/a");
+
+        // Caught the first time round, not after the budget runs out
+        assert_eq!(status.status, Err(CheckError::RedirectLoop { url: "http://loop.test/b".into() }));
+        assert_eq!(status.http_status, Some(302));
+        assert_eq!(status.redirect_chain, ["http://loop.test/b", "http://loop.test/c"]);
+        assert_eq!(client.requests.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_oauth_token_stays_with_checked_origin() {
+        let client = MockClient::default()
+            .respond("http://site.test/", 302, &[("Location", "/login")], "")
+            .respond("http://site.test/login", 302, &[("Location", "http://elsewhere.test/")], "")
+            .respond("http://elsewhere.test/", 200, &[], "");
+        let client = Arc::new(client);
+        let oauth = OAuthSettings { token_url: "http://auth.test/token".into(), client_id: "sitecheck".into(), client_secret: "hunter2".into() };
+        let config = Config::builder().retries(0).oauth(Some(oauth)).build();
+        let checker = Checker::with_client(config, client.clone());
+        checker.oauth().unwrap().set("tok3n", Some(3600));
+
+        assert_eq!(checker.check_once("http://site.test/").status, Ok(200));
+        let sent: Vec<_> = client.requests.lock().unwrap().iter().map(|r| (r.url.clone(), r.authorization.clone())).collect();
+        let token = Some(Authorization::bearer("tok3n"));
+        assert_eq!(
+            sent,
+            [
+                ("http://site.test/".to_string(), token.clone()),
+                ("http://site.test/login".to_string(), token),
+                ("http://elsewhere.test/".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_redirects() {
+        let html = [("Content-Type", "text/html")];
+        let refresh = |to: &str| format!(r#"<meta http-equiv="refresh" content="0; url=/{to}">"#);
+        // A five-page cycle, longer than the redirect limit, so it fails as too many hops
+        let mut client = MockClient::default();
+        for (page, next) in ["a", "b", "c", "d", "e"].iter().zip(["b", "c", "d", "e", "a"]) {
+            client = client.respond(&format!("http://loop.test/{page}"), 200, &html, &refresh(next));
+        }
+        let client = client.respond("http://ok.test/", 200, &html, &refresh("done"));
+        let client = Arc::new(client.respond("http://ok.test/done", 200, &html, "done"));
+        let check = |record: Option<usize>, url: &str| {
+            let config = Config::builder().retries(0).follow_meta_refresh(true).max_redirects(3).record_redirects(record).build();
+            Checker::with_client(config, client.clone()).check_once(url)
+        };
+
+        let status = check(Some(2), "http://loop.test/a");
+        assert_eq!(status.status, Err(CheckError::TooManyRedirects { max: 3 }));
+        assert_eq!(status.redirect_chain, ["http://loop.test/b", "http://loop.test/c", REDIRECTS_TRUNCATED]);
+        let status = check(None, "http://loop.test/a");
+        assert_eq!(status.redirect_chain, ["http://loop.test/b", "http://loop.test/c", "http://loop.test/d"]);
+        // Hops are followed past the record limit, and a chain that fits is not marked
+        let status = check(Some(0), "http://ok.test/");
+        assert_eq!(status.status, Ok(200));
+        assert_eq!(status.redirect_chain, [REDIRECTS_TRUNCATED]);
+        assert_eq!(check(Some(1), "http://ok.test/").redirect_chain, ["http://ok.test/done"]);
+    }
+
+    #[test]
+    fn test_head_size_only() {
+        let client = MockClient::default()
+            .respond("http://cdn.test/video.mp4", 200, &[("Content-Length", "1048576")], "")
+            .respond("http://cdn.test/stream", 200, &[], "")
+            .respond_to(Method::GetFirstByte, "http://cdn.test/stream", 206, &[("Content-Range", "bytes 0-0/5000")]);
+        let client = Arc::new(client);
+        let check = |range_fallback: bool, url: &str| {
+            client.requests.lock().unwrap().clear();
+            let config = Config::builder().retries(0).head_size_only(true, range_fallback).build();
+            let status = Checker::with_client(config, client.clone()).check_once(url);
+            let methods: Vec<Method> = client.requests.lock().unwrap().iter().map(|r| r.method).collect();
+            (status, methods)
+        };
+
+        let (status, methods) = check(false, "http://cdn.test/video.mp4");
+        assert_eq!((status.status, status.content_length), (Ok(200), Some(1048576)));
+        assert_eq!(methods, [Method::Head]);
+        // No size on HEAD: unknown, unless a ranged GET is allowed to ask
+        let (status, methods) = check(false, "http://cdn.test/stream");
+        assert_eq!((status.status, status.content_length), (Ok(200), None));
+        assert_eq!(methods, [Method::Head]);
+        let (status, methods) = check(true, "http://cdn.test/stream");
+        assert_eq!((status.status, status.content_length), (Ok(200), Some(5000)));
+        assert_eq!(methods, [Method::Head, Method::GetFirstByte]);
+
+        // Plain checks send GET and record no size
+        let checker = Checker::with_client(Config::builder().retries(0).build(), client.clone());
+        assert_eq!(checker.check_once("http://cdn.test/video.mp4").content_length, None);
+    }
+
+    /// Times out until it has been asked `failures` times, then answers 200.
+    struct Flaky {
+        failures: usize,
+        calls: AtomicUsize,
+    }
+
+    impl HttpClient for Flaky {
+        fn execute(&self, _req: CheckRequest) -> Result<CheckResponse, TransportError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err(TransportError::ReadTimeout("timed out".into()))
+            } else {
+                Ok(CheckResponse { status: 200, ..Default::default() })
+            }
+        }
+    }
+
+    #[test]
+    fn test_attempts_recorded() {
+        let flaky = |failures| Flaky { failures, calls: AtomicUsize::new(0) };
+        let timeout = Duration::from_secs(1);
+        let timed_out = CheckError::ReadTimeout { detail: "timed out".into() };
+
+        let settings = CheckSettings { max_retries: 3, ..Default::default() };
+        let first_try = check_with_retries(&flaky(0), "http://a.test/", timeout, &settings, None, None);
+        assert_eq!((first_try.attempts, first_try.attempt_errors.len()), (1, 0));
+        let retried = check_with_retries(&flaky(2), "http://a.test/", timeout, &settings, None, None);
+        assert_eq!(retried.status, Ok(200));
+        assert_eq!(retried.attempts, 3);
+        assert_eq!(retried.attempt_errors, vec![timed_out.clone(), timed_out.clone()]);
+
+        // When every attempt fails, the last error is the status, not an attempt error
+        let settings = CheckSettings { max_retries: 1, ..Default::default() };
+        let failed = check_with_retries(&flaky(5), "http://a.test/", timeout, &settings, None, None);
+        assert_eq!(failed.status, Err(timed_out.clone()));
+        assert_eq!((failed.attempts, &failed.attempt_errors), (2, &vec![timed_out]));
+
+        let mut stats = HashMap::new();
+        for status in [first_try, retried, failed] {
+            record_status(&mut stats, status, UpIf::Valid);
+        }
+        let st = &stats["http://a.test/"];
+        assert_eq!((st.checks, st.successes, st.retried), (3, 2, 1));
+        assert_eq!(st.retried_pct(), 50.0);
+    }
+
+    #[test]
+    fn test_http_status_kept_apart_from_outcome() {
+        let client = MockClient::default()
+            .respond("http://site.test/", 200, &[], "maintenance")
+            .fail("http://gone.test/", TransportError::Dns("no such host".into()));
+        let config = Config::builder().retries(0).contains(Some("welcome".into())).build();
+        let checker = Checker::with_client(config, Arc::new(client));
+
+        // 200, but the body check failed: the code survives next to the error
+        let failed = checker.check_once("http://site.test/");
+        assert!(!failed.ok());
+        assert_eq!(failed.http_code(), Some(200));
+        assert_eq!(failed.error(), Some(&CheckError::BodyValidation { missing: "welcome".into() }));
+        assert!(!UpIf::Valid.is_up(&failed) && UpIf::Status2xx.is_up(&failed));
+        let json = serde_json::to_value(&failed).unwrap();
+        assert_eq!((json["http_status"].as_u64(), json["status"]["Err"]["kind"].as_str()), (Some(200), Some("body_validation")));
+
+        // No response at all: no code anywhere
+        let unreachable = checker.check_once("http://gone.test/");
+        assert_eq!((unreachable.ok(), unreachable.http_code()), (false, None));
+        assert_eq!(unreachable.error().map(CheckError::kind), Some("dns"));
+        assert!(!UpIf::AnyResponse.is_up(&unreachable));
+        assert!(serde_json::to_value(&unreachable).unwrap().get("http_status").is_none());
+
+        // Records from before http_status existed still report their code
+        let legacy = r#"{"url":"https://a/","status":{"Ok":200},"response_time":5,"timestamp":"2025-08-21T23:00:00Z"}"#;
+        let legacy: WebsiteStatus = serde_json::from_str(legacy).unwrap();
+        assert_eq!((legacy.http_status, legacy.http_code()), (None, Some(200)));
+        assert!(UpIf::AnyResponse.is_up(&legacy));
+    }
+
+    #[test]
+    fn test_failure_kinds() {
+        let client = MockClient::default()
+            .respond("http://moved.test/", 301, &[], "")
+            .respond("http://down.test/", 502, &[], "")
+            .fail("http://slow.test/", TransportError::ConnectTimeout("timed out".into()));
+        let checker = Checker::with_client(Config::builder().retries(0).build(), Arc::new(client));
+        let mut stats = HashMap::new();
+        for url in ["http://moved.test/", "http://down.test/", "http://slow.test/", "http://down.test/"] {
+            let status = checker.check_once(url);
+            record_status(&mut stats, status, UpIf::Status2xx);
+        }
+        // The 301 passed the check itself but is down under --up-if 2xx
+        assert_eq!(stats["http://moved.test/"].failures, BTreeMap::from([("3xx".to_string(), 1)]));
+        assert_eq!(stats["http://down.test/"].failures, BTreeMap::from([("5xx".to_string(), 2)]));
+        assert_eq!(stats["http://slow.test/"].failures, BTreeMap::from([("connect_timeout".to_string(), 1)]));
+
+        let groups = group_stats(&stats, "team", false);
+        let all = &groups["(none)"].1;
+        assert_eq!(all.failures.values().sum::<u64>(), 4);
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let delays = |cfg: Backoff, n: usize| -> Vec<u64> { (0..n).map(|a| backoff_delay(a, &cfg).as_millis() as u64).collect() };
+        let ms = Duration::from_millis;
+        // The default is the linear 200ms, 400ms, ... it always was
+        assert_eq!(delays(Backoff::default(), 4), [200, 400, 600, 800]);
+        assert_eq!(delays(Backoff { max: Some(ms(500)), ..Backoff::default() }, 4), [200, 400, 500, 500]);
+        assert_eq!(delays(Backoff { base: ms(100), multiplier: Some(2.0), max: None }, 5), [100, 200, 400, 800, 1600]);
+        assert_eq!(delays(Backoff { base: ms(100), multiplier: Some(2.0), max: Some(ms(1000)) }, 6), [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(delays(Backoff { base: ms(300), multiplier: Some(1.0), max: None }, 3), [300, 300, 300]);
+        assert_eq!(delays(Backoff { base: ms(1000), multiplier: Some(1.5), max: None }, 4), [1000, 1500, 2250, 3375]);
+        // Saturates instead of overflowing
+        let huge = Backoff { base: ms(1000), multiplier: Some(10.0), max: None };
+        assert_eq!(backoff_delay(400, &huge), Duration::MAX);
+        assert_eq!(backoff_delay(400, &Backoff { max: Some(ms(30_000)), ..huge }), ms(30_000));
+
+        assert_eq!(parse_backoff_multiplier("1.5"), Ok(1.5));
+        for bad in ["0.5", "-2", "inf", "NaN", "x"] {
+            assert!(parse_backoff_multiplier(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_round_deadline() {
+        let config = Config::builder().threads(2).timeout(Duration::from_secs(2)).retries(1).build();
+        let mut quick = UrlEntry::new("https://b/", UrlSource::Arg(2));
+        quick.options = UrlOptions { timeout: Some(Duration::from_secs(1)), retries: Some(0), ..UrlOptions::default() };
+        let urls = [UrlEntry::new("https://a/", UrlSource::Arg(1)), quick, UrlEntry::new("https://c/", UrlSource::Arg(3))];
+        // Worst check: 2 attempts x 2s x (connect + read) + 200ms backoff, in 2 waves, plus slack
+        assert_eq!(config.round_deadline(&urls), Duration::from_millis(8200 * 2 + 1000));
+        assert_eq!(config.round_deadline(&[]), ROUND_SLACK);
+
+        // Retries back off 100ms then 300ms: 3 attempts x 2s x 2 + 400ms, in 2 waves
+        let backoff = Backoff { base: Duration::from_millis(100), multiplier: Some(3.0), max: None };
+        let config = Config::builder().threads(2).timeout(Duration::from_secs(2)).retries(2).backoff(backoff).build();
+        assert_eq!(config.round_deadline(&urls[..2]), Duration::from_millis(12400 + 1000));
+    }
+
+    /// Panics on URLs containing "boom" and sleeps on ones containing "slow".
+    struct Faulty;
+
+    impl HttpClient for Faulty {
+        fn execute(&self, req: CheckRequest) -> Result<CheckResponse, TransportError> {
+            if req.url.contains("boom") {
+                panic!("client bug");
+            }
+            if req.url.contains("slow") {
+                thread::sleep(Duration::from_secs(3));
+            }
+            Ok(CheckResponse { status: 200, ..Default::default() })
+        }
+    }
+
+    #[test]
+    fn test_worker_survives_panic() {
+        let urls = ["http://boom.test/", "http://ok.test/"].map(|u| UrlEntry::new(u, UrlSource::Arg(1)));
+        let checker = Checker::with_client(Config::builder().threads(1).retries(0).build(), Arc::new(Faulty));
+        let mut results: Vec<WebsiteStatus> = Vec::new();
+        checker.run(&urls, |s| results.push(s));
+
+        // One worker saw both jobs, so it outlived the panic
+        assert_eq!(results.len(), 2);
+        let boom = results.iter().find(|s| s.url == "http://boom.test/").unwrap();
+        assert_eq!(boom.error(), Some(&CheckError::NoResult { detail: "the check panicked".into() }));
+        assert_eq!(boom.attempts, 0);
+        assert!(results.iter().any(|s| s.url == "http://ok.test/" && s.ok()));
+    }
+
+    #[test]
+    fn test_round_deadline_gives_up() {
+        let urls = ["http://slow.test/", "http://ok.test/"].map(|u| UrlEntry::new(u, UrlSource::Arg(1)));
+        let config = Config::builder().threads(2).retries(0).timeout(Duration::from_millis(100)).build();
+        let limit = config.round_deadline(&urls);
+        let checker = Checker::with_client(config, Arc::new(Faulty));
+        let started = Instant::now();
+        let mut results: Vec<(WebsiteStatus, Duration)> = Vec::new();
+        checker.run(&urls, |s| results.push((s, started.elapsed())));
+
+        assert_eq!(results.len(), 2);
+        let (slow, at) = results.iter().find(|(s, _)| s.url == "http://slow.test/").unwrap();
+        assert_eq!(slow.error().map(CheckError::kind), Some("no_result"));
+        assert!(*at >= limit && *at < Duration::from_secs(3), "gave up after {at:?}");
+    }
+
+    #[test]
+    fn test_stagger() {
+        let config = Config::builder().stagger(Some(Duration::from_millis(500))).build();
+        assert_eq!(config.stagger_offset(0, 5), Duration::ZERO);
+        assert_eq!(config.stagger_offset(4, 5), Duration::from_millis(400));
+        assert_eq!(config.stagger_offset(0, 1), Duration::ZERO);
+        assert_eq!(Config::default().stagger_offset(4, 5), Duration::ZERO);
+
+        // Notes when each request goes out
+        struct Stamped(Mutex<Vec<Instant>>);
+        impl HttpClient for Stamped {
+            fn execute(&self, _req: CheckRequest) -> Result<CheckResponse, TransportError> {
+                self.0.lock().unwrap().push(Instant::now());
+                Ok(CheckResponse { status: 200, ..Default::default() })
+            }
+        }
+
+        let urls: Vec<UrlEntry> = (0..5).map(|i| UrlEntry::new(format!("http://ok{i}.test/"), UrlSource::Arg(i + 1))).collect();
+        let window = Duration::from_millis(300);
+        let config = Config::builder().threads(5).retries(0).stagger(Some(window)).build();
+        let client = Arc::new(Stamped(Mutex::new(Vec::new())));
+        let checker = Checker::with_client(config, Arc::clone(&client) as Arc<dyn HttpClient>);
+        let started = Instant::now();
+        let mut results = 0;
+        checker.run(&urls, |_| results += 1);
+
+        // The first check goes out at once, the last no earlier than 4/5 of the window
+        let mut sent: Vec<Duration> = client.0.lock().unwrap().iter().map(|at| at.duration_since(started)).collect();
+        sent.sort();
+        assert_eq!((results, sent.len()), (5, 5));
+        assert!(sent[0] < Duration::from_millis(100), "{sent:?}");
+        assert!(sent[4] >= Duration::from_millis(240), "{sent:?}");
+        assert!(sent[4] < window + Duration::from_millis(500), "{sent:?}");
+    }
+
+    #[test]
+    fn test_think_time() {
+        let t = ThinkTime::parse("100:300").unwrap();
+        assert_eq!((t.min, t.max, t.seed), (Duration::from_millis(100), Duration::from_millis(300), None));
+        assert_eq!(ThinkTime::parse("50").unwrap().max, Duration::from_millis(50));
+        for bad in ["300:100", "a:b", "100:", "-1:5"] {
+            assert!(ThinkTime::parse(bad).is_err(), "{bad}");
+        }
+
+        // A seed repeats the same pauses, all within the range, and differs per worker
+        let t = ThinkTime { seed: Some(42), ..t };
+        let pauses = |id: usize| -> Vec<Duration> {
+            let mut rng = t.rng(id);
+            (0..20).map(|_| t.pick(&mut rng)).collect()
+        };
+        assert_eq!(pauses(0), pauses(0));
+        assert_ne!(pauses(0), pauses(1));
+        assert!(pauses(0).iter().all(|p| (t.min..=t.max).contains(p)));
+
+        // One worker pauses after each check before taking the next
+        let urls: Vec<UrlEntry> = (0..3).map(|i| UrlEntry::new(format!("http://ok{i}.test/"), UrlSource::Arg(i + 1))).collect();
+        let think_time = ThinkTime::parse("150:150").unwrap();
+        let config = Config::builder().threads(1).retries(0).think_time(Some(think_time)).build();
+        assert_eq!(config.round_deadline(&urls), Duration::from_millis((10_000 + 150) * 3 + 1000));
+        let checker = Checker::with_client(config, Arc::new(MockClient::default()));
+        let started = Instant::now();
+        checker.run(&urls, |_| {});
+        assert!(started.elapsed() >= Duration::from_millis(300), "{:?}", started.elapsed());
+    }
+
+    #[test]
+    fn test_stopping_cuts_think_time_short() {
+        let config = Config::builder().threads(1).retries(0).think_time(Some(ThinkTime::parse("60000").unwrap())).build();
+        let checker = Checker::with_client(config, Arc::new(MockClient::default()));
+        let pool = checker.pool();
+        for seq in 0..2 {
+            assert!(pool.submit(1, seq, Arc::new(UrlEntry::new("http://a.test/", UrlSource::Arg(1))), None));
+        }
+        assert!(pool.recv_timeout(Duration::from_secs(5)).is_some());
+        // The worker is now in its minute-long pause; stopping ends it within a slice
+        let stopped = Instant::now();
+        pool.stop();
+        assert!(pool.recv_timeout(Duration::from_secs(5)).is_some());
+        drop(pool);
+        assert!(stopped.elapsed() < Duration::from_secs(1), "{:?}", stopped.elapsed());
+    }
+
+    #[test]
+    fn test_ordered_results() {
+        let urls = ["http://slow.test/", "http://boom.test/", "http://ok.test/"].map(|u| UrlEntry::new(u, UrlSource::Arg(1)));
+        let config = Config::builder().threads(3).retries(0).timeout(Duration::from_millis(100)).ordered(true).build();
+        let checker = Checker::with_client(config, Arc::new(Faulty));
+        let mut results: Vec<WebsiteStatus> = Vec::new();
+        checker.run(&urls, |s| results.push(s));
+
+        // The slow URL is given up on at the deadline, long after the others finished,
+        // but still comes out first
+        let order: Vec<&str> = results.iter().map(|s| s.url.as_str()).collect();
+        assert_eq!(order, ["http://slow.test/", "http://boom.test/", "http://ok.test/"]);
+        assert_eq!(results[0].error().map(CheckError::kind), Some("no_result"));
+        assert!(results[2].ok());
+
+        let mut held = InOrder::default();
+        held.hold(2, missing_result(&urls[2], "late"));
+        held.hold(0, missing_result(&urls[0], "late"));
+        assert_eq!(held.release().map(|s| s.url).collect::<Vec<_>>(), ["http://slow.test/", "http://ok.test/"]);
+        assert_eq!(held.release().count(), 0);
+    }
+
+    #[test]
+    fn test_equal_weight_uptime() {
+        // One URL checked 98 times and always up, one checked twice and always down
+        let mut stats: HashMap<String, UrlStats> = HashMap::new();
+        for (url, ok, n) in [("https://busy/", true, 98), ("https://rare/", false, 2)] {
+            let st = stats.entry(url.to_string()).or_default();
+            for _ in 0..n {
+                st.record(ok, Duration::from_millis(10), 1);
+            }
+            st.labels.insert("team".into(), "web".into());
+        }
+
+        let mut style = SummaryStyle { group_by: Some("team".into()), ..Default::default() };
+        let rows = summary_rows(&stats, &style);
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].1, rows[0].2.checks), (2, 100));
+        assert!((rows[0].2.uptime() - 98.0).abs() < 1e-9);
+
+        style.equal_weight = true;
+        let rows = summary_rows(&stats, &style);
+        assert!((rows[0].2.uptime() - 50.0).abs() < 1e-9);
+        // Only uptime changes; the other totals still count every check
+        assert_eq!(rows[0].2.checks, 100);
+        assert!((rows[0].2.avg_ms() - 10.0).abs() < 1e-9);
+
+        // Single URLs are unaffected
+        style.group_by = None;
+        let rows = summary_rows(&stats, &style);
+        assert!(rows.iter().any(|(url, _, st)| url == "https://busy/" && st.uptime() == 100.0));
+    }
+
+    /// Answers 200 after stalling for its duration.
+    struct Stall(Duration);
+
+    impl HttpClient for Stall {
+        fn execute(&self, _req: CheckRequest) -> Result<CheckResponse, TransportError> {
+            thread::sleep(self.0);
+            Ok(CheckResponse { status: 200, ..Default::default() })
+        }
+    }
+
+    #[test]
+    fn test_deadline_exceeded() {
+        let config = Config::builder().retries(3).deadline(Some(Duration::from_millis(100))).build();
+        let checker = Checker::with_client(config, Arc::new(Stall(Duration::from_millis(300))));
+        let started = Instant::now();
+        let status = checker.check_once("http://slow.test/");
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+
+        // A response that only arrived after the deadline still fails the check
+        assert_eq!(status.error(), Some(&CheckError::DeadlineExceeded { after_ms: 100 }));
+        assert_eq!(status.http_status, Some(200));
+        assert_eq!(status.attempts, 1);
+        assert_eq!(status.error().unwrap().to_string(), "deadline exceeded after 100ms");
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["status"]["Err"]["kind"], "deadline_exceeded");
+        assert_eq!(json["status"]["Err"]["after_ms"], 100);
+        assert_eq!(serde_json::from_value::<WebsiteStatus>(json).unwrap(), status);
+    }
+
+    #[test]
+    fn test_body_snippet_on_failure() {
+        let client = MockClient::default()
+            .respond("http://a.test/", 200, &[], "<h1>Maintenance</h1>\r\n\tback soon\u{1b}")
+            .respond("http://b.test/", 503, &[], "Service Unavailable")
+            .respond("http://c.test/", 200, &[], "Welcome home");
+        let config = |n: usize| {
+            Config::builder().retries(0).contains(Some("Welcome".into())).capture_body_on_failure(Some(n)).max_body_bytes(Some(64)).build()
+        };
+        let checker = Checker::with_client(config(24), Arc::new(client));
+
+        let status = checker.check_once("http://a.test/");
+        assert_eq!(status.error().map(CheckError::kind), Some("body_validation"));
+        assert_eq!(status.body_snippet.as_deref(), Some("<h1>Maintenance</h1>\\r\\n\\tb"));
+        // Error statuses keep their body too, even when it is shorter than asked for
+        assert_eq!(checker.check_once("http://b.test/").body_snippet.as_deref(), Some("Service Unavailable"));
+        // Never on a success
+        let status = checker.check_once("http://c.test/");
+        assert!(status.ok() && status.body_snippet.is_none());
+        assert!(serde_json::to_value(&status).unwrap().get("body_snippet").is_none());
+
+        // A cut through a character decodes it as a replacement character
+        let client = MockClient::default().respond("http://d.test/", 200, &[], "caf\u{e9}");
+        let checker = Checker::with_client(config(4), Arc::new(client));
+        assert_eq!(checker.check_once("http://d.test/").body_snippet.as_deref(), Some("caf\u{fffd}"));
+
+        // A body over the cap fails without a snippet
+        let client = MockClient::default().respond("http://e.test/", 200, &[], &"x".repeat(100));
+        let status = Checker::with_client(config(24), Arc::new(client)).check_once("http://e.test/");
+        assert_eq!(status.error().map(CheckError::kind), Some("body_too_large"));
+        assert!(status.body_snippet.is_none());
+    }
+
+    #[test]
+    fn test_failure_body() {
+        let json = br#"{"error":"bad","token":"abc123"}"#;
+        let pretty = failure_body(json, &[], None);
+        assert_eq!(pretty, "{\n  \"error\": \"bad\",\n  \"token\": \"abc123\"\n}");
+        // Not JSON: shown as text
+        assert_eq!(failure_body(b"<h1>Down</h1>", &[], None), "<h1>Down</h1>");
+        // Cut at the cap exactly, or before a character the cap would split
+        assert_eq!(failure_body(b"abcdef", &[], Some(6)), "abcdef");
+        assert_eq!(failure_body(b"abcdef", &[], Some(4)), format!("abcd{BODY_TRUNCATED}"));
+        assert_eq!(failure_body("caf\u{e9}!".as_bytes(), &[], Some(4)), format!("caf{BODY_TRUNCATED}"));
+        assert_eq!(failure_body("caf\u{e9}!".as_bytes(), &[], Some(5)), format!("caf\u{e9}{BODY_TRUNCATED}"));
+        assert_eq!(failure_body("\u{e9}".as_bytes(), &[], Some(1)), BODY_TRUNCATED);
+        // Redacted before the cut, so a secret can't straddle it
+        let secret = [regex::Regex::new("abc[0-9]+").unwrap()];
+        assert_eq!(failure_body(b"token=abc123 rest", &secret, None), "token=[redacted] rest");
+        assert_eq!(failure_body(b"token=abc123 rest", &secret, Some(10)), format!("token=[red{BODY_TRUNCATED}"));
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let date = Some("Sun, 06 Nov 1994 08:49:37 GMT"); // 784111777
+        assert_eq!(clock_skew(date, 784_111_777_000), ClockSkew::Measured { skew_s: 0 });
+        assert_eq!(clock_skew(date, 784_111_780_400), ClockSkew::Measured { skew_s: -3 });
+        assert_eq!(clock_skew(date, 784_111_700_999), ClockSkew::Measured { skew_s: 77 });
+        assert_eq!(clock_skew(None, 0), ClockSkew::Missing);
+        assert_eq!(clock_skew(Some("yesterday"), 0), ClockSkew::Unparsable { value: "yesterday".into() });
+
+        let client = || {
+            MockClient::default()
+                .respond("http://old.test/", 200, &[("Date", "Sun, 06 Nov 1994 08:49:37 GMT")], "")
+                .respond("http://undated.test/", 200, &[], "")
+        };
+        let config = |warn_only| Config::builder().retries(0).check_clock_skew(Some(60), warn_only).build();
+        let checker = Checker::with_client(config(false), Arc::new(client()));
+        let status = checker.check_once("http://old.test/");
+        assert_eq!(status.error().map(CheckError::kind), Some("clock_skew"));
+        assert!(matches!(status.clock_skew, Some(ClockSkew::Measured { skew_s }) if skew_s < -60));
+        // No Date says nothing about the clock: reported as such, and not a failure
+        let status = checker.check_once("http://undated.test/");
+        assert!(status.ok());
+        assert_eq!(status.clock_skew, Some(ClockSkew::Missing));
+
+        let status = Checker::with_client(config(true), Arc::new(client())).check_once("http://old.test/");
+        assert!(status.ok() && matches!(status.clock_skew, Some(ClockSkew::Measured { .. })));
+    }
+
+    #[test]
+    fn test_require_compression() {
+        use std::io::Write;
+        let page = "<p>compress me</p>".repeat(200);
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(page.as_bytes()).unwrap();
+        let gzipped = gz.finish().unwrap();
+        let client = Arc::new(
+            MockClient::default()
+                .respond_bytes("http://cdn.test/", 200, &[("Content-Encoding", "gzip")], &gzipped)
+                .respond("http://plain.test/", 200, &[], &page)
+                .respond("http://identity.test/", 200, &[("Content-Encoding", "identity")], &page)
+                .respond("http://broken.test/", 200, &[("Content-Encoding", "gzip")], "not gzip"),
+        );
+        let config = Config::builder().retries(0).require_compression(true).contains(Some("compress me".into())).build();
+        let checker = Checker::with_client(config, client.clone());
+
+        // Decoded before --contains looks at it
+        let status = checker.check_once("http://cdn.test/");
+        assert_eq!(status.status, Ok(200));
+        let compression = status.compression.unwrap();
+        assert_eq!(compression.encoding, "gzip");
+        assert_eq!(compression.wire_bytes, Some(gzipped.len() as u64));
+        assert_eq!(compression.decoded_bytes, Some(page.len() as u64));
+        assert!(compression.ratio.unwrap() > 10.0, "{compression:?}");
+        let requests = client.requests.lock().unwrap();
+        assert_eq!(requests[0].accept_encoding.as_deref(), Some(ACCEPT_COMPRESSED));
+        assert_eq!((requests[0].read_body, requests[0].read_until.as_ref()), (ReadBody::Always, None));
+        drop(requests);
+
+        for url in ["http://plain.test/", "http://identity.test/"] {
+            let status = checker.check_once(url);
+            assert_eq!(status.error(), Some(&CheckError::NotCompressed), "{url}");
+            assert_eq!(status.http_status, Some(200));
+            let compression = status.compression.unwrap();
+            assert_eq!((compression.encoding.as_str(), compression.ratio), ("identity", Some(1.0)));
+        }
+        let status = checker.check_once("http://broken.test/");
+        assert_eq!(status.error().map(CheckError::kind), Some("body_read"));
+
+        // Off by default: nothing asked for, nothing recorded
+        let client = Arc::new(MockClient::default().respond("http://plain.test/", 200, &[], &page));
+        let status = Checker::with_client(Config::builder().retries(0).build(), client.clone()).check_once("http://plain.test/");
+        assert!(status.ok() && status.compression.is_none());
+        assert_eq!(client.requests.lock().unwrap()[0].accept_encoding, None);
+    }
+
+    /// Presents `cert` on https://good.test/, and fails the handshake on any
+    /// other host with the same certificate marked invalid.
+    struct CertClient(Certificate);
+
+    impl HttpClient for CertClient {
+        fn execute(&self, req: CheckRequest) -> Result<CheckResponse, TransportError> {
+            if req.url == "https://good.test/" {
+                return Ok(CheckResponse { status: 200, certificate: Some(self.0.clone()), ..Default::default() });
+            }
+            let certificate = Box::new(Certificate { chain_valid: false, chain_error: Some("UnknownIssuer".into()), ..self.0.clone() });
+            Err(TransportError::InvalidCertificate { detail: "tls connection init failed".into(), certificate })
+        }
+    }
+
+    #[test]
+    fn test_cert_info() {
+        let cert = Certificate {
+            subject_cn: Some("good.test".into()),
+            issuer: "CN=Old Intermediate CA, O=Example Corp".into(),
+            not_before: Utc::now(),
+            not_after: Utc::now(),
+            sans: vec!["good.test".into()],
+            chain_valid: true,
+            chain_error: None,
+        };
+        let checker = Checker::with_client(Config::builder().retries(0).build(), Arc::new(CertClient(cert.clone())));
+        let status = checker.check_once("https://good.test/");
+        assert_eq!((status.status, status.certificate), (Ok(200), Some(cert)));
+
+        // The handshake failed, but what the server presented is still reported
+        let status = checker.check_once("https://selfsigned.test/");
+        assert_eq!(status.error().map(CheckError::kind), Some("connection_failed"));
+        let cert = status.certificate.unwrap();
+        assert_eq!((cert.chain_valid, cert.chain_error.as_deref()), (false, Some("UnknownIssuer")));
+    }
+
+    #[test]
+    fn test_compare() {
+        let page = |token: &str| format!("<p>Hi</p><input name=csrf value={token}>");
+        let client = MockClient::default()
+            .respond("http://prod.test/", 200, &[("X-Version", "1")], &page("abc123"))
+            .respond("http://same.test/", 200, &[("X-Version", "1")], &page("ff00"))
+            .respond("http://body.test/", 200, &[("X-Version", "1")], "<p>Hello</p>")
+            .respond("http://down.test/", 503, &[], "Service Unavailable")
+            .respond("http://version.test/", 200, &[("X-Version", "2")], &page("abc123"))
+            .fail("http://gone.test/", TransportError::Dns("no such host".into()));
+        let config = Config::builder()
+            .retries(0)
+            .ordered(true)
+            .capture_header("X-Version")
+            .compare_ignore(parse_compare_ignore("value=[0-9a-f]+").unwrap())
+            .build();
+        let checker = Checker::with_client(config, Arc::new(client));
+        let canaries = ["http://same.test/", "http://body.test/", "http://down.test/", "http://version.test/", "http://gone.test/"];
+        let urls: Vec<UrlEntry> = canaries
+            .iter()
+            .enumerate()
+            .map(|(i, c)| UrlEntry { compare: Some(c.to_string()), ..UrlEntry::new("http://prod.test/", UrlSource::Compare(i + 1)) })
+            .collect();
+        let mut results = Vec::new();
+        checker.run(&urls, |status| results.push(status));
+        let diffs: Vec<Option<String>> = results.iter().map(|r| r.comparison.as_ref().unwrap().diff.clone()).collect();
+        assert_eq!(
+            diffs,
+            [
+                None,
+                Some("bodies differ at byte 4".to_string()),
+                Some("status 200 vs 503".to_string()),
+                Some("headers differ: X-Version".to_string()),
+                Some("canary failed: dns resolution failed: no such host".to_string()),
+            ]
+        );
+        assert!(results[0].ok());
+        assert_eq!(results[2].comparison.as_ref().unwrap().canary_http_status, Some(503));
+        let mismatch = CheckError::CompareMismatch { canary: "http://body.test/".into(), diff: "bodies differ at byte 4".into() };
+        assert_eq!(results[1].error(), Some(&mismatch));
+        assert_eq!(results[1].http_status, Some(200));
+
+        // Each pair keeps stats of its own, apart from the URL checked alone
+        let mut stats = seed_stats(&urls);
+        for status in results {
+            record_status(&mut stats, status, UpIf::Valid);
+        }
+        assert_eq!(stats["http://prod.test/ vs http://same.test/"].successes, 1);
+        assert!(!stats.contains_key("http://prod.test/"));
+
+        assert_eq!(parse_compare("https://a/?next=x=https://b/"), Ok(("https://a/?next=x".into(), "https://b/".into())));
+        assert!(parse_compare("=https://b/").is_err());
+    }
+
+    #[test]
+    fn test_save_failures() {
+        let dir = std::env::temp_dir().join(format!("sitecheck-save-failures-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let client = MockClient::default()
+            .respond("http://a.test/", 200, &[("Server", "test")], "<p>Sorry, we're closed. Welcome back tomorrow</p>")
+            .respond("http://b.test/", 200, &[("Server", "prod")], "Welcome")
+            .fail("http://c.test/", TransportError::Dns("no such host".into()));
+        let save = SaveFailures { dir: dir.clone(), max_bytes: 1 << 20 };
+        let config = Config::builder().retries(0).contains(Some("Welcome".into())).header("Server", "prod").save_failures(Some(save));
+        let checker = Checker::with_client(config.build(), Arc::new(client));
+
+        // A failed validation is saved whole, even past the --contains match
+        let status = checker.check_once("http://a.test/");
+        assert_eq!(status.error().map(CheckError::kind), Some("header_mismatch"));
+        let saved = status.saved_body.clone().unwrap();
+        assert_eq!(std::fs::read_to_string(&saved).unwrap(), "<p>Sorry, we're closed. Welcome back tomorrow</p>");
+        assert_eq!(serde_json::to_value(&status).unwrap()["saved_body"].as_str(), saved.to_str());
+
+        // Transport errors have nothing to save
+        assert!(checker.check_once("http://c.test/").saved_body.is_none());
+        // Nor do successes
+        let status = checker.check_once("http://b.test/");
+        assert!(status.ok() && status.saved_body.is_none());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_deadline_stops_retries() {
+        let client = MockClient::default().fail("http://gone.test/", TransportError::Dns("no such host".into()));
+        let config = Config::builder().retries(3).deadline(Some(Duration::from_millis(300))).build();
+        let checker = Checker::with_client(config, Arc::new(client));
+        let status = checker.check_once("http://gone.test/");
+
+        // After the 200ms backoff only ~100ms is left, less than the next 400ms one,
+        // so the third attempt never starts and the real error is kept
+        assert_eq!(status.attempts, 2);
+        assert_eq!(status.error().map(CheckError::kind), Some("dns"));
+
+        let config = Config::builder().retries(1).deadline(Some(Duration::from_millis(500))).build();
+        assert_eq!(config.round_deadline(&[UrlEntry::new("http://x/", UrlSource::Arg(1))]), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_checks_per_url() {
+        let urls = ["http://a.test/", "http://b.test/"].map(|u| UrlEntry::new(u, UrlSource::Arg(1)));
+        let config = Config::builder().threads(4).retries(0).checks_per_url(Some(3)).build();
+        assert_eq!((config.checks_per_round(), config.max_rounds()), (3, Some(1)));
+        let jobs: Vec<(usize, &str)> = config.round_jobs(&urls).map(|(seq, e)| (seq, e.url.as_str())).collect();
+        assert_eq!(jobs[..3], [(0, "http://a.test/"), (1, "http://b.test/"), (2, "http://a.test/")]);
+        assert_eq!(jobs.len(), 6);
+
+        // One-shot: every copy goes out in the same round
+        let client = MockClient::default().respond("http://a.test/", 200, &[], "").respond("http://b.test/", 503, &[], "");
+        let checker = Checker::with_client(config, Arc::new(client));
+        let mut stats = seed_stats(&urls);
+        checker.run(&urls, |s| record_status(&mut stats, s, UpIf::Valid));
+        assert_eq!(stats["http://a.test/"].checks, 3);
+        assert_eq!(stats["http://a.test/"].successes, 3);
+        assert_eq!(stats["http://b.test/"].checks, 3);
+        assert_eq!(stats["http://b.test/"].failures.get("5xx"), Some(&3));
+
+        // Periodic: one check per URL per round, for N rounds
+        let config = Config::builder().period(Some(Duration::from_secs(1))).checks_per_url(Some(3)).build();
+        assert_eq!((config.checks_per_round(), config.max_rounds()), (1, Some(3)));
+        assert_eq!(config.round_jobs(&urls).count(), 2);
+        assert_eq!(Config::default().max_rounds(), None);
+    }
+
+    #[test]
+    fn test_contains_body_limit() {
+        let mut page = "x".repeat(5 * 1024 * 1024);
+        page.push_str("needle");
+        let client = Arc::new(MockClient::default().respond("http://big.test/", 200, &[], &page));
+        let check = |builder: ConfigBuilder| {
+            let config = builder.retries(0).contains(Some("needle".into())).build();
+            Checker::with_client(config, client.clone()).check_once("http://big.test/")
+        };
+
+        let status = check(Config::builder());
+        assert_eq!(status.error(), Some(&CheckError::BodyTooLarge { limit: DEFAULT_MAX_BODY_BYTES }));
+        assert_eq!(status.http_status, Some(200));
+        assert_eq!(serde_json::to_value(&status).unwrap()["status"]["Err"]["kind"], "body_too_large");
+        assert!(check(Config::builder().max_body_bytes(None)).ok());
+        assert!(check(Config::builder().max_body_bytes(Some(6 * 1024 * 1024))).ok());
+        // Only the --contains text was wanted, so every read was told to stop at it
+        let requests = client.requests.lock().unwrap();
+        assert!(requests.iter().all(|r| r.read_until.as_deref() == Some(&b"needle"[..])));
+    }
+
+    #[test]
+    fn test_config_json_schema_version() {
+        let json = Config::builder().threads(3).build().to_json().unwrap();
+        assert!(json.starts_with("{\n  \"schema_version\": 1,"), "{json}");
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert_eq!(value["worker_threads"], 3);
+    }
+
+    #[test]
+    fn test_queueing_a_round_copies_no_urls() {
+        let urls: Vec<UrlEntry> = (0..10_000)
+            .map(|i| {
+                let mut entry = UrlEntry::new(format!("http://site{i}.test/"), UrlSource::Arg(i + 1));
+                entry.labels.insert("team".into(), "web".into());
+                entry
+            })
+            .collect();
+        let entries: Vec<Arc<UrlEntry>> = urls.iter().cloned().map(Arc::new).collect();
+        let checker = Checker::with_client(Config::builder().threads(4).retries(0).build(), Arc::new(MockClient::default()));
+        let pool = checker.pool();
+        let allocations = || ALLOCATIONS.with(Cell::get);
+
+        let before = allocations();
+        for (seq, entry) in entries.iter().enumerate() {
+            assert!(pool.submit(1, seq, Arc::clone(entry), None));
+        }
+        let queued = allocations() - before;
+
+        // What owned jobs used to cost: at least the URL and a label per entry
+        let before = allocations();
+        let copies = urls.to_vec();
+        let copied = allocations() - before;
+        drop(copies);
+
+        // Queuing only allocates the channel's own blocks
+        assert!(queued < urls.len() / 10, "{queued} allocations to queue {} jobs", urls.len());
+        assert!(copied >= 2 * urls.len(), "{copied}");
+        for _ in &urls {
+            assert!(pool.recv_timeout(Duration::from_secs(5)).is_some());
+        }
+    }
+
+    #[test]
+    fn test_stats_memory_is_bounded() {
+        let template = WebsiteStatus {
+            labels: Labels::from([("team".to_string(), "web".to_string())]),
+            ..WebsiteStatus::sample("https://long-running.test/", Ok(200))
+        };
+        let detail = "x".repeat(500);
+        let live = || LIVE_BYTES.with(Cell::get);
+
+        let before = live();
+        let mut stats = HashMap::new();
+        for i in 0..1_000_000u32 {
+            let mut status = template.clone();
+            status.response_time = Duration::from_millis((i % 5000) as u64);
+            status.attempts = 1 + i % 3;
+            status.status = match i % 4 {
+                0 => Ok(200),
+                1 => Err(CheckError::HttpStatus { code: 100 + (i % 900) as u16, detail: detail.clone() }),
+                2 => Err(CheckError::ReadTimeout { detail: format!("{detail} {i}") }),
+                _ => Err(CheckError::ConnectionRefused { detail: detail.clone() }),
+            };
+            record_status(&mut stats, status, UpIf::default());
+        }
+        let grown = live() - before;
+
+        let st = &stats[&template.url];
+        assert_eq!(st.checks, 1_000_000);
+        assert!(st.failures.len() <= MAX_FAILURE_KINDS + 1, "{:?}", st.failures);
+        assert!(grown < STATS_BYTES_PER_URL as isize, "{grown} bytes for one URL's stats");
+    }
+}
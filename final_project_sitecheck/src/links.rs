@@ -0,0 +1,242 @@
+//! `--check-links`: after a page passes, check the links on it one level deep,
+//! so a static-site deploy that broke an internal link fails the page linking it.
+
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::http::same_origin;
+use crate::{html_attr, CheckError, CheckRequest, CheckResponse, HttpClient, Method, ReadBody, WebsiteStatus};
+
+/// Links checked per page unless told otherwise (`--max-links-per-page`).
+pub const DEFAULT_MAX_LINKS_PER_PAGE: usize = 100;
+
+/// What `--check-links` checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LinkSettings {
+    /// Check links to other origins too, not just the page's own.
+    pub external: bool,
+    /// Links checked per page; the rest are counted as skipped.
+    pub max_per_page: usize,
+}
+
+impl Default for LinkSettings {
+    fn default() -> Self {
+        LinkSettings { external: false, max_per_page: DEFAULT_MAX_LINKS_PER_PAGE }
+    }
+}
+
+/// How the links on a page fared.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinkReport {
+    /// Links checked from this page.
+    pub checked: usize,
+    /// Links found past `--max-links-per-page`, left unchecked.
+    #[serde(default)]
+    pub skipped: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub broken: Vec<BrokenLink>,
+}
+
+/// A link that answered 4xx/5xx, or not at all.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrokenLink {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    pub error: String,
+}
+
+/// The links already claimed by a page this round, so one linked from every
+/// page is still checked once per round.
+#[derive(Debug, Default)]
+pub struct SeenLinks {
+    seen: Mutex<(u64, HashSet<String>)>,
+}
+
+impl SeenLinks {
+    /// Whether `url` is still unchecked in `round`, marking it checked if so.
+    /// A later round starts afresh.
+    pub fn claim(&self, round: u64, url: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        if round > seen.0 {
+            *seen = (round, HashSet::new());
+        }
+        round == seen.0 && seen.1.insert(url.to_string())
+    }
+}
+
+/// The http(s) links in `html` (`href` and `src` attributes), resolved against
+/// `base` (or the page's `<base href>`), without fragments, each once.
+pub fn extract_links(html: &str, base: &str) -> Vec<String> {
+    let Ok(mut base) = url::Url::parse(base) else { return Vec::new() };
+    let mut links = Vec::new();
+    let mut seen = HashSet::new();
+    let mut from = 0;
+    while let Some(pos) = html[from..].find('<') {
+        let start = from + pos;
+        if html[start..].starts_with("<!--") {
+            from = html[start..].find("-->").map_or(html.len(), |e| start + e + 3);
+            continue;
+        }
+        let Some(end) = html[start..].find('>').map(|e| start + e) else { break };
+        let tag = &html[start..end];
+        from = end;
+        if tag.get(..5).is_some_and(|t| t.eq_ignore_ascii_case("<base")) {
+            if let Some(href) = html_attr(tag, "href").and_then(|h| base.join(h.trim()).ok()) {
+                base = href;
+            }
+            continue;
+        }
+        for name in ["href", "src"] {
+            let Some(value) = html_attr(tag, name) else { continue };
+            let Ok(mut link) = base.join(value.trim()) else { continue };
+            if !matches!(link.scheme(), "http" | "https") {
+                continue;
+            }
+            link.set_fragment(None);
+            if seen.insert(link.to_string()) {
+                links.push(link.to_string());
+            }
+        }
+    }
+    links
+}
+
+/// HEAD `url`, found checking `checked_url`, asking again with GET if the server
+/// won't do HEAD; None if it's fine.
+fn check_link(client: &dyn HttpClient, url: &str, checked_url: &str, timeout: Duration, max_redirects: u32) -> Option<BrokenLink> {
+    let request = |method| CheckRequest {
+        url: url.to_string(),
+        method,
+        timeout,
+        max_redirects,
+        read_body: ReadBody::Never,
+        deadline: None,
+        max_body_bytes: None,
+        read_until: None,
+        accept_encoding: None,
+        authorization: None,
+        checked_url: Some(checked_url.to_string()),
+    };
+    let result = match client.execute(request(Method::Head)) {
+        Ok(resp) if matches!(resp.status, 405 | 501) => client.execute(request(Method::Get)),
+        result => result,
+    };
+    let (http_status, error) = match result {
+        Ok(resp) if resp.status < 400 => return None,
+        Ok(resp) => (Some(resp.status), format!("status code {}", resp.status)),
+        Err(e) => (e.status(), CheckError::from(e).to_string()),
+    };
+    Some(BrokenLink { url: url.to_string(), http_status, error })
+}
+
+/// Check the links on the page `status` fetched as `response`, recording them
+/// in `status.links` and failing it if any are broken. Pages that failed, or
+/// aren't HTML, are left alone.
+pub(crate) fn check_page_links(
+    client: &dyn HttpClient,
+    status: &mut WebsiteStatus,
+    response: Option<&CheckResponse>,
+    settings: LinkSettings,
+    (timeout, max_redirects): (Duration, u32),
+    (seen, round): (&SeenLinks, u64),
+) {
+    let Some(body) = response.filter(|r| status.ok() && r.is_html()).and_then(|r| r.body.as_deref()) else { return };
+    let page = status.redirect_chain.last().unwrap_or(&status.url).clone();
+    let (mut checked, mut skipped) = (Vec::new(), 0);
+    for link in extract_links(&String::from_utf8_lossy(body), &page) {
+        if !settings.external && !same_origin(&page, &link) {
+            continue;
+        }
+        // Past the cap a link isn't claimed, so another page may still check it
+        if checked.len() == settings.max_per_page {
+            skipped += 1;
+        } else if seen.claim(round, &link) {
+            checked.push(link);
+        }
+    }
+    let broken: Vec<BrokenLink> = checked.iter().filter_map(|link| check_link(client, link, &status.url, timeout, max_redirects)).collect();
+    if !broken.is_empty() {
+        status.status = Err(CheckError::BrokenLinks { count: broken.len() });
+    }
+    status.links = Some(LinkReport { checked: checked.len(), skipped, broken });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::MockClient;
+    use crate::{missing_result, TransportError, UrlEntry, UrlSource};
+
+    const PAGE: &str = r##"<html><head><link rel="stylesheet" href="/style.css"><script src='app.js'></script></head>
+        <body><!-- <a href="/commented-out"> -->
+        <a href="/about#team">About</a> <a href="/about">again</a> <a HREF=missing.html>gone</a>
+        <img src="https://cdn.test/logo.png"> <a href="mailto:ops@site.test">mail</a> <a href="#top">top</a>
+        </body></html>"##;
+
+    #[test]
+    fn test_extract_links() {
+        let links = extract_links(PAGE, "https://site.test/docs/index.html");
+        assert_eq!(
+            links,
+            [
+                "https://site.test/style.css",
+                "https://site.test/docs/app.js",
+                "https://site.test/about",
+                "https://site.test/docs/missing.html",
+                "https://cdn.test/logo.png",
+                "https://site.test/docs/index.html",
+            ]
+        );
+        let based = extract_links(r#"<base href="https://site.test/v2/"><a href="page">"#, "https://site.test/");
+        assert_eq!(based, ["https://site.test/v2/page"]);
+        assert!(same_origin("https://site.test/a", "https://site.test:443/b"));
+        assert!(!same_origin("https://site.test/a", "http://site.test/b"));
+    }
+
+    #[test]
+    fn test_check_page_links() {
+        let client = MockClient::default()
+            .respond_to(Method::Head, "https://site.test/style.css", 200, &[])
+            .respond_to(Method::Head, "https://site.test/docs/app.js", 405, &[])
+            .respond("https://site.test/docs/app.js", 200, &[], "")
+            .respond_to(Method::Head, "https://site.test/about", 301, &[("Location", "/about/")])
+            .respond_to(Method::Head, "https://site.test/docs/missing.html", 404, &[])
+            .fail("https://site.test/docs/index.html", TransportError::ConnectionRefused("refused".into()));
+        let entry = UrlEntry::new("https://site.test/docs/index.html", UrlSource::Arg(0));
+        let page = || WebsiteStatus { status: Ok(200), http_status: Some(200), ..missing_result(&entry, "") };
+        let response = CheckResponse {
+            status: 200,
+            headers: crate::HeaderView::new([("Content-Type", "text/html")]),
+            body: Some(PAGE.as_bytes().to_vec()),
+            certificate: None,
+        };
+        let seen = SeenLinks::default();
+        let mut status = page();
+        check_page_links(&client, &mut status, Some(&response), LinkSettings::default(), (Duration::from_secs(1), 0), (&seen, 1));
+        assert_eq!(status.error(), Some(&CheckError::BrokenLinks { count: 2 }));
+        let report = status.links.unwrap();
+        // The CDN image is another origin, so unchecked
+        assert_eq!((report.checked, report.skipped), (5, 0));
+        assert_eq!(report.broken[0].url, "https://site.test/docs/missing.html");
+        assert_eq!((report.broken[0].http_status, report.broken[0].error.as_str()), (Some(404), "status code 404"));
+        assert_eq!(report.broken[1].http_status, None);
+
+        // Another page this round links to the same ones: nothing left to check
+        let mut status = page();
+        check_page_links(&client, &mut status, Some(&response), LinkSettings::default(), (Duration::from_secs(1), 0), (&seen, 1));
+        assert!(status.ok());
+        assert_eq!(status.links.map(|l| l.checked), Some(0));
+
+        // Next round they're checked again, up to the cap
+        let mut status = page();
+        let settings = LinkSettings { external: false, max_per_page: 2 };
+        check_page_links(&client, &mut status, Some(&response), settings, (Duration::from_secs(1), 0), (&seen, 2));
+        assert!(status.ok());
+        assert_eq!(status.links.map(|l| (l.checked, l.skipped)), Some((2, 3)));
+        assert_eq!(client.requests.lock().unwrap().len(), 8);
+    }
+}
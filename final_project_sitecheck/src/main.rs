@@ -1,557 +1,2178 @@
-use clap::{Arg, ArgAction, Command};
-use chrono::{DateTime, Utc};
-use serde::Serialize;
-use serde_with::{serde_as, DurationMilliSeconds};
-use std::collections::HashMap;
-use std::io::{self, BufRead};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use log::{debug, error, info, warn};
+use sitecheck::{
+    apply_default_scheme, apply_filters, dedup_urls, expand_braces, format_duration, load_sitemap,
+    parse_backoff_multiplier, parse_compare, parse_compare_ignore, parse_duration, parse_header, parse_label, parse_redact_pattern, parse_thread_count, partition_valid, read_results,
+    read_urls_from_file, missing_result, record_status, resolve_url_files, seed_stats, split_url_options, validate_url,
+    worker_count, write_summary, AlertOn, Backoff, Baselines, Bench, Checker, Config, Csv, Degradation, DnsServer, Har, Heartbeat, HeartbeatOn, InOrder, JobResult, JsonLines, Labels, LinkSettings, MaintenanceWindow, OAuthSettings, RetryBudget, SaveFailures, Sink, Sinks, SortBy,
+    StatsOut, StatusSet, Summary, SummaryStyle, SummaryTo, ThinkTime, TimeDisplay, UpIf, UrlEntry, UrlFilter, UrlSource, UrlStats, UreqClient, Webhook,
+    ACCEPT_COMPRESSED, BASELINE_BLEND, DEFAULT_BACKOFF_BASE, DEFAULT_DNS_CACHE_TTL, DEFAULT_MAX_LINKS_PER_PAGE, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_REDIRECTS, DEFAULT_MAX_THREADS, DEFAULT_RETRIES,
+    DEFAULT_SAVE_FAILURES_MAX_MB, DEFAULT_THREADS, DEFAULT_TIMEOUT, DEFAULT_UNIX_HOST,
+    SUPPORTED_SCHEMES, UNIX_URL_PREFIX,
+};
+#[cfg(unix)]
+use sitecheck::{check_socket, Fifo, FifoPolicy};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, BufRead, IsTerminal};
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
-#[serde_as]
-#[derive(Debug, Clone, Serialize)]
-pub struct WebsiteStatus {
-    pub url: String,
-    pub status: Result<u16, String>,
-    #[serde_as(as = "DurationMilliSeconds<u64>")]
-    pub response_time: Duration,
-    pub timestamp: DateTime<Utc>,
-}
+mod dashboard;
+mod selftest;
 
-#[derive(Debug, Clone)]
-struct Config {
-    worker_threads: usize,
-    timeout: Duration,
-    max_retries: usize,
-    period: Option<Duration>, // None => run once; Some(d) => repeat every d
-    headers: Vec<(String, String)>, // Header validations: (Name, ExpectedValue)
-    contains: Option<String>,       // Body must contain this substring if set
-    urls: Vec<String>,
+/// Flags shared by every way of running checks: bare, `check`, and `watch`.
+fn check_args() -> Vec<Arg> {
+    vec![
+        Arg::new("threads")
+            .short('n')
+            .long("threads")
+            .value_name("NUM")
+            .help("Number of worker threads (default: 50)")
+            .value_parser(parse_thread_count)
+            .num_args(1),
+        Arg::new("max-threads")
+            .long("max-threads")
+            .value_name("NUM")
+            .help("Upper limit for --threads; larger requests are capped with a warning (default: 1024)")
+            .value_parser(parse_thread_count)
+            .num_args(1),
+        Arg::new("timeout")
+            .short('t')
+            .long("timeout")
+            .value_name("DURATION")
+            .help("Request timeout, e.g. 5, 1500ms, 1m (bare numbers are seconds; default: 5s)")
+            .value_parser(parse_duration)
+            .num_args(1),
+        Arg::new("deadline")
+            .long("deadline")
+            .value_name("DURATION")
+            .help("Wall-clock limit on each check, retries and body reading included (default: none)")
+            .value_parser(parse_duration)
+            .num_args(1),
+        Arg::new("retries")
+            .short('r')
+            .long("retries")
+            .value_name("NUM")
+            .help("Max retries per website (default: 1)")
+            .num_args(1),
+        Arg::new("backoff-base-ms")
+            .long("backoff-base-ms")
+            .value_name("MS")
+            .help("Wait before the first retry, in milliseconds (default: 200)")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .num_args(1),
+        Arg::new("backoff-multiplier")
+            .long("backoff-multiplier")
+            .value_name("FACTOR")
+            .help("Multiply the wait by FACTOR (at least 1.0) after each retry; without it waits grow linearly (200ms, 400ms, ...)")
+            .value_parser(parse_backoff_multiplier)
+            .num_args(1),
+        Arg::new("backoff-max-ms")
+            .long("backoff-max-ms")
+            .value_name("MS")
+            .help("Longest wait between retries, in milliseconds (default: no cap)")
+            .value_parser(clap::value_parser!(u64))
+            .num_args(1),
+        Arg::new("retry-budget")
+            .long("retry-budget")
+            .value_name("NUM")
+            .help("Cap on total retries across all URLs in a single round")
+            .value_parser(clap::value_parser!(usize))
+            .num_args(1),
+        Arg::new("max-redirects")
+            .long("max-redirects")
+            .value_name("NUM")
+            .help("Max redirects to follow, including meta-refresh hops (default: 2)")
+            .value_parser(clap::value_parser!(u32))
+            .num_args(1),
+        Arg::new("record-redirects")
+            .long("record-redirects")
+            .value_name("NUM")
+            .help("Keep only the first NUM redirect hops in redirect_chain, marking the rest as truncated (default: all)")
+            .value_parser(clap::value_parser!(usize))
+            .num_args(1),
+        Arg::new("follow-meta-refresh")
+            .long("follow-meta-refresh")
+            .help("Follow <meta http-equiv=\"refresh\"> redirects on text/html pages")
+            .action(ArgAction::SetTrue),
+        up_if_arg(),
+        Arg::new("maintenance")
+            .long("maintenance")
+            .value_name("WINDOW")
+            .help("Weekly window like 'Sun 02:00-03:00' (in --timezone) whose failures are flagged and left out of uptime and alerts (repeatable)")
+            .value_parser(MaintenanceWindow::parse)
+            .action(ArgAction::Append)
+            .num_args(1),
+        Arg::new("file")
+            .short('f')
+            .long("file")
+            .value_name("PATH")
+            .help("URL file, directory of *.txt/*.urls files, or glob pattern; one URL per line, optionally followed by key=value labels (repeatable)")
+            .action(ArgAction::Append),
+        Arg::new("file-label")
+            .long("file-label")
+            .value_name("KEY")
+            .help("Label each URL read from a file with KEY=<file stem>; per-URL labels win"),
+        Arg::new("sitemap")
+            .long("sitemap")
+            .value_name("URL")
+            .help("Fetch a sitemap.xml (or .xml.gz, or sitemap index) and check every <loc> URL (repeatable)")
+            .action(ArgAction::Append),
+        Arg::new("sitemap-limit")
+            .long("sitemap-limit")
+            .value_name("N")
+            .help("Take at most N URLs from each --sitemap")
+            .value_parser(clap::value_parser!(usize)),
+        Arg::new("label")
+            .long("label")
+            .value_name("KEY=VALUE")
+            .help("Label attached to every URL; per-URL labels win on conflict (repeatable)")
+            .action(ArgAction::Append)
+            .num_args(1),
+        Arg::new("header")
+            .short('H')
+            .long("header")
+            .value_name("'Name: Value'")
+            .help("Require response header to match value (repeatable)")
+            .action(ArgAction::Append)
+            .num_args(1),
+        Arg::new("oauth-token-url")
+            .long("oauth-token-url")
+            .value_name("URL")
+            .env("SITECHECK_OAUTH_TOKEN_URL")
+            .help("Get a bearer token from URL with the OAuth2 client_credentials grant before the first check, send it with requests to the checked URL's origin, and get a new one before it expires")
+            .value_parser(parse_token_url)
+            .requires("oauth-client-id")
+            .requires("oauth-client-secret")
+            .num_args(1),
+        Arg::new("oauth-client-id")
+            .long("oauth-client-id")
+            .value_name("ID")
+            .env("SITECHECK_OAUTH_CLIENT_ID")
+            .help("Client ID for --oauth-token-url")
+            .requires("oauth-token-url")
+            .num_args(1),
+        Arg::new("oauth-client-secret")
+            .long("oauth-client-secret")
+            .value_name("SECRET")
+            .env("SITECHECK_OAUTH_CLIENT_SECRET")
+            .hide_env_values(true)
+            .help("Client secret for --oauth-token-url; prefer the environment variable, which other users can't see in the process list")
+            .requires("oauth-token-url")
+            .num_args(1),
+        Arg::new("no-retry-unsafe")
+            .long("no-retry-unsafe")
+            .help("Never retry a non-idempotent request: a failed --oauth-token-url POST isn't sent again on later rounds")
+            .action(ArgAction::SetTrue),
+        Arg::new("capture-header")
+            .long("capture-header")
+            .value_name("NAME")
+            .help("Record response header NAME in each result's captured_headers, null if absent; a CSV column each (repeatable)")
+            .action(ArgAction::Append)
+            .num_args(1),
+        Arg::new("contains")
+            .long("contains")
+            .value_name("TEXT")
+            .help("Require response body to contain TEXT")
+            .num_args(1),
+        Arg::new("capture-body-on-failure")
+            .long("capture-body-on-failure")
+            .value_name("BYTES")
+            .help("Add the first BYTES of the body to failed results as body_snippet; bodies are then always downloaded")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .num_args(1),
+        Arg::new("save-failures")
+            .long("save-failures")
+            .value_name("DIR")
+            .help("Save the body of each check that got a response but failed to DIR, with a .meta.json of its status, headers, and error")
+            .value_parser(clap::value_parser!(PathBuf))
+            .num_args(1),
+        Arg::new("save-failures-max-mb")
+            .long("save-failures-max-mb")
+            .value_name("MB")
+            .help("Keep at most MB megabytes in --save-failures, deleting the oldest saves first (default: 100)")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .requires("save-failures")
+            .num_args(1),
+        Arg::new("show-body-on-fail")
+            .long("show-body-on-fail")
+            .help("Log the body of each check that got a response but failed, JSON pretty-printed and cut to --max-body-bytes")
+            .action(ArgAction::SetTrue),
+        Arg::new("redact-pattern")
+            .long("redact-pattern")
+            .value_name("REGEX")
+            .help("Replace matches of REGEX (a token, a password) with [redacted] in bodies shown by --show-body-on-fail (repeatable)")
+            .requires("show-body-on-fail")
+            .action(ArgAction::Append),
+        Arg::new("check-clock-skew")
+            .long("check-clock-skew")
+            .value_name("SECS")
+            .help("Fail a check whose server clock, going by its Date header, is more than SECS seconds off ours")
+            .value_parser(clap::value_parser!(u64))
+            .num_args(1),
+        Arg::new("clock-skew-warn-only")
+            .long("clock-skew-warn-only")
+            .help("With --check-clock-skew, log a warning instead of failing the check")
+            .requires("check-clock-skew")
+            .action(ArgAction::SetTrue),
+        Arg::new("require-compression")
+            .long("require-compression")
+            .help("Send Accept-Encoding: gzip, br and fail a check whose response isn't compressed")
+            .action(ArgAction::SetTrue),
+        Arg::new("check-links")
+            .long("check-links")
+            .help("Check the same-origin links (href, src) on each HTML page with HEAD, failing a page with broken ones")
+            .action(ArgAction::SetTrue),
+        Arg::new("check-links-external")
+            .long("check-links-external")
+            .help("With --check-links, check links to other origins too")
+            .requires("check-links")
+            .action(ArgAction::SetTrue),
+        Arg::new("max-links-per-page")
+            .long("max-links-per-page")
+            .value_name("N")
+            .help("With --check-links, check at most N links per page (default: 100)")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .requires("check-links")
+            .num_args(1),
+        Arg::new("baseline-file")
+            .long("baseline-file")
+            .value_name("PATH")
+            .help("Keep each URL's median response time in the JSON file PATH, recording URLs it doesn't have yet")
+            .value_parser(clap::value_parser!(PathBuf))
+            .num_args(1),
+        Arg::new("record-baseline")
+            .long("record-baseline")
+            .help("Replace every --baseline-file median with this run's, without judging checks against the old ones")
+            .requires("baseline-file")
+            .action(ArgAction::SetTrue),
+        Arg::new("update-baseline")
+            .long("update-baseline")
+            .help("Blend this run's medians into the --baseline-file ones (20% weight) instead of keeping them as they are")
+            .requires("baseline-file")
+            .conflicts_with("record-baseline")
+            .action(ArgAction::SetTrue),
+        Arg::new("alert-degradation")
+            .long("alert-degradation")
+            .value_name("FACTOR")
+            .help("Fail a check that took more than FACTOR times its URL's --baseline-file median, e.g. 2.0")
+            .value_parser(Degradation::parse_factor)
+            .requires("baseline-file")
+            .num_args(1),
+        Arg::new("degradation-warn-only")
+            .long("degradation-warn-only")
+            .help("With --alert-degradation, log a warning instead of failing the check")
+            .requires("alert-degradation")
+            .action(ArgAction::SetTrue),
+        Arg::new("compare")
+            .long("compare")
+            .value_name("URL=CANARY")
+            .help("Check URL and then CANARY, failing if their status, captured headers, or body differ (repeatable)")
+            .value_parser(parse_compare)
+            .action(ArgAction::Append),
+        Arg::new("compare-ignore-regex")
+            .long("compare-ignore-regex")
+            .value_name("REGEX")
+            .help("Cut matches of REGEX (a CSRF token, a timestamp) from both bodies before comparing (repeatable)")
+            .requires("compare")
+            .action(ArgAction::Append),
+        Arg::new("head-size-only")
+            .long("head-size-only")
+            .help("Send HEAD instead of GET and report the resource's Content-Length as content_length")
+            .conflicts_with_all(["contains", "follow-meta-refresh", "check-links"])
+            .action(ArgAction::SetTrue),
+        Arg::new("range-fallback")
+            .long("range-fallback")
+            .help("With --head-size-only, get the size from a ranged GET (bytes=0-0) when HEAD omits Content-Length")
+            .requires("head-size-only")
+            .action(ArgAction::SetTrue),
+        Arg::new("max-body-bytes")
+            .long("max-body-bytes")
+            .value_name("BYTES")
+            .help("Fail checks whose body is longer than BYTES (default: 4 MiB); only read bodies count")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .num_args(1),
+        Arg::new("unlimited-body")
+            .long("unlimited-body")
+            .help("Read bodies of any size")
+            .conflicts_with("max-body-bytes")
+            .action(ArgAction::SetTrue),
+        Arg::new("dns-cache-ttl")
+            .long("dns-cache-ttl")
+            .value_name("DURATION")
+            .help("Reuse resolved host addresses for DURATION across checks and rounds (default: 60s)")
+            .value_parser(parse_duration)
+            .num_args(1),
+        Arg::new("no-dns-cache")
+            .long("no-dns-cache")
+            .help("Resolve hosts afresh for every connection")
+            .conflicts_with("dns-cache-ttl")
+            .action(ArgAction::SetTrue),
+        Arg::new("dns-server")
+            .long("dns-server")
+            .value_name("IP[:PORT]")
+            .help("Resolve hosts by asking the DNS server at IP (port 53 unless given; UDP, TCP for long answers) instead of the system resolver")
+            .value_parser(DnsServer::parse_addr)
+            .conflicts_with("unix-socket")
+            .num_args(1),
+        Arg::new("max-dns-concurrency")
+            .long("max-dns-concurrency")
+            .value_name("N")
+            .help("Run at most N host lookups at once, so a large round doesn't flood the resolver (cached hosts don't wait)")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .num_args(1),
+        Arg::new("unix-socket")
+            .long("unix-socket")
+            .value_name("PATH")
+            .help("Send requests over the Unix socket at PATH; URLs still name the path, e.g. http://localhost/health")
+            .value_parser(clap::value_parser!(PathBuf))
+            .num_args(1),
+        Arg::new("unix-host")
+            .long("unix-host")
+            .value_name("HOST")
+            .help("Host header sent to http+unix:///path/to.sock:/path targets")
+            .default_value(DEFAULT_UNIX_HOST)
+            .value_parser(parse_unix_host)
+            .num_args(1),
+        Arg::new("cert-info")
+            .long("cert-info")
+            .help("Record each https server's certificate (subject, issuer, validity, SANs, whether the chain validated)")
+            .conflicts_with("unix-socket")
+            .action(ArgAction::SetTrue),
+        Arg::new("har")
+            .long("har")
+            .value_name("PATH")
+            .help("Record every request and response (headers, status, timing, body size) in an HTTP Archive at PATH for browser devtools")
+            .value_parser(clap::value_parser!(PathBuf))
+            .num_args(1),
+        Arg::new("think-time-ms")
+            .long("think-time-ms")
+            .value_name("MIN:MAX")
+            .help("Have each worker pause a random MIN-MAX milliseconds after every check, for gentler traffic")
+            .value_parser(ThinkTime::parse)
+            .num_args(1),
+        Arg::new("think-time-seed")
+            .long("think-time-seed")
+            .value_name("SEED")
+            .help("Seed for --think-time-ms pauses, to repeat them run to run (default: random)")
+            .value_parser(clap::value_parser!(u64))
+            .requires("think-time-ms")
+            .num_args(1),
+        Arg::new("no-keepalive")
+            .long("no-keepalive")
+            .help("Open a new connection for every check, so response times include connection setup")
+            .action(ArgAction::SetTrue),
+        Arg::new("stagger")
+            .long("stagger")
+            .value_name("DURATION")
+            .help("Spread queuing the first round's checks evenly over DURATION, e.g. 500ms, 2s (default: all at once)")
+            .value_parser(parse_duration)
+            .num_args(1),
+        Arg::new("expect-status")
+            .long("expect-status")
+            .value_name("CODES")
+            .help("Require the final status to be one of CODES, e.g. 200, 2xx, 200-204,301 (error statuses listed here count as valid)")
+            .value_parser(StatusSet::parse)
+            .num_args(1),
+        Arg::new("default-scheme")
+            .long("default-scheme")
+            .value_name("SCHEME")
+            .help("Scheme to assume for URLs given without one (default: https)")
+            .num_args(1),
+        Arg::new("skip-invalid")
+            .long("skip-invalid")
+            .help("Warn about invalid URLs and continue with the valid ones instead of exiting")
+            .action(ArgAction::SetTrue),
+        Arg::new("include")
+            .long("include")
+            .value_name("GLOB|re:REGEX")
+            .help("Only check URLs matching this pattern (repeatable)")
+            .action(ArgAction::Append)
+            .num_args(1),
+        Arg::new("exclude")
+            .long("exclude")
+            .value_name("GLOB|re:REGEX")
+            .help("Skip URLs matching this pattern; applied after --include (repeatable)")
+            .action(ArgAction::Append)
+            .num_args(1),
+        Arg::new("allow-empty")
+            .long("allow-empty")
+            .help("Don't fail when filters match nothing or select no URLs")
+            .action(ArgAction::SetTrue),
+        Arg::new("allow-duplicates")
+            .long("allow-duplicates")
+            .help("Keep duplicate URLs (after normalization) instead of collapsing them")
+            .action(ArgAction::SetTrue),
+        Arg::new("checks-per-url")
+            .long("checks-per-url")
+            .value_name("N")
+            .help("Check every URL exactly N times, then stop: all at once, or one per round with --period")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .num_args(1),
+        Arg::new("fail-fast")
+            .long("fail-fast")
+            .help("Stop at the first URL that is down (after its retries) and exit 1")
+            .action(ArgAction::SetTrue),
+        Arg::new("ordered")
+            .long("ordered")
+            .help("Emit each round's results in URL order once the round completes")
+            .overrides_with("unordered")
+            .action(ArgAction::SetTrue),
+        Arg::new("unordered")
+            .long("unordered")
+            .help("Emit results as soon as each check finishes (default)")
+            .overrides_with("ordered")
+            .action(ArgAction::SetTrue),
+        Arg::new("csv")
+            .long("csv")
+            .value_name("PATH")
+            .help("Also append one CSV row per result to PATH")
+            .value_parser(clap::value_parser!(PathBuf))
+            .num_args(1),
+        Arg::new("summary-to")
+            .long("summary-to")
+            .value_name("DEST")
+            .help("Where the stats summary goes: stderr (default), stdout, or file:PATH (appended to)")
+            .value_parser(SummaryTo::parse)
+            .num_args(1),
+        Arg::new("stats-out")
+            .long("stats-out")
+            .value_name("PATH")
+            .help("Write the stats summary as JSON to PATH when the run ends")
+            .value_parser(clap::value_parser!(PathBuf))
+            .num_args(1),
+        Arg::new("stats-checkpoint-secs")
+            .long("stats-checkpoint-secs")
+            .value_name("N")
+            .help("Also write --stats-out every N seconds during the run, so a crash loses at most N seconds of stats")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .requires("stats-out")
+            .num_args(1),
+        Arg::new("webhook")
+            .long("webhook")
+            .value_name("URL")
+            .help("POST each down result as JSON to URL (repeatable)")
+            .value_parser(|s: &str| validate_url(s).map(|()| s.to_string()))
+            .action(ArgAction::Append)
+            .num_args(1),
+        Arg::new("alert-on-status")
+            .long("alert-on-status")
+            .value_name("CODES")
+            .help("Send --webhook results with one of CODES (e.g. 5xx), even passing ones, and no others; failures without a response still alert")
+            .value_parser(StatusSet::parse)
+            .requires("webhook")
+            .num_args(1),
+        Arg::new("no-alert-on-status")
+            .long("no-alert-on-status")
+            .value_name("CODES")
+            .help("Never send --webhook results with one of CODES (e.g. 429); wins over --alert-on-status")
+            .value_parser(StatusSet::parse)
+            .requires("webhook")
+            .num_args(1),
+        Arg::new("fifo")
+            .long("fifo")
+            .value_name("PATH")
+            .help("Also write each result as a JSON line to the named pipe at PATH (create it with mkfifo; Unix only)")
+            .value_parser(clap::value_parser!(PathBuf))
+            .num_args(1),
+        Arg::new("fifo-policy")
+            .long("fifo-policy")
+            .value_name("POLICY")
+            .help("When --fifo's reader is absent or behind and its buffer is full: drop results (default) or block until read")
+            .value_parser(["drop", "block"])
+            .requires("fifo")
+            .num_args(1),
+        Arg::new("heartbeat-url")
+            .long("heartbeat-url")
+            .value_name("URL")
+            .help("GET URL after every round, for a dead-man's switch that alerts if sitecheck stops (tried once, 3s timeout)")
+            .value_parser(|s: &str| validate_url(s).map(|()| s.to_string()))
+            .num_args(1),
+        Arg::new("heartbeat-on")
+            .long("heartbeat-on")
+            .value_name("WHEN")
+            .help("Which rounds ping --heartbeat-url: all (default) or success-only")
+            .value_parser(HeartbeatOn::parse)
+            .requires("heartbeat-url")
+            .num_args(1),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .help("Resolve and validate the configuration, print it, and exit without sending requests")
+            .action(ArgAction::SetTrue),
+        Arg::new("format")
+            .long("format")
+            .value_name("FORMAT")
+            .help("Output format for --dry-run: text (default) or json"),
+        Arg::new("validate-only")
+            .long("validate-only")
+            .help("Run every startup check (URLs, files, headers, regexes, ...) without sending requests, report all problems found, and exit 1 if there were any")
+            .conflicts_with("dry-run")
+            .action(ArgAction::SetTrue),
+        Arg::new("urls")
+            .help("List of URLs to check (http/https)")
+            .num_args(0..)
+            .value_name("URL"),
+    ]
 }
 
-#[derive(Debug, Default, Clone)]
-struct UrlStats {
-    checks: u64,
-    successes: u64,
-    total_response_ms: u128,
+/// Flags that only make sense when checks repeat.
+fn watch_args() -> Vec<Arg> {
+    vec![
+        Arg::new("period")
+            .short('p')
+            .long("period")
+            .value_name("DURATION")
+            .help("If set, run periodically every DURATION, e.g. 60, 30s, 5m (default: run once)")
+            .value_parser(parse_duration)
+            .num_args(1),
+        Arg::new("overlap")
+            .long("overlap")
+            .help("With --period, start each round on schedule even if the previous one is still running")
+            .action(ArgAction::SetTrue),
+        Arg::new("once")
+            .long("once")
+            .help("Run a single round and exit, ignoring --period; handy for trying out a watch config")
+            .action(ArgAction::SetTrue),
+        Arg::new("tui")
+            .long("tui")
+            .help("Show a live full-screen dashboard instead of JSON lines; q quits")
+            .action(ArgAction::SetTrue),
+    ]
 }
-impl UrlStats {
-    fn record(&mut self, ok: bool, rt: Duration) {
-        self.checks += 1;
-        if ok {
-            self.successes += 1;
-        }
-        self.total_response_ms += rt.as_millis();
-    }
-    fn uptime(&self) -> f64 {
-        if self.checks == 0 { 0.0 } else { (self.successes as f64) * 100.0 / (self.checks as f64) }
-    }
-    fn avg_ms(&self) -> f64 {
-        if self.checks == 0 { 0.0 } else { (self.total_response_ms as f64) / (self.checks as f64) }
-    }
+
+/// How the stats summary is laid out. Also used by `report`; JSON records are unaffected.
+fn summary_args() -> Vec<Arg> {
+    vec![
+        Arg::new("group-by")
+            .long("group-by")
+            .value_name("LABEL")
+            .help("Aggregate the stats summary per value of LABEL instead of per URL")
+            .num_args(1),
+        Arg::new("sort-by")
+            .long("sort-by")
+            .value_name("KEY")
+            .help("Order of the stats summary: input (default), url, uptime, avg, checks; ties go by URL")
+            .value_parser(SortBy::parse)
+            .num_args(1),
+        Arg::new("sort-desc")
+            .long("sort-desc")
+            .help("Reverse the --sort-by order (ties still go by URL)")
+            .action(ArgAction::SetTrue),
+        Arg::new("equal-weight")
+            .long("equal-weight")
+            .help("With --group-by, average the URLs' uptimes instead of weighting each URL by its check count")
+            .action(ArgAction::SetTrue),
+        Arg::new("ascii")
+            .long("ascii")
+            .help("Draw the summary's latency sparklines in ASCII, for terminals without Unicode")
+            .action(ArgAction::SetTrue),
+        Arg::new("timezone")
+            .long("timezone")
+            .value_name("ZONE")
+            .help("Time zone for timestamps in summaries and the dashboard: utc (default), local, or an IANA name")
+            .num_args(1),
+        Arg::new("time-format")
+            .long("time-format")
+            .value_name("STRFTIME")
+            .help("strftime format for timestamps in summaries and the dashboard (default: RFC3339)")
+            .num_args(1),
+    ]
 }
 
-fn parse_header(s: &str) -> Option<(String, String)> {
-    if let Some((name, value)) = s.split_once(':') {
-        Some((name.trim().to_string(), value.trim().to_string()))
-    } else {
-        None
-    }
+// Also used by `report`, which recomputes uptime from saved results
+fn up_if_arg() -> Arg {
+    Arg::new("up-if")
+        .long("up-if")
+        .value_name("PRESET")
+        .help("What counts as up for uptime: valid (default), any-response, 2xx, lt-400")
+        .num_args(1)
 }
 
+/// The `check` flags that shape a single request, which `bench` takes too.
+const BENCH_CHECK_ARGS: &[&str] = &[
+    "timeout",
+    "deadline",
+    "header",
+    "contains",
+    "expect-status",
+    "max-redirects",
+    "follow-meta-refresh",
+    "max-body-bytes",
+    "unlimited-body",
+    "no-keepalive",
+    "dns-server",
+    "max-dns-concurrency",
+    "unix-socket",
+    "unix-host",
+    "oauth-token-url",
+    "oauth-client-id",
+    "oauth-client-secret",
+];
+
+/// What a run writes to stdout, as promised in `--help`.
+const OUTPUT_CONTRACT: &str = "stdout carries only result records, one JSON object per line, so it can be piped into jq;
+  the stats summary and all logging go to stderr (see --summary-to).";
+
 fn build_cli() -> Command {
     Command::new("sitecheck")
         .about("Concurrent Website Status Checker (threaded + channels)")
         .arg(
-            Arg::new("threads")
-                .short('n')
-                .long("threads")
-                .value_name("NUM")
-                .help("Number of worker threads (default: 50)")
-                .num_args(1),
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Log more to stderr: -v for each attempt, worker and round, -vv for everything")
+                .action(ArgAction::Count)
+                .global(true),
         )
         .arg(
-            Arg::new("timeout")
-                .short('t')
-                .long("timeout")
-                .value_name("SECS")
-                .help("Request timeout seconds (default: 5)")
-                .num_args(1),
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Log less to stderr: -q for warnings and errors only, -qq for errors only")
+                .action(ArgAction::Count)
+                .global(true),
         )
-        .arg(
-            Arg::new("retries")
-                .short('r')
-                .long("retries")
-                .value_name("NUM")
-                .help("Max retries per website (default: 1)")
-                .num_args(1),
+        // Without a subcommand every flag is accepted, as before subcommands existed
+        .args(check_args())
+        .args(watch_args())
+        .args(summary_args())
+        .after_help(format!(
+"EXAMPLES:
+  sitecheck https://example.com https://rust-lang.org
+  sitecheck check -f urls.txt -n 80 -t 3 -r 2
+  sitecheck check -f teams/ --file-label team --group-by team
+  sitecheck check --sitemap https://example.com/sitemap.xml --sitemap-limit 200 --exclude '*/tag/*'
+  sitecheck watch -p 5m -H 'Server: nginx' --contains 'Welcome' https://example.com > results.ndjson
+  sitecheck report --from results.ndjson --group-by team
+  sitecheck bench https://example.com/health --requests 500 --concurrency 20
+  sitecheck selftest
+
+OUTPUT:
+  {OUTPUT_CONTRACT}"
+        ))
+        .subcommand(
+            Command::new("check")
+                .about("Check the URLs once and exit")
+                .args(check_args())
+                .args(summary_args())
+                .after_help(format!("OUTPUT:\n  {OUTPUT_CONTRACT}")),
         )
-        .arg(
-            Arg::new("period")
-                .short('p')
-                .long("period")
-                .value_name("SECS")
-                .help("If set, run periodically every SECS (default: run once)")
-                .num_args(1),
+        .subcommand(
+            Command::new("watch")
+                .about("Re-check the URLs every --period until Ctrl+C")
+                .args(check_args())
+                .args(watch_args())
+                .args(summary_args())
+                .mut_arg("period", |a| a.required(true))
+                .after_help(format!("OUTPUT:\n  {OUTPUT_CONTRACT}")),
         )
-        .arg(
-            Arg::new("file")
-                .short('f')
-                .long("file")
-                .value_name("PATH")
-                .help("File with one URL per line")
-                .num_args(1),
+        .subcommand(
+            Command::new("report")
+                .about("Recompute the stats summary from previously written NDJSON results")
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .value_name("PATH")
+                        .help("NDJSON results file, or - for stdin")
+                        .required(true),
+                )
+                .arg(up_if_arg())
+                .args(summary_args()),
         )
-        .arg(
-            Arg::new("header")
-                .short('H')
-                .long("header")
-                .value_name("'Name: Value'")
-                .help("Require response header to match value (repeatable)")
-                .action(ArgAction::Append)
-                .num_args(1),
+        .subcommand(
+            Command::new("bench")
+                .about("Check one URL many times concurrently and report throughput and latency percentiles")
+                .arg(Arg::new("url").required(true).value_name("URL").help("URL to check (http/https)"))
+                .arg(
+                    Arg::new("requests")
+                        .long("requests")
+                        .value_name("N")
+                        .help("Requests to send in total (default: 100)")
+                        .value_parser(clap::value_parser!(u64).range(1..)),
+                )
+                .arg(
+                    Arg::new("concurrency")
+                        .long("concurrency")
+                        .value_name("N")
+                        .help("Requests in flight at once (default: 10)")
+                        .value_parser(parse_thread_count),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Report format: text (default) or json")
+                        .value_parser(["text", "json"]),
+                )
+                .args(check_args().into_iter().filter(|a| BENCH_CHECK_ARGS.contains(&a.get_id().as_str()))),
         )
-        .arg(
-            Arg::new("contains")
-                .long("contains")
-                .value_name("TEXT")
-                .help("Require response body to contain TEXT")
-                .num_args(1),
-        )
-        .arg(
-            Arg::new("urls")
-                .help("List of URLs to check (http/https)")
-                .num_args(0..)
-                .value_name("URL"),
+        .subcommand(
+            Command::new("selftest")
+                .about("Check a built-in local server with the real worker pipeline and report pass/fail"),
         )
-        .after_help(
-"EXAMPLES:
-  sitecheck https://example.com https://rust-lang.org
-  sitecheck -f urls.txt -n 80 -t 3 -r 2
-  sitecheck -p 60 -H 'Server: nginx' --contains 'Welcome' https://example.com"
+        .subcommand(
+            Command::new("completions")
+                .about("Print a shell completion script to stdout")
+                .hide(true)
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(clap::value_parser!(clap_complete::Shell))
+                        .help("bash, zsh, fish, powershell, or elvish"),
+                ),
         )
 }
 
-fn read_urls_from_file(path: &PathBuf) -> io::Result<Vec<String>> {
-    let f = std::fs::File::open(path)?;
-    let reader = io::BufReader::new(f);
-    Ok(reader
-        .lines()
-        .filter_map(Result::ok)
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty() && !s.starts_with('#'))
-        .collect())
-}
-
-fn build_agent(timeout: Duration) -> ureq::Agent {
-    ureq::AgentBuilder::new()
-        .timeout_connect(timeout)
-        .timeout_read(timeout)
-        .timeout_write(timeout)
-        .redirects(2)
-        .build()
-}
-
-/// Fetch once with validations. Returns (HTTP status, elapsed).
-fn fetch_once(
-    agent: &ureq::Agent,
-    url: &str,
-    headers_expected: &[(String, String)],
-    contains: &Option<String>,
-) -> Result<(u16, Duration), String> {
-    let start = Instant::now();
-    let resp = agent
-        .get(url)
-        .call()
-        .map_err(|e| format!("request error: {e}"))?;
-
-    let status = resp.status();
-
-    // Header validation (case-insensitive name, exact value match)
-    for (name, value) in headers_expected {
-        // ureq uses case-insensitive header lookup
-        let got = resp.header(name);
-        match got {
-            Some(v) if v == value => {}
-            Some(v) => {
-                return Err(format!("header mismatch: {} expected '{}' got '{}'", name, value, v));
-            }
-            None => {
-                return Err(format!("missing required header: {}", name));
-            }
+/// Human-readable dump of the effective configuration for `--dry-run`.
+fn print_config(cfg: &Config) {
+    let c = &cfg.check;
+    println!("threads:        {}", cfg.worker_threads);
+    println!("timeout:        {}", format_duration(cfg.timeout));
+    println!("retries:        {}", c.max_retries);
+    let b = &c.backoff;
+    match b.multiplier {
+        Some(m) => print!("backoff:        {}, x{m} per retry", format_duration(b.base)),
+        None => print!("backoff:        {}, growing linearly", format_duration(b.base)),
+    }
+    match b.max {
+        Some(max) => println!(", at most {}", format_duration(max)),
+        None => println!(),
+    }
+    match c.deadline {
+        Some(d) => println!("deadline:       {} per check", format_duration(d)),
+        None => println!("deadline:       none"),
+    }
+    match cfg.retry_budget {
+        Some(n) => println!("retry budget:   {n} per round"),
+        None => println!("retry budget:   unlimited"),
+    }
+    match cfg.period {
+        Some(p) => println!("period:         every {}{}", format_duration(p), if cfg.overlap { " (overlapping)" } else { "" }),
+        None => println!("period:         run once"),
+    }
+    if let Some(window) = cfg.stagger {
+        println!("stagger:        first round over {}", format_duration(window));
+    }
+    if let Some(t) = cfg.think_time {
+        let seed = t.seed.map_or(String::new(), |seed| format!(" (seed {seed})"));
+        println!("think time:     {}-{} after each check{seed}", format_duration(t.min), format_duration(t.max));
+    }
+    if let Some(n) = cfg.checks_per_url {
+        println!("checks per url: {n}");
+    }
+    println!("up-if:          {}", cfg.up_if.as_str());
+    for w in &cfg.maintenance {
+        println!("maintenance:    {w} ({})", cfg.summary.times.timezone);
+    }
+    println!("results:        {}", if cfg.ordered { "in URL order, per round" } else { "as they complete" });
+    println!("max redirects:  {} (meta refresh: {})", c.max_redirects, if c.follow_meta_refresh { "on" } else { "off" });
+    if c.head_size_only {
+        println!("request:        HEAD, size only{}", if c.range_fallback { " (ranged GET fallback)" } else { "" });
+    }
+    if let Some(n) = c.record_redirects {
+        println!("record hops:    first {n}");
+    }
+    if let Some(expected) = &c.expect_status {
+        println!("expect status:  {expected}");
+    }
+    for (name, value) in &c.headers {
+        println!("header:         {name}: {value}");
+    }
+    for name in &c.capture_headers {
+        println!("capture:        {name}");
+    }
+    if let Some(needle) = &c.contains {
+        println!("contains:       {needle:?}");
+    }
+    if let Some(n) = c.capture_body_on_failure {
+        println!("body snippet:   first {n} bytes, on failure");
+    }
+    if let Some(save) = &c.save_failures {
+        println!("save failures:  {} (at most {} MB)", save.dir.display(), save.max_bytes / (1024 * 1024));
+    }
+    if c.show_body_on_fail {
+        println!("show body:      on failure");
+    }
+    for re in &c.redact_patterns {
+        println!("redact:         {re}");
+    }
+    for re in &c.compare_ignore {
+        println!("compare ignore: {re}");
+    }
+    if let Some(max_s) = c.max_clock_skew_s {
+        let action = if c.clock_skew_warn_only { "warn" } else { "fail" };
+        println!("clock skew:     {action} past {max_s}s");
+    }
+    if c.require_compression {
+        println!("compression:    required ({ACCEPT_COMPRESSED})");
+    }
+    if let Some(links) = c.check_links {
+        let which = if links.external { "all" } else { "same-origin" };
+        println!("links:          {which}, at most {} per page", links.max_per_page);
+    }
+    match c.max_body_bytes {
+        Some(n) => println!("max body:       {n} bytes"),
+        None => println!("max body:       unlimited"),
+    }
+    match cfg.dns_cache_ttl {
+        Some(ttl) => println!("dns cache:      {}", format_duration(ttl)),
+        None => println!("dns cache:      off"),
+    }
+    if let Some(addr) = cfg.dns_server {
+        println!("dns server:     {addr}");
+    }
+    if let Some(n) = cfg.max_dns_concurrency {
+        println!("dns lookups:    at most {n} at once");
+    }
+    println!("connections:    {}", if cfg.keepalive { "kept alive" } else { "new per check" });
+    if cfg.cert_info {
+        println!("certificates:   recorded");
+    }
+    if let Some(path) = &cfg.unix_socket {
+        println!("unix socket:    {}", path.display());
+    }
+    if cfg.urls.iter().any(|e| e.url.starts_with(UNIX_URL_PREFIX)) {
+        println!("unix host:      {}", cfg.unix_host);
+    }
+    if let Some(path) = &cfg.har {
+        println!("har:            {}", path.display());
+    }
+    if let Some(oauth) = &cfg.oauth {
+        let retried = if cfg.retry_unsafe { "" } else { ", failed requests not retried" };
+        println!("oauth:          client {} via {}{retried}", oauth.client_id, oauth.token_url);
+    }
+    let summary = &cfg.summary;
+    if let Some(key) = &summary.group_by {
+        println!("group by:       {key}");
+        println!("group uptime:   {}", if summary.equal_weight { "mean of URL uptimes" } else { "weighted by checks" });
+    }
+    println!("summary order:  {}{}", summary.sort_by.as_str(), if summary.sort_desc { " (descending)" } else { "" });
+    match &summary.times.time_format {
+        Some(f) => println!("times shown in: {} ({f})", summary.times.timezone),
+        None => println!("times shown in: {}", summary.times.timezone),
+    }
+    println!("urls ({}):", cfg.urls.len());
+    for e in &cfg.urls {
+        let mut line = format!("  {}  [{}]", e.url, e.source);
+        if let Some(t) = e.options.timeout {
+            line.push_str(&format!(" timeout={}", format_duration(t)));
+        }
+        if let Some(r) = e.options.retries {
+            line.push_str(&format!(" retries={r}"));
+        }
+        for w in &e.options.maintenance {
+            line.push_str(&format!(" maintenance={w}"));
+        }
+        for (k, v) in &e.labels {
+            line.push_str(&format!(" {k}={v}"));
+        }
+        println!("{line}");
+    }
+}
+
+/// Startup problems: the first one exits, unless `--validate-only` asked for
+/// every one of them to be logged and counted.
+#[derive(Default)]
+struct Problems {
+    collect: bool,
+    found: usize,
+}
+impl Problems {
+    fn report(&mut self, message: impl std::fmt::Display) {
+        error!("{message}");
+        if !self.collect {
+            std::process::exit(1);
         }
+        self.found += 1;
     }
+}
 
-    // Body validation (if requested)
-    if let Some(needle) = contains {
-        // Read body as string (NOTE: may be large; in production limit size or stream)
-        let body = resp
-            .into_string()
-            .map_err(|e| format!("body read error: {e}"))?;
-        if !body.contains(needle) {
-            return Err(format!("body validation failed: missing substring '{}'", needle));
+fn parse_up_if(m: &ArgMatches, problems: &mut Problems) -> UpIf {
+    match m.get_one::<String>("up-if").map(|s| UpIf::parse(s)) {
+        Some(Ok(up_if)) => up_if,
+        Some(Err(e)) => {
+            problems.report(e);
+            UpIf::default()
         }
-        let elapsed = start.elapsed();
-        Ok((status, elapsed))
+        None => UpIf::default(),
+    }
+}
+
+/// How often rounds repeat; None runs one round. `check` has no --period/--once, so look them up leniently.
+fn parse_period(m: &ArgMatches) -> Option<Duration> {
+    let period = m.try_get_one::<Duration>("period").ok().flatten().copied();
+    let once = m.try_get_one::<bool>("once").ok().flatten().copied().unwrap_or(false);
+    if once && period.is_some() {
+        info!("--once: running a single round instead of every --period");
+    }
+    period.filter(|_| !once)
+}
+
+fn parse_max_redirects(m: &ArgMatches) -> u32 {
+    m.get_one::<u32>("max-redirects").copied().unwrap_or(DEFAULT_MAX_REDIRECTS)
+}
+
+fn parse_save_failures(m: &ArgMatches) -> Option<SaveFailures> {
+    let dir = m.get_one::<PathBuf>("save-failures")?.clone();
+    let max_mb = m.get_one::<u64>("save-failures-max-mb").copied().unwrap_or(DEFAULT_SAVE_FAILURES_MAX_MB);
+    Some(SaveFailures { dir, max_bytes: max_mb.saturating_mul(1024 * 1024) })
+}
+
+fn parse_think_time(m: &ArgMatches) -> Option<ThinkTime> {
+    let think_time = m.get_one::<ThinkTime>("think-time-ms")?;
+    Some(ThinkTime { seed: m.get_one::<u64>("think-time-seed").copied(), ..*think_time })
+}
+
+fn parse_backoff(m: &ArgMatches) -> Backoff {
+    Backoff {
+        base: m.get_one::<u64>("backoff-base-ms").map_or(DEFAULT_BACKOFF_BASE, |&ms| Duration::from_millis(ms)),
+        multiplier: m.get_one::<f64>("backoff-multiplier").copied(),
+        max: m.get_one::<u64>("backoff-max-ms").map(|&ms| Duration::from_millis(ms)),
+    }
+}
+
+fn parse_max_body_bytes(m: &ArgMatches) -> Option<u64> {
+    if m.get_flag("unlimited-body") {
+        None
     } else {
-        // If we didn't read the body above, ensure we close it
-        let _ = resp.into_reader(); // drop the reader; not strictly necessary
-        let elapsed = start.elapsed();
-        Ok((status, elapsed))
-    }
-}
-
-/// Check a URL with retries & validations, returning a WebsiteStatus.
-fn check_with_retries(
-    agent: &ureq::Agent,
-    url: &str,
-    headers_expected: &[(String, String)],
-    contains: &Option<String>,
-    max_retries: usize,
-) -> WebsiteStatus {
-    let mut last_err: Option<String> = None;
-    for attempt in 0..=max_retries {
-        match fetch_once(agent, url, headers_expected, contains) {
-            Ok((code, rt)) => {
-                return WebsiteStatus {
-                    url: url.to_string(),
-                    status: Ok(code),
-                    response_time: rt,
-                    timestamp: Utc::now(),
-                };
-            }
-            Err(e) => {
-                last_err = Some(e);
-                if attempt < max_retries {
-                    // simple linear backoff
-                    thread::sleep(Duration::from_millis(200 * (attempt as u64 + 1)));
-                }
-            }
+        Some(m.get_one::<u64>("max-body-bytes").copied().unwrap_or(DEFAULT_MAX_BODY_BYTES))
+    }
+}
+
+/// `--check-links` and the flags that shape it.
+fn parse_check_links(m: &ArgMatches) -> Option<LinkSettings> {
+    m.get_flag("check-links").then(|| LinkSettings {
+        external: m.get_flag("check-links-external"),
+        max_per_page: m.get_one::<u64>("max-links-per-page").map_or(DEFAULT_MAX_LINKS_PER_PAGE, |&n| n as usize),
+    })
+}
+
+/// A `--unix-host`: something that can stand as the host of a URL.
+fn parse_unix_host(s: &str) -> Result<String, String> {
+    validate_url(&format!("http://{s}/")).map_err(|_| format!("not a host: {s:?}"))?;
+    if s.contains(['/', '?', '#', '@']) {
+        return Err(format!("not a host: {s:?}"));
+    }
+    Ok(s.to_string())
+}
+
+/// An `--oauth-token-url`, which is fetched over TCP, so not an `http+unix://` one.
+fn parse_token_url(s: &str) -> Result<String, String> {
+    validate_url(s)?;
+    if s.starts_with(UNIX_URL_PREFIX) {
+        return Err(format!("not an http(s) URL: {s:?}"));
+    }
+    Ok(s.to_string())
+}
+
+/// `--oauth-token-url` and the client it gets tokens for; clap sees that all three come together.
+fn parse_oauth(m: &ArgMatches) -> Option<OAuthSettings> {
+    Some(OAuthSettings {
+        token_url: m.get_one::<String>("oauth-token-url")?.clone(),
+        client_id: m.get_one::<String>("oauth-client-id")?.clone(),
+        client_secret: m.get_one::<String>("oauth-client-secret")?.clone(),
+    })
+}
+
+/// Get the first `--oauth-token-url` token, so an endpoint that is down or
+/// rejects the client fails the run before any check is made.
+fn fetch_first_token(checker: &Checker) -> Result<(), String> {
+    match checker.oauth() {
+        Some(token) => token.refresh().map_err(|e| format!("--oauth-token-url {}: {e}", token.settings().token_url)),
+        None => Ok(()),
+    }
+}
+
+/// Replace a `--oauth-token-url` token about to expire before a round. If that
+/// fails the round goes ahead with the current one, and the next round tries
+/// again, unless `--no-retry-unsafe` says a failed POST stays failed.
+fn refresh_token(checker: &Checker) {
+    let retry = checker.config().retry_unsafe;
+    if let Some(token) = checker.oauth().filter(|token| token.due() && (retry || !token.failed())) {
+        if let Err(e) = token.refresh() {
+            warn!("--oauth-token-url {}: {e}; keeping the current token", token.settings().token_url);
         }
     }
-    WebsiteStatus {
-        url: url.to_string(),
-        status: Err(last_err.unwrap_or_else(|| "unknown error".to_string())),
-        response_time: Duration::from_millis(0),
-        timestamp: Utc::now(),
+}
+
+/// `--unix-socket`, a problem if it names no usable socket.
+fn parse_unix_socket(m: &ArgMatches, problems: &mut Problems) -> Option<PathBuf> {
+    let path = m.get_one::<PathBuf>("unix-socket")?;
+    #[cfg(unix)]
+    let usable = check_socket(path);
+    #[cfg(not(unix))]
+    let usable: Result<(), String> = Err(format!("--unix-socket {}: only supported on Unix", path.display()));
+    if let Err(e) = usable {
+        problems.report(e);
+        return None;
+    }
+    Some(path.clone())
+}
+
+/// `--baseline-file`, a problem if it exists but can't be read.
+fn parse_baselines(m: &ArgMatches, problems: &mut Problems) -> Option<Baselines> {
+    let path = m.get_one::<PathBuf>("baseline-file")?;
+    Baselines::load(path).map_err(|e| problems.report(e)).ok()
+}
+
+/// Fold the run's medians into `--baseline-file` and write it back.
+fn save_baselines(baselines: &mut Baselines, rerecord: bool, blend: bool) {
+    let changed = baselines.update(rerecord, blend.then_some(BASELINE_BLEND));
+    match baselines.save() {
+        Ok(()) => info!("{}: {changed} baseline(s) recorded or changed", baselines.path().display()),
+        Err(e) => error!("{}: {e}", baselines.path().display()),
+    }
+}
+
+/// Write `--har` out as the run ends.
+fn save_har(har: &Har) {
+    let (entries, dropped) = har.counts();
+    match har.save() {
+        Ok(()) if dropped > 0 => info!("{}: {entries} request(s) recorded, {dropped} older one(s) dropped", har.path().display()),
+        Ok(()) => info!("{}: {entries} request(s) recorded", har.path().display()),
+        Err(e) => error!("{}: {e}", har.path().display()),
+    }
+}
+
+fn parse_summary_style(m: &ArgMatches, problems: &mut Problems) -> SummaryStyle {
+    let timezone = m.get_one::<String>("timezone").map(String::as_str);
+    let time_format = m.get_one::<String>("time-format").map(String::as_str);
+    let times = TimeDisplay::parse(timezone, time_format).unwrap_or_else(|e| {
+        problems.report(e);
+        TimeDisplay::default()
+    });
+    SummaryStyle {
+        group_by: m.get_one::<String>("group-by").cloned(),
+        sort_by: m.get_one::<SortBy>("sort-by").copied().unwrap_or_default(),
+        sort_desc: m.get_flag("sort-desc"),
+        equal_weight: m.get_flag("equal-weight"),
+        ascii: m.get_flag("ascii"),
+        times,
+    }
+}
+
+/// `sitecheck report`: print the stats summary for previously saved results.
+fn run_report(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let from = m.get_one::<String>("from").expect("--from is required");
+    let mut problems = Problems::default();
+    let up_if = parse_up_if(m, &mut problems);
+    let style = parse_summary_style(m, &mut problems);
+    let reader: Box<dyn BufRead> = if from == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(io::BufReader::new(std::fs::File::open(from)?))
+    };
+    let stats = read_results(reader, up_if).map_err(|e| format!("{from}: {e}"))?;
+    if stats.is_empty() {
+        error!("{from}: no results found");
+        std::process::exit(1);
     }
+    summarize(&SummaryTo::Stdout, &stats, &style);
+    Ok(())
 }
 
-fn print_status_json(s: &WebsiteStatus) {
-    // Pretty JSON line for each status
-    match serde_json::to_string(s) {
-        Ok(js) => println!("{}", js),
-        Err(_) => println!("{:?}", s),
+/// `sitecheck bench`: check one URL `--requests` times, `--concurrency` at a
+/// time, without retries, and report throughput and latency. Exits 1 if any
+/// request failed, so it doubles as a correctness-under-load check.
+fn run_bench(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let raw = m.get_one::<String>("url").expect("URL is required");
+    let url = apply_default_scheme(raw, "https").unwrap_or_else(|| raw.clone());
+    if let Err(e) = validate_url(&url) {
+        error!("{url}: {e}");
+        std::process::exit(1);
+    }
+    let requests = m.get_one::<u64>("requests").map_or(100, |&n| n as usize);
+    let concurrency = m.get_one::<usize>("concurrency").copied().unwrap_or(10).min(requests);
+    let mut builder = Config::builder()
+        .threads(concurrency)
+        .timeout(m.get_one::<Duration>("timeout").copied().unwrap_or(DEFAULT_TIMEOUT))
+        .retries(0)
+        .max_redirects(parse_max_redirects(m))
+        .deadline(m.get_one::<Duration>("deadline").copied())
+        .follow_meta_refresh(m.get_flag("follow-meta-refresh"))
+        .contains(m.get_one::<String>("contains").cloned())
+        .max_body_bytes(parse_max_body_bytes(m))
+        .expect_status(m.get_one::<StatusSet>("expect-status").cloned())
+        .keepalive(!m.get_flag("no-keepalive"))
+        .dns_server(m.get_one::<SocketAddr>("dns-server").copied())
+        .max_dns_concurrency(m.get_one::<u64>("max-dns-concurrency").map(|&n| n as usize))
+        .unix_socket(parse_unix_socket(m, &mut Problems::default()))
+        .unix_host(m.get_one::<String>("unix-host").cloned().unwrap_or_else(|| DEFAULT_UNIX_HOST.to_string()))
+        .oauth(parse_oauth(m))
+        .checks_per_url(Some(requests));
+    for (name, value) in m.get_many::<String>("header").into_iter().flatten().filter_map(|s| parse_header(s)) {
+        builder = builder.header(name, value);
+    }
+    let checker = Checker::new(builder.build());
+    fetch_first_token(&checker)?;
+
+    info!("bench: {requests} requests to {url}, {concurrency} at a time");
+    let progress_every = (requests / 10).max(1);
+    let mut bench = Bench::default();
+    let start = Instant::now();
+    checker.run(&[UrlEntry::new(url.clone(), UrlSource::Arg(1))], |status| {
+        bench.record(&status);
+        let done = bench.requests();
+        if done % progress_every == 0 {
+            info!("bench: {done}/{requests} done");
+        }
+    });
+    let report = bench.report(&url, concurrency, start.elapsed());
+    if m.get_one::<String>("format").is_some_and(|f| f == "json") {
+        println!("{}", report.to_json()?);
+    } else {
+        report.write_text(&mut io::stdout())?;
+    }
+    if report.ok < report.requests {
+        std::process::exit(1);
     }
+    Ok(())
 }
 
-fn summarize(stats: &HashMap<String, UrlStats>) {
-    println!("--- stats summary ---");
-    for (url, st) in stats {
-        println!(
-            "{} -> checks: {}, uptime: {:.1}%, avg_rt_ms: {:.1}",
-            url,
-            st.checks,
-            st.uptime(),
-            st.avg_ms()
-        );
+/// Write a one-off summary (reports, and the final one after the dashboard or `--fail-fast`) to `to`.
+fn summarize(to: &SummaryTo, stats: &HashMap<String, UrlStats>, style: &SummaryStyle) {
+    let written = to.open().and_then(|mut out| write_summary(&mut out, stats, None, style));
+    if let Err(e) = written {
+        warn!("writing the summary failed: {e}");
     }
-    println!("---------------------");
+}
+
+/// A round still waiting on results.
+struct PendingRound {
+    /// Positions in the URL list with no result yet.
+    left: BTreeSet<usize>,
+    budget: Option<Arc<RetryBudget>>,
+    /// Results held back for `--ordered`.
+    held: Option<InOrder>,
+    /// When to stop waiting and record the rest as `NoResult` failures.
+    deadline: Instant,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let m = build_cli().get_matches();
+    let top = build_cli().get_matches();
 
-    let worker_threads: usize = m
-        .get_one::<String>("threads")
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(50);
+    // A bare invocation behaves like `check`, but still accepts --period for compatibility
+    let (command, m) = match top.subcommand() {
+        Some(("completions", sub)) => {
+            let shell = *sub.get_one::<clap_complete::Shell>("shell").expect("shell is required");
+            clap_complete::generate(shell, &mut build_cli(), "sitecheck", &mut io::stdout());
+            return Ok(());
+        }
+        Some((name, sub)) => (name, sub),
+        None => ("check", &top),
+    };
+
+    let tui = m.try_get_one::<bool>("tui").ok().flatten().copied().unwrap_or(false);
+    if tui && !io::stdout().is_terminal() {
+        eprintln!("--tui needs a terminal on stdout");
+        std::process::exit(1);
+    }
+
+    // Logs go to stderr so stdout stays a clean stream of result records
+    let verbosity = i16::from(m.get_count("verbose")) - i16::from(m.get_count("quiet"));
+    let level = match verbosity {
+        ..=-2 => log::LevelFilter::Error,
+        -1 => log::LevelFilter::Warn,
+        0 => log::LevelFilter::Info,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    // Log lines would tear through the dashboard, so it runs silent
+    let level = if tui { log::LevelFilter::Off } else { level };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .target(env_logger::Target::Stderr)
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
 
-    let timeout = Duration::from_secs(
-        m.get_one::<String>("timeout")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(5),
-    );
+    if command == "report" {
+        return run_report(m);
+    }
+    if command == "bench" {
+        return run_bench(m);
+    }
+    if command == "selftest" {
+        if !selftest::run()? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let dry_run = m.get_flag("dry-run");
+    // Neither sends a request; --validate-only also carries on past each problem
+    let validate_only = m.get_flag("validate-only");
+    let mut problems = Problems { collect: validate_only, found: 0 };
+    let json_format = match m.get_one::<String>("format").map(String::as_str) {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => {
+            problems.report(format!("unknown --format '{other}' (expected text or json)"));
+            false
+        }
+    };
+
+    let timeout = m.get_one::<Duration>("timeout").copied().unwrap_or(DEFAULT_TIMEOUT);
 
     let max_retries: usize = m
         .get_one::<String>("retries")
         .and_then(|s| s.parse().ok())
-        .unwrap_or(1);
+        .unwrap_or(DEFAULT_RETRIES);
+
+    let period = parse_period(m);
+
+    let max_redirects = parse_max_redirects(m);
 
-    let period = m
-        .get_one::<String>("period")
-        .and_then(|s| s.parse::<u64>().ok())
-        .map(Duration::from_secs);
+    let mut entries: Vec<UrlEntry> = vec![];
 
-    let mut urls: Vec<String> = vec![];
+    let mut files: Vec<PathBuf> = vec![];
+    for spec in m.get_many::<String>("file").into_iter().flatten() {
+        let resolved = resolve_url_files(spec).unwrap_or_else(|e| {
+            problems.report(e);
+            Vec::new()
+        });
+        for path in resolved {
+            // A file named by both a directory and a glob is only read once
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    let file_label = m.get_one::<String>("file-label");
+    for path in &files {
+        let mut loaded = match read_urls_from_file(path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                problems.report(e);
+                continue;
+            }
+        };
+        if let (Some(key), Some(stem)) = (file_label, path.file_stem()) {
+            for e in &mut loaded {
+                e.labels.entry(key.clone()).or_insert_with(|| stem.to_string_lossy().into_owned());
+            }
+        }
+        entries.extend(loaded);
+    }
 
-    if let Some(path) = m.get_one::<String>("file") {
-        let path = PathBuf::from(path);
-        urls.extend(read_urls_from_file(&path)?);
+    let sitemap_limit = m.get_one::<usize>("sitemap-limit").copied();
+    let sitemap_client = UreqClient::new();
+    let sitemaps: Vec<&String> = m.get_many::<String>("sitemap").into_iter().flatten().collect();
+    for url in &sitemaps {
+        if dry_run || validate_only {
+            // Fetching would be a network request; the sitemap's URLs are not listed
+            info!("not fetching sitemap {url} without sending requests");
+            continue;
+        }
+        let loaded = load_sitemap(&sitemap_client, url, sitemap_limit, timeout, max_redirects).unwrap_or_else(|e| {
+            error!("{e}");
+            std::process::exit(1);
+        });
+        info!("Loaded {} URL(s) from sitemap {}", loaded.len(), url);
+        entries.extend(loaded);
     }
 
     if let Some(args) = m.get_many::<String>("urls") {
-        urls.extend(args.into_iter().map(|s| s.to_string()));
+        entries.extend(args.into_iter().enumerate().map(|(i, s)| UrlEntry::new(s, UrlSource::Arg(i + 1))));
+    }
+    if let Some(pairs) = m.get_many::<(String, String)>("compare") {
+        entries.extend(pairs.into_iter().enumerate().map(|(i, (url, canary))| UrlEntry {
+            compare: Some(canary.clone()),
+            ..UrlEntry::new(url.clone(), UrlSource::Compare(i + 1))
+        }));
     }
 
-    if urls.is_empty() {
-        eprintln!("No URLs provided. Provide positional URLs or -f <file>.");
-        std::process::exit(1);
+    // Per-URL option suffixes come off first so they apply to every brace expansion
+    entries.retain_mut(|e| match split_url_options(&e.url) {
+        Ok((url, options)) => {
+            e.url = url;
+            e.options = options;
+            true
+        }
+        Err(err) => {
+            problems.report(format!("{}: '{}': {}", e.source, e.url, err));
+            false
+        }
+    });
+
+    // Brace ranges expand before anything else looks at the URL, so each
+    // expanded URL keeps its line's labels and is validated/deduped on its own
+    let mut expanded = Vec::with_capacity(entries.len());
+    for e in entries {
+        match expand_braces(&e.url) {
+            Ok(urls) => expanded.extend(urls.into_iter().map(|url| UrlEntry { url, ..e.clone() })),
+            Err(err) => problems.report(format!("{}: '{}': {}", e.source, e.url, err)),
+        }
+    }
+    let mut entries = expanded;
+
+    // Global labels apply to every URL; labels from the URL file take precedence
+    let mut global_labels = Labels::new();
+    for raw in m.get_many::<String>("label").into_iter().flatten() {
+        match parse_label(raw) {
+            Ok((k, v)) => {
+                global_labels.insert(k, v);
+            }
+            Err(e) => problems.report(e),
+        }
+    }
+    for e in &mut entries {
+        for (k, v) in &global_labels {
+            e.labels.entry(k.clone()).or_insert_with(|| v.clone());
+        }
+    }
+
+    let mut default_scheme = m
+        .get_one::<String>("default-scheme")
+        .map(|s| s.to_ascii_lowercase())
+        .unwrap_or_else(|| "https".to_string());
+    if !SUPPORTED_SCHEMES.contains(&default_scheme.as_str()) {
+        problems.report(format!(
+            "Unsupported --default-scheme '{}' (expected one of: {})",
+            default_scheme,
+            SUPPORTED_SCHEMES.join(", ")
+        ));
+        default_scheme = "https".to_string();
+    }
+    for e in &mut entries {
+        if let Some(rewritten) = apply_default_scheme(&e.url, &default_scheme) {
+            info!("{}: no scheme in '{}', using '{}'", e.source, e.url, rewritten);
+            e.url = rewritten;
+        }
     }
 
-    let headers: Vec<(String, String)> = m
-        .get_many::<String>("header")
-        .map(|vals| {
-            vals.filter_map(|s| parse_header(s))
-                .collect::<Vec<(String, String)>>()
-        })
-        .unwrap_or_default();
+    let (entries, invalid) = partition_valid(entries);
+    if !invalid.is_empty() {
+        let skip = m.get_flag("skip-invalid");
+        let level = if skip { log::Level::Warn } else { log::Level::Error };
+        for (e, reason) in &invalid {
+            log::log!(level, "{}: '{}': {}", e.source, e.url, reason);
+        }
+        if skip {
+            warn!("Skipping {} invalid URL(s).", invalid.len());
+        } else {
+            problems.report(format!("{} invalid URL(s); fix them or pass --skip-invalid.", invalid.len()));
+        }
+    }
+
+    let (urls, collapsed) = dedup_urls(entries, m.get_flag("allow-duplicates"));
+    if !collapsed.is_empty() {
+        info!("Collapsed {} duplicate URL(s):", collapsed.len());
+        for (raw, url) in &collapsed {
+            info!("  {} ({}) -> {}", raw.url, raw.source, url);
+        }
+    }
+
+    let mut parse_filters = |id: &str| -> Vec<UrlFilter> {
+        m.get_many::<String>(id)
+            .into_iter()
+            .flatten()
+            .filter_map(|s| UrlFilter::parse(s).map_err(|e| problems.report(e)).ok())
+            .collect()
+    };
+    let include = parse_filters("include");
+    let exclude = parse_filters("exclude");
+    let allow_empty = m.get_flag("allow-empty");
+    let mut nothing_selected = false;
+    let urls = if include.is_empty() && exclude.is_empty() {
+        urls
+    } else {
+        let total = urls.len();
+        let selected = apply_filters(urls, &include, &exclude, allow_empty).unwrap_or_else(|e| {
+            problems.report(format!("{e} (pass --allow-empty to permit this)"));
+            Vec::new()
+        });
+        info!("Selected {} of {} URL(s).", selected.len(), total);
+        if selected.is_empty() {
+            info!("Nothing to check.");
+            if !validate_only {
+                return Ok(());
+            }
+            nothing_selected = true;
+        }
+        selected
+    };
+
+    // Without requests sitemaps aren't fetched, so sitemap-only input is not "empty" there
+    let unfetched_sitemaps = (dry_run || validate_only) && !sitemaps.is_empty();
+    if urls.is_empty() && !unfetched_sitemaps && !nothing_selected {
+        problems.report("No URLs provided. Provide positional URLs or -f <file>.");
+    }
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for raw in m.get_many::<String>("header").into_iter().flatten() {
+        match parse_header(raw) {
+            Some(header) => headers.push(header),
+            None => problems.report(format!("invalid header '{raw}' (expected 'Name: Value')")),
+        }
+    }
+    let compare_ignore: Vec<regex::Regex> = m
+        .get_many::<String>("compare-ignore-regex")
+        .into_iter()
+        .flatten()
+        .filter_map(|s| parse_compare_ignore(s).map_err(|e| problems.report(e)).ok())
+        .collect();
+    let redact_patterns: Vec<regex::Regex> = m
+        .get_many::<String>("redact-pattern")
+        .into_iter()
+        .flatten()
+        .filter_map(|s| parse_redact_pattern(s).map_err(|e| problems.report(e)).ok())
+        .collect();
 
     let contains = m.get_one::<String>("contains").cloned();
+    let max_body_bytes = parse_max_body_bytes(m);
 
-    let cfg = Config {
-        worker_threads,
-        timeout,
-        max_retries,
-        period,
-        headers,
-        contains,
-        urls,
+    let dns_cache_ttl = if m.get_flag("no-dns-cache") {
+        None
+    } else {
+        Some(m.get_one::<Duration>("dns-cache-ttl").copied().unwrap_or(DEFAULT_DNS_CACHE_TTL))
     };
 
+    let unix_socket = parse_unix_socket(m, &mut problems);
+
+    let mut baselines = parse_baselines(m, &mut problems);
+    let record_baseline = m.get_flag("record-baseline");
+    let degradation = m
+        .get_one::<f64>("alert-degradation")
+        .map(|&factor| Degradation { factor, warn_only: m.get_flag("degradation-warn-only") });
+
+    let retry_budget = m.get_one::<usize>("retry-budget").copied();
+
+    let up_if = parse_up_if(m, &mut problems);
+    let summary = parse_summary_style(m, &mut problems);
+
+    let max_threads = m.get_one::<usize>("max-threads").copied().unwrap_or(DEFAULT_MAX_THREADS);
+    let requested_threads = m.get_one::<usize>("threads").copied().unwrap_or(DEFAULT_THREADS);
+    let checks_per_url = m.get_one::<u64>("checks-per-url").map(|&n| n as usize);
+    let one_shot_checks = urls.len().saturating_mul(checks_per_url.unwrap_or(1));
+    let worker_threads = worker_count(requested_threads, max_threads, one_shot_checks, period.is_none());
+
+    let mut builder = Config::builder()
+        .threads(worker_threads)
+        .timeout(timeout)
+        .period(period)
+        .retries(max_retries)
+        .backoff(parse_backoff(m))
+        .retry_budget(retry_budget)
+        .max_redirects(max_redirects)
+        .record_redirects(m.get_one::<usize>("record-redirects").copied())
+        .deadline(m.get_one::<Duration>("deadline").copied())
+        .follow_meta_refresh(m.get_flag("follow-meta-refresh"))
+        .head_size_only(m.get_flag("head-size-only"), m.get_flag("range-fallback"))
+        .check_clock_skew(m.get_one::<u64>("check-clock-skew").copied(), m.get_flag("clock-skew-warn-only"))
+        .require_compression(m.get_flag("require-compression"))
+        .check_links(parse_check_links(m))
+        .contains(contains)
+        .capture_body_on_failure(m.get_one::<u64>("capture-body-on-failure").map(|&n| n as usize))
+        .save_failures(parse_save_failures(m))
+        .show_body_on_fail(m.get_flag("show-body-on-fail"))
+        .max_body_bytes(max_body_bytes)
+        .expect_status(m.get_one::<StatusSet>("expect-status").cloned())
+        .up_if(up_if)
+        .overlap(m.try_get_one::<bool>("overlap").ok().flatten().copied().unwrap_or(false))
+        .stagger(m.get_one::<Duration>("stagger").copied())
+        .think_time(parse_think_time(m))
+        .maintenance(m.get_many::<MaintenanceWindow>("maintenance").into_iter().flatten().copied().collect())
+        .fail_fast(m.get_flag("fail-fast"))
+        .ordered(m.get_flag("ordered"))
+        .checks_per_url(checks_per_url)
+        .dns_cache_ttl(dns_cache_ttl)
+        .dns_server(m.get_one::<SocketAddr>("dns-server").copied())
+        .max_dns_concurrency(m.get_one::<u64>("max-dns-concurrency").map(|&n| n as usize))
+        .keepalive(!m.get_flag("no-keepalive"))
+        .cert_info(m.get_flag("cert-info"))
+        .har(m.get_one::<PathBuf>("har").cloned())
+        .oauth(parse_oauth(m))
+        .retry_unsafe(!m.get_flag("no-retry-unsafe"))
+        .unix_socket(unix_socket)
+        .unix_host(m.get_one::<String>("unix-host").cloned().unwrap_or_else(|| DEFAULT_UNIX_HOST.to_string()))
+        .summary(summary)
+        .urls(urls);
+    for (name, value) in headers {
+        builder = builder.header(name, value);
+    }
+    for name in m.get_many::<String>("capture-header").into_iter().flatten() {
+        builder = builder.capture_header(name);
+    }
+    for re in redact_patterns {
+        builder = builder.redact_pattern(re);
+    }
+    for re in compare_ignore {
+        builder = builder.compare_ignore(re);
+    }
+    let checker = Checker::new(builder.build());
+    let cfg = checker.config();
+    if cfg.oauth.is_some() && cfg.period.is_some() && cfg.retry_unsafe {
+        warn!("--oauth-token-url: a failed token request is a POST, which isn't idempotent, and is retried every round; --no-retry-unsafe sends it once");
+    }
+
+    if validate_only {
+        if problems.found > 0 {
+            error!("{} problem(s) found.", problems.found);
+            std::process::exit(1);
+        }
+        println!("Configuration OK: {} URL(s), no problems found.", cfg.urls.len());
+        return Ok(());
+    }
+
+    if dry_run {
+        if json_format {
+            println!("{}", cfg.to_json()?);
+        } else {
+            print_config(cfg);
+        }
+        return Ok(());
+    }
+
     // Graceful shutdown flag
     let stop = Arc::new(AtomicBool::new(false));
     {
         let stop = stop.clone();
         ctrlc::set_handler(move || {
-            eprintln!("
-Ctrl+C detected, shutting down...");
+            info!("Ctrl+C detected, shutting down...");
             stop.store(true, Ordering::SeqCst);
         })?;
     }
 
-    // Channels
-    let (job_tx, job_rx_raw) = mpsc::channel::<String>();
-    let job_rx = Arc::new(Mutex::new(job_rx_raw)); // share one receiver across workers
-    let (res_tx, res_rx) = mpsc::channel::<WebsiteStatus>();
-
-    // Spawn workers
-    let mut workers = Vec::with_capacity(cfg.worker_threads);
-    for _ in 0..cfg.worker_threads {
-        let job_rx = Arc::clone(&job_rx);
-        let res_tx = res_tx.clone();
-        let headers = cfg.headers.clone();
-        let contains = cfg.contains.clone();
-        let timeout = cfg.timeout;
-        let max_retries = cfg.max_retries;
-
-        workers.push(thread::spawn(move || {
-            let agent = build_agent(timeout);
-            loop {
-                // Lock only to receive the next job, then release before doing work
-                let msg = {
-                    let rx = job_rx.lock().unwrap();
-                    rx.recv()
-                };
-                match msg {
-                    Ok(url) => {
-                        let status = check_with_retries(&agent, &url, &headers, &contains, max_retries);
-                        let _ = res_tx.send(status);
-                    }
-                    Err(_) => break, // sender dropped => shutdown
-                }
-            }
-        }));
+    fetch_first_token(&checker)?;
+    if let Some(save) = &cfg.check.save_failures {
+        std::fs::create_dir_all(&save.dir).map_err(|e| format!("{}: {e}", save.dir.display()))?;
     }
-    drop(res_tx); // when all worker clones drop, results channel will close
 
+    // The dashboard replaces per-result JSON and per-round summaries on the
+    // terminal; file and webhook sinks run either way
+    let summary_to = m.get_one::<SummaryTo>("summary-to").cloned().unwrap_or_default();
+    let mut sinks = Sinks::new();
+    if !tui {
+        sinks.add("stdout", JsonLines::new(io::stdout()));
+    }
+    if !tui || matches!(summary_to, SummaryTo::File(_)) {
+        let out = summary_to.open().map_err(|e| format!("--summary-to {summary_to}: {e}"))?;
+        sinks.add("summary", Summary::new(out, cfg.summary.clone()));
+    }
+    if let Some(path) = m.get_one::<PathBuf>("csv") {
+        let csv = Csv::append(path, cfg.up_if, cfg.check.capture_headers.clone()).map_err(|e| format!("{}: {e}", path.display()))?;
+        sinks.add(format!("csv {}", path.display()), csv);
+    }
+    let alert_on = AlertOn {
+        statuses: m.get_one::<StatusSet>("alert-on-status").cloned(),
+        except: m.get_one::<StatusSet>("no-alert-on-status").cloned(),
+    };
+    for url in m.get_many::<String>("webhook").into_iter().flatten() {
+        sinks.add(format!("webhook {url}"), Webhook::new(url, cfg.up_if).alert_on(alert_on.clone()));
+    }
+    if let Some(path) = m.get_one::<PathBuf>("fifo") {
+        #[cfg(unix)]
+        {
+            let policy = m.get_one::<String>("fifo-policy").map_or(Ok(FifoPolicy::Drop), |p| FifoPolicy::parse(p))?;
+            let fifo = Fifo::open(path, policy).map_err(|e| format!("{}: {e}", path.display()))?;
+            sinks.add(format!("fifo {}", path.display()), fifo);
+        }
+        #[cfg(not(unix))]
+        return Err(format!("--fifo {}: only supported on Unix", path.display()).into());
+    }
+    if let Some(url) = m.get_one::<String>("heartbeat-url") {
+        let on = m.get_one::<HeartbeatOn>("heartbeat-on").copied().unwrap_or_default();
+        sinks.add(format!("heartbeat {url}"), Heartbeat::new(url, cfg.up_if, on));
+    }
 
-    let mut stats: HashMap<String, UrlStats> = HashMap::new();
+    let mut stats_out = m.get_one::<PathBuf>("stats-out").map(|path| {
+        StatsOut::new(path, m.get_one::<u64>("stats-checkpoint-secs").map(|&secs| Duration::from_secs(secs)))
+    });
+
+    let mut pool = checker.pool();
+    // Wrapped once, so each round queues shared entries rather than copies
+    let entries: Vec<Arc<UrlEntry>> = cfg.urls.iter().cloned().map(Arc::new).collect();
+    let mut stats = seed_stats(&cfg.urls);
     let mut round: u64 = 0;
+    // Rounds still waiting on results, by round id
+    let mut pending: BTreeMap<u64, PendingRound> = BTreeMap::new();
+    let round_limit = cfg.round_deadline(&cfg.urls);
+    let mut next_start = Instant::now();
+
+    // The dashboard needs waking more often than the 200ms default to stay responsive to keys
+    let mut dash = if tui {
+        Some(dashboard::Dashboard::start(cfg.urls.iter().map(|e| e.url.as_str()), cfg.summary.times.clone())?)
+    } else {
+        None
+    };
+    let tick = Duration::from_millis(if tui { 100 } else { 200 });
 
-    // Main loop (one-shot or periodic)
+    // Main loop (one-shot or periodic). Without --overlap a round must drain before
+    // the next one is scheduled; with it, rounds start on schedule regardless.
     loop {
-        round += 1;
+        if let Some(d) = dash.as_mut() {
+            // `q` takes the same shutdown path as Ctrl+C
+            if d.tick()? {
+                stop.store(true, Ordering::SeqCst);
+            }
+            if stop.load(Ordering::SeqCst) {
+                d.set_stopping();
+            }
+        }
         if stop.load(Ordering::SeqCst) {
-            break;
+            // What is still queued runs without think-time pauses
+            pool.stop();
+        }
+        if let Some(out) = stats_out.as_mut() {
+            out.checkpoint(&stats, Instant::now());
         }
 
-        // Enqueue this round's URLs
-        for url in &cfg.urls {
-            if stop.load(Ordering::SeqCst) {
-                break;
+        let due = Instant::now() >= next_start;
+        let may_start = (round == 0 || cfg.period.is_some()) && cfg.max_rounds().is_none_or(|n| round < n);
+        if due && may_start && !stop.load(Ordering::SeqCst) && (cfg.overlap || pending.is_empty()) {
+            round += 1;
+            if !pending.is_empty() {
+                warn!(
+                    "round {round} starting while {} earlier round(s) are still running; workers may be saturated",
+                    pending.len()
+                );
             }
-            job_tx.send(url.clone()).ok();
-        }
-
-        // Collect this round's results
-        let expected = cfg.urls.len();
-        for _ in 0..expected {
-            match res_rx.recv() {
-                Ok(status) => {
-                    let ok = status.status.is_ok();
-                    print_status_json(&status);
-                    stats
-                        .entry(status.url.clone())
-                        .or_default()
-                        .record(ok, status.response_time);
+            refresh_token(&checker);
+            // Each round gets a fresh retry budget, so overlapping rounds don't share one
+            let budget = cfg.retry_budget.map(|n| Arc::new(RetryBudget::new(n)));
+
+            // Enqueue this round's checks, each numbered by its place in the round;
+            // the first round's are spread over --stagger
+            let mut left = BTreeSet::new();
+            let (queued_from, n) = (Instant::now(), entries.len() * cfg.checks_per_round());
+            for (i, (seq, entry)) in cfg.round_jobs(&entries).enumerate() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
                 }
-                Err(_) => break, // channel closed
+                if round == 1 {
+                    thread::sleep((queued_from + cfg.stagger_offset(i, n)).saturating_duration_since(Instant::now()));
+                }
+                if pool.submit(round, seq, Arc::clone(entry), budget.clone()) {
+                    left.insert(seq);
+                }
+            }
+            debug!("round {round} started: {} check(s) queued", left.len());
+            if !left.is_empty() {
+                let (held, deadline) = (cfg.ordered.then(InOrder::default), Instant::now() + round_limit);
+                pending.insert(round, PendingRound { left, budget, held, deadline });
+            }
+            if let (true, Some(period)) = (cfg.overlap, cfg.period) {
+                next_start += period;
             }
         }
 
-        summarize(&stats);
+        if pending.is_empty() {
+            // Nothing in flight: done if one-shot, interrupted, or out of rounds, else wait for the next one
+            let out_of_rounds = cfg.max_rounds().is_some_and(|n| round >= n);
+            if stop.load(Ordering::SeqCst) || cfg.period.is_none() || out_of_rounds {
+                break;
+            }
+            thread::sleep(next_start.saturating_duration_since(Instant::now()).min(tick));
+            continue;
+        }
+
+        pool.revive();
 
-        // If not periodic, we're done
-        if cfg.period.is_none() {
-            break;
+        // Results a round is still owed past its deadline are given up on, so a
+        // lost one can't hang the round; they count as failed checks
+        let now = Instant::now();
+        let mut arrived: Vec<JobResult> = Vec::new();
+        for (&r, p) in pending.iter().filter(|(_, p)| p.deadline <= now) {
+            for &seq in &p.left {
+                let entry = &cfg.urls[seq % cfg.urls.len()];
+                warn!("no result for {} within the {round_limit:?} round deadline", entry.url);
+                let status = missing_result(entry, format!("none within the {round_limit:?} round deadline"));
+                arrived.push(JobResult { round: r, seq, status });
+            }
+        }
+        if arrived.is_empty() {
+            // Collect results, waking periodically to honor the schedule, the stop flag, and deadlines
+            let mut wait = tick;
+            if cfg.overlap {
+                wait = wait.min(next_start.saturating_duration_since(Instant::now()));
+            }
+            match pool.recv_timeout(wait) {
+                Some(res) => arrived.push(res),
+                None => continue,
+            }
         }
 
-        // Sleep until the next round (or until interrupted)
-        let period = cfg.period.unwrap();
-        let mut slept = Duration::from_secs(0);
-        while slept < period {
-            if stop.load(Ordering::SeqCst) {
-                break;
+        for JobResult { round: r, seq, mut status } in arrived {
+            let Some(p) = pending.get_mut(&r) else { continue };
+            if !p.left.remove(&seq) {
+                // Already given up on; counting it now would count that check twice
+                debug!("round {r}: late result for {} ignored", status.url);
+                continue;
+            }
+            let round_done = p.left.is_empty();
+
+            let entry = &cfg.urls[seq % cfg.urls.len()];
+            if let Some(b) = baselines.as_mut() {
+                let key = entry.stats_key();
+                b.record(&key, &status);
+                // --record-baseline replaces the stored medians, so they say nothing about this run
+                if let (Some(d), Some(baseline), false) = (degradation, b.get(&key), record_baseline) {
+                    d.judge(&mut status, baseline);
+                }
+            }
+            cfg.mark_maintenance(entry, &mut status);
+            let up = cfg.up_if.is_up(&status);
+            if let Some(d) = dash.as_mut() {
+                d.record(&status, up);
+            }
+            match p.held.as_mut() {
+                Some(held) => held.hold(seq, status.clone()),
+                None => sinks.on_result(&status),
+            }
+            // Expected downtime doesn't trip --fail-fast
+            let failure = (!up && !status.maintenance).then(|| (status.url.clone(), status.http_code(), status.error().cloned()));
+            record_status(&mut stats, status, cfg.up_if);
+
+            if let (true, Some((url, code, err))) = (cfg.fail_fast, failure) {
+                // Retries are already spent by the time a result arrives, so this failure is final
+                stop.store(true, Ordering::SeqCst);
+                let skipped = pool.discard_queued();
+                let outstanding: usize = pending.values().map(|p| p.left.len()).sum();
+                // What already arrived still goes out, in order, before the run ends
+                for status in pending.values_mut().filter_map(|p| p.held.as_mut()).flat_map(InOrder::release) {
+                    sinks.on_result(&status);
+                }
+                drop(dash.take());
+                let reason = err.map_or_else(|| "did not meet --up-if".to_string(), |e| e.to_string());
+                match code {
+                    Some(code) => error!("fail-fast: {url} is down (HTTP {code}): {reason}"),
+                    None => error!("fail-fast: {url} is down: {reason}"),
+                }
+                error!(
+                    "fail-fast: skipped {skipped} queued check(s), abandoned {} in flight",
+                    outstanding.saturating_sub(skipped)
+                );
+                summarize(&summary_to, &stats, &cfg.summary);
+                if let Some(out) = stats_out.as_mut() {
+                    out.finish(&stats);
+                }
+                if let Some(har) = checker.har() {
+                    save_har(har);
+                }
+                sinks.on_shutdown();
+                // In-flight checks are abandoned rather than waited on
+                std::process::exit(1);
+            }
+
+            if round_done {
+                let Some(mut done) = pending.remove(&r) else { continue };
+                debug!("round {r} complete");
+                for status in done.held.iter_mut().flat_map(InOrder::release) {
+                    sinks.on_result(&status);
+                }
+                let budget = done.budget;
+                sinks.on_round_end(&stats, budget.as_deref());
+                if !cfg.overlap {
+                    // Sequential mode sleeps a full period after each round completes
+                    next_start = Instant::now() + cfg.period.unwrap_or_default();
+                }
             }
-            let step = Duration::from_millis(200);
-            thread::sleep(step);
-            slept += step;
         }
     }
 
-    // Shutdown: drop sender so workers exit, then join
-    drop(job_tx);
-    for w in workers {
-        let _ = w.join();
+    // Hand the terminal back, then leave a final summary behind
+    if let Some(d) = dash.take() {
+        drop(d);
+        summarize(&summary_to, &stats, &cfg.summary);
+    }
+    if let Some(b) = baselines.as_mut() {
+        save_baselines(b, record_baseline, m.get_flag("update-baseline"));
+    }
+    if let Some(out) = stats_out.as_mut() {
+        out.finish(&stats);
     }
+    if let Some(har) = checker.har() {
+        save_har(har);
+    }
+    sinks.on_shutdown();
+
+    // Shutdown: dropping the pool closes the queue and joins the workers
+    drop(pool);
 
-    eprintln!("Shutdown complete.");
+    info!("Shutdown complete.");
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use httpmock::prelude::*;
 
     #[test]
-    fn test_success_ok() {
-        let server = MockServer::start();
-
-        let _m = server.mock(|when, then| {
-            when.method(GET).path("/ok");
-            then.status(200)
-                .header("Server", "unit-test")
-                .body("hello world");
-        });
+    fn test_cli_rejects_bad_values() {
+        // Zero workers would leave every round waiting forever, so it never gets past the CLI
+        assert!(build_cli().try_get_matches_from(["sitecheck", "-n", "0", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--max-threads", "0", "https://x.com"]).is_err());
+        let m = build_cli().try_get_matches_from(["sitecheck", "-n", "8", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<usize>("threads"), Some(&8));
 
-        let agent = build_agent(Duration::from_secs(2));
-        let headers = vec![("Server".to_string(), "unit-test".to_string())];
-        let contains = Some("hello".to_string());
-        let status =
-            check_with_retries(&agent, &format!("{}/ok", server.base_url()), &headers, &contains, 0);
+        // Rejected by clap before anything runs
+        assert!(build_cli().try_get_matches_from(["sitecheck", "check", "-t", "soon", "https://x.com"]).is_err());
+        let m = build_cli().try_get_matches_from(["sitecheck", "watch", "-p", "5m", "-t", "250ms", "https://x.com"]).unwrap();
+        let (_, sub) = m.subcommand().unwrap();
+        assert_eq!(sub.get_one::<Duration>("period"), Some(&Duration::from_secs(300)));
+        assert_eq!(sub.get_one::<Duration>("timeout"), Some(&Duration::from_millis(250)));
 
-        assert!(status.status.is_ok());
-        assert!(status.response_time.as_millis() > 0);
+        assert!(build_cli().try_get_matches_from(["sitecheck", "report", "--from", "-", "--sort-by", "nope"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--webhook", "not a url", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--expect-status", "2x", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--checks-per-url", "0", "https://x.com"]).is_err());
+        // Even when nothing would be sent
+        for mode in ["--validate-only", "--dry-run"] {
+            assert!(build_cli().try_get_matches_from(["sitecheck", mode, "--retry-budget", "lots", "https://x.com"]).is_err());
+        }
+        let m = build_cli().try_get_matches_from(["sitecheck", "--retry-budget", "5", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<usize>("retry-budget"), Some(&5));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--max-redirects", "2.5", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "bench", "https://x.com", "--max-redirects", "ten"]).is_err());
+        let m = build_cli().try_get_matches_from(["sitecheck", "--max-redirects", "0", "https://x.com"]).unwrap();
+        assert_eq!(parse_max_redirects(&m), 0);
+        let args = ["sitecheck", "--sitemap", "https://x.com/sitemap.xml", "--sitemap-limit", "all"];
+        assert!(build_cli().try_get_matches_from(args).is_err());
     }
 
     #[test]
-    fn test_header_mismatch() {
-        let server = MockServer::start();
-
-        let _m = server.mock(|when, then| {
-            when.method(GET).path("/h");
-            then.status(200)
-                .header("Server", "unit-test")
-                .body("ok");
-        });
+    fn test_cli_completions_and_bare_urls() {
+        build_cli().debug_assert();
 
-        let agent = build_agent(Duration::from_secs(2));
-        let headers = vec![("Server".to_string(), "expected".to_string())];
-        let status =
-            check_with_retries(&agent, &format!("{}/h", server.base_url()), &headers, &None, 0);
+        let m = build_cli().try_get_matches_from(["sitecheck", "completions", "bash"]).unwrap();
+        let (name, sub) = m.subcommand().unwrap();
+        assert_eq!(name, "completions");
+        assert_eq!(sub.get_one::<clap_complete::Shell>("shell"), Some(&clap_complete::Shell::Bash));
 
-        assert!(status.status.is_err());
-        let msg = status.status.err().unwrap();
-        assert!(msg.contains("header mismatch"));
+        // Bare URLs keep working alongside the subcommand
+        let m = build_cli().try_get_matches_from(["sitecheck", "-t", "3", "https://example.com"]).unwrap();
+        assert!(m.subcommand().is_none());
+        let urls: Vec<&String> = m.get_many::<String>("urls").unwrap().collect();
+        assert_eq!(urls, vec!["https://example.com"]);
     }
 
     #[test]
-    fn test_body_contains_validation() {
-        let server = MockServer::start();
+    fn test_subcommand_flags() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "watch", "-p", "60", "-v", "https://x.com"]).unwrap();
+        let (name, sub) = m.subcommand().unwrap();
+        assert_eq!(name, "watch");
+        assert_eq!(sub.get_one::<Duration>("period"), Some(&Duration::from_secs(60)));
+        assert_eq!(sub.get_count("verbose"), 1);
 
-        let _m = server.mock(|when, then| {
-            when.method(GET).path("/b");
-            then.status(200).body("foo bar baz");
-        });
+        // Each subcommand only takes its own flags
+        assert!(build_cli().try_get_matches_from(["sitecheck", "check", "-p", "60", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "watch", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "report", "--from", "r.ndjson", "-t", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_bench_flags() {
+        let args = ["sitecheck", "bench", "https://x.com", "--requests", "500", "--concurrency", "20", "--contains", "ok"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        let (name, sub) = m.subcommand().unwrap();
+        assert_eq!(name, "bench");
+        assert_eq!(sub.get_one::<u64>("requests"), Some(&500));
+        assert_eq!(sub.get_one::<usize>("concurrency"), Some(&20));
+        assert_eq!(sub.get_one::<String>("contains").map(String::as_str), Some("ok"));
+
+        // One URL, a real request count, and only per-request check flags
+        assert!(build_cli().try_get_matches_from(["sitecheck", "bench"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "bench", "https://x.com", "https://y.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "bench", "https://x.com", "--requests", "0"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "bench", "https://x.com", "--concurrency", "0"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "bench", "https://x.com", "-f", "urls.txt"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "bench", "https://x.com", "--format", "csv"]).is_err());
+    }
+
+    #[test]
+    fn test_once_overrides_period() {
+        let period = |args: &[&str]| {
+            let m = build_cli().try_get_matches_from(args).unwrap();
+            parse_period(m.subcommand().map_or(&m, |(_, sub)| sub))
+        };
+        assert_eq!(period(&["sitecheck", "watch", "-p", "5m", "https://x.com"]), Some(Duration::from_secs(300)));
+        // A period of None is what stops the main loop after its first round
+        assert_eq!(period(&["sitecheck", "watch", "-p", "5m", "--once", "https://x.com"]), None);
+        assert_eq!(period(&["sitecheck", "-p", "30s", "--once", "https://x.com"]), None);
+        assert_eq!(period(&["sitecheck", "check", "https://x.com"]), None);
+        assert!(build_cli().try_get_matches_from(["sitecheck", "check", "--once", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_ordered_flags() {
+        let ordered = |args: &[&str]| build_cli().try_get_matches_from(args).unwrap().get_flag("ordered");
+        assert!(!ordered(&["sitecheck", "https://x.com"]));
+        assert!(ordered(&["sitecheck", "--ordered", "https://x.com"]));
+        // The last of the pair wins
+        assert!(!ordered(&["sitecheck", "--ordered", "--unordered", "https://x.com"]));
+        assert!(ordered(&["sitecheck", "--unordered", "--ordered", "https://x.com"]));
+    }
+
+    #[test]
+    fn test_dns_cache_flags() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "--dns-cache-ttl", "5m", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<Duration>("dns-cache-ttl"), Some(&Duration::from_secs(300)));
+        assert!(!m.get_flag("no-dns-cache"));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--no-dns-cache", "https://x.com"]).unwrap().get_flag("no-dns-cache"));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--no-dns-cache", "--dns-cache-ttl", "5m", "https://x.com"]).is_err());
+
+        let m = build_cli().try_get_matches_from(["sitecheck", "--dns-server", "10.0.0.53", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<SocketAddr>("dns-server"), Some(&"10.0.0.53:53".parse().unwrap()));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--dns-server", "dns.example", "https://x.com"]).is_err());
+        let args = ["sitecheck", "--dns-server", "10.0.0.53:53", "--unix-socket", "/run/app.sock", "http://localhost/"];
+        assert!(build_cli().try_get_matches_from(args).is_err());
+
+        let m = build_cli().try_get_matches_from(["sitecheck", "--max-dns-concurrency", "8", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<u64>("max-dns-concurrency"), Some(&8));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--max-dns-concurrency", "0", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_heartbeat_flags() {
+        let m = build_cli()
+            .try_get_matches_from(["sitecheck", "--heartbeat-url", "https://hc.test/ping", "--heartbeat-on", "success-only", "https://x.com"])
+            .unwrap();
+        assert_eq!(m.get_one::<String>("heartbeat-url").map(String::as_str), Some("https://hc.test/ping"));
+        assert_eq!(m.get_one::<HeartbeatOn>("heartbeat-on"), Some(&HeartbeatOn::SuccessOnly));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--heartbeat-on", "all", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_capture_header_flag() {
+        let m = build_cli()
+            .try_get_matches_from(["sitecheck", "--capture-header", "X-Cache", "--capture-header", "CF-Ray", "https://x.com"])
+            .unwrap();
+        let names: Vec<&String> = m.get_many::<String>("capture-header").unwrap().collect();
+        assert_eq!(names, ["X-Cache", "CF-Ray"]);
+    }
+
+    #[test]
+    fn test_backoff_flags() {
+        let m = build_cli()
+            .try_get_matches_from(["sitecheck", "--backoff-base-ms", "100", "--backoff-multiplier", "2", "--backoff-max-ms", "5000", "https://x.com"])
+            .unwrap();
+        let want = Backoff { base: Duration::from_millis(100), multiplier: Some(2.0), max: Some(Duration::from_secs(5)) };
+        assert_eq!(parse_backoff(&m), want);
+        let m = build_cli().try_get_matches_from(["sitecheck", "https://x.com"]).unwrap();
+        assert_eq!(parse_backoff(&m), Backoff::default());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--backoff-multiplier", "0.5", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--backoff-base-ms", "0", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_capture_body_flag() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "--capture-body-on-failure", "200", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<u64>("capture-body-on-failure"), Some(&200));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--capture-body-on-failure", "0", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_think_time_flags() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "--think-time-ms", "100:500", "--think-time-seed", "7", "https://x.com"]).unwrap();
+        let want = ThinkTime { min: Duration::from_millis(100), max: Duration::from_millis(500), seed: Some(7) };
+        assert_eq!(parse_think_time(&m), Some(want));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--think-time-ms", "500:100", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--think-time-seed", "7", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_save_failures_flags() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "--save-failures", "out", "--save-failures-max-mb", "5", "https://x.com"]).unwrap();
+        assert_eq!(parse_save_failures(&m), Some(SaveFailures { dir: PathBuf::from("out"), max_bytes: 5 * 1024 * 1024 }));
+        let m = build_cli().try_get_matches_from(["sitecheck", "--save-failures", "out", "https://x.com"]).unwrap();
+        assert_eq!(parse_save_failures(&m).unwrap().max_bytes, DEFAULT_SAVE_FAILURES_MAX_MB * 1024 * 1024);
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--save-failures-max-mb", "5", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_show_body_on_fail_flags() {
+        let args = ["sitecheck", "--show-body-on-fail", "--redact-pattern", "token=\\w+", "--redact-pattern", "pw=\\w+", "https://x.com"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        assert!(m.get_flag("show-body-on-fail"));
+        assert_eq!(m.get_many::<String>("redact-pattern").unwrap().count(), 2);
+        assert!(parse_redact_pattern("(").unwrap_err().starts_with("invalid --redact-pattern '('"));
+        // Nothing to redact without a body to show
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--redact-pattern", "x", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_compare_flags() {
+        let args = ["sitecheck", "--compare", "https://a.com/p=https://b.com/p", "--compare-ignore-regex", "csrf=[0-9a-f]+"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        let pairs: Vec<&(String, String)> = m.get_many("compare").unwrap().collect();
+        assert_eq!(pairs, [&("https://a.com/p".to_string(), "https://b.com/p".to_string())]);
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--compare", "https://a.com/"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--compare-ignore-regex", "x", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_clock_skew_flags() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "--check-clock-skew", "30", "--clock-skew-warn-only", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<u64>("check-clock-skew"), Some(&30));
+        assert!(m.get_flag("clock-skew-warn-only"));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--clock-skew-warn-only", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--check-clock-skew", "-5", "https://x.com"]).is_err());
+    }
+
+    #[test]
+    fn test_alert_on_status_flags() {
+        let args = ["sitecheck", "--webhook", "https://hook.test/", "--alert-on-status", "5xx", "--no-alert-on-status", "503", "https://x.com"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        assert_eq!(m.get_one::<StatusSet>("alert-on-status").map(ToString::to_string).as_deref(), Some("5xx"));
+        assert!(m.get_one::<StatusSet>("no-alert-on-status").unwrap().contains(503));
+        // Only meaningful with a webhook to filter
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--alert-on-status", "5xx", "https://x.com"]).is_err());
+        let args = ["sitecheck", "--webhook", "https://hook.test/", "--no-alert-on-status", "4x", "https://x.com"];
+        assert!(build_cli().try_get_matches_from(args).is_err());
+    }
+
+    #[test]
+    fn test_cert_info_flag() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "--cert-info", "https://x.com"]).unwrap();
+        assert!(m.get_flag("cert-info"));
+        let args = ["sitecheck", "--cert-info", "--unix-socket", "/run/app.sock", "http://localhost/"];
+        assert!(build_cli().try_get_matches_from(args).is_err());
+    }
+
+    #[test]
+    fn test_baseline_flags() {
+        let args = ["sitecheck", "--baseline-file", "b.json", "--alert-degradation", "2.0", "--degradation-warn-only", "https://x.com"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        assert_eq!(m.get_one::<f64>("alert-degradation"), Some(&2.0));
+        assert!(m.get_flag("degradation-warn-only"));
+        // Each of them needs a file to work from
+        for flag in ["--record-baseline", "--update-baseline"] {
+            assert!(build_cli().try_get_matches_from(["sitecheck", flag, "https://x.com"]).is_err(), "{flag}");
+        }
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--alert-degradation", "2", "https://x.com"]).is_err());
+        let args = ["sitecheck", "--baseline-file", "b.json", "--record-baseline", "--update-baseline", "https://x.com"];
+        assert!(build_cli().try_get_matches_from(args).is_err());
+        let args = ["sitecheck", "--baseline-file", "b.json", "--alert-degradation", "0.5", "https://x.com"];
+        assert!(build_cli().try_get_matches_from(args).is_err());
+    }
+
+    #[test]
+    fn test_summary_to_flag() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "--summary-to", "file:summary.txt", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<SummaryTo>("summary-to"), Some(&SummaryTo::File(PathBuf::from("summary.txt"))));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--summary-to", "syslog", "https://x.com"]).is_err());
+        // The stdout contract is part of the help
+        let mut root = build_cli();
+        let mut watch = root.find_subcommand("watch").unwrap().clone();
+        for cmd in [&mut root, &mut watch] {
+            assert!(cmd.render_long_help().to_string().contains("stdout carries only result records"), "{}", cmd.get_name());
+        }
+    }
+
+    #[test]
+    fn test_stats_out_flags() {
+        let args = ["sitecheck", "--stats-out", "stats.json", "--stats-checkpoint-secs", "30", "https://x.com"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        assert_eq!(m.get_one::<PathBuf>("stats-out"), Some(&PathBuf::from("stats.json")));
+        assert_eq!(m.get_one::<u64>("stats-checkpoint-secs"), Some(&30));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--stats-checkpoint-secs", "30", "https://x.com"]).is_err());
+        let args = ["sitecheck", "--stats-out", "stats.json", "--stats-checkpoint-secs", "0", "https://x.com"];
+        assert!(build_cli().try_get_matches_from(args).is_err());
+    }
+
+    #[test]
+    fn test_har_flag() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "watch", "-p", "30s", "--har", "debug.har", "https://x.com"]).unwrap();
+        let (_, sub) = m.subcommand().unwrap();
+        assert_eq!(sub.get_one::<PathBuf>("har"), Some(&PathBuf::from("debug.har")));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--har"]).is_err());
+    }
 
-        let agent = build_agent(Duration::from_secs(2));
-        let status =
-            check_with_retries(&agent, &format!("{}/b", server.base_url()), &[], &Some("bar".into()), 0);
+    #[test]
+    fn test_check_links_flags() {
+        let args = ["sitecheck", "--check-links", "--check-links-external", "--max-links-per-page", "20", "https://x.com"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        assert_eq!(parse_check_links(&m), Some(LinkSettings { external: true, max_per_page: 20 }));
+        let m = build_cli().try_get_matches_from(["sitecheck", "--check-links", "https://x.com"]).unwrap();
+        assert_eq!(parse_check_links(&m), Some(LinkSettings::default()));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--max-links-per-page", "20", "https://x.com"]).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--check-links", "--head-size-only", "https://x.com"]).is_err());
+    }
 
-        assert!(status.status.is_ok());
+    #[test]
+    fn test_unix_host_flag() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "http+unix:///run/app.sock:/healthz"]).unwrap();
+        assert_eq!(m.get_one::<String>("unix-host").map(String::as_str), Some(DEFAULT_UNIX_HOST));
+        let args = ["sitecheck", "--unix-host", "api.internal:8080", "http+unix:///run/app.sock:/healthz"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        assert_eq!(m.get_one::<String>("unix-host").map(String::as_str), Some("api.internal:8080"));
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--unix-host", "a/b", "https://x.com"]).is_err());
+    }
 
-        let status_fail =
-            check_with_retries(&agent, &format!("{}/b", server.base_url()), &[], &Some("nope".into()), 0);
-        assert!(status_fail.status.is_err());
+    #[test]
+    fn test_oauth_flags() {
+        let args = [
+            "sitecheck",
+            "--oauth-token-url",
+            "https://auth.x.com/token",
+            "--oauth-client-id",
+            "sitecheck",
+            "--oauth-client-secret",
+            "hunter2",
+            "https://x.com",
+        ];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        let want = OAuthSettings {
+            token_url: "https://auth.x.com/token".into(),
+            client_id: "sitecheck".into(),
+            client_secret: "hunter2".into(),
+        };
+        assert_eq!(parse_oauth(&m), Some(want));
+        assert_eq!(parse_oauth(&build_cli().try_get_matches_from(["sitecheck", "https://x.com"]).unwrap()), None);
+        // All three or none
+        let partial = ["sitecheck", "--oauth-token-url", "https://auth.x.com/token", "--oauth-client-id", "sitecheck", "https://x.com"];
+        assert!(build_cli().try_get_matches_from(partial).is_err());
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--oauth-client-secret", "hunter2", "https://x.com"]).is_err());
+        assert!(parse_token_url("http+unix:///run/auth.sock:/token").is_err());
     }
 
     #[test]
-    fn test_timeout_error() {
-        let server = MockServer::start();
-
-        let _m = server.mock(|when, then| {
-            when.method(GET).path("/slow");
-            then.status(200)
-                .delay(std::time::Duration::from_secs(3)) // server delays response
-                .body("slow");
+    fn test_no_retry_unsafe_sends_token_post_once() {
+        let server = httpmock::MockServer::start();
+        let grant = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/token");
+            then.status(503);
         });
+        let oauth = OAuthSettings { token_url: server.url("/token"), client_id: "sitecheck".into(), client_secret: "hunter2".into() };
+        let rounds = |retry_unsafe: bool| {
+            let checker = Checker::new(Config::builder().oauth(Some(oauth.clone())).retry_unsafe(retry_unsafe).build());
+            for _ in 0..3 {
+                refresh_token(&checker);
+            }
+        };
+
+        rounds(false);
+        grant.assert_hits(1);
+        // By default each round asks again
+        rounds(true);
+        grant.assert_hits(4);
 
-        let agent = build_agent(Duration::from_secs(1)); // 1s timeout -> should time out
-        let status =
-            check_with_retries(&agent, &format!("{}/slow", server.base_url()), &[], &None, 0);
-        assert!(status.status.is_err());
-        let msg = status.status.err().unwrap();
-        assert!(msg.contains("error"));
+        let m = build_cli().try_get_matches_from(["sitecheck", "--no-retry-unsafe", "https://x.com"]).unwrap();
+        assert!(m.get_flag("no-retry-unsafe"));
+    }
+
+    #[test]
+    fn test_validate_only_collects_problems() {
+        let args = ["sitecheck", "--validate-only", "--up-if", "sometimes", "--unix-socket", "/nonexistent/app.sock", "https://x.com"];
+        let m = build_cli().try_get_matches_from(args).unwrap();
+        let mut problems = Problems { collect: m.get_flag("validate-only"), found: 0 };
+        assert_eq!(parse_up_if(&m, &mut problems), UpIf::default());
+        assert_eq!(parse_unix_socket(&m, &mut problems), None);
+        assert_eq!(problems.found, 2);
+        assert!(build_cli().try_get_matches_from(["sitecheck", "--validate-only", "--dry-run", "https://x.com"]).is_err());
     }
 
     #[test]
-    fn test_concurrency_50() {
-        let server = MockServer::start();
-
-        // Create 50 endpoints
-        for i in 0..50 {
-            let path = format!("/ok{i}");
-            server.mock(|when, then| {
-                when.method(GET).path(path.clone());
-                then.status(200).body("ok");
-            });
-        }
-
-        let urls: Vec<String> = (0..50)
-            .map(|i| format!("{}/ok{i}", server.base_url()))
-            .collect();
-
-        // Build config-like items
-        let agent = build_agent(Duration::from_secs(2));
-
-        // Spawn 50 threads to simulate concurrency for this test
-        let (tx, rx) = std::sync::mpsc::channel::<WebsiteStatus>();
-        for url in urls.clone() {
-            let tx = tx.clone();
-            let agent = agent.clone();
-            std::thread::spawn(move || {
-                let s = check_with_retries(&agent, &url, &[], &None, 0);
-                tx.send(s).ok();
-            });
-        }
-        drop(tx);
-
-        let results: Vec<WebsiteStatus> = rx.into_iter().collect();
-        assert_eq!(results.len(), 50);
-        assert!(results.iter().all(|s| s.status.is_ok()));
+    fn test_stagger_flag() {
+        let m = build_cli().try_get_matches_from(["sitecheck", "--stagger", "500ms", "https://x.com"]).unwrap();
+        assert_eq!(m.get_one::<Duration>("stagger"), Some(&Duration::from_millis(500)));
+        let m = build_cli().try_get_matches_from(["sitecheck", "watch", "-p", "1m", "--stagger", "2s", "https://x.com"]).unwrap();
+        assert_eq!(m.subcommand_matches("watch").unwrap().get_one::<Duration>("stagger"), Some(&Duration::from_secs(2)));
     }
 }
@@ -0,0 +1,194 @@
+//! Maintenance windows (`--maintenance 'Sun 02:00-03:00'`): weekly spans in
+//! which failures are expected, so they are flagged instead of counted.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Local, TimeZone, Timelike, Utc, Weekday};
+
+use crate::DisplayZone;
+
+const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// A weekly window in wall-clock time. An end before the start runs past
+/// midnight into the next day; the days named are the ones it starts on.
+/// Across a DST change, a window inside the skipped hour doesn't happen that
+/// day and one inside the repeated hour covers it both times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    days: u8,   // Bit 0 is Monday
+    start: u32, // Minutes after midnight
+    end: u32,
+}
+
+impl MaintenanceWindow {
+    /// Parse `DAYS HH:MM-HH:MM`, where DAYS is a day (`Sun`), a list (`Sat,Sun`),
+    /// a range (`Mon-Fri`), or `daily`. `@` may stand in for the space, as it
+    /// must in a URL's `;maintenance=` option. `24:00` ends a window at midnight.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = |why: &str| format!("invalid maintenance window '{s}': {why} (expected e.g. 'Sun 02:00-03:00')");
+        let (days, times) = s
+            .trim()
+            .split_once(|c: char| c == '@' || c.is_whitespace())
+            .ok_or_else(|| invalid("missing times"))?;
+        let (start, end) = times.trim().split_once('-').ok_or_else(|| invalid("missing end time"))?;
+        let days = parse_days(days).ok_or_else(|| invalid("unknown day"))?;
+        let start = parse_time(start).filter(|&m| m < MINUTES_PER_DAY).ok_or_else(|| invalid("bad start time"))?;
+        let end = parse_time(end).ok_or_else(|| invalid("bad end time"))?;
+        if start == end {
+            return Err(invalid("window is empty"));
+        }
+        Ok(MaintenanceWindow { days, start, end })
+    }
+
+    /// Whether the local wall-clock time `minute` (after midnight) on `weekday` is inside.
+    pub fn contains_local(&self, weekday: Weekday, minute: u32) -> bool {
+        let today = weekday.num_days_from_monday();
+        let starts_on = |day: u32| self.days & (1 << day) != 0;
+        if self.start < self.end {
+            starts_on(today) && (self.start..self.end).contains(&minute)
+        } else {
+            // Crosses midnight: the evening part, or the morning after a start day
+            (starts_on(today) && minute >= self.start) || (starts_on((today + 6) % 7) && minute < self.end)
+        }
+    }
+
+    /// Whether `at` is inside, reading the wall clock in `zone`.
+    pub fn contains(&self, at: DateTime<Utc>, zone: DisplayZone) -> bool {
+        let (weekday, minute) = match zone {
+            DisplayZone::Utc => wall_clock(at),
+            DisplayZone::Local => wall_clock(at.with_timezone(&Local)),
+            DisplayZone::Named(tz) => wall_clock(at.with_timezone(&tz)),
+        };
+        self.contains_local(weekday, minute)
+    }
+}
+
+fn wall_clock<Tz: TimeZone>(t: DateTime<Tz>) -> (Weekday, u32) {
+    (t.weekday(), t.hour() * 60 + t.minute())
+}
+
+/// A bitmask of days, bit 0 for Monday.
+fn parse_days(s: &str) -> Option<u8> {
+    if s.eq_ignore_ascii_case("daily") {
+        return Some(0x7f);
+    }
+    let day = |name: &str| DAY_NAMES.iter().position(|d| d.eq_ignore_ascii_case(name.trim()));
+    let mut days = 0u8;
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (day(from)?, day(to)?);
+                // Mon-Fri, or wrapping around the week like Fri-Mon
+                let span = (to + 7 - from) % 7;
+                for i in 0..=span {
+                    days |= 1 << ((from + i) % 7);
+                }
+            }
+            None => days |= 1 << day(part)?,
+        }
+    }
+    Some(days)
+}
+
+/// `HH:MM` as minutes after midnight, up to `24:00`.
+fn parse_time(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    if h.is_empty() || h.len() > 2 || m.len() != 2 {
+        return None;
+    }
+    let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+    let minute = h * 60 + m;
+    (m < 60 && minute <= MINUTES_PER_DAY).then_some(minute)
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        MaintenanceWindow::parse(s)
+    }
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days == 0x7f {
+            write!(f, "daily")?;
+        } else {
+            let names: Vec<&str> = (0..7).filter(|d| self.days & (1 << d) != 0).map(|d| DAY_NAMES[d]).collect();
+            write!(f, "{}", names.join(","))?;
+        }
+        write!(f, " {:02}:{:02}-{:02}:{:02}", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let w = MaintenanceWindow::parse("Sun 02:00-03:00").unwrap();
+        assert_eq!(w.to_string(), "Sun 02:00-03:00");
+        assert_eq!(MaintenanceWindow::parse("sat,SUN@22:30-24:00").unwrap().to_string(), "Sat,Sun 22:30-24:00");
+        assert_eq!(MaintenanceWindow::parse("Mon-Fri 23:00-01:00").unwrap().to_string(), "Mon,Tue,Wed,Thu,Fri 23:00-01:00");
+        assert_eq!(MaintenanceWindow::parse("Sat-Mon 00:00-06:00").unwrap().to_string(), "Mon,Sat,Sun 00:00-06:00");
+        assert_eq!(MaintenanceWindow::parse("daily 04:00-04:15").unwrap().to_string(), "daily 04:00-04:15");
+        assert!(MaintenanceWindow::parse("Sun 00:00-24:00").unwrap().contains_local(Weekday::Sun, 23 * 60 + 59));
+        for bad in ["Sun", "Sun 02:00", "Funday 02:00-03:00", "Sun 2-3", "Sun 25:00-26:00", "Sun 02:60-03:00", "Sun 03:00-03:00"] {
+            assert!(MaintenanceWindow::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_contains_local() {
+        let w = MaintenanceWindow::parse("Sun 02:00-03:00").unwrap();
+        assert!(!w.contains_local(Weekday::Sun, 119));
+        assert!(w.contains_local(Weekday::Sun, 120));
+        assert!(w.contains_local(Weekday::Sun, 179));
+        assert!(!w.contains_local(Weekday::Sun, 180));
+        assert!(!w.contains_local(Weekday::Sat, 150));
+
+        // Crossing midnight: Saturday night into Sunday morning, not Sunday night
+        let w = MaintenanceWindow::parse("Sat 23:00-01:00").unwrap();
+        assert!(w.contains_local(Weekday::Sat, 23 * 60));
+        assert!(w.contains_local(Weekday::Sun, 59));
+        assert!(!w.contains_local(Weekday::Sun, 60));
+        assert!(!w.contains_local(Weekday::Sun, 23 * 60 + 30));
+        assert!(!w.contains_local(Weekday::Fri, 30));
+
+        let w = MaintenanceWindow::parse("Sun 22:00-24:00").unwrap();
+        assert!(w.contains_local(Weekday::Sun, 23 * 60 + 59));
+        assert!(!w.contains_local(Weekday::Mon, 0));
+    }
+
+    #[test]
+    fn test_contains_in_zone() {
+        let w = MaintenanceWindow::parse("Sun 02:00-03:00").unwrap();
+        let berlin = DisplayZone::Named(chrono_tz::Europe::Berlin);
+        // An ordinary Sunday in winter: 02:00 CET is 01:00 UTC
+        assert!(w.contains(utc(2025, 1, 12, 1, 30), berlin));
+        assert!(!w.contains(utc(2025, 1, 12, 2, 30), berlin));
+        assert!(w.contains(utc(2025, 1, 12, 2, 30), DisplayZone::Utc));
+
+        // 2025-03-30: clocks jump from 02:00 to 03:00, so the window never happens
+        assert!(!w.contains(utc(2025, 3, 30, 0, 59), berlin)); // 01:59 CET
+        assert!(!w.contains(utc(2025, 3, 30, 1, 0), berlin)); // 03:00 CEST
+        // 2025-10-26: 02:00-03:00 happens twice, once in CEST and once in CET
+        assert!(!w.contains(utc(2025, 10, 25, 23, 59), berlin)); // 01:59 CEST
+        assert!(w.contains(utc(2025, 10, 26, 0, 0), berlin)); // 02:00 CEST
+        assert!(w.contains(utc(2025, 10, 26, 1, 30), berlin)); // 02:30 CET
+        assert!(!w.contains(utc(2025, 10, 26, 2, 0), berlin)); // 03:00 CET
+
+        // A window over midnight on the night the clocks go back still ends at 01:00 local
+        let w = MaintenanceWindow::parse("Sat 23:00-01:00").unwrap();
+        assert!(w.contains(utc(2025, 10, 25, 21, 0), berlin)); // 23:00 CEST
+        assert!(w.contains(utc(2025, 10, 25, 22, 59), berlin)); // 00:59 CEST
+        assert!(!w.contains(utc(2025, 10, 25, 23, 0), berlin)); // 01:00 CEST
+    }
+}
@@ -0,0 +1,258 @@
+//! `--oauth-token-url`: get a bearer token with the OAuth2 client_credentials
+//! grant, send it with every request, and get a new one before it expires.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::http::same_origin;
+use crate::{build_agent, AgentOptions, CheckRequest, CheckResponse, HttpClient, TransportError};
+
+/// How long before it expires a token is replaced; halfway through its
+/// life instead, for a token that lives less than twice this.
+pub const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Where to get a token, and as whom. The secret is never printed or serialized.
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct OAuthSettings {
+    pub token_url: String,
+    pub client_id: String,
+    #[serde(skip)]
+    pub client_secret: String,
+}
+
+impl fmt::Debug for OAuthSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuthSettings")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An `Authorization` header value, hidden from `Debug` so it can't reach a log.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Authorization(String);
+
+impl Authorization {
+    pub fn bearer(token: &str) -> Self {
+        Authorization(format!("Bearer {token}"))
+    }
+
+    /// The header value, to send and nothing else.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Authorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Authorization(..)")
+    }
+}
+
+/// The token endpoint's answer, as far as it matters here.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    token_type: Option<String>,
+    /// Seconds the token is good for; without it, it never expires.
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Token {
+    authorization: Authorization,
+    refresh_at: Option<Instant>,
+}
+
+/// When a token good for `expires_in` seconds should be replaced, from now.
+fn refresh_after(expires_in: Option<u64>) -> Option<Duration> {
+    let lifetime = Duration::from_secs(expires_in?);
+    Some(lifetime - TOKEN_REFRESH_MARGIN.min(lifetime / 2))
+}
+
+/// The current token, and how to get the next one.
+pub struct OAuthToken {
+    settings: OAuthSettings,
+    agent: ureq::Agent,
+    current: RwLock<Option<Token>>,
+    /// Whether the last token request failed.
+    failed: AtomicBool,
+}
+
+impl fmt::Debug for OAuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuthToken").field("settings", &self.settings).finish_non_exhaustive()
+    }
+}
+
+impl OAuthToken {
+    /// No token yet: `refresh` gets the first.
+    pub fn new(settings: OAuthSettings, timeout: Duration) -> Self {
+        let agent = build_agent(&AgentOptions { timeout, ..AgentOptions::default() });
+        OAuthToken { settings, agent, current: RwLock::new(None), failed: AtomicBool::new(false) }
+    }
+
+    pub fn settings(&self) -> &OAuthSettings {
+        &self.settings
+    }
+
+    /// Whether there is no token yet, or the current one is about to expire.
+    pub fn due(&self) -> bool {
+        match &*self.current.read().unwrap_or_else(PoisonError::into_inner) {
+            Some(token) => token.refresh_at.is_some_and(|at| Instant::now() >= at),
+            None => true,
+        }
+    }
+
+    /// Whether the last `refresh` failed, so sending another would be a retry.
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    /// Get a new token from the endpoint. On failure the current one, if any,
+    /// is kept, and the error says why without repeating anything secret.
+    pub fn refresh(&self) -> Result<(), String> {
+        let result = self.request();
+        self.failed.store(result.is_err(), Ordering::SeqCst);
+        result
+    }
+
+    fn request(&self) -> Result<(), String> {
+        let form = [
+            ("grant_type", "client_credentials"),
+            ("client_id", self.settings.client_id.as_str()),
+            ("client_secret", self.settings.client_secret.as_str()),
+        ];
+        let resp = match self.agent.post(&self.settings.token_url).set("Accept", "application/json").send_form(&form) {
+            Ok(resp) => resp,
+            Err(ureq::Error::Status(code, _)) => return Err(format!("status code {code}")),
+            Err(ureq::Error::Transport(t)) => return Err(t.to_string()),
+        };
+        let token: TokenResponse = resp.into_json().map_err(|e| format!("unreadable token response: {e}"))?;
+        match token.token_type.as_deref() {
+            Some(kind) if !kind.eq_ignore_ascii_case("bearer") => Err(format!("unsupported token_type '{kind}'")),
+            _ => {
+                self.set(&token.access_token, token.expires_in);
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn set(&self, access_token: &str, expires_in: Option<u64>) {
+        let token = Token {
+            authorization: Authorization::bearer(access_token),
+            refresh_at: refresh_after(expires_in).map(|after| Instant::now() + after),
+        };
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Some(token);
+    }
+
+    /// What to send as `Authorization`, once there is a token.
+    pub fn authorization(&self) -> Option<Authorization> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner).as_ref().map(|t| t.authorization.clone())
+    }
+}
+
+/// Sends requests with the current token of an `OAuthToken`, but only to the
+/// origin being checked: not to a redirect or link that leads elsewhere.
+pub struct OAuthClient {
+    inner: Arc<dyn HttpClient>,
+    token: Arc<OAuthToken>,
+}
+
+impl OAuthClient {
+    pub fn new(inner: Arc<dyn HttpClient>, token: Arc<OAuthToken>) -> Self {
+        OAuthClient { inner, token }
+    }
+}
+
+impl HttpClient for OAuthClient {
+    fn execute(&self, req: CheckRequest) -> Result<CheckResponse, TransportError> {
+        let checked = req.checked_url.as_deref().unwrap_or(&req.url);
+        let authorization = same_origin(checked, &req.url).then(|| self.token.authorization()).flatten();
+        self.inner.execute(CheckRequest { authorization, ..req })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::MockClient;
+    use crate::{Method, ReadBody};
+    use httpmock::prelude::*;
+
+    fn settings(token_url: String) -> OAuthSettings {
+        OAuthSettings { token_url, client_id: "sitecheck".into(), client_secret: "hunter2".into() }
+    }
+
+    #[test]
+    fn test_refresh_after() {
+        assert_eq!(refresh_after(Some(3600)), Some(Duration::from_secs(3540)));
+        // A short-lived token is replaced halfway through
+        assert_eq!(refresh_after(Some(90)), Some(Duration::from_secs(45)));
+        assert_eq!(refresh_after(Some(0)), Some(Duration::ZERO));
+        assert_eq!(refresh_after(None), None);
+    }
+
+    #[test]
+    fn test_oauth_client_sends_token() {
+        let token = Arc::new(OAuthToken::new(settings("http://auth.test/token".into()), Duration::from_secs(1)));
+        assert!(token.due() && token.authorization().is_none());
+        token.set("tok3n", Some(3600));
+        assert!(!token.due());
+
+        let mock = Arc::new(MockClient::default().respond("http://a.test/", 200, &[], ""));
+        let client = OAuthClient::new(mock.clone(), Arc::clone(&token));
+        let req = CheckRequest {
+            url: "http://a.test/".into(),
+            method: Method::Get,
+            timeout: Duration::from_secs(1),
+            max_redirects: 0,
+            read_body: ReadBody::Never,
+            deadline: None,
+            max_body_bytes: None,
+            read_until: None,
+            accept_encoding: None,
+            authorization: None,
+            checked_url: None,
+        };
+        client.execute(req).unwrap();
+        let sent = mock.requests.lock().unwrap().pop().unwrap();
+        assert_eq!(sent.authorization.as_ref().map(Authorization::as_str), Some("Bearer tok3n"));
+        // Neither the token nor the secret shows up in debug output
+        let debug = format!("{sent:?} {token:?}");
+        assert!(!debug.contains("tok3n") && !debug.contains("hunter2"), "{debug}");
+
+        // Past its refresh time, a token is due even though it still works
+        token.set("tok3n", Some(0));
+        assert!(token.due() && token.authorization().is_some());
+    }
+
+    #[test]
+    fn test_token_endpoint() {
+        let server = MockServer::start();
+        let grant = server.mock(|when, then| {
+            when.method(POST)
+                .path("/token")
+                .body_contains("grant_type=client_credentials")
+                .body_contains("client_id=sitecheck")
+                .body_contains("client_secret=hunter2");
+            then.status(200).body(r#"{"access_token":"tok3n","token_type":"Bearer","expires_in":3600}"#);
+        });
+        let token = OAuthToken::new(settings(server.url("/token")), Duration::from_secs(3));
+        token.refresh().unwrap();
+        grant.assert_hits(1);
+        assert_eq!(token.authorization(), Some(Authorization::bearer("tok3n")));
+
+        // A failed refresh keeps the token it had
+        let token = OAuthToken { settings: settings(server.url("/missing")), ..token };
+        assert_eq!(token.refresh(), Err("status code 404".to_string()));
+        assert_eq!(token.authorization(), Some(Authorization::bearer("tok3n")));
+    }
+}
@@ -0,0 +1,151 @@
+//! `sitecheck selftest`: run the real worker pipeline against a throwaway local server.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use sitecheck::{Checker, Config, UrlEntry, UrlSource, WebsiteStatus};
+
+const BODY: &str = "sitecheck selftest ok";
+/// How long `/slow` stalls; well past the timeout its case uses.
+const SLOW: Duration = Duration::from_secs(2);
+
+/// What a case must see for it to pass.
+enum Expect {
+    Status(u16),
+    /// A failed check whose error text contains this.
+    Error(&'static str),
+}
+
+struct Case {
+    name: &'static str,
+    path: &'static str,
+    config: Config,
+    timeout: Option<Duration>,
+    expect: Expect,
+}
+
+fn cases() -> Vec<Case> {
+    // No retries: every case should settle on its first attempt
+    let base = || Config::builder().threads(1).retries(0);
+    let header = |value: &str| base().header("X-Selftest", value).build();
+    let contains = |text: &str| base().contains(Some(text.to_string())).build();
+    let case = |name, path, config, expect| Case { name, path, config, timeout: None, expect };
+    vec![
+        case("success", "/ok", base().build(), Expect::Status(200)),
+        case("redirect followed", "/redirect", base().build(), Expect::Status(200)),
+        Case {
+            timeout: Some(Duration::from_millis(300)),
+            ..case("slow response times out", "/slow", base().build(), Expect::Error("timeout"))
+        },
+        case("header matches", "/ok", header("ok"), Expect::Status(200)),
+        case("header mismatch fails", "/ok", header("nope"), Expect::Error("header mismatch")),
+        case("body contains text", "/ok", contains("selftest ok"), Expect::Status(200)),
+        case("body mismatch fails", "/ok", contains("absent"), Expect::Error("body validation failed")),
+        case("error status fails", "/missing", base().build(), Expect::Error("404")),
+    ]
+}
+
+/// Serve canned responses on an ephemeral localhost port for the rest of the process.
+fn start_server() -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || {
+                let _ = respond(stream);
+            });
+        }
+    });
+    Ok(addr)
+}
+
+fn respond(mut stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the request headers; nothing in them matters here
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        line.clear();
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, extra, body) = match path {
+        "/ok" => ("200 OK", "X-Selftest: ok\r\n", BODY),
+        "/redirect" => ("302 Found", "Location: /ok\r\n", ""),
+        "/slow" => {
+            thread::sleep(SLOW);
+            ("200 OK", "", BODY)
+        }
+        _ => ("404 Not Found", "", "not found"),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n{extra}Content-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// Push one URL through the worker pool, exactly as a normal run would.
+fn run_case(case: &Case, index: usize, addr: SocketAddr) -> Result<WebsiteStatus, String> {
+    let mut entry = UrlEntry::new(format!("http://{addr}{}", case.path), UrlSource::Arg(index));
+    entry.options.timeout = case.timeout;
+    let mut result = None;
+    Checker::new(case.config.clone()).run(&[entry], |status| result = Some(status));
+    result.ok_or_else(|| "no result from the worker pool".to_string())
+}
+
+fn verdict(expect: &Expect, status: &WebsiteStatus) -> Result<String, String> {
+    match (expect, &status.status) {
+        (Expect::Status(want), Ok(got)) if want == got => Ok(format!("{got}")),
+        (Expect::Status(want), Ok(got)) => Err(format!("expected {want}, got {got}")),
+        (Expect::Status(want), Err(e)) => Err(format!("expected {want}, got error: {e}")),
+        (Expect::Error(text), Err(e)) if e.to_string().contains(text) => Ok(e.to_string()),
+        (Expect::Error(text), Err(e)) => Err(format!("expected an error containing '{text}', got: {e}")),
+        (Expect::Error(text), Ok(got)) => Err(format!("expected an error containing '{text}', got {got}")),
+    }
+}
+
+/// Run every case and return (name, outcome) pairs in order.
+fn run_all() -> io::Result<Vec<(&'static str, Result<String, String>)>> {
+    let addr = start_server()?;
+    Ok(cases()
+        .iter()
+        .enumerate()
+        .map(|(i, case)| {
+            let outcome = run_case(case, i + 1, addr).and_then(|s| verdict(&case.expect, &s));
+            (case.name, outcome)
+        })
+        .collect())
+}
+
+/// Print a PASS/FAIL line per case; returns whether every case passed.
+pub fn run() -> io::Result<bool> {
+    let results = run_all()?;
+    let width = results.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut passed = 0;
+    for (name, outcome) in &results {
+        match outcome {
+            Ok(detail) => {
+                passed += 1;
+                println!("PASS  {name:<width$}  {detail}");
+            }
+            Err(why) => println!("FAIL  {name:<width$}  {why}"),
+        }
+    }
+    println!("{passed}/{} selftest checks passed", results.len());
+    Ok(passed == results.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selftest_passes() {
+        for (name, outcome) in run_all().unwrap() {
+            assert!(outcome.is_ok(), "{name}: {outcome:?}");
+        }
+    }
+}
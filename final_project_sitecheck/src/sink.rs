@@ -0,0 +1,711 @@
+//! Where results go: the `Sink` trait, the built-in sinks, and `Sinks`, which
+//! drives several at once and keeps a panicking one from ending the run.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::SecondsFormat;
+use log::{debug, error, warn};
+
+use crate::http::{build_agent, AgentOptions};
+use crate::{sparkline, summary_rows, RetryBudget, StatusSet, SummaryStyle, UpIf, UrlStats, WebsiteStatus};
+
+/// Receives results as they are collected. Sinks run on the collecting thread,
+/// so a slow sink delays the ones after it, but never the checks themselves.
+pub trait Sink {
+    /// Called once per check result, in arrival order.
+    fn on_result(&mut self, status: &WebsiteStatus);
+    /// Called when every check of a round is in, with the run's stats so far
+    /// and that round's retry budget, if one was set.
+    fn on_round_end(&mut self, _stats: &HashMap<String, UrlStats>, _budget: Option<&RetryBudget>) {}
+    /// Called once before the run exits, including after `--fail-fast`.
+    fn on_shutdown(&mut self) {}
+}
+
+/// Several named sinks driven together. A sink that panics is logged and dropped;
+/// the others keep running.
+#[derive(Default)]
+pub struct Sinks {
+    sinks: Vec<(String, Box<dyn Sink>)>,
+}
+
+impl Sinks {
+    pub fn new() -> Self {
+        Sinks::default()
+    }
+
+    /// Add a sink; `name` identifies it in log messages.
+    pub fn add(&mut self, name: impl Into<String>, sink: impl Sink + 'static) {
+        self.sinks.push((name.into(), Box::new(sink)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    fn each(&mut self, event: &str, mut call: impl FnMut(&mut dyn Sink)) {
+        self.sinks.retain_mut(|(name, sink)| {
+            match panic::catch_unwind(AssertUnwindSafe(|| call(sink.as_mut()))) {
+                Ok(()) => true,
+                Err(_) => {
+                    error!("{name} sink panicked in {event}; disabling it");
+                    false
+                }
+            }
+        });
+    }
+}
+
+impl Sink for Sinks {
+    fn on_result(&mut self, status: &WebsiteStatus) {
+        self.each("on_result", |s| s.on_result(status));
+    }
+
+    fn on_round_end(&mut self, stats: &HashMap<String, UrlStats>, budget: Option<&RetryBudget>) {
+        self.each("on_round_end", |s| s.on_round_end(stats, budget));
+    }
+
+    fn on_shutdown(&mut self) {
+        self.each("on_shutdown", |s| s.on_shutdown());
+    }
+}
+
+/// One JSON record per result: the NDJSON that `sitecheck report` reads back.
+pub struct JsonLines<W: Write> {
+    out: W,
+    failed: bool,
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(out: W) -> Self {
+        JsonLines { out, failed: false }
+    }
+}
+
+impl<W: Write> Sink for JsonLines<W> {
+    fn on_result(&mut self, status: &WebsiteStatus) {
+        let line = match serde_json::to_string(status) {
+            Ok(js) => js,
+            Err(_) => format!("{status:?}"),
+        };
+        // A closed pipe would fail every write; say so once
+        if let Err(e) = writeln!(self.out, "{line}") {
+            if !self.failed {
+                warn!("writing results failed: {e}");
+                self.failed = true;
+            }
+        }
+    }
+
+    fn on_shutdown(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+/// The stats summary after each round.
+pub struct Summary<W: Write> {
+    out: W,
+    style: SummaryStyle,
+}
+
+impl<W: Write> Summary<W> {
+    pub fn new(out: W, style: SummaryStyle) -> Self {
+        Summary { out, style }
+    }
+}
+
+impl<W: Write> Sink for Summary<W> {
+    fn on_result(&mut self, _status: &WebsiteStatus) {}
+
+    fn on_round_end(&mut self, stats: &HashMap<String, UrlStats>, budget: Option<&RetryBudget>) {
+        if let Err(e) = write_summary(&mut self.out, stats, budget, &self.style) {
+            warn!("writing the summary failed: {e}");
+        }
+    }
+}
+
+/// Where `--summary-to` sends the stats summary. Not stdout by default, so
+/// stdout carries nothing but result records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SummaryTo {
+    Stdout,
+    #[default]
+    Stderr,
+    /// Appended to, like `--csv`.
+    File(PathBuf),
+}
+impl SummaryTo {
+    /// Parse a `--summary-to` value: `stdout`, `stderr`, or `file:PATH`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "stdout" => Ok(SummaryTo::Stdout),
+            "stderr" => Ok(SummaryTo::Stderr),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(SummaryTo::File(PathBuf::from(path))),
+                _ => Err(format!("unknown summary destination '{s}' (expected stdout, stderr, file:PATH)")),
+            },
+        }
+    }
+
+    /// A writer for the summary; a file is created if missing.
+    pub fn open(&self) -> io::Result<Box<dyn Write>> {
+        Ok(match self {
+            SummaryTo::Stdout => Box::new(io::stdout()),
+            SummaryTo::Stderr => Box::new(io::stderr()),
+            SummaryTo::File(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+        })
+    }
+}
+
+impl fmt::Display for SummaryTo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SummaryTo::Stdout => write!(f, "stdout"),
+            SummaryTo::Stderr => write!(f, "stderr"),
+            SummaryTo::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+/// Write the stats summary block: one line per URL (or group), then the retry
+/// budget if there was one.
+pub fn write_summary(
+    out: &mut impl Write,
+    stats: &HashMap<String, UrlStats>,
+    budget: Option<&RetryBudget>,
+    style: &SummaryStyle,
+) -> io::Result<()> {
+    let last = |st: &UrlStats| st.last_check.map(|t| style.times.format(t)).unwrap_or_else(|| "-".to_string());
+    // Most frequent first, so the likeliest cause leads
+    let failures = |st: &UrlStats| {
+        let mut out = String::new();
+        if !st.failures.is_empty() {
+            let mut kinds: Vec<(&String, &u64)> = st.failures.iter().collect();
+            kinds.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let kinds: Vec<String> = kinds.iter().map(|(k, n)| format!("{k}: {n}")).collect();
+            out = format!(", failures: {{{}}}", kinds.join(", "));
+        }
+        if st.maintenance > 0 {
+            out.push_str(&format!(", maintenance: {}", st.maintenance));
+        }
+        out
+    };
+    writeln!(out, "--- stats summary ---")?;
+    for (key, urls, st) in summary_rows(stats, style) {
+        if style.group_by.is_some() {
+            writeln!(
+                out,
+                "{} -> urls: {}, checks: {}, uptime: {:.1}%, retried: {:.1}%, avg_rt_ms: {:.1}, last: {}{}",
+                key,
+                urls,
+                st.checks,
+                st.uptime(),
+                st.retried_pct(),
+                st.avg_ms(),
+                last(&st),
+                failures(&st)
+            )?;
+        } else {
+            let mut key = key;
+            if !st.recent_ms.is_empty() {
+                key.push(' ');
+                key.push_str(&sparkline(st.recent_ms.iter().copied(), style.ascii));
+            }
+            writeln!(
+                out,
+                "{} -> checks: {}, uptime: {:.1}%, retried: {:.1}%, avg_rt_ms: {:.1}, last: {}{}",
+                key,
+                st.checks,
+                st.uptime(),
+                st.retried_pct(),
+                st.avg_ms(),
+                last(&st),
+                failures(&st)
+            )?;
+        }
+    }
+    if let Some(b) = budget {
+        writeln!(out, "retry budget: used {} of {} this round", b.used(), b.limit())?;
+    }
+    writeln!(out, "---------------------")?;
+    out.flush()
+}
+
+/// Columns written by `Csv`, in order, before one per captured header.
+pub const CSV_HEADER: &str = "timestamp,url,up,http_status,response_time_ms,error_kind,error,labels";
+
+/// One CSV row per result, flushed at the end of every round.
+pub struct Csv<W: Write> {
+    out: W,
+    up_if: UpIf,
+    captured: Vec<String>, // `--capture-header` names, a column each
+}
+
+impl<W: Write> Csv<W> {
+    /// Start a CSV stream on `out`, beginning with the header row.
+    pub fn new(out: W, up_if: UpIf) -> io::Result<Self> {
+        Csv::with_captured(out, up_if, Vec::new())
+    }
+
+    /// Like `new`, with a column after `CSV_HEADER`'s for each header in `captured`.
+    pub fn with_captured(mut out: W, up_if: UpIf, captured: Vec<String>) -> io::Result<Self> {
+        let extra: String = captured.iter().map(|name| format!(",{}", csv_field(name))).collect();
+        writeln!(out, "{CSV_HEADER}{extra}")?;
+        Ok(Csv { out, up_if, captured })
+    }
+}
+
+impl Csv<BufWriter<File>> {
+    /// Append to the CSV file at `path`, writing the header only if the file is
+    /// new or empty. An existing file is assumed to have the same columns.
+    pub fn append(path: &Path, up_if: UpIf, captured: Vec<String>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let fresh = file.metadata()?.len() == 0;
+        let out = BufWriter::new(file);
+        if fresh {
+            Csv::with_captured(out, up_if, captured)
+        } else {
+            Ok(Csv { out, up_if, captured })
+        }
+    }
+}
+
+/// Quote a CSV field if it holds a comma, quote, or line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+impl<W: Write> Sink for Csv<W> {
+    fn on_result(&mut self, status: &WebsiteStatus) {
+        let error = status.error();
+        let labels: Vec<String> = status.labels.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        let mut row = vec![
+            status.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+            status.url.clone(),
+            self.up_if.is_up(status).to_string(),
+            status.http_code().map(|c| c.to_string()).unwrap_or_default(),
+            status.response_time.as_millis().to_string(),
+            error.map(|e| e.kind().to_string()).unwrap_or_default(),
+            error.map(|e| e.to_string()).unwrap_or_default(),
+            labels.join(" "),
+        ];
+        // Absent and not captured are both left empty
+        for name in &self.captured {
+            row.push(status.captured_headers.get(name).cloned().flatten().unwrap_or_default());
+        }
+        let row: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        if let Err(e) = writeln!(self.out, "{}", row.join(",")) {
+            warn!("writing CSV row failed: {e}");
+        }
+    }
+
+    fn on_round_end(&mut self, _stats: &HashMap<String, UrlStats>, _budget: Option<&RetryBudget>) {
+        if let Err(e) = self.out.flush() {
+            warn!("flushing CSV failed: {e}");
+        }
+    }
+
+    fn on_shutdown(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+/// Which results alert, by HTTP status rather than pass/fail. With neither
+/// set, a result alerts when it is down.
+#[derive(Debug, Clone, Default)]
+pub struct AlertOn {
+    /// Alert on exactly these statuses, up or down (`--alert-on-status`). A
+    /// result with no response at all still alerts when down: it has no status to match.
+    pub statuses: Option<StatusSet>,
+    /// Never alert on these (`--no-alert-on-status`); wins over `statuses`.
+    pub except: Option<StatusSet>,
+}
+
+impl AlertOn {
+    /// Whether `status` should be sent. Failures during a maintenance window never are.
+    pub fn should_alert(&self, status: &WebsiteStatus, up_if: UpIf) -> bool {
+        if status.maintenance {
+            return false;
+        }
+        let code = status.http_code();
+        if let (Some(except), Some(code)) = (&self.except, code) {
+            if except.contains(code) {
+                return false;
+            }
+        }
+        match (&self.statuses, code) {
+            (Some(statuses), Some(code)) => statuses.contains(code),
+            _ => !up_if.is_up(status),
+        }
+    }
+}
+
+/// POSTs each result that `AlertOn` picks (by default, each one that is down),
+/// as the same JSON record `JsonLines` writes.
+/// A webhook that is unreachable or rejects the request is logged and skipped.
+pub struct Webhook {
+    url: String,
+    agent: ureq::Agent,
+    up_if: UpIf,
+    alert_on: AlertOn,
+}
+
+impl Webhook {
+    pub fn new(url: impl Into<String>, up_if: UpIf) -> Self {
+        Webhook { url: url.into(), agent: build_agent(&AgentOptions::default()), up_if, alert_on: AlertOn::default() }
+    }
+
+    /// Send the results `alert_on` picks instead of every down one.
+    pub fn alert_on(mut self, alert_on: AlertOn) -> Self {
+        self.alert_on = alert_on;
+        self
+    }
+}
+
+impl Sink for Webhook {
+    fn on_result(&mut self, status: &WebsiteStatus) {
+        if !self.alert_on.should_alert(status, self.up_if) {
+            return;
+        }
+        let body = match serde_json::to_string(status) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("webhook {}: could not encode result: {e}", self.url);
+                return;
+            }
+        };
+        let sent = self.agent.post(&self.url).set("Content-Type", "application/json").send_string(&body);
+        if let Err(e) = sent {
+            warn!("webhook {}: {e}", self.url);
+        }
+    }
+}
+
+/// Which rounds `Heartbeat` pings after.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeartbeatOn {
+    /// Every completed round: sitecheck is alive, whatever it found.
+    #[default]
+    All,
+    /// Only rounds in which every check was up, or down for maintenance.
+    SuccessOnly,
+}
+impl HeartbeatOn {
+    /// Parse a `--heartbeat-on` value.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "all" => Ok(HeartbeatOn::All),
+            "success-only" => Ok(HeartbeatOn::SuccessOnly),
+            other => Err(format!("unknown heartbeat mode '{other}' (expected all, success-only)")),
+        }
+    }
+    /// The name `parse` accepts.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeartbeatOn::All => "all",
+            HeartbeatOn::SuccessOnly => "success-only",
+        }
+    }
+}
+
+/// Timeout for a heartbeat ping, short so a slow endpoint barely holds up the next round.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// GETs a URL at the end of each round, for a dead-man's switch (healthchecks.io
+/// style) that alerts when sitecheck itself stops. Pings are tried once; a failed
+/// one is logged and never touches the results.
+pub struct Heartbeat {
+    url: String,
+    agent: ureq::Agent,
+    up_if: UpIf,
+    on: HeartbeatOn,
+    round_failed: bool,
+}
+
+impl Heartbeat {
+    pub fn new(url: impl Into<String>, up_if: UpIf, on: HeartbeatOn) -> Self {
+        let agent = build_agent(&AgentOptions { timeout: HEARTBEAT_TIMEOUT, ..AgentOptions::default() });
+        Heartbeat { url: url.into(), agent, up_if, on, round_failed: false }
+    }
+}
+
+impl Sink for Heartbeat {
+    fn on_result(&mut self, status: &WebsiteStatus) {
+        if !self.up_if.is_up(status) && !status.maintenance {
+            self.round_failed = true;
+        }
+    }
+
+    fn on_round_end(&mut self, _stats: &HashMap<String, UrlStats>, _budget: Option<&RetryBudget>) {
+        let failed = std::mem::take(&mut self.round_failed);
+        if failed && self.on == HeartbeatOn::SuccessOnly {
+            debug!("heartbeat {}: skipped, the round had failures", self.url);
+            return;
+        }
+        if let Err(e) = self.agent.get(&self.url).call() {
+            warn!("heartbeat {}: {e}", self.url);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{record_status, seed_stats, CheckError, UrlEntry, UrlSource, SCHEMA_VERSION};
+    use chrono::{TimeZone, Utc};
+    use httpmock::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    fn status(url: &str, result: Result<u16, CheckError>) -> WebsiteStatus {
+        // A fixed time, so the CSV and JSON lines can be compared whole
+        let timestamp = Utc.with_ymd_and_hms(2025, 8, 21, 23, 0, 0).unwrap();
+        WebsiteStatus { timestamp, ..WebsiteStatus::sample(url, result) }
+    }
+
+    /// Records every call; panics on results for `panic_on`.
+    struct Recorder {
+        seen: Arc<Mutex<Vec<String>>>,
+        panic_on: Option<&'static str>,
+    }
+
+    impl Sink for Recorder {
+        fn on_result(&mut self, status: &WebsiteStatus) {
+            if self.panic_on == Some(status.url.as_str()) {
+                panic!("recorder asked to panic");
+            }
+            self.seen.lock().unwrap().push(status.url.clone());
+        }
+
+        fn on_shutdown(&mut self) {
+            self.seen.lock().unwrap().push("shutdown".to_string());
+        }
+    }
+
+    #[test]
+    fn test_sinks_survive_a_panicking_sink() {
+        let good = Arc::new(Mutex::new(Vec::new()));
+        let bad = Arc::new(Mutex::new(Vec::new()));
+        let mut sinks = Sinks::new();
+        sinks.add("bad", Recorder { seen: bad.clone(), panic_on: Some("https://b/") });
+        sinks.add("good", Recorder { seen: good.clone(), panic_on: None });
+
+        for url in ["https://a/", "https://b/", "https://c/"] {
+            sinks.on_result(&status(url, Ok(200)));
+        }
+        sinks.on_shutdown();
+
+        assert_eq!(*good.lock().unwrap(), ["https://a/", "https://b/", "https://c/", "shutdown"]);
+        // The panicking sink saw results up to its panic and nothing after
+        assert_eq!(*bad.lock().unwrap(), ["https://a/"]);
+        assert_eq!(sinks.len(), 1);
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut sink = JsonLines::new(Vec::new());
+        sink.on_result(&status("https://a/", Ok(200)));
+        sink.on_result(&status("https://b/", Err(CheckError::Dns { detail: "no such host".into() })));
+        let out = String::from_utf8(sink.out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for line in &lines {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(value["schema_version"], SCHEMA_VERSION, "{line}");
+        }
+        let b: WebsiteStatus = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(b.status, Err(CheckError::Dns { detail: "no such host".into() }));
+    }
+
+    #[test]
+    fn test_summary() {
+        let urls = [UrlEntry::new("https://a/", UrlSource::Arg(1))];
+        let mut stats = seed_stats(&urls);
+        record_status(&mut stats, status("https://a/", Ok(200)), UpIf::Valid);
+        let timeout = || CheckError::ReadTimeout { detail: "timed out".into() };
+        let unavailable = CheckError::HttpStatus { code: 503, detail: "status code 503".into() };
+        for error in [timeout(), unavailable, timeout()] {
+            record_status(&mut stats, status("https://a/", Err(error)), UpIf::Valid);
+        }
+        let mut sink = Summary::new(Vec::new(), SummaryStyle::default());
+        sink.on_round_end(&stats, Some(&RetryBudget::new(3)));
+        assert_eq!(
+            String::from_utf8(sink.out).unwrap(),
+            "--- stats summary ---\n\
+             https://a/ ████ -> checks: 4, uptime: 25.0%, retried: 0.0%, avg_rt_ms: 42.0, last: 2025-08-21T23:00:00Z, \
+             failures: {read_timeout: 2, 5xx: 1}\n\
+             retry budget: used 0 of 3 this round\n\
+             ---------------------\n"
+        );
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut sink = Csv::new(Vec::new(), UpIf::Valid).unwrap();
+        let mut up = status("https://a/", Ok(200));
+        up.labels.insert("team".into(), "web".into());
+        sink.on_result(&up);
+        let mismatch = CheckError::HeaderMismatch { name: "Server".into(), expected: "a,b".into(), got: "\"c\"".into() };
+        let mut down = status("https://b/", Err(mismatch));
+        down.http_status = Some(200);
+        sink.on_result(&down);
+        let out = String::from_utf8(sink.out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "2025-08-21T23:00:00Z,https://a/,true,200,42,,,team=web");
+        assert_eq!(
+            lines[2],
+            r#"2025-08-21T23:00:00Z,https://b/,false,200,42,header_mismatch,"header mismatch: Server expected 'a,b' got '""c""'","#
+        );
+    }
+
+    #[test]
+    fn test_csv_captured_headers() {
+        let mut sink = Csv::with_captured(Vec::new(), UpIf::Valid, vec!["X-Cache".into(), "CF-Ray".into()]).unwrap();
+        let mut hit = status("https://a/", Ok(200));
+        hit.captured_headers = [("X-Cache".to_string(), Some("HIT, MISS".to_string())), ("CF-Ray".to_string(), None)].into();
+        sink.on_result(&hit);
+        let out = String::from_utf8(sink.out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], format!("{CSV_HEADER},X-Cache,CF-Ray"));
+        assert_eq!(lines[1], "2025-08-21T23:00:00Z,https://a/,true,200,42,,,,\"HIT, MISS\",");
+    }
+
+    #[test]
+    fn test_csv_append_writes_header_once() {
+        let path = std::env::temp_dir().join(format!("sitecheck-csv-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for _ in 0..2 {
+            let mut sink = Csv::append(&path, UpIf::Valid, Vec::new()).unwrap();
+            sink.on_result(&status("https://a/", Ok(200)));
+            sink.on_shutdown();
+        }
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.lines().filter(|l| *l == CSV_HEADER).count(), 1);
+        assert_eq!(written.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_webhook_posts_failures_only() {
+        let server = MockServer::start();
+        let hook = server.mock(|when, then| {
+            when.method(POST).path("/hook").header("Content-Type", "application/json")
+                .body_contains("\"schema_version\":1,")
+                .body_contains("\"kind\":\"dns\"");
+            then.status(204);
+        });
+
+        let mut sink = Webhook::new(format!("{}/hook", server.base_url()), UpIf::Valid);
+        sink.on_result(&status("https://a/", Ok(200)));
+        sink.on_result(&status("https://b/", Err(CheckError::Dns { detail: "no such host".into() })));
+        let mut planned = status("https://c/", Err(CheckError::Dns { detail: "no such host".into() }));
+        planned.maintenance = true;
+        sink.on_result(&planned);
+        hook.assert_hits(1);
+    }
+
+    #[test]
+    fn test_alert_on() {
+        let set = |s: &str| Some(StatusSet::parse(s).unwrap());
+        let down = |code: u16| WebsiteStatus {
+            http_status: Some(code),
+            ..status("https://a/", Err(CheckError::HttpStatus { code, detail: String::new() }))
+        };
+        let dns = status("https://a/", Err(CheckError::Dns { detail: "no such host".into() }));
+
+        // By default: down results, whatever their status
+        let default = AlertOn::default();
+        assert!(default.should_alert(&down(404), UpIf::Valid) && default.should_alert(&dns, UpIf::Valid));
+        assert!(!default.should_alert(&status("https://a/", Ok(503)), UpIf::Valid));
+
+        // An allowlist alerts on its statuses even when the check passed, and only on those
+        let only_5xx = AlertOn { statuses: set("5xx"), except: None };
+        assert!(only_5xx.should_alert(&status("https://a/", Ok(503)), UpIf::AnyResponse));
+        assert!(only_5xx.should_alert(&down(500), UpIf::Valid));
+        assert!(!only_5xx.should_alert(&down(404), UpIf::Valid));
+        assert!(only_5xx.should_alert(&dns, UpIf::Valid));
+
+        // A denylist on its own trims the default
+        let not_429 = AlertOn { statuses: None, except: set("429") };
+        assert!(!not_429.should_alert(&down(429), UpIf::Valid));
+        assert!(not_429.should_alert(&down(500), UpIf::Valid));
+
+        // Both matching: deny wins
+        let both = AlertOn { statuses: set("4xx,5xx"), except: set("429,503") };
+        assert!(!both.should_alert(&down(429), UpIf::Valid));
+        assert!(!both.should_alert(&status("https://a/", Ok(503)), UpIf::AnyResponse));
+        assert!(both.should_alert(&down(404), UpIf::Valid));
+
+        let mut planned = down(500);
+        planned.maintenance = true;
+        assert!(!only_5xx.should_alert(&planned, UpIf::Valid));
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let server = MockServer::start();
+        let ping = server.mock(|when, then| {
+            when.method(GET).path("/ping");
+            then.status(200);
+        });
+        let url = format!("{}/ping", server.base_url());
+        let stats = HashMap::new();
+        let down = status("https://b/", Err(CheckError::Dns { detail: "no such host".into() }));
+
+        let mut all = Heartbeat::new(&url, UpIf::Valid, HeartbeatOn::All);
+        let mut success_only = Heartbeat::new(&url, UpIf::Valid, HeartbeatOn::SuccessOnly);
+        for sink in [&mut all, &mut success_only] {
+            // A round with a failure, then a clean one
+            sink.on_result(&status("https://a/", Ok(200)));
+            sink.on_result(&down);
+            sink.on_round_end(&stats, None);
+            sink.on_result(&status("https://a/", Ok(200)));
+            sink.on_round_end(&stats, None);
+        }
+        ping.assert_hits(3);
+
+        // An unreachable endpoint is only logged
+        let mut dead = Heartbeat::new("http://127.0.0.1:9/ping", UpIf::Valid, HeartbeatOn::All);
+        dead.on_round_end(&stats, None);
+        assert_eq!(HeartbeatOn::parse("success-only"), Ok(HeartbeatOn::SuccessOnly));
+        assert!(HeartbeatOn::parse("never").is_err());
+    }
+
+    #[test]
+    fn test_summary_to() {
+        assert_eq!(SummaryTo::default(), SummaryTo::Stderr);
+        assert_eq!(SummaryTo::parse("stdout"), Ok(SummaryTo::Stdout));
+        assert_eq!(SummaryTo::parse("file:out/summary.txt"), Ok(SummaryTo::File(PathBuf::from("out/summary.txt"))));
+        assert_eq!(SummaryTo::parse("file:out/summary.txt").unwrap().to_string(), "file:out/summary.txt");
+        for bad in ["file:", "terminal", "STDOUT"] {
+            assert!(SummaryTo::parse(bad).is_err(), "{bad}");
+        }
+
+        // A file collects every summary written to it
+        let path = std::env::temp_dir().join(format!("sitecheck-summary-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let to = SummaryTo::File(path.clone());
+        let mut stats = seed_stats(&[UrlEntry::new("https://a/", UrlSource::Arg(0))]);
+        record_status(&mut stats, status("https://a/", Ok(200)), UpIf::Valid);
+        for _ in 0..2 {
+            let mut sink = Summary::new(to.open().unwrap(), SummaryStyle::default());
+            sink.on_round_end(&stats, None);
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.matches("--- stats summary ---\n").count(), 2, "{text}");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,144 @@
+//! `--stats-out`: the stats summary as a JSON file, written when the run ends
+//! and, with `--stats-checkpoint-secs`, every so often while it runs.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::Serialize;
+
+use crate::{write_atomic, Labels, UrlStats, SCHEMA_VERSION};
+
+/// One URL's row in the file.
+#[derive(Debug, Serialize)]
+struct StatsRow<'a> {
+    checks: u64,
+    successes: u64,
+    retried: u64,
+    uptime: f64,
+    avg_rt_ms: f64,
+    failures: &'a BTreeMap<String, u64>,
+    maintenance: u64,
+    last_check: Option<DateTime<Utc>>,
+    labels: &'a Labels,
+}
+
+#[derive(Debug, Serialize)]
+struct Snapshot<'a> {
+    schema_version: u32,
+    written_at: DateTime<Utc>,
+    urls: BTreeMap<&'a str, StatsRow<'a>>,
+}
+
+/// `stats` as the JSON `--stats-out` writes, by URL.
+pub fn stats_json(stats: &HashMap<String, UrlStats>) -> serde_json::Result<String> {
+    let urls = stats
+        .iter()
+        .map(|(url, st)| {
+            let row = StatsRow {
+                checks: st.checks,
+                successes: st.successes,
+                retried: st.retried,
+                uptime: st.uptime(),
+                avg_rt_ms: st.avg_ms(),
+                failures: &st.failures,
+                maintenance: st.maintenance,
+                last_check: st.last_check,
+                labels: &st.labels,
+            };
+            (url.as_str(), row)
+        })
+        .collect();
+    serde_json::to_string(&Snapshot { schema_version: SCHEMA_VERSION, written_at: Utc::now(), urls })
+}
+
+/// Writes the stats to `--stats-out`, and checkpoints them every `every`.
+#[derive(Debug)]
+pub struct StatsOut {
+    path: PathBuf,
+    every: Option<Duration>,
+    last: Instant,
+    failed: bool, // Warn once per run of failed writes, not on every checkpoint
+}
+
+impl StatsOut {
+    pub fn new(path: impl Into<PathBuf>, every: Option<Duration>) -> Self {
+        StatsOut { path: path.into(), every, last: Instant::now(), failed: false }
+    }
+
+    /// Write the stats if a checkpoint is due at `now`; returns whether one was.
+    /// Only the main loop touches `stats`, so this holds up nothing but the
+    /// handling of results, which wait in the channel meanwhile.
+    pub fn checkpoint(&mut self, stats: &HashMap<String, UrlStats>, now: Instant) -> bool {
+        match self.every {
+            Some(every) if now.duration_since(self.last) >= every => {
+                self.last = now;
+                self.write_logged(stats);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Write the stats now, replacing the file whole so a reader (or a crash)
+    /// never sees half of it.
+    pub fn write(&self, stats: &HashMap<String, UrlStats>) -> io::Result<()> {
+        let json = stats_json(stats).map_err(io::Error::other)?;
+        write_atomic(&self.path, format!("{json}\n").as_bytes())
+    }
+
+    fn write_logged(&mut self, stats: &HashMap<String, UrlStats>) {
+        match self.write(stats) {
+            Ok(()) => self.failed = false,
+            Err(e) if !self.failed => {
+                warn!("writing {} failed: {e}", self.path.display());
+                self.failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Write the final stats as the run ends.
+    pub fn finish(&mut self, stats: &HashMap<String, UrlStats>) {
+        self.failed = false;
+        self.write_logged(stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{missing_result, record_status, seed_stats, UpIf, UrlEntry, UrlSource};
+    use std::fs;
+
+    #[test]
+    fn test_checkpoint() {
+        let path = std::env::temp_dir().join(format!("sitecheck-stats-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let entry = UrlEntry::new("https://x.com/", UrlSource::Arg(0));
+        let mut stats = seed_stats(std::slice::from_ref(&entry));
+        record_status(&mut stats, missing_result(&entry, "gone"), UpIf::default());
+
+        let mut out = StatsOut::new(&path, Some(Duration::from_secs(10)));
+        let start = Instant::now();
+        assert!(!out.checkpoint(&stats, start));
+        assert!(!path.exists());
+
+        // Due mid-run: the file holds the stats so far, as complete JSON
+        assert!(out.checkpoint(&stats, start + Duration::from_secs(10)));
+        let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["schema_version"], SCHEMA_VERSION);
+        assert_eq!(written["urls"]["https://x.com/"]["checks"], 1);
+        assert_eq!(written["urls"]["https://x.com/"]["failures"]["no_result"], 1);
+        // and the next one is N seconds after it
+        assert!(!out.checkpoint(&stats, start + Duration::from_secs(15)));
+        assert!(out.checkpoint(&stats, start + Duration::from_secs(20)));
+
+        // Without --stats-checkpoint-secs only the end of the run writes
+        assert!(!StatsOut::new(&path, None).checkpoint(&stats, start + Duration::from_secs(3600)));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,362 @@
+//! Checks over a Unix domain socket (`--unix-socket`), for local services that
+//! don't listen on TCP. ureq can't connect to one, so this is a minimal
+//! HTTP/1.1 client: one request per connection, `Connection: close`. URLs keep
+//! their `http://localhost/path` form; the host only fills the `Host` header.
+//! `http+unix:///run/app.sock:/healthz` targets name their own socket instead.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::http::read_body;
+use crate::{CheckRequest, CheckResponse, HeaderView, HttpClient, Method, ReadBody, TransportError, UNIX_URL_PREFIX};
+
+/// Longest status line plus headers accepted.
+const MAX_HEAD_BYTES: u64 = 64 * 1024;
+
+/// Sends every request to one Unix socket, whatever host the URL names.
+/// Redirects are followed over the same socket.
+#[derive(Debug, Clone)]
+pub struct UnixSocketClient {
+    path: PathBuf,
+}
+
+impl UnixSocketClient {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        UnixSocketClient { path: path.into() }
+    }
+
+    /// The socket requests go to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// One request for `url`, its body read as `req` asks.
+    fn get(&self, url: &url::Url, req: &CheckRequest) -> Result<CheckResponse, TransportError> {
+        if url.scheme() != "http" {
+            return Err(TransportError::Other(format!("{url}: only http:// URLs can be checked over a Unix socket")));
+        }
+        let stream = UnixStream::connect(&self.path).map_err(|e| {
+            let detail = format!("{}: {e}", self.path.display());
+            match e.kind() {
+                io::ErrorKind::ConnectionRefused => TransportError::ConnectionRefused(detail),
+                _ => TransportError::ConnectionFailed(detail),
+            }
+        })?;
+        // Zero would mean no timeout at all, so a spent deadline still gets a sliver
+        let timeout = match req.deadline {
+            Some(d) => req.timeout.min(d.saturating_duration_since(Instant::now())).max(Duration::from_millis(1)),
+            None => req.timeout,
+        };
+        stream.set_read_timeout(Some(timeout)).and_then(|_| stream.set_write_timeout(Some(timeout))).map_err(io_error)?;
+
+        let target = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (host, None) => host.unwrap_or("localhost").to_string(),
+            (None, Some(_)) => "localhost".to_string(),
+        };
+        let (method, range) = match req.method {
+            Method::Get => ("GET", ""),
+            Method::Head => ("HEAD", ""),
+            Method::GetFirstByte => ("GET", "Range: bytes=0-0\r\n"),
+        };
+        let encoding = req.accept_encoding.as_ref().map(|e| format!("Accept-Encoding: {e}\r\n")).unwrap_or_default();
+        let auth = req.authorization.as_ref().map(|a| format!("Authorization: {}\r\n", a.as_str())).unwrap_or_default();
+        let request = format!(
+            "{method} {target} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: sitecheck\r\nAccept: */*\r\n{range}{encoding}{auth}Connection: close\r\n\r\n"
+        );
+        (&stream).write_all(request.as_bytes()).map_err(io_error)?;
+
+        let mut reader = BufReader::new(stream);
+        let (status, headers) = read_head(&mut reader).map_err(io_error)?;
+        let mut resp = CheckResponse { status, headers, body: None, certificate: None };
+        let wanted = match req.read_body {
+            ReadBody::Never => false,
+            ReadBody::Always => true,
+            ReadBody::IfHtml => resp.is_html(),
+        };
+        // A HEAD response has no body, whatever its headers say
+        if wanted && req.method != Method::Head {
+            let chunked = resp.headers.get("transfer-encoding").is_some_and(|te| te.to_ascii_lowercase().contains("chunked"));
+            let length = resp.headers.get("content-length").and_then(|n| n.trim().parse::<u64>().ok());
+            let body: Box<dyn Read> = match (chunked, length) {
+                (true, _) => Box::new(Chunked { inner: reader, left: 0, done: false }),
+                (false, Some(n)) => Box::new(reader.take(n)),
+                // Without either, the body runs until the server closes the connection
+                (false, None) => Box::new(reader),
+            };
+            resp.body = Some(read_body(body, status, req)?);
+        }
+        Ok(resp)
+    }
+}
+
+impl HttpClient for UnixSocketClient {
+    fn execute(&self, req: CheckRequest) -> Result<CheckResponse, TransportError> {
+        let mut url = url::Url::parse(&req.url).map_err(|e| TransportError::Other(format!("{}: {e}", req.url)))?;
+        let mut redirects = 0;
+        loop {
+            let resp = self.get(&url, &req)?;
+            let location = match resp.headers.get("location") {
+                Some(location) if (300..400).contains(&resp.status) && redirects < req.max_redirects => location,
+                _ => return Ok(resp),
+            };
+            url = url.join(location).map_err(|e| TransportError::Other(format!("{url}: bad redirect to {location:?}: {e}")))?;
+            redirects += 1;
+        }
+    }
+}
+
+/// An `http+unix://` URL taken apart: the socket and the path requested over it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnixTarget {
+    pub socket: PathBuf,
+    /// Path and query, `/` when the URL names none.
+    pub path: String,
+}
+
+impl UnixTarget {
+    /// Parse `http+unix:///run/app.sock:/healthz`: the socket path runs up to
+    /// the first `:/`, and the request path is the rest.
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let expected = || format!("expected {UNIX_URL_PREFIX}/path/to.sock:/request/path");
+        let rest = url.strip_prefix(UNIX_URL_PREFIX).ok_or_else(expected)?;
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (socket, path) = match rest.find(":/") {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, "/"),
+        };
+        if !socket.starts_with('/') || socket.len() < 2 {
+            return Err(format!("missing socket path ({})", expected()));
+        }
+        Ok(UnixTarget { socket: PathBuf::from(socket), path: path.to_string() })
+    }
+}
+
+/// Sends each `http+unix://` URL to the socket it names, with `host` in the
+/// `Host` header, and every other URL to `inner`.
+pub struct UnixTargets {
+    inner: Arc<dyn HttpClient>,
+    host: String,
+}
+
+impl UnixTargets {
+    pub fn new(inner: Arc<dyn HttpClient>, host: impl Into<String>) -> Self {
+        UnixTargets { inner, host: host.into() }
+    }
+}
+
+impl HttpClient for UnixTargets {
+    fn execute(&self, mut req: CheckRequest) -> Result<CheckResponse, TransportError> {
+        if !req.url.starts_with(UNIX_URL_PREFIX) {
+            return self.inner.execute(req);
+        }
+        let target = UnixTarget::parse(&req.url).map_err(|e| TransportError::Other(format!("{}: {e}", req.url)))?;
+        req.url = format!("http://{}{}", self.host, target.path);
+        UnixSocketClient::new(target.socket).execute(req)
+    }
+}
+
+/// A timeout while talking to the socket, or any other failure.
+fn io_error(e: io::Error) -> TransportError {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => TransportError::ReadTimeout(e.to_string()),
+        _ => TransportError::Other(e.to_string()),
+    }
+}
+
+/// Parse the status line and headers, leaving `reader` at the body.
+fn read_head(reader: &mut impl BufRead) -> io::Result<(u16, HeaderView)> {
+    let invalid = |what: String| io::Error::new(io::ErrorKind::InvalidData, what);
+    let mut head = reader.take(MAX_HEAD_BYTES);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    let status = line
+        .strip_prefix("HTTP/1.")
+        .and_then(|rest| rest.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| invalid(format!("bad status line {:?}", line.trim_end())))?;
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if head.read_line(&mut line)? == 0 {
+            return Err(invalid(format!("response headers cut off or over {MAX_HEAD_BYTES} bytes")));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            return Ok((status, HeaderView::new(headers)));
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid(format!("bad header line {line:?}")))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+}
+
+/// Decodes a `Transfer-Encoding: chunked` body; trailers are ignored.
+struct Chunked<R> {
+    inner: R,
+    /// Bytes left in the current chunk.
+    left: u64,
+    done: bool,
+}
+
+impl<R: BufRead> Read for Chunked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.left == 0 {
+            let mut line = String::new();
+            self.inner.read_line(&mut line)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            self.left = u64::from_str_radix(size, 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("bad chunk size {size:?}")))?;
+            if self.left == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+        }
+        let max = self.left.min(buf.len() as u64) as usize;
+        let n = self.inner.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "chunked body cut off"));
+        }
+        self.left -= n as u64;
+        if self.left == 0 {
+            let mut crlf = [0u8; 2];
+            self.inner.read_exact(&mut crlf)?;
+        }
+        Ok(n)
+    }
+}
+
+/// Fail unless `path` exists and is a socket, so a typo is caught at startup.
+pub fn check_socket(path: &Path) -> Result<(), String> {
+    let meta = fs::metadata(path).map_err(|e| format!("--unix-socket {}: {e}", path.display()))?;
+    if !meta.file_type().is_socket() {
+        return Err(format!("--unix-socket {}: not a socket", path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    fn get(url: &str, read_body: ReadBody) -> CheckRequest {
+        CheckRequest {
+            url: url.to_string(),
+            method: Method::Get,
+            timeout: Duration::from_secs(2),
+            max_redirects: 2,
+            read_body,
+            deadline: None,
+            max_body_bytes: None,
+            read_until: None,
+            accept_encoding: None,
+            authorization: None,
+            checked_url: None,
+        }
+    }
+
+    /// A socket at a fresh path answering each connection with the next of
+    /// `responses`; the server thread returns the request lines it got.
+    fn serve(name: &str, responses: &[&str]) -> (PathBuf, thread::JoinHandle<Vec<String>>) {
+        let path = std::env::temp_dir().join(format!("sitecheck-{name}-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let responses: Vec<String> = responses.iter().map(|r| r.to_string()).collect();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                requests.push(line.trim_end().to_string());
+                while line != "\r\n" {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                }
+                (&stream).write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (path, server)
+    }
+
+    #[test]
+    fn test_unix_socket_client() {
+        let (path, server) = serve(
+            "unix",
+            &[
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nX-Test: yes\r\nContent-Length: 9\r\n\r\n<p>hi</p>",
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4;ext\r\nheal\r\n3\r\nthy\r\n0\r\n\r\n",
+                "HTTP/1.1 301 Moved\r\nLocation: /new?x=1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.0 503 Service Unavailable\r\n\r\ndown",
+            ],
+        );
+        check_socket(&path).unwrap();
+        let client = UnixSocketClient::new(&path);
+        let resp = client.execute(get("http://localhost/page", ReadBody::IfHtml)).unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.headers.get("x-test"), Some("yes"));
+        assert_eq!(resp.body.as_deref(), Some(&b"<p>hi</p>"[..]));
+
+        let resp = client.execute(get("http://localhost/health", ReadBody::Always)).unwrap();
+        assert_eq!(resp.body.as_deref(), Some(&b"healthy"[..]));
+
+        // Redirects are followed over the same socket, and bodies read only when asked for
+        let resp = client.execute(get("http://localhost/old", ReadBody::IfHtml)).unwrap();
+        assert_eq!((resp.status, resp.body), (503, None));
+        let requests = server.join().unwrap();
+        assert_eq!(requests, ["GET /page HTTP/1.1", "GET /health HTTP/1.1", "GET /old HTTP/1.1", "GET /new?x=1 HTTP/1.1"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unix_socket_errors() {
+        let path = std::env::temp_dir().join(format!("sitecheck-missing-{}.sock", std::process::id()));
+        let err = UnixSocketClient::new(&path).execute(get("http://localhost/", ReadBody::Never)).unwrap_err();
+        assert!(matches!(err, TransportError::ConnectionFailed(_)), "{err:?}");
+        assert!(check_socket(&path).unwrap_err().starts_with("--unix-socket "));
+        assert!(check_socket(&std::env::temp_dir()).unwrap_err().ends_with("not a socket"));
+
+        let err = UnixSocketClient::new(&path).execute(get("https://localhost/", ReadBody::Never)).unwrap_err();
+        assert!(err.to_string().contains("only http://"), "{err}");
+
+        let (path, server) = serve("unix-garbage", &["SSH-2.0-OpenSSH\r\n\r\n"]);
+        let err = UnixSocketClient::new(&path).execute(get("http://localhost/", ReadBody::Never)).unwrap_err();
+        assert!(err.to_string().contains("bad status line"), "{err}");
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unix_targets() {
+        let target = UnixTarget::parse("http+unix:///run/my app.sock:/healthz?deep=1#top").unwrap();
+        assert_eq!(target, UnixTarget { socket: PathBuf::from("/run/my app.sock"), path: "/healthz?deep=1".to_string() });
+        assert_eq!(UnixTarget::parse("http+unix:///run/app.sock").unwrap().path, "/");
+        assert!(UnixTarget::parse("http+unix://:/healthz").unwrap_err().starts_with("missing socket path"));
+        assert!(UnixTarget::parse("http+unix://run/app.sock:/healthz").is_err());
+        assert!(crate::validate_url("http+unix:///run/app.sock:/healthz").is_ok());
+
+        let (path, server) = serve("targets", &["HTTP/1.1 204 No Content\r\n\r\n"]);
+        let inner = crate::http::MockClient::default().respond("https://x.com/", 200, &[], "");
+        let client = UnixTargets::new(Arc::new(inner), "api.internal");
+        let url = format!("http+unix://{}:/healthz", path.display());
+        assert_eq!(client.execute(get(&url, ReadBody::Never)).unwrap().status, 204);
+        assert_eq!(client.execute(get("https://x.com/", ReadBody::Never)).unwrap().status, 200);
+        assert_eq!(server.join().unwrap(), ["GET /healthz HTTP/1.1"]);
+        fs::remove_file(&path).unwrap();
+    }
+}
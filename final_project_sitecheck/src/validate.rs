@@ -0,0 +1,277 @@
+//! Response validation: the `Validator` trait and the built-in header, body, and
+//! status validators. `CheckSettings::validators` is the one place settings become validators.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{CheckError, CheckSettings};
+
+/// A response's headers, looked up case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderView {
+    headers: Vec<(String, String)>, // Names lowercased
+}
+
+impl HeaderView {
+    pub fn new<N: Into<String>, V: Into<String>>(headers: impl IntoIterator<Item = (N, V)>) -> Self {
+        let headers = headers.into_iter().map(|(n, v)| (n.into().to_ascii_lowercase(), v.into())).collect();
+        HeaderView { headers }
+    }
+
+    /// The first value of header `name`, if present.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// Every header as `(name, value)`, names lowercased, in the order received.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+/// Why a response failed validation. Becomes the matching `CheckError` in results.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    UnexpectedStatus { code: u16, expected: String },
+    HeaderMismatch { name: String, expected: String, got: String },
+    MissingHeader { name: String },
+    BodyValidation { missing: String },
+}
+
+impl From<ValidationError> for CheckError {
+    fn from(e: ValidationError) -> Self {
+        match e {
+            ValidationError::UnexpectedStatus { code, expected } => CheckError::UnexpectedStatus { code, expected },
+            ValidationError::HeaderMismatch { name, expected, got } => CheckError::HeaderMismatch { name, expected, got },
+            ValidationError::MissingHeader { name } => CheckError::MissingHeader { name },
+            ValidationError::BodyValidation { missing } => CheckError::BodyValidation { missing },
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        CheckError::from(self.clone()).fmt(f)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// One check on the final response of a fetch (after any redirect hops).
+pub trait Validator {
+    /// `body` is `Some` whenever any validator in the check asked for it.
+    fn validate(&self, status: u16, headers: &HeaderView, body: Option<&[u8]>) -> Result<(), ValidationError>;
+    /// Whether this validator reads the body; the body is only downloaded if one does.
+    fn needs_body(&self) -> bool {
+        false
+    }
+}
+
+/// Header `name` must be present with exactly `value`.
+#[derive(Debug, Clone)]
+pub struct HeaderEquals {
+    pub name: String,
+    pub value: String,
+}
+
+impl Validator for HeaderEquals {
+    fn validate(&self, _status: u16, headers: &HeaderView, _body: Option<&[u8]>) -> Result<(), ValidationError> {
+        match headers.get(&self.name) {
+            Some(v) if v == self.value => Ok(()),
+            Some(v) => Err(ValidationError::HeaderMismatch {
+                name: self.name.clone(),
+                expected: self.value.clone(),
+                got: v.to_string(),
+            }),
+            None => Err(ValidationError::MissingHeader { name: self.name.clone() }),
+        }
+    }
+}
+
+/// The body must contain `text`.
+#[derive(Debug, Clone)]
+pub struct BodyContains {
+    pub text: String,
+}
+
+impl Validator for BodyContains {
+    fn validate(&self, _status: u16, _headers: &HeaderView, body: Option<&[u8]>) -> Result<(), ValidationError> {
+        let needle = self.text.as_bytes();
+        let found = body.is_some_and(|b| needle.is_empty() || b.windows(needle.len()).any(|w| w == needle));
+        if found {
+            Ok(())
+        } else {
+            Err(ValidationError::BodyValidation { missing: self.text.clone() })
+        }
+    }
+
+    fn needs_body(&self) -> bool {
+        true
+    }
+}
+
+/// HTTP status codes a check accepts, e.g. `200`, `2xx`, `200-299`, or a
+/// comma-separated mix like `200,301-302,4xx`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusSet {
+    spec: String,
+    ranges: Vec<(u16, u16)>,
+}
+
+impl StatusSet {
+    pub fn parse(s: &str) -> Result<StatusSet, String> {
+        let code = |t: &str| match t.trim().parse::<u16>() {
+            Ok(c) if (100..=599).contains(&c) => Ok(c),
+            _ => Err(format!("invalid status code '{}' in '{s}' (expected 100-599)", t.trim())),
+        };
+        let mut ranges = Vec::new();
+        for part in s.split(',').map(str::trim) {
+            let range = if let Some(class) = part.strip_suffix("xx").or_else(|| part.strip_suffix("XX")) {
+                match class.parse::<u16>() {
+                    Ok(d @ 1..=5) if class.len() == 1 => (d * 100, d * 100 + 99),
+                    _ => return Err(format!("invalid status class '{part}' in '{s}' (expected 1xx-5xx)")),
+                }
+            } else if let Some((lo, hi)) = part.split_once('-') {
+                let (lo, hi) = (code(lo)?, code(hi)?);
+                if lo > hi {
+                    return Err(format!("invalid status range '{part}' in '{s}' (start is after end)"));
+                }
+                (lo, hi)
+            } else {
+                let c = code(part)?;
+                (c, c)
+            };
+            ranges.push(range);
+        }
+        Ok(StatusSet { spec: s.trim().to_string(), ranges })
+    }
+
+    pub fn contains(&self, code: u16) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&code))
+    }
+}
+
+impl fmt::Display for StatusSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+impl FromStr for StatusSet {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StatusSet::parse(s)
+    }
+}
+
+/// The status must be in `expected`.
+#[derive(Debug, Clone)]
+pub struct StatusIn {
+    pub expected: StatusSet,
+}
+
+impl Validator for StatusIn {
+    fn validate(&self, status: u16, _headers: &HeaderView, _body: Option<&[u8]>) -> Result<(), ValidationError> {
+        if self.expected.contains(status) {
+            Ok(())
+        } else {
+            Err(ValidationError::UnexpectedStatus { code: status, expected: self.expected.to_string() })
+        }
+    }
+}
+
+impl CheckSettings {
+    /// The validators these settings ask for, in the order they run: status,
+    /// then headers, then body. A new validation needs one impl and one line here.
+    pub fn validators(&self) -> Vec<Box<dyn Validator>> {
+        let mut validators: Vec<Box<dyn Validator>> = Vec::new();
+        if let Some(expected) = &self.expect_status {
+            validators.push(Box::new(StatusIn { expected: expected.clone() }));
+        }
+        for (name, value) in &self.headers {
+            validators.push(Box::new(HeaderEquals { name: name.clone(), value: value.clone() }));
+        }
+        if let Some(text) = &self.contains {
+            validators.push(Box::new(BodyContains { text: text.clone() }));
+        }
+        validators
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Checker, Config};
+    use httpmock::prelude::*;
+
+    #[test]
+    fn test_header_equals() {
+        let headers = HeaderView::new([("Server", "nginx"), ("X-Cache", "HIT")]);
+        let check = |name: &str, value: &str| HeaderEquals { name: name.into(), value: value.into() }.validate(200, &headers, None);
+        assert_eq!(check("server", "nginx"), Ok(()));
+        assert_eq!(
+            check("X-CACHE", "MISS"),
+            Err(ValidationError::HeaderMismatch { name: "X-CACHE".into(), expected: "MISS".into(), got: "HIT".into() })
+        );
+        assert_eq!(check("Via", "x"), Err(ValidationError::MissingHeader { name: "Via".into() }));
+    }
+
+    #[test]
+    fn test_body_contains() {
+        let v = BodyContains { text: "ok".into() };
+        let headers = HeaderView::default();
+        assert!(v.needs_body());
+        assert_eq!(v.validate(200, &headers, Some(b"all ok here")), Ok(()));
+        let missing = Err(ValidationError::BodyValidation { missing: "ok".into() });
+        assert_eq!(v.validate(200, &headers, Some(b"nope")), missing);
+        assert_eq!(v.validate(200, &headers, None), missing);
+        // Bytes that are not UTF-8 around the match don't matter
+        assert_eq!(v.validate(200, &headers, Some(&[0xff, b'o', b'k', 0xfe])), Ok(()));
+    }
+
+    #[test]
+    fn test_status_set() {
+        let set = StatusSet::parse("200, 301-302,4xx").unwrap();
+        assert!(set.contains(200) && set.contains(302) && set.contains(404) && set.contains(499));
+        assert!(!set.contains(201) && !set.contains(303) && !set.contains(500));
+        assert_eq!(set.to_string(), "200, 301-302,4xx");
+        for bad in ["", "abc", "6xx", "20x", "99", "600", "302-301", "200,"] {
+            assert!(StatusSet::parse(bad).is_err(), "{bad}");
+        }
+
+        let v = StatusIn { expected: StatusSet::parse("2xx").unwrap() };
+        assert_eq!(v.validate(204, &HeaderView::default(), None), Ok(()));
+        let err = v.validate(404, &HeaderView::default(), None).unwrap_err();
+        assert_eq!(err, ValidationError::UnexpectedStatus { code: 404, expected: "2xx".into() });
+        assert_eq!(err.to_string(), "unexpected status 404 (expected 2xx)");
+    }
+
+    #[test]
+    fn test_validators_from_settings() {
+        let config = Config::builder().header("Server", "x").header("Via", "y").contains(Some("ok".into())).build();
+        let validators = config.check.validators();
+        assert_eq!(validators.len(), 3);
+        assert!(validators.iter().any(|v| v.needs_body()));
+        assert!(Config::default().check.validators().is_empty());
+    }
+
+    #[test]
+    fn test_expected_error_status() {
+        let server = MockServer::start();
+        let _gone = server.mock(|when, then| {
+            when.method(GET).path("/gone");
+            then.status(404).body("gone");
+        });
+        let _up = server.mock(|when, then| {
+            when.method(GET).path("/up");
+            then.status(200);
+        });
+
+        let checker = Checker::new(Config::builder().retries(0).expect_status(Some(StatusSet::parse("404").unwrap())).build());
+        let status = checker.check_once(&format!("{}/gone", server.base_url()));
+        assert_eq!(status.status, Ok(404));
+        let status = checker.check_once(&format!("{}/up", server.base_url()));
+        assert_eq!(status.status, Err(CheckError::UnexpectedStatus { code: 200, expected: "404".into() }));
+        assert_eq!(status.http_status, Some(200));
+    }
+}
@@ -0,0 +1,45 @@
+//! The stdout contract: a run writes result records there and nothing else, so
+//! `sitecheck ... | jq` never chokes on a summary or a log line.
+
+use std::process::Command;
+
+use httpmock::prelude::*;
+
+#[test]
+fn test_stdout_is_only_json_records() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(GET).path("/up");
+        then.status(200).body("ok");
+    });
+    server.mock(|when, then| {
+        when.method(GET).path("/down");
+        then.status(503).body("maintenance");
+    });
+    let urls = [server.url("/up"), server.url("/down"), "http://127.0.0.1:9/refused".to_string()];
+
+    let out = Command::new(env!("CARGO_BIN_EXE_sitecheck"))
+        .args(["check", "--timeout", "2s", "--retries", "1", "--backoff-base-ms", "1"])
+        .args(&urls)
+        .env("RUST_LOG", "debug")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+
+    let mut seen: Vec<String> = stdout
+        .lines()
+        .map(|line| {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap_or_else(|e| panic!("{e}: {line:?}"));
+            record["url"].as_str().unwrap().to_string()
+        })
+        .collect();
+    seen.sort();
+    let mut expected = urls.to_vec();
+    expected.sort();
+    assert_eq!(seen, expected, "{stdout}");
+
+    // The summary and the logging went to stderr instead
+    assert!(stderr.contains("--- stats summary ---"), "{stderr}");
+    assert!(stderr.contains("DEBUG"), "{stderr}");
+}
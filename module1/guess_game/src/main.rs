@@ -1,3 +1,9 @@
+use rand::Rng;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::process;
+
 fn check_guess(guess: i32, secret: i32) -> i32 {
     if guess == secret {
         0
@@ -8,23 +14,399 @@ fn check_guess(guess: i32, secret: i32) -> i32 {
     }
 }
 
-fn main() {
-    let secret = 7;
-    let guesses = [3, 5, 8, 7]; // simulated input
-    let mut attempts = 0;
-
-    for guess in guesses {
-        attempts += 1;
-        match check_guess(guess, secret) {
-            0 => {
-                println!("Guess {guess} is correct! 🎉");
-                break;
+/// How close a wrong guess was, relative to the size of the range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Hint {
+    VeryHot, // Within 10% of the range
+    Warm,    // Within 25%
+    Cold,
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Hint::VeryHot => "very hot",
+            Hint::Warm => "warm",
+            Hint::Cold => "cold",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// Pure so the tier boundaries can be tested without any I/O. Computed in i64
+// so distances across the whole i32 range can't overflow.
+fn proximity(guess: i32, secret: i32, range: RangeInclusive<i32>) -> Hint {
+    let span = (i64::from(*range.end()) - i64::from(*range.start())).max(1);
+    let distance = (i64::from(guess) - i64::from(secret)).abs();
+    if distance * 10 <= span {
+        Hint::VeryHot
+    } else if distance * 4 <= span {
+        Hint::Warm
+    } else {
+        Hint::Cold
+    }
+}
+
+/// Range and attempt cap for one game, from a difficulty preset and/or flags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Settings {
+    min: i32,
+    max: i32,
+    max_attempts: Option<u32>, // None => unlimited
+    hints: bool,               // Follow wrong guesses with a hot/cold hint
+}
+
+impl Settings {
+    fn difficulty(name: &str) -> Result<Settings, String> {
+        let (min, max, attempts) = match name {
+            "easy" => (1, 10, 5),
+            "medium" => (1, 100, 7),
+            "hard" => (1, 1000, 10),
+            other => {
+                return Err(format!(
+                    "unknown difficulty '{other}' (expected easy, medium, hard)"
+                ));
             }
-            1 => println!("Guess {guess} is too high."),
-            -1 => println!("Guess {guess} is too low."),
-            _ => {}
+        };
+        Ok(Settings {
+            min,
+            max,
+            max_attempts: Some(attempts),
+            hints: false,
+        })
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            min: 1,
+            max: 100,
+            max_attempts: None,
+            hints: false,
         }
     }
+}
+
+/// What a single guess did to the game.
+#[derive(Debug, PartialEq)]
+enum Step {
+    OutOfRange, // Not counted as an attempt
+    TooHigh,
+    TooLow,
+    Correct,
+}
+
+/// How a game ended.
+#[derive(Debug, PartialEq)]
+enum Ending {
+    Won(u32),
+    Lost,
+    NoInput, // Input ran out before the game was decided
+}
+
+struct Game {
+    secret: i32,
+    settings: Settings,
+    attempts: u32,
+}
+
+impl Game {
+    fn new(secret: i32, settings: Settings) -> Game {
+        Game {
+            secret,
+            settings,
+            attempts: 0,
+        }
+    }
+
+    // One guess, reusing `check_guess` for the comparison
+    fn guess(&mut self, n: i32) -> Step {
+        if !(self.settings.min..=self.settings.max).contains(&n) {
+            return Step::OutOfRange;
+        }
+        self.attempts += 1;
+        match check_guess(n, self.secret) {
+            0 => Step::Correct,
+            1 => Step::TooHigh,
+            _ => Step::TooLow,
+        }
+    }
+
+    fn out_of_attempts(&self) -> bool {
+        self.settings
+            .max_attempts
+            .is_some_and(|cap| self.attempts >= cap)
+    }
+}
+
+// Play `game` to the end, reading guesses line by line from `input`
+fn play(game: &mut Game, input: impl BufRead, out: &mut impl Write) -> io::Result<Ending> {
+    let Settings {
+        min, max, hints, ..
+    } = game.settings;
+    let mut lines = input.lines();
+    loop {
+        match game.settings.max_attempts {
+            Some(cap) => write!(
+                out,
+                "Guess a number between {min} and {max} ({} left): ",
+                cap - game.attempts
+            )?,
+            None => write!(out, "Guess a number between {min} and {max}: ")?,
+        }
+        out.flush()?;
+        let Some(line) = lines.next() else {
+            writeln!(out)?;
+            return Ok(Ending::NoInput);
+        };
+        let line = line?;
+        // Anything that isn't a number just asks again and doesn't count
+        let Ok(n) = line.trim().parse::<i32>() else {
+            writeln!(out, "'{}' is not a number, try again.", line.trim())?;
+            continue;
+        };
+        match game.guess(n) {
+            Step::OutOfRange => writeln!(out, "Please pick a number between {min} and {max}.")?,
+            Step::Correct => {
+                writeln!(out, "Guess {n} is correct! 🎉")?;
+                return Ok(Ending::Won(game.attempts));
+            }
+            step @ (Step::TooHigh | Step::TooLow) => {
+                let direction = if step == Step::TooHigh { "high" } else { "low" };
+                if hints {
+                    let hint = proximity(n, game.secret, min..=max);
+                    writeln!(out, "Guess {n} is too {direction} ({hint}).")?;
+                } else {
+                    writeln!(out, "Guess {n} is too {direction}.")?;
+                }
+            }
+        }
+        if game.out_of_attempts() {
+            return Ok(Ending::Lost);
+        }
+    }
+}
 
-    println!("Total guesses: {attempts}");
-}
\ No newline at end of file
+const USAGE: &str =
+    "usage: guess_game [--difficulty easy|medium|hard] [--min N] [--max N] [--max-attempts N] [--hints]
+  easy: 1-10, 5 attempts   medium: 1-100, 7 attempts   hard: 1-1000, 10 attempts
+  Without a difficulty the range is 1-100 with unlimited attempts.
+  --min/--max/--max-attempts override the difficulty's values.
+  --hints adds very hot / warm / cold after each wrong guess.";
+
+// A difficulty sets the base values; explicit flags win regardless of order
+fn parse_args(args: &[String]) -> Result<Settings, String> {
+    let mut settings = Settings::default();
+    let (mut min, mut max, mut max_attempts) = (None, None, None);
+    let mut hints = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{name} needs a value"));
+        let number = |s: &String| {
+            s.parse::<i32>()
+                .map_err(|e| format!("invalid number '{s}': {e}"))
+        };
+        match arg.as_str() {
+            "--hints" => hints = true,
+            "--difficulty" => settings = Settings::difficulty(value("--difficulty")?)?,
+            "--min" => min = Some(number(value("--min")?)?),
+            "--max" => max = Some(number(value("--max")?)?),
+            "--max-attempts" => {
+                let s = value("--max-attempts")?;
+                match s.parse::<u32>() {
+                    Ok(n) if n > 0 => max_attempts = Some(n),
+                    _ => return Err(format!("invalid --max-attempts '{s}' (expected 1 or more)")),
+                }
+            }
+            other => return Err(format!("unknown argument '{other}'")),
+        }
+    }
+    settings.min = min.unwrap_or(settings.min);
+    settings.max = max.unwrap_or(settings.max);
+    settings.max_attempts = max_attempts.or(settings.max_attempts);
+    settings.hints = hints;
+    if settings.min >= settings.max {
+        return Err(format!(
+            "--min ({}) must be less than --max ({})",
+            settings.min, settings.max
+        ));
+    }
+    Ok(settings)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{USAGE}");
+        return;
+    }
+    let settings = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("{e}\n{USAGE}");
+        process::exit(1);
+    });
+    let secret = rand::rng().random_range(settings.min..=settings.max);
+    let mut game = Game::new(secret, settings);
+
+    match play(&mut game, io::stdin().lock(), &mut io::stdout()) {
+        Ok(Ending::Won(attempts)) => println!("Total guesses: {attempts}"),
+        Ok(Ending::Lost) => {
+            println!("Out of attempts! The number was {secret}.");
+            println!("Total guesses: {}", game.attempts);
+        }
+        Ok(Ending::NoInput) => println!("No more input. The number was {secret}."),
+        Err(e) => {
+            eprintln!("failed to read guess: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(min: i32, max: i32, max_attempts: Option<u32>) -> Settings {
+        Settings {
+            min,
+            max,
+            max_attempts,
+            hints: false,
+        }
+    }
+
+    fn run(secret: i32, max_attempts: Option<u32>, input: &str) -> (Ending, String) {
+        let mut game = Game::new(secret, settings(1, 10, max_attempts));
+        let mut out = Vec::new();
+        let ending = play(&mut game, input.as_bytes(), &mut out).unwrap();
+        (ending, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_play_counts_attempts() {
+        let (ending, out) = run(7, None, "3\n8\n7\n");
+        assert_eq!(ending, Ending::Won(3));
+        assert!(out.contains("Guess 3 is too low."));
+        assert!(out.contains("Guess 8 is too high."));
+        assert!(out.contains("Guess 7 is correct!"));
+    }
+
+    #[test]
+    fn test_play_reprompts_on_bad_input() {
+        let (ending, out) = run(5, None, "five\n\n42\n5\n");
+        assert_eq!(ending, Ending::Won(1));
+        assert!(out.contains("'five' is not a number"));
+        assert!(out.contains("Please pick a number between 1 and 10."));
+
+        let (ending, _) = run(5, None, "1\n2\n");
+        assert_eq!(ending, Ending::NoInput);
+    }
+
+    #[test]
+    fn test_play_runs_out_of_attempts() {
+        // Bad input doesn't use up attempts
+        let (ending, out) = run(5, Some(2), "x\n1\n0\n9\n5\n");
+        assert_eq!(ending, Ending::Lost);
+        assert!(out.contains("(2 left)"));
+        assert!(out.contains("(1 left)"));
+        assert!(!out.contains("Guess 5"));
+
+        let (ending, _) = run(5, Some(2), "1\n5\n");
+        assert_eq!(ending, Ending::Won(2));
+    }
+
+    #[test]
+    fn test_guess_at_range_boundaries() {
+        let mut game = Game::new(1, settings(1, 100, None));
+        assert_eq!(game.guess(100), Step::TooHigh);
+        assert_eq!(game.guess(1), Step::Correct);
+        assert_eq!(game.attempts, 2);
+
+        let mut game = Game::new(100, settings(1, 100, None));
+        assert_eq!(game.guess(1), Step::TooLow);
+        assert_eq!(game.guess(100), Step::Correct);
+
+        let mut game = Game::new(50, settings(1, 100, Some(1)));
+        assert_eq!(game.guess(0), Step::OutOfRange);
+        assert_eq!(game.guess(101), Step::OutOfRange);
+        assert_eq!(game.attempts, 0);
+        assert!(!game.out_of_attempts());
+        assert_eq!(game.guess(100), Step::TooHigh);
+        assert!(game.out_of_attempts());
+    }
+
+    #[test]
+    fn test_proximity_tiers() {
+        // Range 1..=101 spans 100: up to 10 away is very hot, up to 25 is warm
+        let range = || 1..=101;
+        assert_eq!(proximity(60, 50, range()), Hint::VeryHot);
+        assert_eq!(proximity(40, 50, range()), Hint::VeryHot);
+        assert_eq!(proximity(61, 50, range()), Hint::Warm);
+        assert_eq!(proximity(75, 50, range()), Hint::Warm);
+        assert_eq!(proximity(25, 50, range()), Hint::Warm);
+        assert_eq!(proximity(76, 50, range()), Hint::Cold);
+        assert_eq!(proximity(101, 1, range()), Hint::Cold);
+
+        // A tiny range still gives sensible hints, and extremes don't overflow
+        assert_eq!(proximity(1, 2, 1..=2), Hint::Cold);
+        assert_eq!(
+            proximity(i32::MIN, i32::MAX, i32::MIN..=i32::MAX),
+            Hint::Cold
+        );
+    }
+
+    #[test]
+    fn test_play_with_hints() {
+        let mut game = Game::new(
+            50,
+            Settings {
+                hints: true,
+                ..settings(1, 101, None)
+            },
+        );
+        let mut out = Vec::new();
+        play(
+            &mut game,
+            "55
+90
+50
+"
+            .as_bytes(),
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Guess 55 is too high (very hot)."));
+        assert!(out.contains("Guess 90 is too high (cold)."));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(parse_args(&[]), Ok(Settings::default()));
+        assert_eq!(
+            parse_args(&args(&["--difficulty", "hard"])),
+            Ok(settings(1, 1000, Some(10)))
+        );
+        // Explicit flags override the preset, whichever comes first
+        assert_eq!(
+            parse_args(&args(&["--max", "20", "--difficulty", "easy"])),
+            Ok(settings(1, 20, Some(5)))
+        );
+        assert_eq!(
+            parse_args(&args(&["--min", "-5", "--max", "5", "--max-attempts", "3"])),
+            Ok(settings(-5, 5, Some(3)))
+        );
+        assert!(parse_args(&args(&["--min", "10", "--max", "10"])).is_err());
+        assert!(parse_args(&args(&["--min", "10", "--max", "1"])).is_err());
+        assert!(parse_args(&args(&["--max-attempts", "0"])).is_err());
+        assert!(parse_args(&args(&["--difficulty", "insane"])).is_err());
+        assert!(parse_args(&args(&["--max"])).is_err());
+        assert!(parse_args(&args(&["7"])).is_err());
+        assert!(
+            parse_args(&args(&["--hints", "--difficulty", "easy"]))
+                .unwrap()
+                .hints
+        );
+    }
+}
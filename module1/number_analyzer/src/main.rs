@@ -1,40 +1,37 @@
-fn is_even(n: i32) -> bool {
-    n % 2 == 0
-}
-
-fn main() {
-    let numbers = [4, 15, 6, 9, 20, 13, 30, 11, 3, 10];
+use std::io::{self, Read};
+use std::process;
 
-    // Check even/odd and FizzBuzz rules
-    for &n in numbers.iter() {
-        if n % 3 == 0 && n % 5 == 0 {
-            println!("{n} => FizzBuzz");
-        } else if n % 3 == 0 {
-            println!("{n} => Fizz");
-        } else if n % 5 == 0 {
-            println!("{n} => Buzz");
-        } else if is_even(n) {
-            println!("{n} => Even");
-        } else {
-            println!("{n} => Odd");
-        }
-    }
+use common::numbers::{analyze, parse_numbers};
 
-    // While loop to find sum
-    let mut sum = 0;
-    let mut i = 0;
-    while i < numbers.len() {
-        sum += numbers[i];
-        i += 1;
-    }
-    println!("\nSum of numbers: {sum}");
-
-    // Find and print largest number
-    let mut max = numbers[0];
-    for &n in numbers.iter() {
-        if n > max {
-            max = n;
+fn main() {
+    // Numbers come from the command line, or from stdin if none were given
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let parsed = if args.is_empty() {
+        let mut input = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut input) {
+            eprintln!("failed to read stdin: {e}");
+            process::exit(1);
         }
+        parse_numbers(input.split_whitespace())
+    } else {
+        parse_numbers(args.iter().map(String::as_str))
+    };
+    let numbers = parsed.unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    let a = analyze(&numbers);
+    for (n, class) in &a.classes {
+        println!("{n} => {class}");
     }
+    let (Some(max), Some(min), Some(mean), Some(median)) = (a.max, a.min, a.mean, a.median) else {
+        println!("no numbers");
+        return;
+    };
+    println!("\nSum of numbers: {}", a.sum);
     println!("Largest number: {max}");
-}
\ No newline at end of file
+    println!("Smallest number: {min}");
+    println!("Mean: {mean:.2}");
+    println!("Median: {median}");
+    println!("Evens: {}, Odds: {}", a.evens, a.odds);
+}
@@ -1,21 +1,238 @@
-const FREEZING_POINT_F: f64 = 32.0;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
 
-fn fahrenheit_to_celsius(f: f64) -> f64 {
-    (f - FREEZING_POINT_F) * 5.0 / 9.0
+use common::temperature::{Unit, convert};
+
+const USAGE: &str = "usage: tempconv [VALUE | --file PATH] --from UNIT --to UNIT [--precision N]
+  UNIT is C, F, K, or R (or celsius, fahrenheit, kelvin, rankine).
+  Without VALUE, converts one value per line of --file (or stdin);
+  blank lines and # comments are skipped.
+  --precision sets the decimal places printed (default 2).";
+
+/// Where the temperatures to convert come from.
+#[derive(Debug, PartialEq)]
+enum Input {
+    Value(f64),
+    File(String),
+    Stdin,
 }
 
-fn celsius_to_fahrenheit(c: f64) -> f64 {
-    (c * 9.0 / 5.0) + FREEZING_POINT_F
+/// One run requested on the command line.
+#[derive(Debug, PartialEq)]
+struct Args {
+    input: Input,
+    from: Unit,
+    to: Unit,
+    precision: usize,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let (mut value, mut file, mut from, mut to, mut precision) = (None, None, None, None, 2);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut next = |name: &str| args.next().ok_or_else(|| format!("{name} needs a value"));
+        match arg.as_str() {
+            "--from" => from = Some(next("--from")?.parse::<Unit>()?),
+            "--to" => to = Some(next("--to")?.parse::<Unit>()?),
+            "--file" => file = Some(next("--file")?.clone()),
+            "--precision" => {
+                let s = next("--precision")?;
+                precision = s
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid --precision '{s}' (expected 0 or more)"))?;
+            }
+            // Negative temperatures look like flags, so only `--` marks one
+            other if other.starts_with("--") => return Err(format!("unknown argument '{other}'")),
+            other if value.is_some() => return Err(format!("unexpected argument '{other}'")),
+            other => {
+                let v = other
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid temperature '{other}': {e}"))?;
+                value = Some(v);
+            }
+        }
+    }
+    let input = match (value, file) {
+        (Some(_), Some(_)) => return Err("give either VALUE or --file, not both".to_string()),
+        (Some(v), None) => Input::Value(v),
+        (None, Some(path)) => Input::File(path),
+        (None, None) => Input::Stdin,
+    };
+    Ok(Args {
+        input,
+        from: from.ok_or("missing --from")?,
+        to: to.ok_or("missing --to")?,
+        precision,
+    })
+}
+
+/// Convert one value per line of `input`, writing a result line for each.
+/// Blank lines and `#` comments are skipped. A bad line is recorded as a
+/// `line N: ...` error and the batch carries on; returns how many lines
+/// converted, and the errors.
+fn convert_batch(
+    input: impl BufRead,
+    out: &mut impl Write,
+    args: &Args,
+) -> io::Result<(usize, Vec<String>)> {
+    let (mut converted, mut errors) = (0, Vec::new());
+    for (idx, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = line
+            .parse::<f64>()
+            .map_err(|e| format!("invalid temperature '{line}': {e}"))
+            .and_then(|v| convert(v, args.from, args.to).map_err(|e| e.to_string()));
+        match result {
+            Ok(v) => {
+                writeln!(
+                    out,
+                    "{line}{} = {v:.prec$}{}",
+                    args.from,
+                    args.to,
+                    prec = args.precision
+                )?;
+                converted += 1;
+            }
+            Err(e) => errors.push(format!("line {}: {e}", idx + 1)),
+        }
+    }
+    Ok((converted, errors))
 }
 
 fn main() {
-    let mut fahrenheit = 32.0;
-    let celsius = fahrenheit_to_celsius(fahrenheit);
-    println!("{fahrenheit}°F is {celsius:.2}°C");
-
-    for _ in 0..5 {
-        fahrenheit += 1.0;
-        let c = fahrenheit_to_celsius(fahrenheit);
-        println!("{fahrenheit}°F is {c:.2}°C");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{USAGE}");
+        return;
+    }
+    let args = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("{e}\n{USAGE}");
+        process::exit(1);
+    });
+    let input: Box<dyn BufRead> = match &args.input {
+        Input::Value(value) => match convert(*value, args.from, args.to) {
+            Ok(v) => {
+                println!("{v:.prec$}{}", args.to, prec = args.precision);
+                return;
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                process::exit(1);
+            }
+        },
+        Input::File(path) => match File::open(path) {
+            Ok(f) => Box::new(BufReader::new(f)),
+            Err(e) => {
+                eprintln!("failed to open {path}: {e}");
+                process::exit(1);
+            }
+        },
+        Input::Stdin => Box::new(io::stdin().lock()),
+    };
+    let (converted, errors) =
+        convert_batch(input, &mut io::stdout().lock(), &args).unwrap_or_else(|e| {
+            eprintln!("failed to read input: {e}");
+            process::exit(1);
+        });
+    if !errors.is_empty() {
+        for e in &errors {
+            eprintln!("{e}");
+        }
+        eprintln!(
+            "{} of {} values failed to convert",
+            errors.len(),
+            converted + errors.len()
+        );
+        process::exit(1);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args(&["100", "--from", "C", "--to", "F"])),
+            Ok(Args {
+                input: Input::Value(100.0),
+                from: Unit::Celsius,
+                to: Unit::Fahrenheit,
+                precision: 2
+            })
+        );
+        assert_eq!(
+            parse_args(&args(&[
+                "--to",
+                "k",
+                "--precision",
+                "0",
+                "--from",
+                "f",
+                "-40"
+            ])),
+            Ok(Args {
+                input: Input::Value(-40.0),
+                from: Unit::Fahrenheit,
+                to: Unit::Kelvin,
+                precision: 0
+            })
+        );
+        assert!(parse_args(&args(&["abc", "--from", "C", "--to", "F"])).is_err());
+        assert!(parse_args(&args(&["1", "--from", "Q", "--to", "F"])).is_err());
+        assert!(parse_args(&args(&["1", "--from", "C"])).is_err());
+        assert_eq!(
+            parse_args(&args(&["--from", "C", "--to", "F"])).map(|a| a.input),
+            Ok(Input::Stdin)
+        );
+        assert_eq!(
+            parse_args(&args(&["--file", "t.txt", "--from", "C", "--to", "F"])).map(|a| a.input),
+            Ok(Input::File("t.txt".to_string()))
+        );
+        assert!(parse_args(&args(&["1", "--file", "t.txt", "--from", "C", "--to", "F"])).is_err());
+        assert!(parse_args(&args(&["1", "2", "--from", "C", "--to", "F"])).is_err());
+        assert!(
+            parse_args(&args(&[
+                "1",
+                "--from",
+                "C",
+                "--to",
+                "F",
+                "--precision",
+                "-1"
+            ]))
+            .is_err()
+        );
+        assert!(parse_args(&args(&["1", "--from", "C", "--to"])).is_err());
+        assert!(parse_args(&args(&["1", "--from", "C", "--to", "F", "--round"])).is_err());
+    }
+
+    #[test]
+    fn test_convert_batch() {
+        let args = parse_args(&args(&["--from", "C", "--to", "F", "--precision", "1"])).unwrap();
+        let input = "# readings\n100\n\n  -40  \nwarm\n-300\n0\n";
+        let mut out = Vec::new();
+        let (converted, errors) = convert_batch(input.as_bytes(), &mut out, &args).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "100°C = 212.0°F\n-40°C = -40.0°F\n0°C = 32.0°F\n"
+        );
+        assert_eq!(converted, 3);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("line 5: invalid temperature 'warm'"));
+        assert_eq!(
+            errors[1],
+            "line 6: -300°C is below absolute zero (-273.15°C)"
+        );
+    }
+}
@@ -1,23 +1,241 @@
-fn sum_with_step(total: &mut i32, low: i32, high: i32, step: i32) {
-    let mut current = low;
-    let step = step.abs(); // handle negative steps safely
-    *total = 0;
+use std::fmt;
 
-    while current <= high {
-        *total += current;
-        current += step;
+/// Why `sum_with_step` refused to run.
+#[derive(Debug, PartialEq)]
+enum StepError {
+    /// A step of 0 would never advance past `low`, so the loop would spin forever.
+    ZeroStep,
+    /// The sum does not fit in the `i32` that `sum_with_step` writes to.
+    Overflow,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepError::ZeroStep => write!(f, "step must not be zero"),
+            StepError::Overflow => write!(f, "sum does not fit in an i32"),
+        }
     }
 }
 
-fn main() {
-    let mut result = 0;
-    sum_with_step(&mut result, 0, 100, 1);
-    println!("Sum 0 to 100, step 1: {}", result);
+impl std::error::Error for StepError {}
+
+/// Sum every `step`-th value from `low` towards `high`, stopping at or before `high`.
+/// The range runs upwards when `low <= high` (`low, low + step, ...`) and
+/// downwards when `low > high` (`low, low - step, ...`), so `sum_range(15, 5, 3)`
+/// is 15 + 12 + 9 + 6. Only the step's magnitude is used: the direction always
+/// comes from `low` and `high`. `low == high` sums to `low`, and a zero step is
+/// rejected with `StepError::ZeroStep`.
+fn sum_range(low: i32, high: i32, step: i32) -> Result<i64, StepError> {
+    sum_between(low, high, step, true)
+}
+
+/// Like `sum_range`, but `high` itself is never included, matching Rust's
+/// `low..high`: `sum_range_exclusive(0, 10, 2)` is 0 + 2 + 4 + 6 + 8, where
+/// `sum_range` would add the 10 too. Descending ranges stop just above `high`,
+/// and `low == high` is empty, summing to 0.
+fn sum_range_exclusive(low: i32, high: i32, step: i32) -> Result<i64, StepError> {
+    sum_between(low, high, step, false)
+}
+
+fn sum_between(low: i32, high: i32, step: i32, inclusive: bool) -> Result<i64, StepError> {
+    if step == 0 {
+        return Err(StepError::ZeroStep);
+    }
+    // Arithmetic series in i128, so no intermediate step can overflow
+    let (low, high) = (i128::from(low), i128::from(high));
+    let direction = if low <= high { 1 } else { -1 };
+    let last = match inclusive {
+        true => high,
+        false if low == high => return Ok(0),
+        false => high - direction,
+    };
+    let step = direction * i128::from(step).abs();
+    let terms = (last - low) / step + 1;
+    let sum = terms * low + step * terms * (terms - 1) / 2;
+    // At most 2^32 terms of magnitude at most 2^31, so the total always fits
+    Ok(i64::try_from(sum).expect("sum of an i32 range fits in i64"))
+}
 
-    sum_with_step(&mut result, 0, 10, 2);
-    println!("Sum 0 to 10, step 2: {}", result);
+/// Older out-parameter form of `sum_range`, writing the sum into `total`.
+/// Ranges run in the same direction as `sum_range`'s, always including `high`.
+/// A sum too big for `i32` is `StepError::Overflow`; on any error `total` is
+/// left untouched. Prefer `sum_range`, whose `i64` result cannot overflow.
+#[allow(dead_code)]
+fn sum_with_step(total: &mut i32, low: i32, high: i32, step: i32) -> Result<(), StepError> {
+    if step == 0 {
+        return Err(StepError::ZeroStep);
+    }
+    let step = step.checked_abs().unwrap_or(i32::MAX); // handle negative steps safely
+    let descending = low > high;
+    let mut sum: i32 = 0;
+    let mut current = Some(low);
 
-    sum_with_step(&mut result, 5, 15, 3);
-    println!("Sum 5 to 15, step 3: {}", result);
+    while let Some(value) = current.filter(|&v| if descending { v >= high } else { v <= high }) {
+        sum = sum.checked_add(value).ok_or(StepError::Overflow)?;
+        // Stepping past i32::MAX (or MIN) also means stepping past `high`
+        current = if descending {
+            value.checked_sub(step)
+        } else {
+            value.checked_add(step)
+        };
+    }
+    *total = sum;
+    Ok(())
 }
 
+fn main() {
+    for (low, high, step) in [(0, 100, 1), (0, 10, 2), (5, 15, 3), (15, 5, 3), (0, 10, 0)] {
+        match sum_range(low, high, step) {
+            Ok(result) => println!("Sum {low} to {high}, step {step}: {result}"),
+            Err(e) => println!("Sum {low} to {high}, step {step}: error: {e}"),
+        }
+    }
+    if let Ok(result) = sum_range_exclusive(0, 10, 2) {
+        println!("Sum 0 up to (not including) 10, step 2: {result}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sums() {
+        let mut total = 0;
+        sum_with_step(&mut total, 0, 100, 1).unwrap();
+        assert_eq!(total, 5050);
+        sum_with_step(&mut total, 5, 15, 3).unwrap();
+        assert_eq!(total, 5 + 8 + 11 + 14);
+        sum_with_step(&mut total, 0, 10, -2).unwrap();
+        assert_eq!(total, 30);
+    }
+
+    #[test]
+    fn test_sum_range_matches_sum_with_step() {
+        let cases = [
+            (0, 100, 1),
+            (0, 10, 2),
+            (5, 15, 3),
+            (0, 10, -2),
+            (-20, 20, 7),
+            (-5, -1, 1),
+            (3, 3, 5),
+            (10, 0, 1),
+            (15, 5, 3),
+            (15, 5, -3),
+            (-1, -20, 4),
+        ];
+        for (low, high, step) in cases {
+            let mut total = 0;
+            sum_with_step(&mut total, low, high, step).unwrap();
+            assert_eq!(
+                sum_range(low, high, step),
+                Ok(i64::from(total)),
+                "{low}..={high} step {step}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sum_range_is_overflow_safe() {
+        // Far past i32::MAX, which the out-parameter version could not hold
+        let n = i64::from(i32::MAX);
+        assert_eq!(sum_range(0, i32::MAX, 1), Ok(n * (n + 1) / 2));
+        assert_eq!(sum_range(0, 10, 0), Err(StepError::ZeroStep));
+        assert_eq!(sum_range(i32::MIN, i32::MAX, 1), Ok(i64::from(i32::MIN)));
+    }
+
+    #[test]
+    fn test_sum_with_step_overflow() {
+        // Used to panic in debug builds and wrap to a wrong answer in release
+        let mut total = 7;
+        assert_eq!(
+            sum_with_step(&mut total, 0, i32::MAX, 1),
+            Err(StepError::Overflow)
+        );
+        assert_eq!(total, 7);
+        // The widened version gives the right answer for the same range
+        let n = i64::from(i32::MAX);
+        assert_eq!(sum_range(0, i32::MAX, 1), Ok(n * (n + 1) / 2));
+
+        // Stepping past i32::MAX ends the loop instead of wrapping around
+        sum_with_step(&mut total, i32::MAX, i32::MAX, 5).unwrap();
+        assert_eq!(total, i32::MAX);
+        // i32::MIN has no positive counterpart; it still just takes one term
+        sum_with_step(&mut total, i32::MAX - 3, i32::MAX, i32::MIN).unwrap();
+        assert_eq!(total, i32::MAX - 3);
+        assert_eq!(sum_range(5, 100, i32::MIN), Ok(5));
+    }
+
+    #[test]
+    fn test_directions() {
+        // Ascending: low up to high
+        assert_eq!(sum_range(5, 15, 3), Ok(5 + 8 + 11 + 14));
+        assert_eq!(sum_range(5, 15, -3), Ok(5 + 8 + 11 + 14));
+        // Descending: low down to high, whatever the step's sign
+        assert_eq!(sum_range(15, 5, 3), Ok(15 + 12 + 9 + 6));
+        assert_eq!(sum_range(15, 5, -3), Ok(15 + 12 + 9 + 6));
+        assert_eq!(sum_range(10, -10, 5), Ok(10 + 5 - 5 - 10));
+        let mut total = 0;
+        sum_with_step(&mut total, 15, 5, 3).unwrap();
+        assert_eq!(total, 42);
+
+        // A single element, whatever the step
+        assert_eq!(sum_range(7, 7, 1), Ok(7));
+        assert_eq!(sum_range(7, 7, -100), Ok(7));
+        sum_with_step(&mut total, -3, -3, 2).unwrap();
+        assert_eq!(total, -3);
+
+        // Stepping past i32::MIN ends a descending range instead of wrapping
+        assert_eq!(
+            sum_range(i32::MIN + 1, i32::MIN, 5),
+            Ok(i64::from(i32::MIN) + 1)
+        );
+        sum_with_step(&mut total, i32::MIN + 2, i32::MIN + 1, 5).unwrap();
+        assert_eq!(total, i32::MIN + 2);
+    }
+
+    #[test]
+    fn test_inclusive_vs_exclusive() {
+        // `high` landed on exactly by a step: only the inclusive form adds it
+        assert_eq!(sum_range(0, 10, 2), Ok(30));
+        assert_eq!(sum_range_exclusive(0, 10, 2), Ok(20));
+        assert_eq!(sum_range(15, 6, 3), Ok(15 + 12 + 9 + 6));
+        assert_eq!(sum_range_exclusive(15, 6, 3), Ok(15 + 12 + 9));
+        // `high` stepped over: the two agree
+        assert_eq!(sum_range(0, 9, 2), Ok(20));
+        assert_eq!(sum_range_exclusive(0, 9, 2), Ok(20));
+        // One element inclusive, none exclusive
+        assert_eq!(sum_range(7, 7, 1), Ok(7));
+        assert_eq!(sum_range_exclusive(7, 7, 1), Ok(0));
+        assert_eq!(sum_range_exclusive(7, 7, 0), Err(StepError::ZeroStep));
+
+        // Same answers as std's exclusive ranges
+        for (low, high, step) in [(0, 10, 2), (0, 9, 2), (-7, 20, 3), (5, 6, 4), (3, 3, 1)] {
+            let expected: i64 = (low..high).step_by(step as usize).map(i64::from).sum();
+            assert_eq!(
+                sum_range_exclusive(low, high, step),
+                Ok(expected),
+                "{low}..{high} step {step}"
+            );
+        }
+        let descending: i64 = (6 + 1..=15).rev().step_by(3).map(i64::from).sum();
+        assert_eq!(sum_range_exclusive(15, 6, 3), Ok(descending));
+        assert_eq!(
+            sum_range_exclusive(i32::MIN, i32::MAX, 1),
+            Ok(i64::from(i32::MIN) - i64::from(i32::MAX))
+        );
+    }
+
+    #[test]
+    fn test_zero_step_is_an_error() {
+        // Before the guard this call never returned
+        let mut total = 42;
+        assert_eq!(
+            sum_with_step(&mut total, 0, 10, 0),
+            Err(StepError::ZeroStep)
+        );
+        assert_eq!(total, 42);
+    }
+}
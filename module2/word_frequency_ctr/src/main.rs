@@ -1,29 +1,77 @@
-fn most_frequent_word(text: &str) -> (String, usize) {
-    let words: Vec<&str> = text.split_whitespace().collect();
-    let mut unique_words: Vec<&str> = Vec::new();
-    let mut counts: Vec<usize> = Vec::new();
+use std::fs;
+use std::io;
+use std::process;
 
-    for word in words {
-        if let Some(pos) = unique_words.iter().position(|&w| w == word) {
-            counts[pos] += 1;
-        } else {
-            unique_words.push(word);
-            counts.push(1);
+use common::words::{WordFilter, builtin_stopwords, count_words, load_stopwords};
+
+const USAGE: &str = "usage: word_frequency_ctr [--normalize] [--stopwords[=FILE]] [--top N] [PATH]
+  Counts words in PATH, or stdin if no PATH is given.
+  --normalize        ignore case and surrounding punctuation
+  --stopwords        also skip common English words (implies --normalize)
+  --stopwords=FILE   skip the words listed in FILE instead
+  --top N            list the N most frequent words";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{msg}\n{USAGE}");
+    process::exit(1);
+}
+
+fn main() {
+    let mut filter = WordFilter::Raw;
+    let mut top: Option<usize> = None;
+    let mut path: Option<String> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            "--normalize" => {
+                if let WordFilter::Raw = filter {
+                    filter = WordFilter::Normalized;
+                }
+            }
+            "--stopwords" => filter = WordFilter::Excluding(builtin_stopwords()),
+            "--top" => {
+                let n = args.next().unwrap_or_else(|| fail("--top needs a number"));
+                let n = n
+                    .parse()
+                    .unwrap_or_else(|_| fail(&format!("invalid --top value '{n}'")));
+                top = Some(n);
+            }
+            _ if arg.starts_with("--stopwords=") => {
+                let file = &arg["--stopwords=".len()..];
+                let stopwords = load_stopwords(file).unwrap_or_else(|e| {
+                    fail(&format!("failed to read stopwords from {file}: {e}"))
+                });
+                filter = WordFilter::Excluding(stopwords);
+            }
+            _ if arg.starts_with('-') && arg != "-" => fail(&format!("unknown option '{arg}'")),
+            _ if path.is_none() => path = Some(arg),
+            _ => fail("only one input file can be given"),
         }
     }
 
-    let mut max_index = 0;
-    for i in 1..counts.len() {
-        if counts[i] > counts[max_index] {
-            max_index = i;
+    let counted = match path.as_deref() {
+        None | Some("-") => count_words(io::stdin().lock(), &filter),
+        Some(p) => fs::File::open(p).and_then(|f| count_words(io::BufReader::new(f), &filter)),
+    };
+    let counts = counted.unwrap_or_else(|e| {
+        eprintln!("failed to read {}: {e}", path.as_deref().unwrap_or("stdin"));
+        process::exit(1);
+    });
+
+    match (top, counts.most_frequent()) {
+        (_, None) => println!("No words found in input."),
+        (None, Some((word, count))) => {
+            println!("Most frequent word: \"{}\" ({} times)", word, count)
+        }
+        (Some(n), Some(_)) => {
+            for (rank, (word, count)) in counts.top_n(n).iter().enumerate() {
+                println!("{:>3}. {} ({})", rank + 1, word, count);
+            }
         }
     }
-
-    (unique_words[max_index].to_string(), counts[max_index])
 }
-
-fn main() {
-    let text = "the quick brown fox jumps over the lazy dog the quick brown fox";
-    let (word, count) = most_frequent_word(text);
-    println!("Most frequent word: \"{}\" ({} times)", word, count);
-}
\ No newline at end of file